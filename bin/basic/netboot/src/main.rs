@@ -0,0 +1,124 @@
+#![no_std]
+#![no_main]
+
+runtime::binInit!();
+
+extern crate alloc;
+
+mod net;
+mod sha256;
+mod tftp;
+
+use core::fmt;
+use net::{Ipv4Addr, NetStack};
+use runtime::elf::{self, Elf, Program};
+use runtime::{process, virtio};
+
+/// Start of DRAM on the QEMU virt machine, where raw images are loaded
+const RAM_BASE: usize = 0x8000_0000;
+
+/// Address slirp expects the guest to use
+const LOCAL_IP: Ipv4Addr = [10, 0, 2, 15];
+
+/// Address of slirp's built-in TFTP server (the virtual gateway)
+const SERVER_IP: Ipv4Addr = [10, 0, 2, 2];
+
+/// Image to fetch, relative to the `tftp=` directory given to QEMU.
+/// Override at build time with `NETBOOT_FILE=...`.
+const BOOT_FILE: &str = match option_env!("NETBOOT_FILE") {
+    Some(file) => file,
+    None => "boot.elf",
+};
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    Device(virtio::Error),
+    Timeout(&'static str),
+    Tftp { code: u16, message: String },
+    Refused(&'static str),
+    Image(elf::Error),
+    Checksum,
+    MalformedChecksum,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Device(err) => write!(f, "network device unavailable: {:?}", err),
+            Error::Timeout(what) => write!(f, "timed out during {}", what),
+            Error::Tftp { code, message } => write!(f, "TFTP error {}: {}", code, message),
+            Error::Refused(reason) => write!(f, "TFTP transfer refused: {}", reason),
+            Error::Image(reason) => write!(f, "bad boot image: {}", reason),
+            Error::Checksum => write!(f, "checksum mismatch, refusing to boot"),
+            Error::MalformedChecksum => write!(f, "malformed checksum file"),
+        }
+    }
+}
+
+fn fmt_ip(ip: Ipv4Addr) -> String {
+    alloc::format!("{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3])
+}
+
+fn fmt_digest(digest: &sha256::Digest) -> String {
+    digest.iter().map(|b| alloc::format!("{:02x}", b)).collect()
+}
+
+fn main() {
+    println!("=== AM-RS Netboot ===");
+
+    if let Err(err) = netboot() {
+        println!("netboot: {}", err);
+        process::exit(1);
+    }
+}
+
+fn netboot() -> Result<(), Error> {
+    let nic = virtio::net::VirtioNet::new().map_err(Error::Device)?;
+    let mut net = NetStack::new(nic, LOCAL_IP);
+
+    let mac = net.mac();
+    println!(
+        "virtio-net: mac {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}, ip {}",
+        mac[0],
+        mac[1],
+        mac[2],
+        mac[3],
+        mac[4],
+        mac[5],
+        fmt_ip(LOCAL_IP)
+    );
+
+    println!("Fetching {} from {}", BOOT_FILE, fmt_ip(SERVER_IP));
+    let data = tftp::fetch(&mut net, SERVER_IP, BOOT_FILE)?;
+    println!("Received {} bytes", data.len());
+
+    let checksum_file = alloc::format!("{}.sha256", BOOT_FILE);
+    let expected = sha256::parse_hex(&tftp::fetch(&mut net, SERVER_IP, &checksum_file)?)
+        .ok_or(Error::MalformedChecksum)?;
+    let actual = sha256::digest(&data);
+    println!("sha256: {}", fmt_digest(&actual));
+    if actual != expected {
+        println!("expected: {}", fmt_digest(&expected));
+        return Err(Error::Checksum);
+    }
+
+    // ELF images are loaded by their program headers, anything else is a
+    // raw image entered at the start of RAM
+    let (format, program) = match Elf::parse(&data) {
+        Err(elf::Error::NotElf) => ("Raw", Program::raw(&data, RAM_BASE)),
+        elf => ("Elf", elf.and_then(|elf| elf.program())),
+    };
+    let program = program.map_err(Error::Image)?;
+    println!(
+        "{} image, {} bytes to load, entry 0x{:08x}",
+        format,
+        program.load_size(),
+        program.entry()
+    );
+
+    // Stop the device before its receive buffers get overwritten
+    drop(net);
+
+    println!("Booting...");
+    Err(Error::Image(program.exec(&[BOOT_FILE], elf::Mode::Machine)))
+}
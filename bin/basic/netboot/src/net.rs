@@ -0,0 +1,255 @@
+//! Minimal Ethernet/ARP/IPv4/UDP stack
+//!
+//! Just enough networking to talk to QEMU's slirp TFTP server: static
+//! addressing, ARP resolution with a small cache, unfragmented IPv4 and UDP
+//! without checksums (which IPv4 permits).
+
+runtime::libInit!();
+
+use crate::Error;
+use runtime::clint::uptime_ms;
+use runtime::virtio::net::{MAX_FRAME_LEN, VirtioNet};
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const IP_PROTO_UDP: u8 = 17;
+
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+
+const BROADCAST_MAC: [u8; 6] = [0xff; 6];
+
+const ETH_HEADER_LEN: usize = 14;
+const IPV4_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+
+/// How long to wait for an ARP reply before retrying
+const ARP_TIMEOUT_MS: u64 = 500;
+const ARP_RETRIES: u32 = 4;
+
+/// An IPv4 address
+pub type Ipv4Addr = [u8; 4];
+
+/// A UDP datagram addressed to us
+pub struct Datagram {
+    pub src_ip: Ipv4Addr,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub payload: Vec<u8>,
+}
+
+/// Network interface state
+pub struct NetStack {
+    nic: VirtioNet,
+    mac: [u8; 6],
+    ip: Ipv4Addr,
+    arp_cache: Vec<(Ipv4Addr, [u8; 6])>,
+    frame: Box<[u8; MAX_FRAME_LEN]>,
+    ip_id: u16,
+}
+
+fn be16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+/// Internet checksum (RFC 1071) of a header
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+impl NetStack {
+    /// Create an interface with a static address
+    ///
+    /// # Arguments
+    /// * `nic` - Initialized network device
+    /// * `ip` - Address of this host
+    pub fn new(nic: VirtioNet, ip: Ipv4Addr) -> Self {
+        Self {
+            mac: nic.mac(),
+            nic,
+            ip,
+            arp_cache: Vec::new(),
+            frame: Box::new([0; MAX_FRAME_LEN]),
+            ip_id: 0,
+        }
+    }
+
+    /// MAC address of the interface
+    pub fn mac(&self) -> [u8; 6] {
+        self.mac
+    }
+
+    fn send_frame(&mut self, dst_mac: [u8; 6], ethertype: u16, payload: &[u8]) {
+        let mut frame = Vec::with_capacity(ETH_HEADER_LEN + payload.len());
+        frame.extend_from_slice(&dst_mac);
+        frame.extend_from_slice(&self.mac);
+        frame.extend_from_slice(&ethertype.to_be_bytes());
+        frame.extend_from_slice(payload);
+        self.nic.send(&frame);
+    }
+
+    fn send_arp(&mut self, op: u16, target_mac: [u8; 6], target_ip: Ipv4Addr) {
+        let mut arp = Vec::with_capacity(28);
+        arp.extend_from_slice(&1u16.to_be_bytes()); // Ethernet
+        arp.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        arp.extend_from_slice(&[6, 4]);
+        arp.extend_from_slice(&op.to_be_bytes());
+        arp.extend_from_slice(&self.mac);
+        arp.extend_from_slice(&self.ip);
+        arp.extend_from_slice(&target_mac);
+        arp.extend_from_slice(&target_ip);
+
+        let dst = if op == ARP_REQUEST {
+            BROADCAST_MAC
+        } else {
+            target_mac
+        };
+        self.send_frame(dst, ETHERTYPE_ARP, &arp);
+    }
+
+    fn handle_arp(&mut self, arp: &[u8]) {
+        if arp.len() < 28 {
+            return;
+        }
+
+        let op = be16(arp, 6);
+        let sender_mac: [u8; 6] = arp[8..14].try_into().unwrap();
+        let sender_ip: Ipv4Addr = arp[14..18].try_into().unwrap();
+        let target_ip: Ipv4Addr = arp[24..28].try_into().unwrap();
+
+        if target_ip != self.ip {
+            return;
+        }
+
+        self.arp_cache.retain(|(ip, _)| *ip != sender_ip);
+        self.arp_cache.push((sender_ip, sender_mac));
+
+        if op == ARP_REQUEST {
+            self.send_arp(ARP_REPLY, sender_mac, sender_ip);
+        }
+    }
+
+    fn resolve(&mut self, ip: Ipv4Addr) -> Result<[u8; 6], Error> {
+        for _ in 0..ARP_RETRIES {
+            if let Some((_, mac)) = self.arp_cache.iter().find(|(cached, _)| *cached == ip) {
+                return Ok(*mac);
+            }
+
+            self.send_arp(ARP_REQUEST, [0; 6], ip);
+            let deadline = uptime_ms() + ARP_TIMEOUT_MS;
+            while uptime_ms() < deadline {
+                // Datagrams arriving before the reply are dropped, the
+                // sender retransmits them anyway
+                let _ = self.poll();
+                if self.arp_cache.iter().any(|(cached, _)| *cached == ip) {
+                    break;
+                }
+            }
+        }
+
+        self.arp_cache
+            .iter()
+            .find(|(cached, _)| *cached == ip)
+            .map(|(_, mac)| *mac)
+            .ok_or(Error::Timeout("ARP resolution"))
+    }
+
+    /// Send a UDP datagram, resolving the destination MAC first
+    ///
+    /// # Arguments
+    /// * `dst_ip` - Destination address, on the local subnet or the gateway
+    /// * `src_port` - Local port
+    /// * `dst_port` - Remote port
+    /// * `payload` - Datagram payload, small enough to avoid fragmentation
+    pub fn send_udp(
+        &mut self,
+        dst_ip: Ipv4Addr,
+        src_port: u16,
+        dst_port: u16,
+        payload: &[u8],
+    ) -> Result<(), Error> {
+        let dst_mac = self.resolve(dst_ip)?;
+
+        let udp_len = UDP_HEADER_LEN + payload.len();
+        let total_len = IPV4_HEADER_LEN + udp_len;
+        self.ip_id = self.ip_id.wrapping_add(1);
+
+        let mut packet = Vec::with_capacity(total_len);
+        packet.extend_from_slice(&[0x45, 0]);
+        packet.extend_from_slice(&(total_len as u16).to_be_bytes());
+        packet.extend_from_slice(&self.ip_id.to_be_bytes());
+        packet.extend_from_slice(&0x4000u16.to_be_bytes()); // don't fragment
+        packet.extend_from_slice(&[64, IP_PROTO_UDP, 0, 0]);
+        packet.extend_from_slice(&self.ip);
+        packet.extend_from_slice(&dst_ip);
+        let sum = checksum(&packet);
+        packet[10..12].copy_from_slice(&sum.to_be_bytes());
+
+        packet.extend_from_slice(&src_port.to_be_bytes());
+        packet.extend_from_slice(&dst_port.to_be_bytes());
+        packet.extend_from_slice(&(udp_len as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 0]); // no checksum
+        packet.extend_from_slice(payload);
+
+        self.send_frame(dst_mac, ETHERTYPE_IPV4, &packet);
+        Ok(())
+    }
+
+    /// Process one received frame (non-blocking)
+    ///
+    /// ARP traffic is answered internally; everything except unfragmented
+    /// UDP addressed to this host is dropped.
+    ///
+    /// # Returns
+    /// * `Some(datagram)` - A UDP datagram for this host arrived
+    /// * `None` - Nothing (of interest) was pending
+    pub fn poll(&mut self) -> Option<Datagram> {
+        let len = self.nic.receive(&mut self.frame[..])?;
+        let frame = self.frame[..len].to_vec();
+        if frame.len() < ETH_HEADER_LEN {
+            return None;
+        }
+
+        match be16(&frame, 12) {
+            ETHERTYPE_ARP => {
+                self.handle_arp(&frame[ETH_HEADER_LEN..]);
+                None
+            }
+            ETHERTYPE_IPV4 => self.parse_udp(&frame[ETH_HEADER_LEN..]),
+            _ => None,
+        }
+    }
+
+    fn parse_udp(&self, ip: &[u8]) -> Option<Datagram> {
+        if ip.len() < IPV4_HEADER_LEN || ip[0] >> 4 != 4 {
+            return None;
+        }
+
+        let header_len = (ip[0] & 0x0f) as usize * 4;
+        let total_len = (be16(ip, 2) as usize).min(ip.len());
+        let fragmented = be16(ip, 6) & 0x3fff != 0;
+        if ip[9] != IP_PROTO_UDP || fragmented || ip[16..20] != self.ip {
+            return None;
+        }
+
+        let udp = ip.get(header_len..total_len)?;
+        if udp.len() < UDP_HEADER_LEN {
+            return None;
+        }
+        let udp_len = (be16(udp, 4) as usize).clamp(UDP_HEADER_LEN, udp.len());
+
+        Some(Datagram {
+            src_ip: ip[12..16].try_into().unwrap(),
+            src_port: be16(udp, 0),
+            dst_port: be16(udp, 2),
+            payload: udp[UDP_HEADER_LEN..udp_len].to_vec(),
+        })
+    }
+}
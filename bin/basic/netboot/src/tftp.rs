@@ -0,0 +1,197 @@
+//! TFTP client (RFC 1350 with the RFC 2347-2349 option extensions)
+//!
+//! Files are fetched in octet mode. The client asks for a larger block size
+//! and the transfer size; servers that ignore options fall back to plain
+//! 512-byte blocks.
+
+runtime::libInit!();
+
+use crate::Error;
+use crate::net::{Ipv4Addr, NetStack};
+use runtime::clint::uptime_ms;
+
+/// Well-known TFTP server port
+const TFTP_PORT: u16 = 69;
+
+const OP_RRQ: u16 = 1;
+const OP_DATA: u16 = 3;
+const OP_ACK: u16 = 4;
+const OP_ERROR: u16 = 5;
+const OP_OACK: u16 = 6;
+
+/// Error codes sent to the server when the client gives up
+const ERROR_DISK_FULL: u16 = 3;
+const ERROR_BAD_OPTION: u16 = 8;
+
+/// Block size without the blksize option
+const DEFAULT_BLOCK_SIZE: usize = 512;
+
+/// Block size requested from the server; slirp's maximum, fits in one frame.
+/// The server may only lower it (RFC 2348), down to `MIN_BLOCK_SIZE`.
+const MAX_BLOCK_SIZE: usize = 1428;
+const MIN_BLOCK_SIZE: usize = 8;
+
+/// Retransmission timeout and retry limit
+const TIMEOUT_MS: u64 = 1000;
+const MAX_RETRIES: u32 = 5;
+
+/// Print a progress dot after this many bytes
+const PROGRESS_STEP: usize = 64 * 1024;
+
+fn read_request(filename: &str) -> Vec<u8> {
+    let block_size = alloc::format!("{}", MAX_BLOCK_SIZE);
+    let mut packet = Vec::new();
+    packet.extend_from_slice(&OP_RRQ.to_be_bytes());
+    for field in [filename, "octet", "blksize", &block_size, "tsize", "0"] {
+        packet.extend_from_slice(field.as_bytes());
+        packet.push(0);
+    }
+    packet
+}
+
+fn ack(block: u16) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(4);
+    packet.extend_from_slice(&OP_ACK.to_be_bytes());
+    packet.extend_from_slice(&block.to_be_bytes());
+    packet
+}
+
+fn error(code: u16, message: &str) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(5 + message.len());
+    packet.extend_from_slice(&OP_ERROR.to_be_bytes());
+    packet.extend_from_slice(&code.to_be_bytes());
+    packet.extend_from_slice(message.as_bytes());
+    packet.push(0);
+    packet
+}
+
+/// Split an option acknowledgement into (name, value) pairs
+fn parse_options(data: &[u8]) -> Vec<(String, String)> {
+    let fields: Vec<String> = data
+        .split(|&b| b == 0)
+        .map(|field| String::from_utf8_lossy(field).to_lowercase())
+        .collect();
+
+    fields
+        .chunks_exact(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect()
+}
+
+/// Download a file from a TFTP server
+///
+/// # Arguments
+/// * `net` - Interface to use
+/// * `server` - Address of the TFTP server
+/// * `filename` - Path of the file relative to the server's root
+///
+/// # Returns
+/// * `Ok(data)` - Contents of the file
+/// * `Err(error)` - The server reported an error, stopped responding or
+///   acknowledged options the client cannot accept
+pub fn fetch(net: &mut NetStack, server: Ipv4Addr, filename: &str) -> Result<Vec<u8>, Error> {
+    // Pick an ephemeral port that differs between consecutive transfers
+    let local_port = 49152 + (uptime_ms() % 16384) as u16;
+
+    let mut last_packet = read_request(filename);
+    let mut peer_port: Option<u16> = None;
+    let mut block_size = DEFAULT_BLOCK_SIZE;
+    let mut expected_block: u16 = 1;
+    let mut data = Vec::new();
+    let mut next_progress = PROGRESS_STEP;
+
+    net.send_udp(server, local_port, TFTP_PORT, &last_packet)?;
+    let mut deadline = uptime_ms() + TIMEOUT_MS;
+    let mut retries = 0;
+
+    loop {
+        let Some(datagram) = net.poll() else {
+            if uptime_ms() >= deadline {
+                retries += 1;
+                if retries > MAX_RETRIES {
+                    return Err(Error::Timeout("TFTP transfer"));
+                }
+                let port = peer_port.unwrap_or(TFTP_PORT);
+                net.send_udp(server, local_port, port, &last_packet)?;
+                deadline = uptime_ms() + TIMEOUT_MS;
+            }
+            continue;
+        };
+
+        if datagram.src_ip != server
+            || datagram.dst_port != local_port
+            || peer_port.is_some_and(|port| port != datagram.src_port)
+            || datagram.payload.len() < 4
+        {
+            continue;
+        }
+
+        let payload = &datagram.payload;
+        let opcode = u16::from_be_bytes([payload[0], payload[1]]);
+        let field = u16::from_be_bytes([payload[2], payload[3]]);
+
+        match opcode {
+            OP_OACK if peer_port.is_none() => {
+                for (name, value) in parse_options(&payload[2..]) {
+                    let refused = match name.as_str() {
+                        "blksize" => match value.parse() {
+                            Ok(size) if (MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&size) => {
+                                block_size = size;
+                                None
+                            }
+                            _ => Some((ERROR_BAD_OPTION, "unacceptable blksize")),
+                        },
+                        "tsize" => match data.try_reserve(value.parse().unwrap_or(0)) {
+                            Ok(()) => None,
+                            Err(_) => Some((ERROR_DISK_FULL, "file too large")),
+                        },
+                        _ => None,
+                    };
+                    if let Some((code, message)) = refused {
+                        let packet = error(code, message);
+                        net.send_udp(server, local_port, datagram.src_port, &packet)?;
+                        return Err(Error::Refused(message));
+                    }
+                }
+                peer_port = Some(datagram.src_port);
+                last_packet = ack(0);
+            }
+            OP_DATA if field == expected_block => {
+                peer_port = Some(datagram.src_port);
+                let block = &payload[4..];
+                data.extend_from_slice(block);
+                last_packet = ack(field);
+
+                if data.len() >= next_progress {
+                    runtime::print!(".");
+                    next_progress += PROGRESS_STEP;
+                }
+
+                if block.len() < block_size {
+                    net.send_udp(server, local_port, datagram.src_port, &last_packet)?;
+                    if next_progress > PROGRESS_STEP {
+                        runtime::println!();
+                    }
+                    return Ok(data);
+                }
+                expected_block = expected_block.wrapping_add(1);
+            }
+            OP_DATA if peer_port.is_some() && field == expected_block.wrapping_sub(1) => {
+                // Our acknowledgement was lost, the server resent the block
+            }
+            OP_ERROR => {
+                let message = String::from_utf8_lossy(&payload[4..]);
+                let message = String::from(message.trim_end_matches('\0'));
+                return Err(Error::Tftp {
+                    code: field,
+                    message,
+                });
+            }
+            _ => continue,
+        }
+
+        net.send_udp(server, local_port, datagram.src_port, &last_packet)?;
+        deadline = uptime_ms() + TIMEOUT_MS;
+        retries = 0;
+    }
+}
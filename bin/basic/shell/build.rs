@@ -0,0 +1,6 @@
+fn main() {
+    build_helper::link_helper();
+
+    // Programs bundled with `just shell-stage`, launched with `run`
+    build_helper::pack_ramdisk("programs");
+}
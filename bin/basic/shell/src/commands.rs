@@ -0,0 +1,450 @@
+//! Monitor commands
+
+runtime::libInit!();
+
+use core::fmt;
+use runtime::elf::{self, Elf, Mode};
+use runtime::io::Editor;
+use runtime::{arch, fs, platform, process, time::SystemTime};
+
+#[derive(Debug)]
+pub enum Error {
+    UnknownCommand,
+    /// Wrong arguments, with the expected ones
+    Usage(&'static str),
+    BadNumber(String),
+    Misaligned(usize),
+    Fs(fs::Error),
+    Elf(elf::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownCommand => write!(f, "unknown command, try `help`"),
+            Error::Usage(args) => write!(f, "usage: {}", args),
+            Error::BadNumber(text) => write!(f, "not a number: {}", text),
+            Error::Misaligned(addr) => write!(f, "misaligned address 0x{:08x}", addr),
+            Error::Fs(err) => write!(f, "{}", err),
+            Error::Elf(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<fs::Error> for Error {
+    fn from(err: fs::Error) -> Self {
+        Error::Fs(err)
+    }
+}
+
+impl From<elf::Error> for Error {
+    fn from(err: elf::Error) -> Self {
+        Error::Elf(err)
+    }
+}
+
+type Result<T> = core::result::Result<T, Error>;
+
+pub struct Command {
+    pub name: &'static str,
+    /// Argument synopsis, also shown on a usage error
+    pub usage: &'static str,
+    pub help: &'static str,
+    /// Whether the arguments are ramdisk paths, for completion
+    paths: bool,
+    handler: fn(&Editor, &[&str]) -> Result<()>,
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        usage: "help",
+        help: "List commands",
+        paths: false,
+        handler: help,
+    },
+    Command {
+        name: "peek",
+        usage: "peek <addr> [b|h|w]",
+        help: "Read a byte, halfword or word (default)",
+        paths: false,
+        handler: peek,
+    },
+    Command {
+        name: "poke",
+        usage: "poke <addr> <value> [b|h|w]",
+        help: "Write a byte, halfword or word (default)",
+        paths: false,
+        handler: poke,
+    },
+    Command {
+        name: "hexdump",
+        usage: "hexdump <addr> [len]",
+        help: "Dump memory, 256 bytes by default",
+        paths: false,
+        handler: hexdump,
+    },
+    Command {
+        name: "regs",
+        usage: "regs",
+        help: "Show machine-mode CSRs and the ABI pointer registers",
+        paths: false,
+        handler: regs,
+    },
+    Command {
+        name: "devices",
+        usage: "devices",
+        help: "Show the platform memory map",
+        paths: false,
+        handler: devices,
+    },
+    Command {
+        name: "time",
+        usage: "time <command> [args...]",
+        help: "Run a command and report how long it took",
+        paths: false,
+        handler: time,
+    },
+    Command {
+        name: "ls",
+        usage: "ls [path]",
+        help: "List a ramdisk directory",
+        paths: true,
+        handler: ls,
+    },
+    Command {
+        name: "run",
+        usage: "run [-u] <program> [args...]",
+        help: "Replace the shell with a bundled ELF program, in U-mode with -u",
+        paths: true,
+        handler: run,
+    },
+    Command {
+        name: "history",
+        usage: "history",
+        help: "Show previous lines",
+        paths: false,
+        handler: history,
+    },
+    Command {
+        name: "exit",
+        usage: "exit [code]",
+        help: "Leave the shell",
+        paths: false,
+        handler: exit,
+    },
+];
+
+/// Run one command line, already split into words
+pub fn execute(editor: &Editor, words: &[&str]) -> Result<()> {
+    let (name, args) = words.split_first().ok_or(Error::UnknownCommand)?;
+    let command = COMMANDS
+        .iter()
+        .find(|command| command.name == *name)
+        .ok_or(Error::UnknownCommand)?;
+    (command.handler)(editor, args)
+}
+
+/// Tab completion: command names first, then paths for commands taking them
+pub fn complete(head: &str, word: &str) -> Vec<String> {
+    let words: Vec<&str> = head.split_whitespace().collect();
+    // `time` takes a whole command line
+    let words = match words.split_first() {
+        Some((&"time", rest)) => rest,
+        _ => &words[..],
+    };
+
+    match words.first() {
+        None => COMMANDS
+            .iter()
+            .filter(|command| command.name.starts_with(word))
+            .map(|command| String::from(command.name))
+            .collect(),
+        Some(name)
+            if COMMANDS
+                .iter()
+                .any(|command| command.name == *name && command.paths) =>
+        {
+            complete_path(word)
+        }
+        Some(_) => Vec::new(),
+    }
+}
+
+fn complete_path(word: &str) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(slash) => (&word[..slash + 1], &word[slash + 1..]),
+        None => ("", word),
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter(|entry| entry.file_name().starts_with(prefix))
+        .map(|entry| {
+            let slash = if entry.file_type() == fs::FileType::Dir {
+                "/"
+            } else {
+                ""
+            };
+            alloc::format!("{}{}{}", dir, entry.file_name(), slash)
+        })
+        .collect()
+}
+
+fn parse_number(text: &str) -> Result<usize> {
+    let digits = text.replace('_', "");
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => digits.parse(),
+    };
+    value.map_err(|_| Error::BadNumber(String::from(text)))
+}
+
+/// Access width of `peek` and `poke`
+#[derive(Clone, Copy)]
+enum Width {
+    Byte = 1,
+    Half = 2,
+    Word = 4,
+}
+
+impl Width {
+    fn parse(text: Option<&&str>, usage: &'static str) -> Result<Self> {
+        match text.copied() {
+            Some("b") => Ok(Width::Byte),
+            Some("h") => Ok(Width::Half),
+            Some("w") | None => Ok(Width::Word),
+            Some(_) => Err(Error::Usage(usage)),
+        }
+    }
+
+    fn check(self, addr: usize) -> Result<()> {
+        if addr.is_multiple_of(self as usize) {
+            Ok(())
+        } else {
+            Err(Error::Misaligned(addr))
+        }
+    }
+
+    /// Whether `value` can be written in this many bytes
+    fn fits(self, value: usize) -> bool {
+        (value as u64) >> (8 * self as u32) == 0
+    }
+}
+
+fn help(_: &Editor, _: &[&str]) -> Result<()> {
+    for command in COMMANDS {
+        println!("  {:<30} {}", command.usage, command.help);
+    }
+    println!("Numbers are decimal, or hexadecimal with 0x.");
+    Ok(())
+}
+
+fn peek(_: &Editor, args: &[&str]) -> Result<()> {
+    const USAGE: &str = "peek <addr> [b|h|w]";
+    let [addr, rest @ ..] = args else {
+        return Err(Error::Usage(USAGE));
+    };
+    let addr = parse_number(addr)?;
+    let width = Width::parse(rest.first(), USAGE)?;
+    width.check(addr)?;
+
+    let value = unsafe {
+        match width {
+            Width::Byte => (addr as *const u8).read_volatile() as usize,
+            Width::Half => (addr as *const u16).read_volatile() as usize,
+            Width::Word => (addr as *const u32).read_volatile() as usize,
+        }
+    };
+    println!(
+        "0x{:08x}: 0x{:0width$x}",
+        addr,
+        value,
+        width = 2 * width as usize
+    );
+    Ok(())
+}
+
+fn poke(_: &Editor, args: &[&str]) -> Result<()> {
+    const USAGE: &str = "poke <addr> <value> [b|h|w]";
+    let [addr, value, rest @ ..] = args else {
+        return Err(Error::Usage(USAGE));
+    };
+    let (addr, value) = (parse_number(addr)?, parse_number(value)?);
+    let width = Width::parse(rest.first(), USAGE)?;
+    if !width.fits(value) {
+        return Err(Error::Usage(USAGE));
+    }
+    width.check(addr)?;
+
+    unsafe {
+        match width {
+            Width::Byte => (addr as *mut u8).write_volatile(value as u8),
+            Width::Half => (addr as *mut u16).write_volatile(value as u16),
+            Width::Word => (addr as *mut u32).write_volatile(value as u32),
+        }
+    }
+    Ok(())
+}
+
+fn hexdump(_: &Editor, args: &[&str]) -> Result<()> {
+    let (addr, len) = match args {
+        [addr] => (parse_number(addr)?, 256),
+        [addr, len] => (parse_number(addr)?, parse_number(len)?),
+        _ => return Err(Error::Usage("hexdump <addr> [len]")),
+    };
+
+    let end = addr.saturating_add(len);
+    for line in (addr..end).step_by(16) {
+        let count = (end - line).min(16);
+        let bytes: Vec<u8> = (line..line + count)
+            .map(|byte| unsafe { (byte as *const u8).read_volatile() })
+            .collect();
+
+        print!("{:08x}: ", line);
+        for i in 0..16 {
+            match bytes.get(i) {
+                Some(byte) => print!("{:02x} ", byte),
+                None => print!("   "),
+            }
+        }
+        let text: String = bytes
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        println!(" |{}|", text);
+    }
+    Ok(())
+}
+
+macro_rules! read_csr {
+    ($name:literal) => {{
+        let value: usize;
+        unsafe { core::arch::asm!(concat!("csrr {}, ", $name), out(reg) value) };
+        ($name, value)
+    }};
+}
+
+macro_rules! read_reg {
+    ($name:literal) => {{
+        let value: usize;
+        unsafe { core::arch::asm!(concat!("mv {}, ", $name), out(reg) value) };
+        ($name, value)
+    }};
+}
+
+fn regs(_: &Editor, _: &[&str]) -> Result<()> {
+    let registers = [
+        read_csr!("mhartid"),
+        read_csr!("misa"),
+        read_csr!("mstatus"),
+        read_csr!("mtvec"),
+        read_csr!("mie"),
+        read_csr!("mip"),
+        read_csr!("mscratch"),
+        read_csr!("mepc"),
+        read_csr!("mcause"),
+        read_csr!("mtval"),
+        read_reg!("ra"),
+        read_reg!("sp"),
+        read_reg!("gp"),
+        read_reg!("tp"),
+    ];
+    for pair in registers.chunks(2) {
+        for (name, value) in pair {
+            print!("{:>9} 0x{:08x}", name, value);
+        }
+        println!();
+    }
+    println!("cycles {}", arch::cycles());
+    Ok(())
+}
+
+fn devices(_: &Editor, _: &[&str]) -> Result<()> {
+    let info = platform::info();
+    println!("{}-{}", info.isa, info.name);
+    println!("{:<10} {:>10} {:>10}  kind", "name", "base", "size");
+    for region in info.memory_map {
+        println!(
+            "{:<10} 0x{:08x} 0x{:08x}  {}",
+            region.name, region.base, region.size, region.kind
+        );
+    }
+    Ok(())
+}
+
+fn time(editor: &Editor, args: &[&str]) -> Result<()> {
+    if args.is_empty() {
+        return Err(Error::Usage("time <command> [args...]"));
+    }
+
+    let (start, cycles) = (SystemTime::now(), arch::cycles());
+    let result = execute(editor, args);
+    let cycles = arch::cycles() - cycles;
+    let elapsed = start.elapsed().unwrap_or_default();
+
+    println!("{:?}, {} cycles", elapsed, cycles);
+    result
+}
+
+fn ls(_: &Editor, args: &[&str]) -> Result<()> {
+    let path = match args {
+        [] => "",
+        [path] => path,
+        _ => return Err(Error::Usage("ls [path]")),
+    };
+
+    for entry in fs::read_dir(path)? {
+        let metadata = entry.metadata();
+        let kind = if metadata.is_dir() { 'd' } else { '-' };
+        println!("{} {:>10}  {}", kind, metadata.len(), entry.file_name());
+    }
+    Ok(())
+}
+
+fn run(_: &Editor, args: &[&str]) -> Result<()> {
+    let (mode, args) = match args {
+        ["-u", rest @ ..] => (Mode::User, rest),
+        _ => (Mode::Machine, args),
+    };
+    let Some(path) = args.first() else {
+        return Err(Error::Usage("run [-u] <program> [args...]"));
+    };
+
+    let data = fs::read(path)?;
+    let program = Elf::parse(&data)?.program()?;
+    println!(
+        "Loading {}, {} bytes, entry 0x{:08x}",
+        path,
+        program.load_size(),
+        program.entry()
+    );
+    Err(program.exec(args, mode).into())
+}
+
+fn history(editor: &Editor, _: &[&str]) -> Result<()> {
+    for (i, line) in editor.history().iter().enumerate() {
+        println!("{:>4}  {}", i + 1, line);
+    }
+    Ok(())
+}
+
+fn exit(_: &Editor, args: &[&str]) -> Result<()> {
+    let code = match args {
+        [] => 0,
+        [code] => parse_number(code)? as i32,
+        _ => return Err(Error::Usage("exit [code]")),
+    };
+    process::exit(code)
+}
@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+runtime::binInit!();
+runtime::ramdisk!();
+
+extern crate alloc;
+
+mod commands;
+
+use runtime::io::Editor;
+use runtime::panic::{self, AssertUnwindSafe};
+use runtime::process;
+
+fn main() {
+    println!("=== AM-RS Shell ===");
+    println!("Type `help` for a list of commands.");
+
+    let mut editor = Editor::new().with_completer(commands::complete);
+    loop {
+        // ^C cancels the line, ^D leaves
+        let line = match editor.read_line("> ") {
+            Ok(Some(line)) => line,
+            Ok(None) => process::exit(0),
+            Err(_) => continue,
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+
+        // With the runtime's `unwind` feature, a panicking command comes
+        // back here instead of ending the shell
+        match panic::catch_unwind(AssertUnwindSafe(|| commands::execute(&editor, &words))) {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => println!("{}: {}", words[0], err),
+            Err(_) => println!("{}: panicked", words[0]),
+        }
+    }
+}
@@ -15,7 +15,7 @@ fn main() {
     println!("Enter your name (press Enter): ");
     let mut name = String::new();
     stdin().read_line(&mut name).unwrap_or(0);
-    println!("Hello, {}!\n", name);
+    println!("Hello, {}!\n", name.trim_end());
 
     println!("\n=== All tests completed! ===");
 }
@@ -5,4 +5,41 @@ runtime::binInit!();
 #[cfg(test)]
 runtime::addtest!();
 
-fn main() {}
+use runtime::graphic::{self, Framebuffer};
+use runtime::input;
+
+/// Fill the display with a red-green gradient over a blue square
+fn draw(fb: &mut dyn Framebuffer) {
+    let (width, height) = (fb.width(), fb.height());
+    let side = width.min(height) / 2;
+    let (left, top) = ((width - side) / 2, (height - side) / 2);
+    for (index, pixel) in fb.pixels().iter_mut().enumerate() {
+        let (x, y) = (index % width, index / width);
+        let red = (x * 255 / width) as u32;
+        let green = (y * 255 / height) as u32;
+        let blue = if (left..left + side).contains(&x) && (top..top + side).contains(&y) {
+            0xff
+        } else {
+            0
+        };
+        *pixel = (red << 16) | (green << 8) | blue;
+    }
+    fb.flush();
+}
+
+fn main() {
+    let Some(mut fb) = graphic::framebuffer() else {
+        println!("No display");
+        return;
+    };
+    draw(&mut *fb);
+    println!("Drew a {}x{} frame", fb.width(), fb.height());
+
+    if let Some(mut keyboard) = input::keyboard() {
+        while let Some(event) = keyboard.poll() {
+            if event.pressed {
+                println!("Key: {:?}", event.key);
+            }
+        }
+    }
+}
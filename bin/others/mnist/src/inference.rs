@@ -1,7 +1,25 @@
 #[cfg(not(test))]
 runtime::libInit!();
 
-include!(concat!(env!("OUT_DIR"), "/embedded_images.rs"));
+/// Test images packed into the ramdisk by build.rs, in name order
+fn test_images() -> Vec<Vec<u8>> {
+    let Ok(entries) = runtime::fs::read_dir("/") else {
+        return Vec::new();
+    };
+
+    entries
+        .filter(|entry| entry.metadata().is_file() && entry.file_name().ends_with(".bin"))
+        .filter_map(|entry| runtime::fs::read(entry.path()).ok())
+        .collect()
+}
+
+/// First test image, used as the benchmark input
+fn benchmark_image() -> Vec<u8> {
+    test_images()
+        .into_iter()
+        .next()
+        .expect("no test images in the ramdisk")
+}
 
 // Benchmark configuration
 const BENCHMARK_ITERATIONS: usize = 1000;
@@ -66,19 +84,19 @@ impl Inference {
         let (fc3_weights, fc3_scale) = FC3_PARSED;
 
         println!("Model weights loaded successfully!");
-        println!(
+        runtime::dprintln!(
             "FC1: {}x{}, scale: {:.6}",
             fc1_weights.len(),
             fc1_weights[0].len(),
             fc1_scale
         );
-        println!(
+        runtime::dprintln!(
             "FC2: {}x{}, scale: {:.6}",
             fc2_weights.len(),
             fc2_weights[0].len(),
             fc2_scale
         );
-        println!(
+        runtime::dprintln!(
             "FC3: {}x{}, scale: {:.6}\n",
             fc3_weights.len(),
             fc3_weights[0].len(),
@@ -91,9 +109,9 @@ impl Inference {
 
         // Print quantization information
         println!("Quantization Scales (Fixed Point):");
-        println!("  FC1_SCALE: {:.6} -> Q16: {}", fc1_scale, fc1_scale_q16);
-        println!("  FC2_SCALE: {:.6} -> Q16: {}", fc2_scale, fc2_scale_q16);
-        println!("  FC3_SCALE: {:.6} -> Q16: {}", fc3_scale, fc3_scale_q16);
+        runtime::dprintln!("  FC1_SCALE: {:.6} -> Q16: {}", fc1_scale, fc1_scale_q16);
+        runtime::dprintln!("  FC2_SCALE: {:.6} -> Q16: {}", fc2_scale, fc2_scale_q16);
+        runtime::dprintln!("  FC3_SCALE: {:.6} -> Q16: {}", fc3_scale, fc3_scale_q16);
         println!();
 
         Self {
@@ -156,7 +174,7 @@ impl Inference {
     }
 
     pub(crate) fn test(&self) {
-        let test_images_data = EMBEDDED_TEST_IMAGES;
+        let test_images_data = test_images();
 
         let total_images = test_images_data.len();
         let mut correct_predictions = 0;
@@ -164,7 +182,7 @@ impl Inference {
         for (img_idx, image_data_bytes) in test_images_data.iter().enumerate() {
             println!("=== Test Image {} ===", img_idx + 1);
 
-            let (image_data, true_label) = Self::parse_image_binary(*image_data_bytes);
+            let (image_data, true_label) = Self::parse_image_binary(image_data_bytes);
             println!("True label: {}", true_label);
 
             // Run pure INT8 inference with embedded weights
@@ -197,9 +215,9 @@ impl Inference {
     fn read_cycle_counter() -> usize {
         let cycles: usize;
         unsafe {
-            #[cfg(not(test))]
+            #[cfg(not(any(am_platform = "host", am_platform = "native")))]
             core::arch::asm!("rdcycle {}", out(reg) cycles);
-            #[cfg(test)]
+            #[cfg(any(am_platform = "host", am_platform = "native"))]
             {
                 let low: u32;
                 let high: u32;
@@ -221,8 +239,8 @@ impl Inference {
         println!("Benchmark iterations: {}", BENCHMARK_ITERATIONS);
 
         // Use a representative test image for benchmarking
-        let benchmark_image_data = EMBEDDED_TEST_IMAGES[0];
-        let (image_data, _) = Self::parse_image_binary(benchmark_image_data);
+        let benchmark_image_data = benchmark_image();
+        let (image_data, _) = Self::parse_image_binary(&benchmark_image_data);
 
         // Warmup phase
         println!("Running warmup...");
@@ -279,7 +297,7 @@ impl Inference {
         };
 
         println!("Total MAC operations: {}", total_mac_operations);
-        println!("MACs per cycle: {:.4}", macs_per_cycle);
+        runtime::dprintln!("MACs per cycle: {:.4}", macs_per_cycle);
         println!("Note: Higher MACs/cycle indicates better vectorization");
 
         if BENCHMARK_ITERATIONS > 0 {
@@ -293,8 +311,8 @@ impl Inference {
     pub(crate) fn detailed_performance_analysis(&self) {
         println!("=== DETAILED PERFORMANCE ANALYSIS ===");
 
-        let benchmark_image_data = EMBEDDED_TEST_IMAGES[0];
-        let (image_data, _) = Self::parse_image_binary(benchmark_image_data);
+        let benchmark_image_data = benchmark_image();
+        let (image_data, _) = Self::parse_image_binary(&benchmark_image_data);
         let normalized_input = Self::normalize_and_quantize_input(&image_data);
 
         // Benchmark individual components
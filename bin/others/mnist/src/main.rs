@@ -2,16 +2,25 @@
 
 #[cfg(not(test))]
 runtime::binInit!();
+#[cfg(not(test))]
+runtime::ramdisk!();
 #[cfg(test)]
 runtime::addtest!();
 
 macros::mod_flat!(inference);
 
+/// The target, from build_helper::link_helper
+#[allow(dead_code)]
+mod platform {
+    include!(concat!(env!("OUT_DIR"), "/platform.rs"));
+}
+
 // Command line arguments simulation (for benchmark mode)
 // In a real embedded system, this would come from boot parameters or configuration
 static BENCHMARK_MODE: bool = false; // Set to true for benchmark-only mode
 
 fn main() {
+    println!("MNIST inference on {}", platform::ARCH);
     let infer = Inference::new();
 
     // Run benchmarks based on mode
@@ -14,6 +14,7 @@ use rv64emu::{
     tools::rc_refcell_new,
 };
 
+use std::logger::{debug, info, warn};
 use std::rc::Rc;
 
 // Import modules
@@ -59,7 +60,7 @@ mod device_addr {
 
 fn main() {
     println!("=== rv64emu on AM-RS Platform ===");
-    println!("Initializing RISC-V 64-bit emulator...");
+    info!("Initializing RISC-V 64-bit emulator...");
 
     // Create system bus that manages all devices (PLIC, CLINT, DRAM)
     let bus_u = rc_refcell_new(Bus::new());
@@ -73,12 +74,12 @@ fn main() {
     config.set_isa(cpu_config::ISA);
     let config = Rc::new(config);
 
-    println!("CPU Configuration:");
-    println!("  ISA: {}", cpu_config::ISA);
-    println!("  MMU: {}", cpu_config::MMU_TYPE);
-    println!("  TLB size: {}", cpu_config::TLB_SIZE);
-    println!("  I-cache: {} bytes", cpu_config::ICACHE_SIZE);
-    println!("  Decode cache: {} entries", cpu_config::DECODE_CACHE_SIZE);
+    debug!("CPU Configuration:");
+    debug!("  ISA: {}", cpu_config::ISA);
+    debug!("  MMU: {}", cpu_config::MMU_TYPE);
+    debug!("  TLB size: {}", cpu_config::TLB_SIZE);
+    debug!("  I-cache: {} bytes", cpu_config::ICACHE_SIZE);
+    debug!("  Decode cache: {} entries", cpu_config::DECODE_CACHE_SIZE);
 
     // Create hart0 (hardware thread) with supervisor mode support
     // - Boot PC: Where kernel starts (configured in cpu_config)
@@ -90,16 +91,16 @@ fn main() {
         .with_smode(true)
         .build();
 
-    println!(
+    info!(
         "Hart {} initialized (boot PC: 0x{:08x})",
         cpu_config::HART_ID,
         cpu_config::BOOT_PC
     );
 
     // Setup main memory (DRAM)
-    println!("Allocating {} KB of DRAM...", MEM_SIZE / 1024);
+    info!("Allocating {} KB of DRAM...", MEM_SIZE / 1024);
     let mem_size = setup_memory(&bus_u, MEM_SIZE);
-    println!("DRAM mounted (size: {} bytes)", mem_size);
+    info!("DRAM mounted (size: {} bytes)", mem_size);
 
     // Setup UART devices
     let uart_devices = setup_uart_devices(
@@ -110,11 +111,11 @@ fn main() {
         device_addr::SIFIVE_UART_SIZE,
     );
 
-    println!(
+    info!(
         "16550A UART device registered at 0x{:08x}",
         device_addr::UART_16550A
     );
-    println!(
+    info!(
         "SiFive UART device registered at 0x{:08x}",
         device_addr::SIFIVE_UART
     );
@@ -131,15 +132,15 @@ fn main() {
 
     // Load kernel image into memory
     if bin_file::LINUX_FILE.is_empty() {
-        println!("\nWarning: No kernel image loaded (bin_file::LINUX_FILE is empty)");
-        println!("To load a kernel, update bin_file::LINUX_FILE with your kernel binary");
+        warn!("No kernel image loaded (bin_file::LINUX_FILE is empty)");
+        warn!("To load a kernel, update bin_file::LINUX_FILE with your kernel binary");
     } else {
-        println!(
-            "\nLoading kernel image ({} bytes)...",
+        info!(
+            "Loading kernel image ({} bytes)...",
             bin_file::LINUX_FILE.len()
         );
         sim.load_image_from_slice(bin_file::LINUX_FILE);
-        println!("Kernel loaded successfully");
+        info!("Kernel loaded successfully");
     }
 
     println!("\n=== Starting Emulation ===");
@@ -161,7 +162,7 @@ fn main() {
 
         // Periodically print status (if enabled)
         if PRINT_INTERVAL > 0 && instruction_count % PRINT_INTERVAL == 0 {
-            println!("[INFO] Executed {} instructions", instruction_count);
+            info!("Executed {} instructions", instruction_count);
         }
 
         // Handle UART output (TX FIFO -> console)
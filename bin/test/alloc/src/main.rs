@@ -21,5 +21,6 @@ fn main() {
     s.push_str("heap allocation!");
     println!("{}", s);
 
+    println!("Heap: {}", runtime::mem::heap_stats());
     println!("All tests passed!");
 }
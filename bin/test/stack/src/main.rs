@@ -0,0 +1,31 @@
+#![cfg_attr(not(test), no_std, no_main)]
+
+#[cfg(not(test))]
+runtime::binInit!();
+#[cfg(test)]
+runtime::addtest!();
+
+use core::hint::black_box;
+
+/// Recurse `depth` times, with a kilobyte of stack per call
+fn recurse(depth: usize) -> usize {
+    let buf = black_box([depth as u8; 1024]);
+    if depth == 0 {
+        return buf[0] as usize;
+    }
+    recurse(depth - 1) + buf[1023] as usize
+}
+
+fn main() {
+    let before = runtime::mem::stack_high_water();
+    println!("Stack size: {} bytes", runtime::mem::stack_size());
+    println!("High-water mark at start: {} bytes", before);
+
+    black_box(recurse(black_box(64)));
+    let after = runtime::mem::stack_high_water();
+    println!("High-water mark after recursion: {} bytes", after);
+
+    // The native platform leaves the stack to the host, unpainted
+    #[cfg(not(any(test, am_platform = "native")))]
+    assert!(after >= before + 64 * 1024);
+}
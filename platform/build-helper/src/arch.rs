@@ -0,0 +1,253 @@
+//! The target a bin is built for
+//!
+//! `ARCH` names it as `<isa>-<platform>`, as in `scripts/arch/main.nu`.
+//! [`link_helper`](crate::link_helper) checks it against the Rust target
+//! and the runtime's platform feature, then describes it to the bin as
+//! `cfg` flags and as constants (see [`platform_source`]).
+
+use crate::memory::regions_source;
+use crate::{MemoryLayout, Platform, Region, RegionKind};
+use std::fmt::Write;
+
+/// ISAs known to the build system, with the Rust target each builds for
+pub const SUPPORTED_ISAS: &[(&str, &str)] = &[
+    ("riscv32i", "riscv32i-unknown-none-elf"),
+    ("riscv32im", "riscv32im-unknown-none-elf"),
+    ("riscv32imac", "riscv32imac-unknown-none-elf"),
+    ("riscv32im_zve32x", "riscv32im-unknown-none-elf"),
+];
+
+/// An ISA and a platform, parsed from `ARCH`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arch {
+    pub isa: &'static str,
+    pub platform: Platform,
+}
+
+impl Arch {
+    /// Parse and validate `<isa>-<platform>`
+    ///
+    /// # Returns
+    /// * `Ok(arch)` - A known ISA that the platform supports
+    /// * `Err(message)` - What is wrong with it
+    pub fn parse(arch: &str) -> Result<Self, String> {
+        let Some((isa, platform)) = arch.split_once('-') else {
+            return Err(format!(
+                "invalid ARCH `{}`, expected <isa>-<platform>",
+                arch
+            ));
+        };
+        let Some(&(isa, _)) = SUPPORTED_ISAS.iter().find(|(name, _)| *name == isa) else {
+            return Err(format!("unsupported ISA `{}` in ARCH `{}`", isa, arch));
+        };
+        let Some(platform) = Platform::from_str(platform) else {
+            return Err(format!(
+                "unknown platform `{}` in ARCH `{}`",
+                platform, arch
+            ));
+        };
+        if !platform.supported_isas().contains(&isa) {
+            return Err(format!(
+                "{} does not support ISA {}, only {}",
+                platform.fmt(),
+                isa,
+                platform.supported_isas().join(", ")
+            ));
+        }
+        Ok(Self { isa, platform })
+    }
+
+    /// The Rust target triple the ISA builds for
+    pub fn target(&self) -> &'static str {
+        SUPPORTED_ISAS
+            .iter()
+            .find(|(isa, _)| *isa == self.isa)
+            .map(|(_, target)| *target)
+            .unwrap()
+    }
+
+    /// Check the arch against the build
+    ///
+    /// # Arguments
+    /// * `target` - The Rust target triple being built for
+    /// * `runtime_platform` - The platform feature enabled on `runtime`
+    ///
+    /// # Returns
+    /// * `Err(message)` - The arch disagrees with one of them
+    pub fn check(&self, target: &str, runtime_platform: Option<&str>) -> Result<(), String> {
+        if target != self.target() {
+            return Err(format!(
+                "ARCH {} builds for {}, but the target is {}",
+                self,
+                self.target(),
+                target
+            ));
+        }
+        match runtime_platform {
+            Some(platform) if platform != self.platform.fmt() => Err(format!(
+                "ARCH {} is for {}, but runtime is built with its `{}` feature",
+                self,
+                self.platform.fmt(),
+                platform
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl std::fmt::Display for Arch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.isa, self.platform.fmt())
+    }
+}
+
+/// `cargo:rustc-check-cfg` lines declaring `am_platform` and `am_isa`, with
+/// `host` and the host's architecture for host builds
+pub fn check_cfg_lines(host_arch: &str) -> [String; 2] {
+    let values = |values: Vec<&str>| {
+        let quoted: Vec<String> = values
+            .iter()
+            .map(|value| format!("\"{}\"", value))
+            .collect();
+        quoted.join(", ")
+    };
+    let mut platforms: Vec<&str> = Platform::ALL.iter().map(Platform::fmt).collect();
+    platforms.extend(["host", "native"]);
+    let mut isas: Vec<&str> = SUPPORTED_ISAS.iter().map(|(isa, _)| *isa).collect();
+    isas.push(host_arch);
+    [
+        format!(
+            "cargo:rustc-check-cfg=cfg(am_platform, values({}))",
+            values(platforms)
+        ),
+        format!(
+            "cargo:rustc-check-cfg=cfg(am_isa, values({}))",
+            values(isas)
+        ),
+    ]
+}
+
+/// Render the constants describing a build
+///
+/// `PLATFORM`, `ISA` and `ARCH` name the target, `STACK_SIZE` and
+/// `HEAP_SIZE` give the bin's [`MemoryLayout`], and the platform's memory
+/// map follows as from [`memory_map_source`](crate::memory_map_source), with RAM as the bin is linked
+/// for. Host builds get their platform and ISA and an empty map.
+///
+/// # Arguments
+/// * `platform` - `nemu`, `qemu`, `spike`, `native` or `host`
+/// * `isa` - The ISA, or the host's architecture
+/// * `layout` - The bin's memory layout; `None` for host builds
+pub fn platform_source(platform: &str, isa: &str, layout: Option<&MemoryLayout>) -> String {
+    let mut out = String::from("// Generated by build_helper::link_helper\n");
+    writeln!(out, "\n/// The platform the bin is built for").unwrap();
+    writeln!(out, "pub const PLATFORM: &str = {:?};", platform).unwrap();
+    writeln!(out, "\n/// The ISA the bin is built for").unwrap();
+    writeln!(out, "pub const ISA: &str = {:?};", isa).unwrap();
+    writeln!(out, "\n/// `<isa>-<platform>`, as in `ARCH`").unwrap();
+    writeln!(out, "pub const ARCH: &str = \"{}-{}\";", isa, platform).unwrap();
+
+    let Some(layout) = layout else {
+        out.push_str(&regions_source(&[]));
+        return out;
+    };
+    writeln!(out, "\n/// Size of the stack in bytes").unwrap();
+    writeln!(
+        out,
+        "pub const STACK_SIZE: usize = {:#x};",
+        layout.stack_size
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "\n/// Size of the heap in bytes, `None` when it extends to the stack"
+    )
+    .unwrap();
+    let heap_size = match layout.heap_size {
+        Some(size) => format!("Some({:#x})", size),
+        None => "None".to_string(),
+    };
+    writeln!(out, "pub const HEAP_SIZE: Option<usize> = {};", heap_size).unwrap();
+
+    let platform = Platform::from_str(platform).expect("a target platform");
+    let regions: Vec<Region> = platform
+        .memory_map()
+        .iter()
+        .map(|region| match region.kind {
+            RegionKind::Ram => Region {
+                base: layout.ram_origin,
+                size: layout.ram_length,
+                ..*region
+            },
+            _ => *region,
+        })
+        .collect();
+    out.push_str(&regions_source(&regions));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_arch() {
+        let arch = Arch::parse("riscv32im_zve32x-qemu").unwrap();
+        assert_eq!(
+            (arch.isa, arch.platform),
+            ("riscv32im_zve32x", Platform::Qemu)
+        );
+        assert_eq!(arch.target(), "riscv32im-unknown-none-elf");
+        assert_eq!(arch.to_string(), "riscv32im_zve32x-qemu");
+
+        assert!(Arch::parse("riscv32im").unwrap_err().contains("expected"));
+        assert!(
+            Arch::parse("riscv64gc-qemu")
+                .unwrap_err()
+                .contains("unsupported ISA")
+        );
+        assert!(
+            Arch::parse("riscv32im-fpga")
+                .unwrap_err()
+                .contains("unknown platform")
+        );
+        assert!(
+            Arch::parse("riscv32imac-spike")
+                .unwrap_err()
+                .contains("does not support")
+        );
+    }
+
+    #[test]
+    fn test_check_arch() {
+        let arch = Arch::parse("riscv32im-qemu").unwrap();
+        assert_eq!(
+            arch.check("riscv32im-unknown-none-elf", Some("qemu")),
+            Ok(())
+        );
+        assert!(
+            arch.check("riscv32imac-unknown-none-elf", Some("qemu"))
+                .is_err()
+        );
+        let err = arch
+            .check("riscv32im-unknown-none-elf", Some("nemu"))
+            .unwrap_err();
+        assert!(err.contains("`nemu` feature"));
+    }
+
+    #[test]
+    fn test_platform_source() {
+        let mut layout = MemoryLayout::for_platform(&Platform::Spike);
+        layout.ram_length = 0x1000_0000;
+        layout.heap_size = Some(0x4000);
+        let source = platform_source("spike", "riscv32im", Some(&layout));
+        assert!(source.contains("pub const ARCH: &str = \"riscv32im-spike\";"));
+        assert!(source.contains("pub const HEAP_SIZE: Option<usize> = Some(0x4000);"));
+        assert!(source.contains("pub const RAM_SIZE: usize = 0x10000000;"));
+        assert!(source.contains("pub const UART0_BASE: usize = 0x10000000;"));
+
+        let source = platform_source("host", "x86_64", None);
+        assert!(source.contains("pub const PLATFORM: &str = \"host\";"));
+        assert!(source.contains("pub const REGIONS: &[Region] = &[\n];"));
+    }
+}
@@ -1,5 +1,22 @@
-use std::{env, path::Path};
-
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+mod arch;
+mod linker;
+mod memory;
+mod ramdisk;
+mod requirement;
+mod symtab;
+pub use arch::{Arch, SUPPORTED_ISAS, check_cfg_lines, platform_source};
+pub use linker::{DEFAULT_STACK_SIZE, ExtraRegion, MemoryLayout, linker_script, parse_size};
+pub use memory::{Region, RegionKind, check_memory_map, emit_memory_map, memory_map_source};
+pub use ramdisk::{build_ramdisk, pack_ramdisk};
+pub use requirement::{Capability, check_requirements, requirements};
+pub use symtab::{build_symtab, embed_symbols};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Platform {
     Nemu,
     Qemu,
@@ -7,6 +24,9 @@ pub enum Platform {
 }
 
 impl Platform {
+    /// Every platform
+    pub const ALL: [Platform; 3] = [Platform::Nemu, Platform::Qemu, Platform::Spike];
+
     fn fmt(&self) -> &'static str {
         match self {
             Platform::Nemu => "nemu",
@@ -24,128 +44,119 @@ impl Platform {
             _ => None,
         }
     }
-}
 
-/// Get platform from environment variable
-/// If ARCH is not set (e.g., in rust-analyzer), defaults to "nemu"
-fn get_platform() -> Platform {
-    let arch = match env::var("ARCH") {
-        Ok(arch) => arch,
-        Err(_) => {
-            // Default to nemu for rust-analyzer and other tools that don't set ARCH
-            println!("cargo:warning=ARCH not set, defaulting to nemu (rust-analyzer mode)");
-            return Platform::Nemu;
+    /// ISAs the platform runs, as `PLATFORM_CONFIGS` in
+    /// `scripts/arch/main.nu`
+    pub fn supported_isas(&self) -> &'static [&'static str] {
+        match self {
+            Platform::Nemu | Platform::Spike => &["riscv32i", "riscv32im", "riscv32im_zve32x"],
+            Platform::Qemu => &["riscv32i", "riscv32im", "riscv32imac", "riscv32im_zve32x"],
         }
-    };
+    }
+}
 
-    let platform_env = match arch.split_once('-') {
-        Some((_, platform)) => platform,
-        None => {
-            println!(
-                "cargo:warning=Invalid ARCH format '{}', defaulting to nemu",
-                arch
-            );
-            return Platform::Nemu;
-        }
-    };
+/// Get the arch from the ARCH environment variable
+///
+/// Without ARCH (e.g. in rust-analyzer) the platform is the one runtime is
+/// built for and the ISA the target's. A malformed ARCH, or one that
+/// disagrees with the target or runtime's platform feature, fails the
+/// build.
+///
+/// # Arguments
+/// * `target` - The Rust target triple being built for
+fn get_arch(target: &str) -> Arch {
+    // Set by runtime's build script, see its `links`
+    let runtime_platform = env::var("DEP_AM_RUNTIME_PLATFORM").ok();
 
-    match Platform::from_str(platform_env) {
-        Some(platform) => platform,
-        None => {
-            println!(
-                "cargo:warning=Unknown platform '{}', defaulting to nemu",
-                platform_env
-            );
-            Platform::Nemu
-        }
+    let arch = match env::var("ARCH") {
+        Ok(arch) => Arch::parse(&arch),
+        Err(_) => match runtime_platform.as_deref() {
+            Some(platform) => {
+                let isa = target.split('-').next().unwrap_or_default();
+                println!("cargo:warning=ARCH not set, using {}-{}", isa, platform);
+                Arch::parse(&format!("{}-{}", isa, platform))
+            }
+            None => Err("ARCH not set, and runtime has no platform feature".to_string()),
+        },
+    };
+    match arch.and_then(|arch| {
+        arch.check(target, runtime_platform.as_deref())
+            .map(|_| arch)
+    }) {
+        Ok(arch) => arch,
+        Err(message) => panic!("{}", message),
     }
 }
 
 /// Link helper function to be called from build.rs
 ///
 /// This function:
-/// 1. Determines the platform from ARCH environment variable
-/// 2. Finds the appropriate linker script based on target architecture
-/// 3. Configures cargo to use that linker script
+/// 1. Determines the arch from the ARCH environment variable, checked
+///    against the target and runtime's platform feature
+/// 2. Sets the `am_platform` and `am_isa` cfg flags, e.g.
+///    `#[cfg(am_platform = "qemu")]`; host builds get `am_platform = "native"`
+///    with runtime's `native` feature and `am_platform = "host"` without
+/// 3. Writes the constants of [`platform_source`] to `$OUT_DIR/platform.rs`,
+///    to be pulled in with `include!(concat!(env!("OUT_DIR"), "/platform.rs"))`
+/// 4. Checks that the target has what the bin requires in
+///    `[package.metadata.requirement]` (see [`check_requirements`])
+/// 5. Generates the bin's linker script from the platform's memory map and
+///    the `[package.metadata.memory]` table of its manifest (see
+///    [`MemoryLayout`])
+/// 6. Configures cargo to use that linker script
 pub fn link_helper() {
-    // Get platform from environment variable
-    let platform = get_platform();
-
-    // Get the target triple
-    let target = env::var("TARGET").unwrap_or("riscv32im-nemu".into());
-
-    // Extract the target prefix (part before the first '-')
-    // e.g., "riscv32i-unknown-none-elf" -> "riscv32i"
-    let target_prefix = match target.split('-').next() {
-        Some(prefix) => prefix,
-        None => {
-            println!("cargo:warning=Invalid target format, using 'riscv32i' as default");
-            "riscv32i"
-        }
-    };
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let target = env::var("TARGET").unwrap();
+    let host_arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap();
+    for line in check_cfg_lines(&host_arch) {
+        println!("{}", line);
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+    // Rerun if ARCH environment variable changes
+    println!("cargo:rerun-if-env-changed=ARCH");
 
-    if target_prefix == "x86_64" {
+    let native = env::var("DEP_AM_RUNTIME_PLATFORM").is_ok_and(|platform| platform == "native");
+    if native || target.starts_with("x86_64") {
         // Skip linking for host target
         println!("cargo:warning=Host target detected, skipping linker script configuration");
+        let platform = if native { "native" } else { "host" };
+        println!("cargo:rustc-cfg=am_platform=\"{}\"", platform);
+        println!("cargo:rustc-cfg=am_isa=\"{}\"", host_arch);
+        let source = platform_source(platform, &host_arch, None);
+        fs::write(out_dir.join("platform.rs"), source).unwrap();
         return;
     }
 
-    // Get the workspace root (go up from bin/xxx to project root)
-    let output = std::process::Command::new(env!("CARGO"))
-        .arg("locate-project")
-        .arg("--workspace")
-        .arg("--message-format=plain")
-        .output()
-        .unwrap()
-        .stdout;
-    let workspace_root = Path::new(std::str::from_utf8(&output).unwrap().trim())
-        .parent()
-        .unwrap()
-        .to_path_buf();
-
-    // Construct linker script filename based on target prefix
-    // e.g., "riscv32i" -> "riscv32i_link.x"
-    let linker_script_name = format!("{}_link.x", target_prefix);
-
-    // Path to the linker script in platform/<platform>/linker_scripts
-    let linker_script = workspace_root
-        .join("platform")
-        .join("runtimes")
-        .join(platform.fmt())
-        .join("linker_scripts")
-        .join(&linker_script_name);
-
-    // Check if linker script exists
-    if !linker_script.exists() {
-        panic!(
-            "Linker script not found at: {}\n\
-             Platform: {}\n\
-             Target: {}\n\
-             Expected linker script: {}\n\
-             Please ensure the linker script exists before building.",
-            linker_script.display(),
-            platform.fmt(),
-            target,
-            linker_script_name
-        );
+    let arch = get_arch(&target);
+    println!("cargo:rustc-cfg=am_platform=\"{}\"", arch.platform.fmt());
+    println!("cargo:rustc-cfg=am_isa=\"{}\"", arch.isa);
+
+    let manifest_path = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("Cargo.toml");
+    let manifest = fs::read_to_string(&manifest_path).unwrap();
+    let bin = env::var("CARGO_PKG_NAME").unwrap();
+    if let Err(message) = check_requirements(&bin, &arch, &manifest) {
+        panic!("{}", message);
     }
+    let layout = match MemoryLayout::for_platform(&arch.platform).with_manifest(&manifest) {
+        Ok(layout) => layout,
+        Err(message) => panic!("{}: {}", manifest_path.display(), message),
+    };
 
-    // Tell cargo to pass the linker script to the linker
-    println!("cargo:rustc-link-arg=-T{}", linker_script.display());
+    let source = platform_source(arch.platform.fmt(), arch.isa, Some(&layout));
+    fs::write(out_dir.join("platform.rs"), source).unwrap();
 
-    // Rerun if the linker script changes
-    println!("cargo:rerun-if-changed={}", linker_script.display());
-    println!("cargo:rerun-if-changed=build.rs");
+    let linker_script_path = out_dir.join("link.x");
+    fs::write(&linker_script_path, linker_script(&arch.platform, &layout)).unwrap();
 
-    // Rerun if ARCH environment variable changes
-    println!("cargo:rerun-if-env-changed=ARCH");
-    println!("cargo:rerun-if-env-changed=TARGET");
+    // Tell cargo to pass the linker script to the linker
+    println!("cargo:rustc-link-arg=-T{}", linker_script_path.display());
+
+    // Rerun if the memory layout changes
+    println!("cargo:rerun-if-changed={}", manifest_path.display());
 
     // Print info for debugging
     println!(
-        "cargo:warning=Linker config: script={}, platform={}, target={}",
-        linker_script_name,
-        platform.fmt(),
-        target_prefix
+        "cargo:warning=Linker config: arch={}, target={}, ram={:#x}+{:#x}, stack={:#x}",
+        arch, target, layout.ram_origin, layout.ram_length, layout.stack_size
     );
 }
@@ -0,0 +1,399 @@
+//! Linker scripts
+//!
+//! [`link_helper`](crate::link_helper) generates the linker script of a bin
+//! from the platform's RAM region and a [`MemoryLayout`]. The layout
+//! defaults to a 1 MiB stack at the top of RAM and a heap taking everything
+//! between the program and the stack. A bin overrides it in the
+//! `[package.metadata.memory]` table of its manifest, with these keys:
+//! * `ram-origin`, `ram-length` - Where RAM is, for a machine started with
+//!   more or less memory
+//! * `stack-size` - Size of the stack
+//! * `heap-size` - Size of the heap, which then ends before the stack
+//!   rather than at it
+//! * `regions` - Extra memory regions, as an array of tables with `name`,
+//!   `origin` and `length`. Region `name` gets a `.name` section, for
+//!   statics placed there with `#[link_section]`; it is not loaded, and
+//!   `_sname` and `_ename` mark its bounds.
+//!
+//! Sizes and addresses are integers or strings such as `"0x1000"`, `"64K"`
+//! or `"16M"`.
+
+use crate::{Platform, RegionKind};
+use std::fmt::Write;
+use toml_edit::{DocumentMut, Item, TableLike};
+
+/// Stack size when the bin does not set one
+pub const DEFAULT_STACK_SIZE: u64 = 1024 * 1024;
+
+/// An extra memory region of a bin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtraRegion {
+    pub name: String,
+    pub origin: u64,
+    pub length: u64,
+}
+
+/// Where a bin's program, heap and stack go
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryLayout {
+    pub ram_origin: u64,
+    pub ram_length: u64,
+    pub stack_size: u64,
+    /// `None` for all the RAM left between the program and the stack
+    pub heap_size: Option<u64>,
+    pub regions: Vec<ExtraRegion>,
+}
+
+impl MemoryLayout {
+    /// The default layout on a platform
+    pub fn for_platform(platform: &Platform) -> Self {
+        let ram = platform
+            .memory_map()
+            .iter()
+            .find(|region| region.kind == RegionKind::Ram)
+            .expect("every platform has RAM");
+        Self {
+            ram_origin: ram.base,
+            ram_length: ram.size,
+            stack_size: DEFAULT_STACK_SIZE,
+            heap_size: None,
+            regions: Vec::new(),
+        }
+    }
+
+    /// Apply the `[package.metadata.memory]` table of a manifest
+    ///
+    /// # Arguments
+    /// * `manifest` - The contents of a `Cargo.toml`
+    ///
+    /// # Returns
+    /// * `Ok(layout)` - The layout with the bin's settings, checked
+    /// * `Err(message)` - The manifest or the table is invalid
+    pub fn with_manifest(mut self, manifest: &str) -> Result<Self, String> {
+        let document: DocumentMut = manifest.parse().map_err(|err| format!("{}", err))?;
+        let table = document
+            .get("package")
+            .and_then(|package| package.get("metadata"))
+            .and_then(|metadata| metadata.get("memory"));
+        let Some(table) = table else {
+            return Ok(self);
+        };
+        let table = table
+            .as_table_like()
+            .ok_or("package.metadata.memory is not a table")?;
+
+        for (key, value) in table.iter() {
+            match key {
+                "ram-origin" => self.ram_origin = size(key, value)?,
+                "ram-length" => self.ram_length = size(key, value)?,
+                "stack-size" => self.stack_size = size(key, value)?,
+                "heap-size" => self.heap_size = Some(size(key, value)?),
+                "regions" => self.regions = regions(value)?,
+                _ => return Err(format!("unknown key `{}` in package.metadata.memory", key)),
+            }
+        }
+        self.check()?;
+        Ok(self)
+    }
+
+    /// Check that the stack fits in RAM and extra regions stay clear of it
+    fn check(&self) -> Result<(), String> {
+        if self.stack_size == 0 || self.stack_size >= self.ram_length {
+            return Err(format!(
+                "stack-size {:#x} does not fit in {:#x} bytes of RAM",
+                self.stack_size, self.ram_length
+            ));
+        }
+        let ram_end = self.ram_origin + self.ram_length;
+        for region in &self.regions {
+            let valid = region
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if region.name.is_empty() || !valid || region.name.eq_ignore_ascii_case("ram") {
+                return Err(format!("invalid region name `{}`", region.name));
+            }
+            if region.origin < ram_end && self.ram_origin < region.origin + region.length {
+                return Err(format!("region {} overlaps RAM", region.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A size or address: an integer, or a string in hex or with a K, M or G
+/// suffix
+fn size(key: &str, value: &Item) -> Result<u64, String> {
+    let parsed = match (value.as_integer(), value.as_str()) {
+        (Some(value), _) => u64::try_from(value).ok(),
+        (_, Some(value)) => parse_size(value),
+        _ => None,
+    };
+    parsed.ok_or_else(|| format!("invalid size for `{}`, found {}", key, value.type_name()))
+}
+
+/// Parse `"0x1000"`, `"4096"`, `"64K"`, `"16M"` or `"1G"`
+pub fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    if let Some(hex) = text.strip_prefix("0x") {
+        return u64::from_str_radix(&hex.replace('_', ""), 16).ok();
+    }
+    let (number, unit) = match text.char_indices().last()? {
+        (i, 'K' | 'k') => (&text[..i], 1 << 10),
+        (i, 'M' | 'm') => (&text[..i], 1 << 20),
+        (i, 'G' | 'g') => (&text[..i], 1 << 30),
+        _ => (text, 1),
+    };
+    number.trim().parse::<u64>().ok()?.checked_mul(unit)
+}
+
+fn regions(value: &Item) -> Result<Vec<ExtraRegion>, String> {
+    let tables: Option<Vec<&dyn TableLike>> = match value.as_array_of_tables() {
+        Some(tables) => Some(tables.iter().map(|table| table as &dyn TableLike).collect()),
+        None => value.as_array().and_then(|array| {
+            let inline = array.iter().map(|value| value.as_inline_table());
+            inline
+                .map(|table| table.map(|table| table as &dyn TableLike))
+                .collect()
+        }),
+    };
+    let tables = tables.ok_or("`regions` is not an array of tables")?;
+
+    let mut regions = Vec::new();
+    for table in tables {
+        let name = table
+            .get("name")
+            .and_then(Item::as_str)
+            .ok_or("region without a `name`")?;
+        let field = |key: &str| match table.get(key) {
+            Some(value) => size(key, value),
+            None => Err(format!("region {} without `{}`", name, key)),
+        };
+        regions.push(ExtraRegion {
+            name: name.to_string(),
+            origin: field("origin")?,
+            length: field("length")?,
+        });
+    }
+    Ok(regions)
+}
+
+/// Sections and symbols shared by all platforms; `@...@` are filled in by
+/// [`linker_script`]
+const TEMPLATE: &str = "\
+/* Generated by build_helper::link_helper for @PLATFORM@, see
+   build-helper/src/linker.rs */
+MEMORY {
+  RAM : ORIGIN = @RAM_ORIGIN@, LENGTH = @RAM_LENGTH@
+@MEMORY@}
+
+ENTRY(_start)
+
+SECTIONS {
+  . = ORIGIN(RAM);
+
+  .text : {
+    KEEP(*(.text._start))
+    KEEP(*(.text.__start__))
+    *(.text .text.*)
+  } > RAM
+
+  .rodata : {
+    *(.rodata .rodata.*)
+  } > RAM
+
+  /* Unwind tables and landing pads, for the runtime's `unwind` feature */
+  .eh_frame : {
+    __eh_frame_start = .;
+    KEEP(*(.eh_frame))
+    __eh_frame_end = .;
+  } > RAM
+
+  .gcc_except_table : {
+    *(.gcc_except_table .gcc_except_table.*)
+    __gcc_except_table_end = .;
+  } > RAM
+
+  /* Function names for backtraces, filled in after linking by
+     build_helper::embed_symbols */
+  .am_symtab : {
+    KEEP(*(.am_symtab))
+  } > RAM
+
+  /* Ramdisk archive linked in with runtime::ramdisk!(), possibly empty */
+  .ramdisk : {
+    _sramdisk = .;
+    KEEP(*(.ramdisk))
+    _eramdisk = .;
+  } > RAM
+
+  .data : {
+    *(.data .data.*)
+  } > RAM
+
+  .bss : {
+    *(.bss .bss.*)
+    *(COMMON)
+@BSS@  } > RAM
+
+  . = ALIGN(4);
+  _sdata = ADDR(.data);
+  _edata = ADDR(.data) + SIZEOF(.data);
+  _sbss = ADDR(.bss);
+  _ebss = ADDR(.bss) + SIZEOF(.bss);
+
+  /* Stack region - at the top of RAM. Its first word is the canary
+     checked by runtime::mem, painted at boot with the rest */
+  _stack_top = ORIGIN(RAM) + LENGTH(RAM);
+  _sstack = _stack_top - @STACK_SIZE@;
+
+  /* Heap region - after BSS, @HEAP@ */
+  . = ALIGN(4);
+  _sheap = .;
+  _eheap = @HEAP_END@;
+  ASSERT(_eheap <= _sstack, \"The program and heap do not fit below the stack\")
+@SECTIONS@
+  /* Format strings interned by runtime::dprintln!, for the host decoder;
+     kept in the ELF but not loaded */
+  .am_fmt 0 (INFO) : {
+    KEEP(*(.am_fmt .am_fmt.*))
+  }
+}
+";
+
+/// What a platform adds to `.bss`
+fn bss_extra(platform: &Platform) -> &'static str {
+    match platform {
+        Platform::Spike => {
+            "    . = ALIGN(8);
+    /* HTIF communication mailboxes for Spike */
+    tohost = .;
+    QUAD(0);
+    fromhost = .;
+    QUAD(0);
+"
+        }
+        Platform::Nemu | Platform::Qemu => "",
+    }
+}
+
+/// Generate the linker script of a bin
+///
+/// # Arguments
+/// * `platform` - The platform the bin runs on
+/// * `layout` - Where its memory goes
+pub fn linker_script(platform: &Platform, layout: &MemoryLayout) -> String {
+    let mut memory = String::new();
+    let mut sections = String::new();
+    for region in &layout.regions {
+        let upper = region.name.to_uppercase();
+        writeln!(
+            memory,
+            "  {} : ORIGIN = {:#x}, LENGTH = {:#x}",
+            upper, region.origin, region.length
+        )
+        .unwrap();
+        write!(
+            sections,
+            "\n  /* Extra region {name}, from [package.metadata.memory] */
+  .{name} (NOLOAD) : {{
+    _s{name} = .;
+    KEEP(*(.{name} .{name}.*))
+    _e{name} = .;
+  }} > {upper}
+",
+            name = region.name,
+            upper = upper
+        )
+        .unwrap();
+    }
+
+    let (heap, heap_end) = match layout.heap_size {
+        Some(size) => (
+            format!("{:#x} bytes", size),
+            format!("_sheap + {:#x}", size),
+        ),
+        None => ("up to the stack".to_string(), "_sstack".to_string()),
+    };
+    TEMPLATE
+        .replace("@PLATFORM@", platform.fmt())
+        .replace("@RAM_ORIGIN@", &format!("{:#x}", layout.ram_origin))
+        .replace("@RAM_LENGTH@", &format!("{:#x}", layout.ram_length))
+        .replace("@MEMORY@", &memory)
+        .replace("@BSS@", bss_extra(platform))
+        .replace("@STACK_SIZE@", &format!("{:#x}", layout.stack_size))
+        .replace("@HEAP@", &heap)
+        .replace("@HEAP_END@", &heap_end)
+        .replace("@SECTIONS@", &sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("0x1000"), Some(0x1000));
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("64K"), Some(64 << 10));
+        assert_eq!(parse_size("16M"), Some(16 << 20));
+        assert_eq!(parse_size("M"), None);
+        assert_eq!(parse_size("lots"), None);
+    }
+
+    #[test]
+    fn test_manifest_layout() {
+        let manifest = r#"
+            [package]
+            name = "bin"
+
+            [package.metadata.memory]
+            ram-length = "256M"
+            stack-size = 0x4000
+            heap-size = "16K"
+            regions = [{ name = "dma", origin = "0x90000000", length = "64K" }]
+        "#;
+        let layout = MemoryLayout::for_platform(&Platform::Qemu)
+            .with_manifest(manifest)
+            .unwrap();
+        assert_eq!(layout.ram_length, 256 << 20);
+        assert_eq!(layout.stack_size, 0x4000);
+        assert_eq!(layout.heap_size, Some(16 << 10));
+        assert_eq!(layout.regions[0].origin, 0x9000_0000);
+
+        // Bins without the table keep the platform's layout
+        let default = MemoryLayout::for_platform(&Platform::Qemu);
+        let plain = default.clone().with_manifest("[package]\nname = \"bin\"\n");
+        assert_eq!(plain, Ok(default));
+
+        let script = linker_script(&Platform::Qemu, &layout);
+        assert!(script.contains("RAM : ORIGIN = 0x80000000, LENGTH = 0x10000000"));
+        assert!(script.contains("DMA : ORIGIN = 0x90000000, LENGTH = 0x10000"));
+        assert!(script.contains("_sstack = _stack_top - 0x4000;"));
+        assert!(script.contains("_eheap = _sheap + 0x4000;"));
+        assert!(!script.contains('@'));
+
+        let script = linker_script(
+            &Platform::Spike,
+            &MemoryLayout::for_platform(&Platform::Spike),
+        );
+        assert!(script.contains("tohost = .;"));
+        assert!(script.contains("_eheap = _sstack;"));
+    }
+
+    #[test]
+    fn test_invalid_layout() {
+        let layout = MemoryLayout::for_platform(&Platform::Nemu);
+        let with = |table: &str| {
+            let manifest = format!("[package.metadata.memory]\n{}", table);
+            layout.clone().with_manifest(&manifest).unwrap_err()
+        };
+        assert!(with("stack-size = \"1G\"").contains("does not fit"));
+        assert!(with("heap-size = true").contains("invalid size"));
+        assert!(with("stack = 1").contains("unknown key"));
+        assert!(
+            with("regions = [{ name = \"low\", origin = \"0x80001000\", length = 4 }]")
+                .contains("overlaps")
+        );
+    }
+}
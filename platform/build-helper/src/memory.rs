@@ -0,0 +1,281 @@
+//! Platform memory maps
+//!
+//! The physical memory layout of each platform, for build scripts that need
+//! addresses. A platform runtime turns its map into Rust constants with
+//! [`emit_memory_map`].
+
+use crate::Platform;
+use std::{env, fmt::Write, fs, path::PathBuf};
+
+const KIB: u64 = 1024;
+const MIB: u64 = 1024 * KIB;
+
+/// What a memory region holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    /// Main memory, holding the program
+    Ram,
+    /// A NOR flash bank, erased in sectors
+    Flash { sector_size: u64 },
+    /// Part of a flash bank set aside for one use
+    Partition { bank: &'static str },
+    /// Memory-mapped device registers
+    Device,
+}
+
+impl RegionKind {
+    fn fmt(&self) -> &'static str {
+        match self {
+            RegionKind::Ram => "ram",
+            RegionKind::Flash { .. } => "flash",
+            RegionKind::Partition { .. } => "partition",
+            RegionKind::Device => "device",
+        }
+    }
+}
+
+/// A named range of physical addresses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub name: &'static str,
+    pub base: u64,
+    pub size: u64,
+    pub kind: RegionKind,
+}
+
+const RAM: Region = Region {
+    name: "ram",
+    base: 0x8000_0000,
+    size: 128 * MIB,
+    kind: RegionKind::Ram,
+};
+
+const fn device(name: &'static str, base: u64, size: u64) -> Region {
+    Region {
+        name,
+        base,
+        size,
+        kind: RegionKind::Device,
+    }
+}
+
+/// NEMU: the AM serial, RTC and disk devices
+const NEMU_MAP: &[Region] = &[
+    RAM,
+    device("serial", 0x1000_03f8, 0x8),
+    device("disk", 0x1000_0300, 0x20),
+    device("rtc", 0x1000_0048, 0x8),
+];
+
+/// Spike: a 16550 UART
+const SPIKE_MAP: &[Region] = &[RAM, device("uart0", 0x1000_0000, 0x100)];
+
+/// QEMU virt: two CFI flash banks of 32 MiB with 256 KiB sectors
+const QEMU_MAP: &[Region] = &[
+    RAM,
+    // QEMU boots from unit 0 when it is attached, so it is left to firmware
+    Region {
+        name: "flash0",
+        base: 0x2000_0000,
+        size: 32 * MIB,
+        kind: RegionKind::Flash {
+            sector_size: 256 * KIB,
+        },
+    },
+    Region {
+        name: "flash1",
+        base: 0x2200_0000,
+        size: 32 * MIB,
+        kind: RegionKind::Flash {
+            sector_size: 256 * KIB,
+        },
+    },
+    // runtime::kv, in the first two sectors of unit 1
+    Region {
+        name: "kvstore",
+        base: 0x2200_0000,
+        size: 512 * KIB,
+        kind: RegionKind::Partition { bank: "flash1" },
+    },
+    device("test", 0x10_0000, 0x1000),
+    device("rtc", 0x10_1000, 0x1000),
+    device("clint", 0x200_0000, 0x1_0000),
+    device("plic", 0xc00_0000, 0x60_0000),
+    device("uart0", 0x1000_0000, 0x100),
+    // Eight virtio-mmio transports, 0x1000 apart
+    device("virtio", 0x1000_1000, 0x8000),
+];
+
+impl Platform {
+    /// Memory regions of the platform
+    pub fn memory_map(&self) -> &'static [Region] {
+        match self {
+            Platform::Nemu => NEMU_MAP,
+            Platform::Qemu => QEMU_MAP,
+            Platform::Spike => SPIKE_MAP,
+        }
+    }
+}
+
+/// Check that partitions lie within their bank, on sector boundaries
+///
+/// # Returns
+/// * `Ok(())` - The map is consistent
+/// * `Err(message)` - The first problem found
+pub fn check_memory_map(regions: &[Region]) -> Result<(), String> {
+    for region in regions {
+        let RegionKind::Partition { bank } = region.kind else {
+            continue;
+        };
+        let parent = regions
+            .iter()
+            .find(|r| r.name == bank)
+            .ok_or_else(|| format!("{}: unknown bank {}", region.name, bank))?;
+        let RegionKind::Flash { sector_size } = parent.kind else {
+            return Err(format!("{}: {} is not a flash bank", region.name, bank));
+        };
+
+        let end = region.base + region.size;
+        if region.base < parent.base || end > parent.base + parent.size {
+            return Err(format!("{}: outside of {}", region.name, bank));
+        }
+        if (region.base - parent.base) % sector_size != 0 || region.size % sector_size != 0 {
+            return Err(format!("{}: not aligned to {} sectors", region.name, bank));
+        }
+    }
+    Ok(())
+}
+
+/// Render a memory map as Rust constants
+///
+/// Each region `name` gets `NAME_BASE` and `NAME_SIZE`; flash banks also get
+/// `NAME_SECTOR_SIZE`, and partitions the `NAME_BANK_BASE` and
+/// `NAME_SECTOR_SIZE` of their bank. The whole map is also listed in
+/// `REGIONS`, as `common::MemoryRegion`s for the platform's
+/// `Platform::MEMORY_MAP`.
+pub fn memory_map_source(regions: &[Region]) -> String {
+    let mut out = String::from("// Generated by build_helper::emit_memory_map\n");
+    out.push_str("\npub use common::MemoryRegion as Region;\n");
+    out.push_str(&regions_body(regions));
+    out
+}
+
+/// The constants of [`memory_map_source`] with their own `Region` type, for
+/// crates that do not depend on `common`
+pub(crate) fn regions_source(regions: &[Region]) -> String {
+    let mut out = String::new();
+    out.push_str(
+        "\n/// A named range of physical addresses\n\
+         #[derive(Debug, Clone, Copy)]\n\
+         pub struct Region {\n\
+         \x20   pub name: &'static str,\n\
+         \x20   pub base: usize,\n\
+         \x20   pub size: usize,\n\
+         \x20   /// `ram`, `flash`, `partition` or `device`\n\
+         \x20   pub kind: &'static str,\n\
+         }\n",
+    );
+    out.push_str(&regions_body(regions));
+    out
+}
+
+fn regions_body(regions: &[Region]) -> String {
+    let mut out = String::new();
+    out.push_str("\n/// Every region, in map order\npub const REGIONS: &[Region] = &[\n");
+    for region in regions {
+        writeln!(
+            out,
+            "    Region {{ name: {:?}, base: {:#x}, size: {:#x}, kind: {:?} }},",
+            region.name,
+            region.base,
+            region.size,
+            region.kind.fmt()
+        )
+        .unwrap();
+    }
+    out.push_str("];\n");
+    for region in regions {
+        let name = region.name.to_uppercase();
+        writeln!(out, "\n/// `{}` region", region.name).unwrap();
+        writeln!(out, "pub const {}_BASE: usize = {:#x};", name, region.base).unwrap();
+        writeln!(out, "pub const {}_SIZE: usize = {:#x};", name, region.size).unwrap();
+
+        let bank = match region.kind {
+            RegionKind::Ram | RegionKind::Device => None,
+            RegionKind::Flash { sector_size } => Some((None, sector_size)),
+            RegionKind::Partition { bank } => {
+                regions
+                    .iter()
+                    .find(|r| r.name == bank)
+                    .and_then(|bank| match bank.kind {
+                        RegionKind::Flash { sector_size } => Some((Some(bank.base), sector_size)),
+                        _ => None,
+                    })
+            }
+        };
+        if let Some((bank_base, sector_size)) = bank {
+            if let Some(bank_base) = bank_base {
+                writeln!(
+                    out,
+                    "pub const {}_BANK_BASE: usize = {:#x};",
+                    name, bank_base
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "pub const {}_SECTOR_SIZE: usize = {:#x};",
+                name, sector_size
+            )
+            .unwrap();
+        }
+    }
+    out
+}
+
+/// Generate a platform's memory map constants
+///
+/// To be called from a platform runtime's build.rs. Writes
+/// `$OUT_DIR/memory_map.rs`, to be pulled in with
+/// `include!(concat!(env!("OUT_DIR"), "/memory_map.rs"))`.
+///
+/// # Arguments
+/// * `platform` - The platform whose map to generate
+pub fn emit_memory_map(platform: Platform) {
+    let regions = platform.memory_map();
+    if let Err(message) = check_memory_map(regions) {
+        panic!("Invalid {} memory map: {}", platform.fmt(), message);
+    }
+
+    let path = PathBuf::from(env::var("OUT_DIR").unwrap()).join("memory_map.rs");
+    fs::write(&path, memory_map_source(regions)).unwrap();
+    println!("cargo:rerun-if-changed=build.rs");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_platform_maps() {
+        for platform in [Platform::Nemu, Platform::Qemu, Platform::Spike] {
+            assert_eq!(check_memory_map(platform.memory_map()), Ok(()));
+        }
+
+        let source = memory_map_source(Platform::Qemu.memory_map());
+        assert!(source.contains("pub use common::MemoryRegion as Region;"));
+        assert!(source.contains("pub const RAM_BASE: usize = 0x80000000;"));
+        assert!(source.contains("pub const FLASH1_SECTOR_SIZE: usize = 0x40000;"));
+        assert!(source.contains("pub const KVSTORE_BANK_BASE: usize = 0x22000000;"));
+        assert!(source.contains(
+            r#"Region { name: "uart0", base: 0x10000000, size: 0x100, kind: "device" },"#
+        ));
+    }
+
+    #[test]
+    fn test_misaligned_partition() {
+        let mut map = QEMU_MAP.to_vec();
+        map[3].base += 4 * KIB;
+        assert!(check_memory_map(&map).unwrap_err().contains("not aligned"));
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+/// Append one cpio "newc" member
+fn write_member(out: &mut Vec<u8>, name: &str, mode: u32, mtime: u64, data: &[u8]) {
+    let fields = [
+        0,                     // ino
+        mode,                  // mode
+        0,                     // uid
+        0,                     // gid
+        1,                     // nlink
+        mtime as u32,          // mtime
+        data.len() as u32,     // filesize
+        0,                     // devmajor
+        0,                     // devminor
+        0,                     // rdevmajor
+        0,                     // rdevminor
+        name.len() as u32 + 1, // namesize
+        0,                     // check
+    ];
+
+    out.extend_from_slice(b"070701");
+    for field in fields {
+        out.extend_from_slice(format!("{:08x}", field).as_bytes());
+    }
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    out.resize(out.len().next_multiple_of(4), 0);
+    out.extend_from_slice(data);
+    out.resize(out.len().next_multiple_of(4), 0);
+}
+
+fn mtime(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Recursively append a directory's contents, sorted by name
+fn pack_dir(out: &mut Vec<u8>, root: &Path, dir: &Path, deps: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.sort();
+
+    for path in paths {
+        let metadata = fs::metadata(&path)?;
+        let name = path
+            .strip_prefix(root)
+            .unwrap()
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if metadata.is_dir() {
+            write_member(out, &name, S_IFDIR | 0o755, mtime(&metadata), &[]);
+            pack_dir(out, root, &path, deps)?;
+        } else if metadata.is_file() {
+            let data = fs::read(&path)?;
+            write_member(out, &name, S_IFREG | 0o644, mtime(&metadata), &data);
+        }
+        deps.push(path);
+    }
+
+    Ok(())
+}
+
+/// Pack a directory into a cpio "newc" archive
+///
+/// A missing directory produces an empty archive.
+///
+/// # Arguments
+/// * `dir` - Directory to pack; member paths are relative to it
+///
+/// # Returns
+/// * The archive, and every packed path (for change tracking)
+pub fn build_ramdisk(dir: &Path) -> io::Result<(Vec<u8>, Vec<PathBuf>)> {
+    let mut archive = Vec::new();
+    let mut deps = Vec::new();
+    if dir.is_dir() {
+        pack_dir(&mut archive, dir, dir, &mut deps)?;
+    }
+    write_member(&mut archive, "TRAILER!!!", 0, 0, &[]);
+    Ok((archive, deps))
+}
+
+/// Pack a directory into the ramdisk linked by `runtime::ramdisk!()`
+///
+/// To be called from build.rs. This function:
+/// 1. Packs `dir` (relative to the package root) into `$OUT_DIR/ramdisk.cpio`
+/// 2. Points `AM_RAMDISK` at the archive for `runtime::ramdisk!()`
+/// 3. Reruns the build script when anything under `dir` changes
+///
+/// # Arguments
+/// * `dir` - Directory to pack, such as `"assets"`
+pub fn pack_ramdisk(dir: impl AsRef<Path>) {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let dir = manifest_dir.join(dir);
+
+    let (archive, deps) = build_ramdisk(&dir)
+        .unwrap_or_else(|err| panic!("Failed to pack ramdisk {}: {}", dir.display(), err));
+
+    let archive_path = PathBuf::from(env::var("OUT_DIR").unwrap()).join("ramdisk.cpio");
+    fs::write(&archive_path, &archive).unwrap();
+
+    println!("cargo:rustc-env=AM_RAMDISK={}", archive_path.display());
+    println!("cargo:rerun-if-changed={}", dir.display());
+    for path in deps {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_ramdisk() {
+        let dir = env::temp_dir().join(format!("am-ramdisk-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/b.bin"), b"abc").unwrap();
+        fs::write(dir.join("a.txt"), b"hello").unwrap();
+
+        let (archive, deps) = build_ramdisk(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(deps.len(), 3);
+        assert!(archive.starts_with(b"070701"));
+        assert_eq!(archive.len() % 4, 0);
+        let text = String::from_utf8_lossy(&archive);
+        let order: Vec<_> = ["a.txt\0hello", "sub\0", "sub/b.bin\0", "TRAILER!!!"]
+            .iter()
+            .map(|needle| text.find(needle).unwrap())
+            .collect();
+        assert!(order.is_sorted());
+
+        let (empty, _) = build_ramdisk(&dir).unwrap();
+        assert!(String::from_utf8_lossy(&empty).contains("TRAILER!!!"));
+    }
+}
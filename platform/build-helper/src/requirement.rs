@@ -0,0 +1,282 @@
+//! Bin requirements
+//!
+//! A bin declares what it needs from the target in its manifest, one table
+//! per group: `[package.metadata.requirement.arch]` for ISA extensions and
+//! `[package.metadata.requirement.io]` for devices, each capability set to
+//! `true`. [`check_requirements`] compares them with what the target
+//! provides, so that building for a target without them fails with a
+//! message naming the missing capability rather than a link or codegen
+//! error.
+
+use crate::{Arch, Platform};
+use toml_edit::DocumentMut;
+
+/// Something a bin may need from its target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// Atomic read-modify-write instructions, the A extension
+    Atomic,
+    /// Vector instructions, the Zve32x extension
+    Vector,
+    /// A display
+    Graphic,
+    /// Keyboard or pointer input
+    Input,
+    /// A block device
+    Block,
+    /// A network device
+    Net,
+    /// Timer alarms, see `rtc_set_alarm`
+    Timer,
+}
+
+impl Capability {
+    /// Every capability
+    pub const ALL: [Capability; 7] = [
+        Capability::Atomic,
+        Capability::Vector,
+        Capability::Graphic,
+        Capability::Input,
+        Capability::Block,
+        Capability::Net,
+        Capability::Timer,
+    ];
+
+    /// Key of the capability in its table
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::Atomic => "atomic",
+            Capability::Vector => "vector",
+            Capability::Graphic => "graphic",
+            Capability::Input => "input",
+            Capability::Block => "block",
+            Capability::Net => "net",
+            Capability::Timer => "timer",
+        }
+    }
+
+    /// The requirement table the capability goes in
+    pub fn group(&self) -> &'static str {
+        match self {
+            Capability::Atomic | Capability::Vector => "arch",
+            _ => "io",
+        }
+    }
+
+    fn from_key(group: &str, name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|cap| cap.group() == group && cap.name() == name)
+    }
+}
+
+impl Platform {
+    /// Devices of the platform, that its runtime drives: those its
+    /// `Platform` implementation provides, and the virtio network device of
+    /// QEMU, driven by bins through `runtime::virtio`
+    pub fn devices(&self) -> &'static [Capability] {
+        match self {
+            Platform::Nemu => &[Capability::Block],
+            Platform::Qemu => &[Capability::Block, Capability::Net, Capability::Timer],
+            Platform::Spike => &[],
+        }
+    }
+}
+
+impl Arch {
+    /// Whether the target provides a capability: ISA extensions come from
+    /// the ISA, devices from the platform
+    pub fn supports(&self, capability: Capability) -> bool {
+        let (base, extensions) = self.isa.split_once('_').unwrap_or((self.isa, ""));
+        let base = base.trim_start_matches("riscv32");
+        match capability {
+            Capability::Atomic => base.contains('a'),
+            Capability::Vector => {
+                base.contains('v') || extensions.split('_').any(|ext| ext.starts_with("zve"))
+            }
+            device => self.platform.devices().contains(&device),
+        }
+    }
+}
+
+/// Read the requirements of a bin
+///
+/// # Arguments
+/// * `manifest` - The contents of the bin's `Cargo.toml`
+///
+/// # Returns
+/// * `Ok(capabilities)` - Those set to `true`, none without the table
+/// * `Err(message)` - An unknown group or capability, or a value that is
+///   not a boolean
+pub fn requirements(manifest: &str) -> Result<Vec<Capability>, String> {
+    let document: DocumentMut = manifest.parse().map_err(|err| format!("{}", err))?;
+    let table = document
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("requirement"));
+    let Some(table) = table else {
+        return Ok(Vec::new());
+    };
+    let table = table
+        .as_table_like()
+        .ok_or("package.metadata.requirement is not a table")?;
+
+    let mut capabilities = Vec::new();
+    for (group, entries) in table.iter() {
+        let entries = entries
+            .as_table_like()
+            .ok_or_else(|| format!("package.metadata.requirement.{} is not a table", group))?;
+        for (name, value) in entries.iter() {
+            let capability = Capability::from_key(group, name).ok_or_else(|| {
+                format!(
+                    "unknown requirement `{}` in package.metadata.requirement.{}",
+                    name, group
+                )
+            })?;
+            let required = value
+                .as_bool()
+                .ok_or_else(|| format!("requirement `{}.{}` must be true or false", group, name))?;
+            if required {
+                capabilities.push(capability);
+            }
+        }
+    }
+    Ok(capabilities)
+}
+
+/// Check that a target provides everything a bin requires
+///
+/// # Arguments
+/// * `bin` - Name of the bin, for the message
+/// * `arch` - The target
+/// * `manifest` - The contents of the bin's `Cargo.toml`
+///
+/// # Returns
+/// * `Err(message)` - The first missing capability, with the targets that
+///   have everything the bin requires
+pub fn check_requirements(bin: &str, arch: &Arch, manifest: &str) -> Result<(), String> {
+    let required = requirements(manifest)?;
+    let Some(missing) = required.iter().find(|&&cap| !arch.supports(cap)) else {
+        return Ok(());
+    };
+
+    let candidates: Vec<String> = Platform::ALL
+        .iter()
+        .flat_map(|&platform| {
+            platform
+                .supported_isas()
+                .iter()
+                .map(move |&isa| Arch { isa, platform })
+        })
+        .filter(|arch| required.iter().all(|&cap| arch.supports(cap)))
+        .map(|arch| arch.to_string())
+        .collect();
+    let runs_on = if candidates.is_empty() {
+        "no supported target".to_string()
+    } else {
+        candidates.join(", ")
+    };
+    Err(format!(
+        "{} requires `{}` (package.metadata.requirement.{}), which {} does not have; it builds for {}",
+        bin,
+        missing.name(),
+        missing.group(),
+        arch,
+        runs_on
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requirements() {
+        let manifest = r#"
+            [package]
+            name = "bin"
+
+            [package.metadata.requirement.arch]
+            atomic = true
+            vector = false
+
+            [package.metadata.requirement.io]
+            net = true
+        "#;
+        assert_eq!(
+            requirements(manifest),
+            Ok(vec![Capability::Atomic, Capability::Net])
+        );
+        assert_eq!(requirements("[package]\nname = \"bin\"\n"), Ok(Vec::new()));
+
+        let unknown = "[package.metadata.requirement.io]\nsound = true\n";
+        assert!(
+            requirements(unknown)
+                .unwrap_err()
+                .contains("unknown requirement `sound`")
+        );
+        let misplaced = "[package.metadata.requirement.io]\natomic = true\n";
+        assert!(requirements(misplaced).is_err());
+    }
+
+    #[test]
+    fn test_check_requirements() {
+        let manifest = "[package.metadata.requirement.arch]\natomic = true\n";
+        let imac = Arch::parse("riscv32imac-qemu").unwrap();
+        assert_eq!(check_requirements("rv64emu", &imac, manifest), Ok(()));
+
+        let im = Arch::parse("riscv32im-qemu").unwrap();
+        let err = check_requirements("rv64emu", &im, manifest).unwrap_err();
+        assert!(err.contains("requires `atomic`"));
+        assert!(err.ends_with("it builds for riscv32imac-qemu"));
+
+        let vector = Arch::parse("riscv32im_zve32x-nemu").unwrap();
+        assert!(vector.supports(Capability::Vector) && vector.supports(Capability::Block));
+        assert!(!vector.supports(Capability::Net));
+
+        let graphic = "[package.metadata.requirement.io]\ngraphic = true\n";
+        let err = check_requirements("graphic", &im, graphic).unwrap_err();
+        assert!(err.ends_with("it builds for no supported target"));
+    }
+
+    /// Each device is listed exactly for the platforms whose runtime drives
+    /// it, as read from the platform crates, and the build scripts agree
+    #[test]
+    fn test_devices_match_runtimes() {
+        let script = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../scripts/build/compilable.nu"
+        );
+        let script = std::fs::read_to_string(script).unwrap();
+        // The `Platform` method a runtime implements to drive the device
+        let methods = [
+            (Capability::Graphic, "fn framebuffer("),
+            (Capability::Input, "fn keyboard("),
+            (Capability::Block, "fn block_device("),
+            (Capability::Timer, "fn rtc_set_alarm("),
+        ];
+        for platform in Platform::ALL {
+            let src = format!(
+                "{}/../runtimes/{}/src",
+                env!("CARGO_MANIFEST_DIR"),
+                platform.fmt()
+            );
+            let lib = std::fs::read_to_string(format!("{}/lib.rs", src)).unwrap();
+            for (device, method) in methods {
+                assert_eq!(
+                    platform.devices().contains(&device),
+                    lib.contains(method),
+                    "{:?} on {}",
+                    device,
+                    platform.fmt()
+                );
+            }
+            let net = std::path::Path::new(&format!("{}/virtio/net.rs", src)).exists();
+            assert_eq!(platform.devices().contains(&Capability::Net), net);
+
+            let names: Vec<&str> = platform.devices().iter().map(|cap| cap.name()).collect();
+            let line = format!("    {}: [{}]\n", platform.fmt(), names.join(" "));
+            assert!(script.contains(&line), "compilable.nu lacks {:?}", line);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use elf::ElfBytes;
+use elf::abi::STT_FUNC;
+use elf::endian::AnyEndian;
+use std::{fs, path::Path};
+
+// Must match `common::symtab`
+const SYMTAB_SECTION: &str = ".am_symtab";
+const SYMTAB_MAGIC: u32 = 0x544d_5953;
+const NO_NAME: u32 = u32::MAX;
+
+/// Longest name kept, names are shortened further when the table is full
+const MAX_NAME_LEN: usize = 255;
+
+/// Name lengths tried, in turn, until the table fits
+const NAME_LIMITS: [usize; 6] = [MAX_NAME_LEN, 96, 64, 40, 24, 12];
+
+/// Turn a legacy (`_ZN...E`) or simple v0 (`_R...`) Rust symbol into its
+/// path, without the hash; other names are returned unchanged
+fn demangle(name: &str) -> String {
+    let demangled = if let Some(rest) = name.strip_prefix("_ZN") {
+        demangle_legacy(rest)
+    } else if let Some(rest) = name.strip_prefix("_R") {
+        let mut rest = rest.trim_start_matches(|ch: char| ch.is_ascii_digit());
+        demangle_v0(&mut rest)
+    } else {
+        None
+    };
+    demangled.unwrap_or_else(|| String::from(name))
+}
+
+fn demangle_legacy(mut rest: &str) -> Option<String> {
+    let mut parts = Vec::new();
+    while !rest.starts_with('E') {
+        let digits = rest.find(|ch: char| !ch.is_ascii_digit())?;
+        let len: usize = rest[..digits].parse().ok()?;
+        let part = rest.get(digits..digits + len)?;
+        rest = &rest[digits + len..];
+
+        let is_hash = part.len() == 17
+            && part.starts_with('h')
+            && part[1..].chars().all(|ch| ch.is_ascii_hexdigit());
+        if !is_hash {
+            parts.push(unescape_legacy(
+                part.strip_prefix("_$").map_or(part, |_| &part[1..]),
+            )?);
+        }
+    }
+    Some(parts.join("::"))
+}
+
+fn unescape_legacy(mut part: &str) -> Option<String> {
+    let mut out = String::new();
+    while !part.is_empty() {
+        if let Some(rest) = part.strip_prefix("..") {
+            out.push_str("::");
+            part = rest;
+        } else if let Some(rest) = part.strip_prefix('$') {
+            let end = rest.find('$')?;
+            out.push(match &rest[..end] {
+                "SP" => '@',
+                "BP" => '*',
+                "RF" => '&',
+                "LT" => '<',
+                "GT" => '>',
+                "LP" => '(',
+                "RP" => ')',
+                "C" => ',',
+                code => char::from_u32(u32::from_str_radix(code.strip_prefix('u')?, 16).ok()?)?,
+            });
+            part = &rest[end + 1..];
+        } else {
+            let ch = part.chars().next()?;
+            out.push(ch);
+            part = &part[ch.len_utf8()..];
+        }
+    }
+    Some(out)
+}
+
+/// Crate roots and nested paths of the v0 scheme; generic arguments, impls
+/// and back-references are left mangled
+fn demangle_v0(rest: &mut &str) -> Option<String> {
+    let tag = rest.chars().next()?;
+    *rest = &rest[1..];
+    match tag {
+        'C' => v0_ident(rest),
+        'N' => {
+            // Namespace
+            *rest = rest.get(1..)?;
+            let path = demangle_v0(rest)?;
+            let ident = v0_ident(rest)?;
+            // Closures and other unnamed items have empty identifiers
+            Some(if ident.is_empty() {
+                path
+            } else {
+                format!("{}::{}", path, ident)
+            })
+        }
+        _ => None,
+    }
+}
+
+fn v0_ident(rest: &mut &str) -> Option<String> {
+    // Disambiguator
+    if let Some(after) = rest.strip_prefix('s') {
+        *rest = &after[after.find('_')? + 1..];
+    }
+    let punycode = rest.starts_with('u');
+    if punycode {
+        *rest = &rest[1..];
+    }
+    let digits = rest.find(|ch: char| !ch.is_ascii_digit())?;
+    let len: usize = rest[..digits].parse().ok()?;
+    let mut start = digits;
+    if rest[start..].starts_with('_') {
+        start += 1;
+    }
+    let ident = rest.get(start..start + len)?;
+    *rest = &rest[start + len..];
+    (!punycode).then(|| String::from(ident))
+}
+
+/// Keep the end of a long name, which names the function itself
+fn shorten(name: &str, limit: usize) -> &str {
+    if name.len() <= limit {
+        return name;
+    }
+    let mut start = name.len() - limit;
+    while !name.is_char_boundary(start) {
+        start += 1;
+    }
+    &name[start..]
+}
+
+fn encode(functions: &[(u32, String)], end: u32, limit: usize) -> Vec<u8> {
+    let names_start = 8 + 8 * (functions.len() + 1);
+    let mut table = Vec::new();
+    let mut names = Vec::new();
+
+    table.extend_from_slice(&SYMTAB_MAGIC.to_le_bytes());
+    table.extend_from_slice(&(functions.len() as u32 + 1).to_le_bytes());
+    for (addr, name) in functions {
+        let name = shorten(name, limit);
+        table.extend_from_slice(&addr.to_le_bytes());
+        table.extend_from_slice(&((names_start + names.len()) as u32).to_le_bytes());
+        names.push(name.len() as u8);
+        names.extend_from_slice(name.as_bytes());
+    }
+    table.extend_from_slice(&end.to_le_bytes());
+    table.extend_from_slice(&NO_NAME.to_le_bytes());
+    table.extend_from_slice(&names);
+    table
+}
+
+/// Build the symbol table of an image, see `common::symtab`
+///
+/// # Arguments
+/// * `image` - The linked ELF
+/// * `capacity` - Space reserved for the table
+///
+/// # Returns
+/// * `Ok((table, count))` - The table and the number of functions in it;
+///   names are shortened as needed to fit
+/// * `Err(message)` - The ELF cannot be read, or even the shortest names do
+///   not fit
+pub fn build_symtab(image: &[u8], capacity: usize) -> Result<(Vec<u8>, usize), String> {
+    let file = ElfBytes::<AnyEndian>::minimal_parse(image).map_err(|err| err.to_string())?;
+    let (symbols, strings) = file
+        .symbol_table()
+        .map_err(|err| err.to_string())?
+        .ok_or("the ELF has no symbol table")?;
+
+    let mut functions = Vec::new();
+    let mut end = 0;
+    for symbol in symbols.iter() {
+        if symbol.st_symtype() != STT_FUNC || symbol.st_value == 0 {
+            continue;
+        }
+        let name = strings
+            .get(symbol.st_name as usize)
+            .map_err(|err| err.to_string())?;
+        functions.push((symbol.st_value as u32, demangle(name)));
+        end = end.max((symbol.st_value + symbol.st_size) as u32);
+    }
+    functions.sort();
+    functions.dedup_by_key(|(addr, _)| *addr);
+
+    for limit in NAME_LIMITS {
+        let table = encode(&functions, end, limit);
+        if table.len() <= capacity {
+            return Ok((table, functions.len()));
+        }
+    }
+    Err(format!(
+        "{} functions do not fit in {} bytes, even with short names",
+        functions.len(),
+        capacity
+    ))
+}
+
+/// Fill in the symbol table reserved in a linked image, for backtraces
+///
+/// Called on the ELF after every build (cargo has no post-link step, the
+/// build scripts run `tools/embed-symbols`). The image is patched in place:
+/// only the contents of `.am_symtab` change.
+///
+/// # Returns
+/// * `Ok(count)` - The number of functions in the table
+/// * `Err(message)` - The ELF cannot be read or has no `.am_symtab`
+pub fn embed_symbols(path: &Path) -> Result<usize, String> {
+    let mut image = fs::read(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let (offset, size) = {
+        let file = ElfBytes::<AnyEndian>::minimal_parse(&image).map_err(|err| err.to_string())?;
+        let section = file
+            .section_header_by_name(SYMTAB_SECTION)
+            .map_err(|err| err.to_string())?
+            .ok_or(format!(
+                "no {} section in {}",
+                SYMTAB_SECTION,
+                path.display()
+            ))?;
+        (section.sh_offset as usize, section.sh_size as usize)
+    };
+
+    let (mut table, count) = build_symtab(&image, size)?;
+    table.resize(size, 0);
+    image[offset..offset + size].copy_from_slice(&table);
+    fs::write(path, image).map_err(|err| format!("{}: {}", path.display(), err))?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_demangle() {
+        assert_eq!(
+            demangle("_ZN5mnist9inference9Inference3new17h0f6924d7d1865abcE"),
+            "mnist::inference::Inference::new"
+        );
+        assert_eq!(
+            demangle("_ZN42_$LT$f32$u20$as$u20$runtime..dlog..Arg$GT$6encode17hb0cf9c277d3947b9E"),
+            "<f32 as runtime::dlog::Arg>::encode"
+        );
+        assert_eq!(
+            demangle("_RNvNvNtCsa2TiqjX7nU2_5alloc3fmt6format12format_inner"),
+            "alloc::fmt::format::format_inner"
+        );
+        assert_eq!(demangle("memcpy"), "memcpy");
+    }
+
+    #[test]
+    fn test_encode() {
+        let functions = [
+            (0x100, String::from("main")),
+            (0x180, String::from("a::long::name")),
+        ];
+        let table = encode(&functions, 0x200, 4);
+        assert_eq!(table.len(), 8 + 8 * 3 + 5 + 5);
+        assert_eq!(&table[4..8], 3u32.to_le_bytes());
+        assert_eq!(&table[32..], b"\x04main\x04name");
+    }
+}
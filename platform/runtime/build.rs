@@ -3,7 +3,7 @@ use std::env;
 fn main() {
     // List all supported platform features
     // To add a new platform: just add the platform name to this array
-    let platforms = ["nemu", "qemu", "spike"];
+    let platforms = ["nemu", "qemu", "spike", "native"];
 
     // Check which platforms are enabled
     let enabled_platforms: Vec<&str> = platforms
@@ -28,6 +28,8 @@ fn main() {
         }
         1 => {
             println!("cargo:warning=Using platform: {}", enabled_platforms[0]);
+            // DEP_AM_RUNTIME_PLATFORM for bins, checked against ARCH
+            println!("cargo:platform={}", enabled_platforms[0]);
         }
         _ => {
             eprintln!("ERROR: Multiple platform features enabled!");
@@ -47,6 +49,8 @@ fn main() {
     // When adding a new platform, add a corresponding line here with the uppercase feature name
     println!("cargo:rerun-if-env-changed=CARGO_FEATURE_NEMU");
     println!("cargo:rerun-if-env-changed=CARGO_FEATURE_QEMU");
+    println!("cargo:rerun-if-env-changed=CARGO_FEATURE_SPIKE");
+    println!("cargo:rerun-if-env-changed=CARGO_FEATURE_NATIVE");
     // Example for future platforms:
     // println!("cargo:rerun-if-env-changed=CARGO_FEATURE_FPGA");
     // println!("cargo:rerun-if-env-changed=CARGO_FEATURE_NEXYS");
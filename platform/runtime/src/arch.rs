@@ -0,0 +1,66 @@
+//! ISA-level helpers shared by all platforms
+
+/// Read the cycle counter
+///
+/// Uses the `cycle` CSR on RISC-V and `rdtsc` on x86_64 host builds.
+///
+/// # Returns
+/// * Number of cycles since reset, or 0 on ISAs without a supported counter
+pub fn cycles() -> u64 {
+    #[cfg(target_arch = "riscv32")]
+    loop {
+        // Read the high half twice to detect a carry from the low half
+        let (hi, lo, hi_again): (u32, u32, u32);
+        unsafe {
+            core::arch::asm!(
+                "rdcycleh {0}",
+                "rdcycle {1}",
+                "rdcycleh {2}",
+                out(reg) hi,
+                out(reg) lo,
+                out(reg) hi_again,
+                options(nomem, nostack)
+            );
+        }
+        if hi == hi_again {
+            return ((hi as u64) << 32) | lo as u64;
+        }
+    }
+
+    #[cfg(target_arch = "riscv64")]
+    {
+        let cycles: u64;
+        unsafe { core::arch::asm!("rdcycle {}", out(reg) cycles, options(nomem, nostack)) };
+        cycles
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        core::arch::x86_64::_rdtsc()
+    }
+
+    #[cfg(not(any(
+        target_arch = "riscv32",
+        target_arch = "riscv64",
+        target_arch = "x86_64"
+    )))]
+    0
+}
+
+/// Read the id of the hart running this code
+///
+/// Reads `mhartid`, so it must be called in machine mode.
+///
+/// # Returns
+/// * The hart id, 0 on ISAs without one
+pub fn hart_id() -> usize {
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    {
+        let id: usize;
+        unsafe { core::arch::asm!("csrr {}, mhartid", out(reg) id, options(nomem, nostack)) };
+        id
+    }
+
+    #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+    0
+}
@@ -0,0 +1,10 @@
+//! Stack backtraces
+//!
+//! The panic handler prints a [`Backtrace`] after the panic message; on
+//! QEMU, an unexpected trap panics with a backtrace starting at the trapped
+//! instruction. Frames are found through frame pointers, and named from the
+//! symbol table the build scripts embed into the image after linking
+//! (`build_helper::embed_symbols`, run by `tools/embed-symbols`). Without
+//! it, only the addresses are printed.
+
+pub use common::{Backtrace, MAX_FRAMES, symbolize};
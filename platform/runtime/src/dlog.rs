@@ -0,0 +1,274 @@
+//! Deferred-formatting logging
+//!
+//! [`dprintln!`](crate::dprintln) takes a format string and arguments like
+//! `println!`, but with the `dlog` feature nothing is formatted on the
+//! target. The format string is interned into the `.am_fmt` section, which
+//! stays in the ELF but is not loaded, and only its offset there and the raw
+//! arguments go over the console. `tools/dlog-decode` turns the stream back
+//! into text using the ELF. Without the feature, `dprintln!` is `println!`.
+//!
+//! Frames start with [`FRAME_START`], which never occurs in UTF-8 text, so
+//! they mix freely with ordinary console output. A frame is:
+//! - `FRAME_START`
+//! - the format string offset, LEB128
+//! - each argument, a [`Tag`] byte followed by its value
+//!
+//! Only `{}` and `{:spec}` placeholders are supported, and each argument
+//! must implement [`Arg`].
+
+use crate::io::{flush_stdout, putc};
+
+/// First byte of every frame
+pub const FRAME_START: u8 = 0xff;
+
+/// Kind of an encoded argument, followed by its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Tag {
+    /// LEB128
+    Unsigned = 0,
+    /// Zigzag LEB128
+    Signed = 1,
+    /// 4 bytes, little-endian
+    F32 = 2,
+    /// 8 bytes, little-endian
+    F64 = 3,
+    /// 1 byte, 0 or 1
+    Bool = 4,
+    /// The code point, LEB128
+    Char = 5,
+    /// The length in bytes, LEB128, then UTF-8
+    Str = 6,
+}
+
+/// Writes the bytes of a frame
+pub struct Encoder<'a> {
+    out: &'a mut dyn FnMut(u8),
+}
+
+impl Encoder<'_> {
+    fn byte(&mut self, byte: u8) {
+        (self.out)(byte);
+    }
+
+    fn leb128(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                return self.byte(byte);
+            }
+            self.byte(byte | 0x80);
+        }
+    }
+
+    fn tagged(&mut self, tag: Tag) {
+        self.byte(tag as u8);
+    }
+}
+
+/// A value [`dprintln!`](crate::dprintln) can send
+///
+/// Implement it for a wrapper type by encoding one of the primitive types.
+pub trait Arg {
+    fn encode(&self, out: &mut Encoder);
+}
+
+impl<T: Arg + ?Sized> Arg for &T {
+    fn encode(&self, out: &mut Encoder) {
+        (**self).encode(out);
+    }
+}
+
+macro_rules! unsigned {
+    ($($ty:ty),*) => {$(
+        impl Arg for $ty {
+            fn encode(&self, out: &mut Encoder) {
+                out.tagged(Tag::Unsigned);
+                out.leb128(*self as u64);
+            }
+        }
+    )*};
+}
+
+macro_rules! signed {
+    ($($ty:ty),*) => {$(
+        impl Arg for $ty {
+            fn encode(&self, out: &mut Encoder) {
+                let value = *self as i64;
+                out.tagged(Tag::Signed);
+                out.leb128(((value << 1) ^ (value >> 63)) as u64);
+            }
+        }
+    )*};
+}
+
+unsigned!(u8, u16, u32, u64, usize);
+signed!(i8, i16, i32, i64, isize);
+
+impl Arg for f32 {
+    fn encode(&self, out: &mut Encoder) {
+        out.tagged(Tag::F32);
+        self.to_le_bytes()
+            .into_iter()
+            .for_each(|byte| out.byte(byte));
+    }
+}
+
+impl Arg for f64 {
+    fn encode(&self, out: &mut Encoder) {
+        out.tagged(Tag::F64);
+        self.to_le_bytes()
+            .into_iter()
+            .for_each(|byte| out.byte(byte));
+    }
+}
+
+impl Arg for bool {
+    fn encode(&self, out: &mut Encoder) {
+        out.tagged(Tag::Bool);
+        out.byte(*self as u8);
+    }
+}
+
+impl Arg for char {
+    fn encode(&self, out: &mut Encoder) {
+        out.tagged(Tag::Char);
+        out.leb128(*self as u64);
+    }
+}
+
+impl Arg for str {
+    fn encode(&self, out: &mut Encoder) {
+        out.tagged(Tag::Str);
+        out.leb128(self.len() as u64);
+        self.bytes().for_each(|byte| out.byte(byte));
+    }
+}
+
+fn encode_frame(index: usize, args: &[&dyn Arg], out: &mut dyn FnMut(u8)) {
+    let mut encoder = Encoder { out };
+    encoder.byte(FRAME_START);
+    encoder.leb128(index as u64);
+    for arg in args {
+        arg.encode(&mut encoder);
+    }
+}
+
+/// Send one frame, used by [`dprintln!`](crate::dprintln)
+///
+/// # Arguments
+/// * `index` - Offset of the format string in `.am_fmt`
+/// * `args` - The arguments, as many as the format string has placeholders
+#[doc(hidden)]
+pub fn write(index: usize, args: &[&dyn Arg]) {
+    critical_section::with(|_| {
+        flush_stdout();
+        encode_frame(index, args, &mut putc);
+    });
+}
+
+/// The format string as stored in `.am_fmt`, NUL-terminated
+#[doc(hidden)]
+pub const fn intern<const N: usize>(fmt: &str) -> [u8; N] {
+    let bytes = fmt.as_bytes();
+    let mut out = [0; N];
+    let mut i = 0;
+    while i < bytes.len() {
+        out[i] = bytes[i];
+        i += 1;
+    }
+    out
+}
+
+/// Number of placeholders in a format string, `{{` being an escaped brace
+#[doc(hidden)]
+pub const fn placeholders(fmt: &str) -> usize {
+    let bytes = fmt.as_bytes();
+    let (mut count, mut i) = (0, 0);
+    while i < bytes.len() {
+        if bytes[i] == b'{' {
+            if i + 1 < bytes.len() && bytes[i + 1] == b'{' {
+                i += 1;
+            } else {
+                count += 1;
+            }
+        }
+        i += 1;
+    }
+    count
+}
+
+/// Counts one macro argument
+#[doc(hidden)]
+pub const fn one(_: &str) -> usize {
+    1
+}
+
+/// Print a line with deferred formatting, see [`dlog`](crate::dlog)
+#[cfg(feature = "dlog")]
+#[macro_export]
+macro_rules! dprintln {
+    ($fmt:literal $(, $arg:expr)* $(,)?) => {{
+        const _: () = assert!(
+            $crate::dlog::placeholders($fmt) == 0 $(+ $crate::dlog::one(stringify!($arg)))*,
+            "dprintln!: placeholder and argument counts differ"
+        );
+        #[unsafe(link_section = ".am_fmt")]
+        #[used]
+        static FMT: [u8; $fmt.len() + 1] = $crate::dlog::intern($fmt);
+        $crate::dlog::write(
+            core::ptr::addr_of!(FMT) as usize,
+            &[$(&$arg as &dyn $crate::dlog::Arg),*],
+        );
+    }};
+}
+
+/// Print a line with deferred formatting, see [`dlog`](crate::dlog)
+#[cfg(not(feature = "dlog"))]
+#[macro_export]
+macro_rules! dprintln {
+    ($fmt:literal $(, $arg:expr)* $(,)?) => {{
+        const _: () = assert!(
+            $crate::dlog::placeholders($fmt) == 0 $(+ $crate::dlog::one(stringify!($arg)))*,
+            "dprintln!: placeholder and argument counts differ"
+        );
+        $crate::println!($fmt $(, $arg)*);
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_encode_frame() {
+        let mut out = Vec::new();
+        encode_frame(
+            300,
+            &[&5u8, &-2i32, &1.5f32, &true, &'é', &"hi"],
+            &mut |byte| out.push(byte),
+        );
+        assert_eq!(
+            out,
+            [
+                0xff, 0xac, 0x02, // index
+                0, 5, // 5u8
+                1, 3, // -2 zigzagged
+                2, 0x00, 0x00, 0xc0, 0x3f, // 1.5f32
+                4, 1, // true
+                5, 0xe9, 0x01, // 'é'
+                6, 2, b'h', b'i', // "hi"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(placeholders("no arguments"), 0);
+        assert_eq!(placeholders("{} and {:08x}"), 2);
+        assert_eq!(placeholders("{{literal}} {}"), 1);
+        assert_eq!(intern::<4>("abc"), *b"abc\0");
+    }
+}
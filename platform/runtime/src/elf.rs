@@ -0,0 +1,769 @@
+//! ELF program loading
+//!
+//! [`Elf::parse`] reads little-endian RISC-V ELF32 and ELF64 images, and
+//! [`Program::exec`] replaces the running program with one: the PT_LOAD
+//! segments are copied to their physical addresses (segments the file
+//! already holds in place are left where they are), the rest of each
+//! segment is zeroed, and the entry point is called on a fresh stack holding
+//! `argc` and `argv`, in M-mode or U-mode.
+//!
+//! Every bin of this workspace links at the start of RAM, so loading one
+//! usually overwrites the running program. The final copy is therefore done
+//! by a small position-independent trampoline that runs from the stack,
+//! which no segment may overlap, and ends with `fence.i` so that the new
+//! code is fetched. The loaded program finds its arguments through
+//! [`crate::env::args`].
+//!
+//! The new stack starts at the top of RAM, as for any other start:
+//!
+//! | Address          | Content                              |
+//! |------------------|--------------------------------------|
+//! | `sp`             | `argc`                               |
+//! | `sp + 4`         | `argv[0]` .. `argv[argc - 1]`, null  |
+//! | after `argv`     | null (empty environment)             |
+//! | up to stack top  | the NUL-terminated argument strings  |
+//!
+//! with `argc` in `a0`, `argv` in `a1` and [`common::ARGS_MAGIC`] in `a2`.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const EM_RISCV: u16 = 243;
+const PT_LOAD: u32 = 1;
+
+/// Segment flag: executable
+pub const PF_X: u32 = 1;
+/// Segment flag: writable
+pub const PF_W: u32 = 2;
+/// Segment flag: readable
+pub const PF_R: u32 = 4;
+
+/// Upper bound on loadable segments, the segment table lives on the stack
+const MAX_SEGMENTS: usize = 16;
+
+/// Room reserved on the stack for the trampoline code, in words
+#[cfg(target_arch = "riscv32")]
+const TRAMPOLINE_WORDS: usize = 64;
+
+/// Largest argument block, strings and pointers included
+const MAX_ARGS_SIZE: usize = 4096;
+
+/// Errors from parsing or loading an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The image has no ELF magic
+    NotElf,
+    /// A header field has a value this loader does not handle
+    Unsupported(&'static str),
+    /// A header or segment lies outside of the image
+    Truncated,
+    /// The image has no loadable segments
+    NoSegments,
+    /// The image has more than 16 loadable segments
+    TooManySegments,
+    /// A segment does not fit in RAM below the stack
+    OutOfMemory,
+    /// The segments cannot be copied without overwriting each other
+    Overlap,
+    /// The arguments take more than 4 KiB
+    ArgsTooLong,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotElf => write!(f, "not an ELF image"),
+            Error::Unsupported(what) => write!(f, "unsupported image: {}", what),
+            Error::Truncated => write!(f, "truncated image"),
+            Error::NoSegments => write!(f, "no loadable segments"),
+            Error::TooManySegments => write!(f, "too many loadable segments"),
+            Error::OutOfMemory => write!(f, "segment outside of usable RAM"),
+            Error::Overlap => write!(f, "segments overlap the staged image"),
+            Error::ArgsTooLong => write!(f, "arguments too long"),
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Width of an ELF image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    Elf32,
+    Elf64,
+}
+
+/// A PT_LOAD program header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramHeader {
+    /// Offset of the segment data in the file
+    pub offset: u64,
+    pub vaddr: u64,
+    pub paddr: u64,
+    /// Bytes taken from the file
+    pub filesz: u64,
+    /// Bytes in memory, the rest after `filesz` is zeroed
+    pub memsz: u64,
+    /// `PF_R`, `PF_W` and `PF_X` bits
+    pub flags: u32,
+}
+
+/// A parsed ELF image
+#[derive(Debug, Clone)]
+pub struct Elf<'a> {
+    data: &'a [u8],
+    class: Class,
+    entry: u64,
+    segments: Vec<ProgramHeader>,
+}
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N]> {
+    let end = offset.checked_add(N).ok_or(Error::Truncated)?;
+    let bytes = data.get(offset..end).ok_or(Error::Truncated)?;
+    Ok(bytes.try_into().unwrap())
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    read(data, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    read(data, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64> {
+    read(data, offset).map(u64::from_le_bytes)
+}
+
+impl<'a> Elf<'a> {
+    /// Parse an ELF image
+    ///
+    /// # Arguments
+    /// * `data` - The whole file
+    ///
+    /// # Returns
+    /// * `Ok(elf)` - The entry point and the non-empty PT_LOAD segments
+    /// * `Err(error)` - The file is not a little-endian RISC-V ELF, or a
+    ///   header or segment lies outside of it
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        if !data.starts_with(ELF_MAGIC) {
+            return Err(Error::NotElf);
+        }
+        let class = match data.get(4) {
+            Some(&ELFCLASS32) => Class::Elf32,
+            Some(&ELFCLASS64) => Class::Elf64,
+            _ => return Err(Error::Unsupported("unknown ELF class")),
+        };
+        if data.get(5) != Some(&ELFDATA2LSB) {
+            return Err(Error::Unsupported("big-endian ELF"));
+        }
+        if read_u16(data, 18)? != EM_RISCV {
+            return Err(Error::Unsupported("not a RISC-V ELF"));
+        }
+
+        // Word-sized fields: entry, phoff, then phentsize and phnum
+        let word = |offset32: usize, offset64: usize| match class {
+            Class::Elf32 => read_u32(data, offset32).map(u64::from),
+            Class::Elf64 => read_u64(data, offset64),
+        };
+        let entry = word(24, 24)?;
+        let phoff = word(28, 32)?;
+        let (phentsize, phnum) = match class {
+            Class::Elf32 => (read_u16(data, 42)?, read_u16(data, 44)?),
+            Class::Elf64 => (read_u16(data, 54)?, read_u16(data, 56)?),
+        };
+
+        let mut segments = Vec::new();
+        for i in 0..phnum as u64 {
+            let ph = usize::try_from(phoff + i * phentsize as u64).map_err(|_| Error::Truncated)?;
+            if read_u32(data, ph)? != PT_LOAD {
+                continue;
+            }
+            let header = match class {
+                Class::Elf32 => ProgramHeader {
+                    offset: read_u32(data, ph + 4)?.into(),
+                    vaddr: read_u32(data, ph + 8)?.into(),
+                    paddr: read_u32(data, ph + 12)?.into(),
+                    filesz: read_u32(data, ph + 16)?.into(),
+                    memsz: read_u32(data, ph + 20)?.into(),
+                    flags: read_u32(data, ph + 24)?,
+                },
+                Class::Elf64 => ProgramHeader {
+                    flags: read_u32(data, ph + 4)?,
+                    offset: read_u64(data, ph + 8)?,
+                    vaddr: read_u64(data, ph + 16)?,
+                    paddr: read_u64(data, ph + 24)?,
+                    filesz: read_u64(data, ph + 32)?,
+                    memsz: read_u64(data, ph + 40)?,
+                },
+            };
+            if header.memsz == 0 {
+                continue;
+            }
+            let in_file = header
+                .offset
+                .checked_add(header.filesz)
+                .is_some_and(|end| end <= data.len() as u64);
+            if !in_file || header.filesz > header.memsz {
+                return Err(Error::Truncated);
+            }
+            segments.push(header);
+        }
+
+        Ok(Self {
+            data,
+            class,
+            entry,
+            segments,
+        })
+    }
+
+    pub fn class(&self) -> Class {
+        self.class
+    }
+
+    pub fn entry(&self) -> u64 {
+        self.entry
+    }
+
+    /// The non-empty PT_LOAD segments, in file order
+    pub fn segments(&self) -> &[ProgramHeader] {
+        &self.segments
+    }
+
+    /// The file contents of a segment
+    pub fn segment_data(&self, header: &ProgramHeader) -> &'a [u8] {
+        &self.data[header.offset as usize..(header.offset + header.filesz) as usize]
+    }
+
+    /// Stage the image for [`Program::exec`]
+    ///
+    /// Segments are loaded at their physical addresses.
+    ///
+    /// # Returns
+    /// * `Err(Error::Unsupported)` - The class does not match the running
+    ///   hart, or an address does not fit in a pointer
+    pub fn program(&self) -> Result<Program> {
+        let native = match usize::BITS {
+            32 => Class::Elf32,
+            _ => Class::Elf64,
+        };
+        if self.class != native {
+            return Err(Error::Unsupported("ELF class does not match this hart"));
+        }
+        let address = |value: u64| {
+            usize::try_from(value).map_err(|_| Error::Unsupported("address too large"))
+        };
+
+        let mut segments = Vec::new();
+        for header in &self.segments {
+            segments.push(Segment {
+                dst: address(header.paddr)?,
+                src: self.segment_data(header).as_ptr() as usize,
+                filesz: address(header.filesz)?,
+                memsz: address(header.memsz)?,
+            });
+        }
+
+        Program::new(address(self.entry)?, segments)
+    }
+}
+
+/// One region to copy, as consumed by the trampoline
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Segment {
+    dst: usize,
+    src: usize,
+    filesz: usize,
+    memsz: usize,
+}
+
+impl Segment {
+    #[cfg(target_arch = "riscv32")]
+    const EMPTY: Segment = Segment {
+        dst: 0,
+        src: 0,
+        filesz: 0,
+        memsz: 0,
+    };
+}
+
+fn overlaps(a_start: usize, a_len: usize, b_start: usize, b_len: usize) -> bool {
+    a_len != 0 && b_len != 0 && a_start < b_start + b_len && b_start < a_start + a_len
+}
+
+/// Order the copies so that none overwrites staged data that a later copy
+/// still reads
+fn order(segments: &mut [Segment]) -> Result<()> {
+    let safe = |segments: &[Segment]| {
+        segments.iter().enumerate().all(|(i, seg)| {
+            segments[i + 1..].iter().all(|later| {
+                later.dst == later.src || !overlaps(seg.dst, seg.memsz, later.src, later.filesz)
+            })
+        })
+    };
+
+    segments.sort_by_key(|seg| seg.dst);
+    if safe(segments) {
+        return Ok(());
+    }
+    segments.reverse();
+    if safe(segments) {
+        return Ok(());
+    }
+    Err(Error::Overlap)
+}
+
+/// Privilege mode to enter a program in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Machine,
+    /// U-mode, with all memory accessible through PMP entry 0
+    ///
+    /// The program must not touch M-mode CSRs. Bins of this workspace do
+    /// during startup, so they are entered in [`Mode::Machine`].
+    User,
+}
+
+/// A staged image, ready to replace the running program
+///
+/// The memory the image was staged in must stay alive until
+/// [`Program::exec`].
+#[derive(Debug, Clone)]
+pub struct Program {
+    entry: usize,
+    segments: Vec<Segment>,
+}
+
+impl Program {
+    fn new(entry: usize, mut segments: Vec<Segment>) -> Result<Self> {
+        if segments.is_empty() {
+            return Err(Error::NoSegments);
+        }
+        if segments.len() > MAX_SEGMENTS {
+            return Err(Error::TooManySegments);
+        }
+
+        let stack_bottom = stack_bottom();
+        for seg in &segments {
+            let end = seg.dst.checked_add(seg.memsz);
+            if seg.dst == 0 || end.is_none_or(|end| end > stack_bottom) {
+                return Err(Error::OutOfMemory);
+            }
+        }
+
+        order(&mut segments)?;
+        Ok(Self { entry, segments })
+    }
+
+    /// Stage a raw binary, loaded and entered at `addr`
+    pub fn raw(image: &[u8], addr: usize) -> Result<Self> {
+        let segment = Segment {
+            dst: addr,
+            src: image.as_ptr() as usize,
+            filesz: image.len(),
+            memsz: image.len(),
+        };
+        Self::new(addr, alloc::vec![segment])
+    }
+
+    pub fn entry(&self) -> usize {
+        self.entry
+    }
+
+    /// Total number of bytes occupied at the load addresses
+    pub fn load_size(&self) -> usize {
+        self.segments.iter().map(|seg| seg.memsz).sum()
+    }
+
+    /// Replace the running program
+    ///
+    /// Stdout is flushed and interrupts are disabled first. Device drivers
+    /// must have been shut down beforehand, so nothing DMAs into memory that
+    /// is about to be overwritten.
+    ///
+    /// # Arguments
+    /// * `args` - Arguments for the program, by convention starting with its
+    ///   name
+    /// * `mode` - Privilege mode to enter the program in
+    ///
+    /// # Returns
+    /// Only on failure, with the reason
+    pub fn exec(&self, args: &[&str], mode: Mode) -> Error {
+        crate::io::flush_stdout();
+        let argc = args.len();
+        let (block, sp) = match arg_block(stack_top(), args) {
+            Ok(block) => block,
+            Err(err) => return err,
+        };
+        let mut copies = Vec::with_capacity(self.segments.len() + 1);
+        // The arguments go first: they sit above the trampoline, which
+        // checks that there is room
+        copies.push(Segment {
+            dst: sp,
+            src: block.as_ptr() as usize,
+            filesz: block.len(),
+            memsz: block.len(),
+        });
+        copies.extend_from_slice(&self.segments);
+
+        enter(&copies, self.entry, sp, argc, mode)
+    }
+}
+
+/// Lay out the argument block for a stack ending at `top`
+///
+/// # Returns
+/// * `Ok((block, sp))` - The block, with pointers already relocated to its
+///   final address `sp`, which is 16-byte aligned
+fn arg_block(top: usize, args: &[&str]) -> Result<(Vec<u8>, usize)> {
+    const WORD: usize = core::mem::size_of::<usize>();
+
+    let pointers = (args.len() + 3) * WORD;
+    let strings: usize = args.iter().map(|arg| arg.len() + 1).sum();
+    let size = (pointers + strings).next_multiple_of(16);
+    if size > MAX_ARGS_SIZE || args.iter().any(|arg| arg.contains('\0')) {
+        return Err(Error::ArgsTooLong);
+    }
+    let sp = (top - size) & !15;
+    let size = top - sp;
+
+    let mut block = alloc::vec![0u8; size];
+    let mut put = |index: usize, value: usize| {
+        block[index * WORD..(index + 1) * WORD].copy_from_slice(&value.to_ne_bytes());
+    };
+    put(0, args.len());
+    let mut string = pointers;
+    for (i, arg) in args.iter().enumerate() {
+        put(1 + i, sp + string);
+        string += arg.len() + 1;
+    }
+    // argv[argc] and the empty environment stay null
+
+    let mut string = pointers;
+    for arg in args {
+        block[string..string + arg.len()].copy_from_slice(arg.as_bytes());
+        string += arg.len() + 1;
+    }
+
+    Ok((block, sp))
+}
+
+#[cfg(target_arch = "riscv32")]
+fn stack_bottom() -> usize {
+    unsafe extern "C" {
+        static _sstack: u8;
+    }
+    &raw const _sstack as usize
+}
+
+#[cfg(target_arch = "riscv32")]
+fn stack_top() -> usize {
+    unsafe extern "C" {
+        static _stack_top: u8;
+    }
+    &raw const _stack_top as usize
+}
+
+#[cfg(not(target_arch = "riscv32"))]
+fn stack_bottom() -> usize {
+    usize::MAX
+}
+
+#[cfg(not(target_arch = "riscv32"))]
+fn stack_top() -> usize {
+    usize::MAX
+}
+
+// Copy every segment (memmove semantics, skipped when already in place),
+// zero the rest of each segment, then enter the program. Uses only registers
+// and PC-relative branches so it can run from any address.
+//
+// a0 = segment table, a1 = segment count, a2 = entry point, a3 = stack,
+// a4 = argc, a5 = argv, a6 = 1 for U-mode
+#[cfg(target_arch = "riscv32")]
+core::arch::global_asm!(
+    r#"
+    .section .text.elf_trampoline, "ax"
+    .balign 4
+    .global elf_trampoline_start
+    .global elf_trampoline_end
+elf_trampoline_start:
+1:  beqz    a1, 8f
+    lw      t0, 0(a0)
+    lw      t1, 4(a0)
+    lw      t2, 8(a0)
+    lw      t3, 12(a0)
+    add     t2, t0, t2
+    add     t3, t0, t3
+    beq     t0, t1, 5f
+    bgtu    t0, t1, 3f
+2:  beq     t0, t2, 5f
+    lbu     t4, 0(t1)
+    sb      t4, 0(t0)
+    addi    t0, t0, 1
+    addi    t1, t1, 1
+    j       2b
+3:  sub     t4, t2, t0
+    add     t1, t1, t4
+    mv      t5, t0
+    mv      t0, t2
+4:  beq     t0, t5, 5f
+    addi    t0, t0, -1
+    addi    t1, t1, -1
+    lbu     t4, 0(t1)
+    sb      t4, 0(t0)
+    j       4b
+5:  mv      t0, t2
+6:  beq     t0, t3, 7f
+    sb      zero, 0(t0)
+    addi    t0, t0, 1
+    j       6b
+7:  addi    a0, a0, 16
+    addi    a1, a1, -1
+    j       1b
+8:  fence
+    .word   0x0000100f
+    mv      t6, a2
+    mv      sp, a3
+    mv      a0, a4
+    mv      a1, a5
+    li      a2, {magic}
+    beqz    a6, 9f
+    li      t0, -1
+    csrw    pmpaddr0, t0
+    li      t0, 0x1f
+    csrw    pmpcfg0, t0
+    li      t0, 0x1800
+    csrc    mstatus, t0
+    csrw    mepc, t6
+    mret
+9:  jr      t6
+elf_trampoline_end:
+"#,
+    magic = const common::ARGS_MAGIC,
+);
+
+#[cfg(target_arch = "riscv32")]
+fn enter(copies: &[Segment], entry: usize, sp: usize, argc: usize, mode: Mode) -> Error {
+    unsafe extern "C" {
+        static elf_trampoline_start: u8;
+        static elf_trampoline_end: u8;
+    }
+    type Trampoline = extern "C" fn(*const Segment, usize, usize, usize, usize, usize, usize) -> !;
+
+    let mut table = [Segment::EMPTY; MAX_SEGMENTS + 1];
+    table[..copies.len()].copy_from_slice(copies);
+
+    let start = &raw const elf_trampoline_start;
+    let len = &raw const elf_trampoline_end as usize - start as usize;
+    let mut code = [0u32; TRAMPOLINE_WORDS];
+    assert!(len <= core::mem::size_of_val(&code));
+
+    // The argument block is copied first and must not reach the table or
+    // the code, which live in this frame
+    let frame_end = (table.as_ptr_range().end as usize).max(code.as_ptr_range().end as usize);
+    if sp < frame_end {
+        return Error::ArgsTooLong;
+    }
+
+    unsafe {
+        core::ptr::copy_nonoverlapping(start, code.as_mut_ptr() as *mut u8, len);
+        core::arch::asm!(
+            "csrw mie, zero",
+            "csrci mstatus, 8",
+            "fence",
+            // fence.i, so the copied trampoline is visible to instruction fetch
+            ".word 0x0000100f",
+        );
+
+        let trampoline: Trampoline = core::mem::transmute(code.as_ptr());
+        let argv = sp + core::mem::size_of::<usize>();
+        trampoline(
+            table.as_ptr(),
+            copies.len(),
+            entry,
+            sp,
+            argc,
+            argv,
+            (mode == Mode::User) as usize,
+        )
+    }
+}
+
+#[cfg(not(target_arch = "riscv32"))]
+fn enter(_copies: &[Segment], _entry: usize, _sp: usize, _argc: usize, _mode: Mode) -> Error {
+    Error::Unsupported("programs can only be run on the target")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build an ELF image with one PT_LOAD segment holding `payload`
+    fn image(class: Class, paddr: u64, payload: &[u8], memsz: u64) -> Vec<u8> {
+        let (ehsize, phentsize) = match class {
+            Class::Elf32 => (52, 32),
+            Class::Elf64 => (64, 56),
+        };
+        let offset = (ehsize + phentsize) as u64;
+        let mut data = alloc::vec![0u8; ehsize + phentsize];
+        data[..4].copy_from_slice(ELF_MAGIC);
+        data[4] = if class == Class::Elf32 {
+            ELFCLASS32
+        } else {
+            ELFCLASS64
+        };
+        data[5] = ELFDATA2LSB;
+        data[18..20].copy_from_slice(&EM_RISCV.to_le_bytes());
+
+        let ph = ehsize;
+        match class {
+            Class::Elf32 => {
+                data[24..28].copy_from_slice(&(paddr as u32 + 4).to_le_bytes());
+                data[28..32].copy_from_slice(&(ehsize as u32).to_le_bytes());
+                data[42..44].copy_from_slice(&(phentsize as u16).to_le_bytes());
+                data[44..46].copy_from_slice(&1u16.to_le_bytes());
+                let fields = [
+                    PT_LOAD,
+                    offset as u32,
+                    paddr as u32,
+                    paddr as u32,
+                    payload.len() as u32,
+                    memsz as u32,
+                    PF_R | PF_X,
+                ];
+                for (i, field) in fields.iter().enumerate() {
+                    data[ph + 4 * i..ph + 4 * i + 4].copy_from_slice(&field.to_le_bytes());
+                }
+            }
+            Class::Elf64 => {
+                data[24..32].copy_from_slice(&(paddr + 4).to_le_bytes());
+                data[32..40].copy_from_slice(&(ehsize as u64).to_le_bytes());
+                data[54..56].copy_from_slice(&(phentsize as u16).to_le_bytes());
+                data[56..58].copy_from_slice(&1u16.to_le_bytes());
+                data[ph..ph + 4].copy_from_slice(&PT_LOAD.to_le_bytes());
+                data[ph + 4..ph + 8].copy_from_slice(&(PF_R | PF_X).to_le_bytes());
+                let fields = [offset, paddr, paddr, payload.len() as u64, memsz];
+                for (i, field) in fields.iter().enumerate() {
+                    data[ph + 8 + 8 * i..ph + 16 + 8 * i].copy_from_slice(&field.to_le_bytes());
+                }
+            }
+        }
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test]
+    fn test_parse() {
+        for class in [Class::Elf32, Class::Elf64] {
+            let data = image(class, 0x8000_0000, b"code", 0x100);
+            let elf = Elf::parse(&data).unwrap();
+            assert_eq!(elf.class(), class);
+            assert_eq!(elf.entry(), 0x8000_0004);
+            assert_eq!(elf.segments().len(), 1);
+
+            let segment = elf.segments()[0];
+            assert_eq!(segment.paddr, 0x8000_0000);
+            assert_eq!(segment.memsz, 0x100);
+            assert_eq!(segment.flags, PF_R | PF_X);
+            assert_eq!(elf.segment_data(&segment), b"code");
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(Elf::parse(b"MZ").unwrap_err(), Error::NotElf);
+
+        let mut data = image(Class::Elf32, 0x8000_0000, b"code", 4);
+        data.truncate(data.len() - 1);
+        assert_eq!(Elf::parse(&data).unwrap_err(), Error::Truncated);
+
+        let mut data = image(Class::Elf32, 0x8000_0000, b"code", 4);
+        data[18] = 62;
+        assert!(matches!(Elf::parse(&data), Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_order() {
+        // Both segments are staged right where the other one is loaded from
+        let mut segments = alloc::vec![
+            Segment {
+                dst: 0x1000,
+                src: 0x2000,
+                filesz: 0x100,
+                memsz: 0x100
+            },
+            Segment {
+                dst: 0x2000,
+                src: 0x3000,
+                filesz: 0x100,
+                memsz: 0x100
+            },
+        ];
+        order(&mut segments).unwrap();
+        assert_eq!(segments[0].dst, 0x1000);
+
+        let mut segments = alloc::vec![
+            Segment {
+                dst: 0x2000,
+                src: 0x1000,
+                filesz: 0x100,
+                memsz: 0x100
+            },
+            Segment {
+                dst: 0x3000,
+                src: 0x2000,
+                filesz: 0x100,
+                memsz: 0x100
+            },
+        ];
+        order(&mut segments).unwrap();
+        assert_eq!(segments[0].dst, 0x3000);
+
+        let mut segments = alloc::vec![
+            Segment {
+                dst: 0x1000,
+                src: 0x2000,
+                filesz: 0x100,
+                memsz: 0x100
+            },
+            Segment {
+                dst: 0x2000,
+                src: 0x1000,
+                filesz: 0x100,
+                memsz: 0x100
+            },
+        ];
+        assert_eq!(order(&mut segments), Err(Error::Overlap));
+    }
+
+    #[test]
+    fn test_arg_block() {
+        const WORD: usize = core::mem::size_of::<usize>();
+        let top = 0x9000_0000;
+        let (block, sp) = arg_block(top, &["ls", "-l"]).unwrap();
+        assert_eq!(sp % 16, 0);
+        assert_eq!(sp + block.len(), top);
+
+        let word =
+            |index: usize| usize::from_ne_bytes(block[index * WORD..][..WORD].try_into().unwrap());
+        assert_eq!(word(0), 2);
+        assert_eq!(word(3), 0);
+        assert_eq!(word(4), 0);
+        let string = |pointer: usize| {
+            let start = pointer - sp;
+            let end = start + block[start..].iter().position(|&b| b == 0).unwrap();
+            core::str::from_utf8(&block[start..end]).unwrap()
+        };
+        assert_eq!(string(word(1)), "ls");
+        assert_eq!(string(word(2)), "-l");
+
+        assert_eq!(arg_block(top, &["a\0b"]), Err(Error::ArgsTooLong));
+    }
+}
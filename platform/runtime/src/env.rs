@@ -0,0 +1,49 @@
+//! Program arguments
+//!
+//! A program started by [`crate::elf`] gets the arguments its loader passed;
+//! one started directly by the platform has none.
+
+use core::ffi::CStr;
+
+/// Iterator over the program arguments, see [`args`]
+#[derive(Debug, Clone)]
+pub struct Args {
+    argv: *const *const u8,
+    index: usize,
+    count: usize,
+}
+
+/// The arguments the program was started with
+///
+/// By convention the first one is the program name. Arguments that are not
+/// valid UTF-8 are returned as empty strings.
+pub fn args() -> Args {
+    let (count, argv) = common::startup_args();
+
+    Args {
+        argv,
+        index: 0,
+        count: if argv.is_null() { 0 } else { count },
+    }
+}
+
+impl Iterator for Args {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<&'static str> {
+        if self.index == self.count {
+            return None;
+        }
+        // The strings live at the top of the stack, above every frame
+        let arg = unsafe { CStr::from_ptr(*self.argv.add(self.index) as *const core::ffi::c_char) };
+        self.index += 1;
+        Some(arg.to_str().unwrap_or(""))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.count - self.index;
+        (left, Some(left))
+    }
+}
+
+impl ExactSizeIterator for Args {}
@@ -0,0 +1,418 @@
+//! FAT directory entries and file names
+//!
+//! Every file has a short (8.3) entry. Names that do not fit 8.3 in upper
+//! case are also stored as a chain of long file name (LFN) entries, placed
+//! right before the short entry, last part first.
+
+use crate::time::{DateTime, SystemTime};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Size of one directory entry
+pub(super) const ENTRY_SIZE: usize = 32;
+
+pub(super) const ATTR_READ_ONLY: u8 = 0x01;
+pub(super) const ATTR_VOLUME_ID: u8 = 0x08;
+pub(super) const ATTR_DIRECTORY: u8 = 0x10;
+pub(super) const ATTR_ARCHIVE: u8 = 0x20;
+/// Attribute combination marking an LFN entry
+pub(super) const ATTR_LONG_NAME: u8 = 0x0f;
+
+/// First name byte of a deleted entry
+pub(super) const MARK_FREE: u8 = 0xe5;
+/// First name byte of the entry after the last one in use
+pub(super) const MARK_END: u8 = 0x00;
+
+/// Set in the sequence number of the LFN entry holding the end of the name
+const LAST_LONG_ENTRY: u8 = 0x40;
+/// UCS-2 characters held by one LFN entry
+const CHARS_PER_LFN: usize = 13;
+/// Byte offsets of the characters within an LFN entry
+const LFN_CHAR_OFFSETS: [usize; CHARS_PER_LFN] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+/// Longest long name
+const MAX_NAME_LEN: usize = 255;
+
+/// `NTRes` flags: base name and extension stored in lower case
+const NT_LOWER_BASE: u8 = 0x08;
+const NT_LOWER_EXT: u8 = 0x10;
+
+/// Characters never allowed in names
+const INVALID_CHARS: &str = "\"*/:<>?\\|";
+/// Characters allowed in long names but not in short names
+const LONG_ONLY_CHARS: &str = "+,.;=[] ";
+
+fn le16(raw: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([raw[offset], raw[offset + 1]])
+}
+
+fn le32(raw: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        raw[offset],
+        raw[offset + 1],
+        raw[offset + 2],
+        raw[offset + 3],
+    ])
+}
+
+/// A short (8.3) directory entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct ShortEntry {
+    /// Base name and extension, space padded
+    pub name: [u8; 11],
+    pub attr: u8,
+    pub nt_res: u8,
+    pub create_time: u16,
+    pub create_date: u16,
+    pub first_cluster: u32,
+    pub write_time: u16,
+    pub write_date: u16,
+    pub size: u32,
+}
+
+impl ShortEntry {
+    /// A fresh entry stamped with the current time
+    pub fn new(name: [u8; 11], attr: u8) -> Self {
+        let (date, time) = timestamp(SystemTime::now());
+        Self {
+            name,
+            attr,
+            nt_res: 0,
+            create_time: time,
+            create_date: date,
+            first_cluster: 0,
+            write_time: time,
+            write_date: date,
+            size: 0,
+        }
+    }
+
+    pub fn parse(raw: &[u8]) -> Self {
+        let mut name = [0u8; 11];
+        name.copy_from_slice(&raw[..11]);
+        Self {
+            name,
+            attr: raw[11],
+            nt_res: raw[12],
+            create_time: le16(raw, 14),
+            create_date: le16(raw, 16),
+            first_cluster: (le16(raw, 20) as u32) << 16 | le16(raw, 26) as u32,
+            write_time: le16(raw, 22),
+            write_date: le16(raw, 24),
+            size: le32(raw, 28),
+        }
+    }
+
+    pub fn encode(&self, raw: &mut [u8]) {
+        raw[..ENTRY_SIZE].fill(0);
+        raw[..11].copy_from_slice(&self.name);
+        raw[11] = self.attr;
+        raw[12] = self.nt_res;
+        raw[14..16].copy_from_slice(&self.create_time.to_le_bytes());
+        raw[16..18].copy_from_slice(&self.create_date.to_le_bytes());
+        raw[18..20].copy_from_slice(&self.write_date.to_le_bytes());
+        raw[20..22].copy_from_slice(&((self.first_cluster >> 16) as u16).to_le_bytes());
+        raw[22..24].copy_from_slice(&self.write_time.to_le_bytes());
+        raw[24..26].copy_from_slice(&self.write_date.to_le_bytes());
+        raw[26..28].copy_from_slice(&(self.first_cluster as u16).to_le_bytes());
+        raw[28..32].copy_from_slice(&self.size.to_le_bytes());
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+
+    /// Stamp the entry as modified now
+    pub fn touch(&mut self) {
+        let (date, time) = timestamp(SystemTime::now());
+        self.write_date = date;
+        self.write_time = time;
+        self.attr |= ATTR_ARCHIVE;
+    }
+
+    /// The name as shown when there is no long name, e.g. `README.TXT`
+    pub fn display_name(&self) -> String {
+        let lower = |bytes: &[u8], flag: u8| -> String {
+            let text = String::from_utf8_lossy(bytes);
+            let text = text.trim_end_matches(' ');
+            if self.nt_res & flag != 0 {
+                text.to_ascii_lowercase()
+            } else {
+                String::from(text)
+            }
+        };
+
+        let mut base = self.name;
+        if base[0] == 0x05 {
+            base[0] = MARK_FREE;
+        }
+        let mut name = lower(&base[..8], NT_LOWER_BASE);
+        let ext = lower(&base[8..], NT_LOWER_EXT);
+        if !ext.is_empty() {
+            name.push('.');
+            name.push_str(&ext);
+        }
+        name
+    }
+}
+
+/// Checksum of a short name, stored in its LFN entries
+pub(super) fn lfn_checksum(name: &[u8; 11]) -> u8 {
+    name.iter()
+        .fold(0u8, |sum, &b| sum.rotate_right(1).wrapping_add(b))
+}
+
+/// Collects the LFN entries preceding a short entry
+#[derive(Default)]
+pub(super) struct LongName {
+    /// Parts indexed by sequence number - 1
+    units: Vec<u16>,
+    checksum: u8,
+    /// Sequence number expected next, 0 when not collecting
+    next_ord: u8,
+    /// Number of LFN entries collected
+    count: u32,
+}
+
+impl LongName {
+    pub fn reset(&mut self) {
+        self.next_ord = 0;
+        self.count = 0;
+    }
+
+    /// Feed an LFN entry
+    pub fn push(&mut self, raw: &[u8]) {
+        let ord = raw[0] & !LAST_LONG_ENTRY;
+        if raw[0] & LAST_LONG_ENTRY != 0 {
+            if ord == 0 || ord as usize * CHARS_PER_LFN > MAX_NAME_LEN + CHARS_PER_LFN {
+                self.reset();
+                return;
+            }
+            self.units.clear();
+            self.units.resize(ord as usize * CHARS_PER_LFN, 0xffff);
+            self.checksum = raw[13];
+            self.count = 0;
+        } else if ord == 0 || ord != self.next_ord || raw[13] != self.checksum {
+            self.reset();
+            return;
+        }
+
+        let start = (ord as usize - 1) * CHARS_PER_LFN;
+        for (i, &offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+            self.units[start + i] = le16(raw, offset);
+        }
+        self.next_ord = ord - 1;
+        self.count += 1;
+    }
+
+    /// Finish with the short entry that follows the LFN entries
+    ///
+    /// # Returns
+    /// * `Some((name, count))` - The long name and its number of LFN entries
+    /// * `None` - No complete long name belongs to this short entry
+    pub fn finish(&mut self, short: &ShortEntry) -> Option<(String, u32)> {
+        let complete =
+            self.count > 0 && self.next_ord == 0 && self.checksum == lfn_checksum(&short.name);
+        let count = self.count;
+        self.reset();
+        if !complete {
+            return None;
+        }
+
+        let end = self
+            .units
+            .iter()
+            .position(|&u| u == 0 || u == 0xffff)
+            .unwrap_or(self.units.len());
+        let name = char::decode_utf16(self.units[..end].iter().copied())
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect();
+        Some((name, count))
+    }
+}
+
+/// Encode a long name as LFN entries, in on-disk order
+pub(super) fn encode_long_name(name: &str, checksum: u8) -> Vec<[u8; ENTRY_SIZE]> {
+    let mut units: Vec<u16> = name.encode_utf16().collect();
+    let count = units.len().div_ceil(CHARS_PER_LFN);
+    if units.len() < count * CHARS_PER_LFN {
+        units.push(0);
+        units.resize(count * CHARS_PER_LFN, 0xffff);
+    }
+
+    (0..count)
+        .rev()
+        .map(|part| {
+            let mut raw = [0u8; ENTRY_SIZE];
+            raw[0] = part as u8 + 1;
+            if part + 1 == count {
+                raw[0] |= LAST_LONG_ENTRY;
+            }
+            raw[11] = ATTR_LONG_NAME;
+            raw[13] = checksum;
+            for (i, &offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+                let unit = units[part * CHARS_PER_LFN + i];
+                raw[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+            }
+            raw
+        })
+        .collect()
+}
+
+/// Whether a name can be stored at all
+pub(super) fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && name.encode_utf16().count() <= MAX_NAME_LEN
+        && !name.ends_with(['.', ' '])
+        && !name
+            .chars()
+            .any(|c| (c as u32) < 0x20 || INVALID_CHARS.contains(c))
+}
+
+fn is_short_char(c: char) -> bool {
+    c.is_ascii_graphic() && !INVALID_CHARS.contains(c) && !LONG_ONLY_CHARS.contains(c)
+}
+
+/// The short entry name, if the name fits 8.3 exactly in upper case
+pub(super) fn exact_short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, ext) = name.split_once('.').unwrap_or((name, ""));
+    let fits = |part: &str, max: usize| {
+        part.len() <= max
+            && part
+                .chars()
+                .all(|c| is_short_char(c) && !c.is_ascii_lowercase())
+    };
+    if base.is_empty() || !fits(base, 8) || !fits(ext, 3) {
+        return None;
+    }
+
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.as_bytes());
+    short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+    Some(short)
+}
+
+/// A short name for a long name, with a numeric tail (`LONGNA~1.TXT`)
+///
+/// # Arguments
+/// * `name` - The long name
+/// * `n` - Number for the tail, from 1
+pub(super) fn numbered_short_name(name: &str, n: u32) -> [u8; 11] {
+    let convert = |part: &str| -> Vec<u8> {
+        part.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| {
+                if is_short_char(c) {
+                    c.to_ascii_uppercase() as u8
+                } else {
+                    b'_'
+                }
+            })
+            .collect()
+    };
+
+    let trimmed = name.trim_start_matches('.');
+    let (base, ext) = match trimmed.rsplit_once('.') {
+        Some((base, ext)) => (convert(base), convert(ext)),
+        None => (convert(trimmed), Vec::new()),
+    };
+
+    let tail = alloc::format!("~{}", n);
+    let base_len = base.len().min(8 - tail.len()).max(1);
+    let mut short = [b' '; 11];
+    if base.is_empty() {
+        short[0] = b'_';
+    } else {
+        short[..base_len].copy_from_slice(&base[..base_len]);
+    }
+    short[base_len..base_len + tail.len()].copy_from_slice(tail.as_bytes());
+    let ext_len = ext.len().min(3);
+    short[8..8 + ext_len].copy_from_slice(&ext[..ext_len]);
+    short
+}
+
+/// FAT date and time for a point in time, clamped to 1980-2107
+pub(super) fn timestamp(time: SystemTime) -> (u16, u16) {
+    let dt = time.to_datetime();
+    if dt.year < 1980 {
+        return ((1 << 5) | 1, 0);
+    }
+    let year = (dt.year - 1980).min(127) as u16;
+    let date = year << 9 | (dt.month as u16) << 5 | dt.day as u16;
+    let time = (dt.hour as u16) << 11 | (dt.minute as u16) << 5 | (dt.second as u16 / 2);
+    (date, time)
+}
+
+/// Seconds since the UNIX epoch for a FAT date and time
+pub(super) fn unix_secs(date: u16, time: u16) -> u64 {
+    let dt = DateTime {
+        year: 1980 + (date >> 9) as u32,
+        month: (date >> 5 & 0xf) as u8,
+        day: (date & 0x1f) as u8,
+        hour: (time >> 11) as u8,
+        minute: (time >> 5 & 0x3f) as u8,
+        second: (time & 0x1f) as u8 * 2,
+        nanosecond: 0,
+    };
+    dt.to_unix_nanos().map_or(0, |nanos| nanos / 1_000_000_000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_names() {
+        assert_eq!(exact_short_name("README.TXT"), Some(*b"README  TXT"));
+        assert_eq!(exact_short_name("readme.txt"), None);
+        assert_eq!(exact_short_name("A.TAR.GZ"), None);
+        assert_eq!(numbered_short_name("results.json", 1), *b"RESULT~1JSO");
+        assert_eq!(numbered_short_name(".config", 2), *b"CONFIG~2   ");
+        assert_eq!(numbered_short_name("a b+c.txt", 1), *b"AB_C~1  TXT");
+    }
+
+    #[test]
+    fn test_long_name_roundtrip() {
+        let short = numbered_short_name("A long file name.txt", 1);
+        let checksum = lfn_checksum(&short);
+        let entries = encode_long_name("A long file name.txt", checksum);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0][0], 0x42);
+        assert_eq!(entries[1][0], 0x01);
+
+        let mut long = LongName::default();
+        for raw in &entries {
+            long.push(raw);
+        }
+        let entry = ShortEntry::new(short, 0);
+        assert_eq!(
+            long.finish(&entry),
+            Some((String::from("A long file name.txt"), 2))
+        );
+    }
+
+    #[test]
+    fn test_checksum() {
+        // Reference value computed with the algorithm from the FAT specification
+        assert_eq!(lfn_checksum(b"FOO     BAR"), 0x53);
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let time = DateTime {
+            year: 2024,
+            month: 2,
+            day: 29,
+            hour: 13,
+            minute: 5,
+            second: 9,
+            nanosecond: 0,
+        };
+        let (date, fat_time) = timestamp(time.to_system_time().unwrap());
+        assert_eq!(
+            unix_secs(date, fat_time),
+            time.to_unix_nanos().unwrap() / 1_000_000_000 - 1
+        );
+    }
+}
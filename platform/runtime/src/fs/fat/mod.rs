@@ -0,0 +1,1210 @@
+//! FAT16 and FAT32 volumes on block devices
+//!
+//! A volume is either the whole device (a "superfloppy", as written by
+//! `mkfs.fat` on an image file) or the first FAT partition of an MBR
+//! partition table. Sectors must be 512 bytes; FAT12 is not supported.
+//!
+//! Every change is written through to the device immediately, so a volume is
+//! consistent whenever no call is in progress. Only the FSInfo free cluster
+//! count of FAT32 is left stale, marked unknown as the specification allows.
+
+use super::{Error, Result};
+use crate::time::SystemTime;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use common::{BLOCK_SIZE, BlockDevice};
+
+mod dir;
+use dir::{
+    ATTR_DIRECTORY, ATTR_LONG_NAME, ATTR_READ_ONLY, ATTR_VOLUME_ID, ENTRY_SIZE, LongName, MARK_END,
+    MARK_FREE, ShortEntry,
+};
+
+const SECTOR: usize = BLOCK_SIZE;
+const ENTRIES_PER_SECTOR: u32 = (SECTOR / ENTRY_SIZE) as u32;
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+
+/// MBR partition types of FAT16 and FAT32 partitions
+const FAT_PARTITION_TYPES: [u8; 5] = [0x04, 0x06, 0x0b, 0x0c, 0x0e];
+/// Offset of the MBR partition table
+const PARTITION_TABLE: usize = 446;
+
+/// FSInfo signatures and fields
+const FSINFO_LEAD_SIG: u32 = 0x4161_5252;
+const FSINFO_STRUC_SIG: u32 = 0x6141_7272;
+const FSINFO_TRAIL_SIG: u32 = 0xaa55_0000;
+const FSINFO_FREE_COUNT: usize = 488;
+const FSINFO_NEXT_FREE: usize = 492;
+
+/// Names of the `.` and `..` entries
+const DOT: [u8; 11] = *b".          ";
+const DOT_DOT: [u8; 11] = *b"..         ";
+
+/// Clusters below this count make a FAT12 volume
+const MIN_FAT16_CLUSTERS: u32 = 4085;
+/// Clusters below this count make a FAT16 volume
+const MIN_FAT32_CLUSTERS: u32 = 65525;
+
+fn le16(raw: &[u8], offset: usize) -> u32 {
+    u16::from_le_bytes([raw[offset], raw[offset + 1]]) as u32
+}
+
+fn le32(raw: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        raw[offset],
+        raw[offset + 1],
+        raw[offset + 2],
+        raw[offset + 3],
+    ])
+}
+
+fn put16(raw: &mut [u8], offset: usize, value: u32) {
+    raw[offset..offset + 2].copy_from_slice(&(value as u16).to_le_bytes());
+}
+
+fn put32(raw: &mut [u8], offset: usize, value: u32) {
+    raw[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// FAT variant of a volume
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    /// Value marking the last cluster of a chain
+    fn end_of_chain(self) -> u32 {
+        match self {
+            FatType::Fat16 => 0xffff,
+            FatType::Fat32 => 0x0fff_ffff,
+        }
+    }
+
+    /// Smallest value that also ends a chain
+    fn min_end_of_chain(self) -> u32 {
+        match self {
+            FatType::Fat16 => 0xfff8,
+            FatType::Fat32 => 0x0fff_fff8,
+        }
+    }
+
+    fn entry_size(self) -> u32 {
+        match self {
+            FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        }
+    }
+}
+
+/// A directory on a volume
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Dir {
+    Root,
+    /// A subdirectory, by first cluster
+    Cluster(u32),
+}
+
+/// A file or directory found on a volume
+#[derive(Debug, Clone)]
+pub(super) struct Node {
+    /// Long name if there is one, the short name otherwise
+    pub name: String,
+    entry: ShortEntry,
+    parent: Dir,
+    /// Index of the short entry in the parent
+    slot: u32,
+    /// Number of LFN entries before the short entry
+    long_entries: u32,
+}
+
+impl Node {
+    pub fn is_dir(&self) -> bool {
+        self.entry.is_dir()
+    }
+
+    /// Size in bytes, 0 for directories
+    pub fn len(&self) -> u64 {
+        self.entry.size as u64
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.entry.attr & ATTR_READ_ONLY != 0
+    }
+
+    /// Modification time in seconds since the UNIX epoch
+    pub fn mtime(&self) -> u64 {
+        dir::unix_secs(self.entry.write_date, self.entry.write_time)
+    }
+
+    fn as_dir(&self) -> Dir {
+        match self.entry.first_cluster {
+            0 => Dir::Root,
+            cluster => Dir::Cluster(cluster),
+        }
+    }
+}
+
+/// A mounted FAT volume
+pub(super) struct FatFs {
+    device: Box<dyn BlockDevice + Send>,
+    /// First device block of the volume
+    start: u64,
+    fat_type: FatType,
+    sectors_per_cluster: u32,
+    /// First sector of the first FAT
+    fat_start: u32,
+    fat_size: u32,
+    fats: u32,
+    /// First sector and length of the FAT16 root directory
+    root_start: u32,
+    root_sectors: u32,
+    /// First cluster of the FAT32 root directory
+    root_cluster: u32,
+    data_start: u32,
+    /// Number of data clusters, numbered from 2
+    clusters: u32,
+    /// Sector of the FAT32 FSInfo structure
+    fsinfo: Option<u32>,
+    /// Where the search for a free cluster starts
+    next_free: u32,
+    /// Whether the FSInfo free count has been marked unknown
+    fsinfo_stale: bool,
+    /// The last sector accessed, by volume sector number
+    cached: Option<u32>,
+    cache: [u8; SECTOR],
+}
+
+/// Whether a sector holds a FAT boot sector with 512-byte sectors
+fn is_boot_sector(sector: &[u8; SECTOR]) -> bool {
+    let sectors_per_cluster = sector[13];
+    sector[510..] == BOOT_SIGNATURE
+        && matches!(sector[0], 0xeb | 0xe9)
+        && le16(sector, 11) as usize == SECTOR
+        && sectors_per_cluster.is_power_of_two()
+        && le16(sector, 14) != 0
+        && sector[16] != 0
+}
+
+/// First block of the first FAT partition in an MBR
+fn find_partition(mbr: &[u8; SECTOR]) -> Option<u64> {
+    if mbr[510..] != BOOT_SIGNATURE {
+        return None;
+    }
+    mbr[PARTITION_TABLE..PARTITION_TABLE + 64]
+        .chunks_exact(16)
+        .find(|part| FAT_PARTITION_TYPES.contains(&part[4]))
+        .map(|part| le32(part, 8) as u64)
+}
+
+impl FatFs {
+    /// Mount the volume on a device
+    ///
+    /// # Returns
+    /// * `Ok(fs)` - The mounted volume
+    /// * `Err(Error::InvalidFilesystem)` - No FAT16 or FAT32 volume was found
+    pub fn mount(mut device: Box<dyn BlockDevice + Send>) -> Result<Self> {
+        let mut boot = [0u8; SECTOR];
+        device.read_blocks(0, &mut boot)?;
+        let start = if is_boot_sector(&boot) {
+            0
+        } else {
+            let start = find_partition(&boot).ok_or(Error::InvalidFilesystem)?;
+            device.read_blocks(start, &mut boot)?;
+            if !is_boot_sector(&boot) {
+                return Err(Error::InvalidFilesystem);
+            }
+            start
+        };
+
+        let sectors_per_cluster = boot[13] as u32;
+        let reserved = le16(&boot, 14);
+        let fats = boot[16] as u32;
+        let root_entries = le16(&boot, 17);
+        let total = match le16(&boot, 19) {
+            0 => le32(&boot, 32),
+            total => total,
+        };
+        let fat_size = match le16(&boot, 22) {
+            0 => le32(&boot, 36),
+            size => size,
+        };
+
+        let root_sectors = (root_entries * ENTRY_SIZE as u32).div_ceil(SECTOR as u32);
+        let root_start = reserved + fats * fat_size;
+        let data_start = root_start + root_sectors;
+        let clusters = total
+            .checked_sub(data_start)
+            .ok_or(Error::InvalidFilesystem)?
+            / sectors_per_cluster;
+        if start + total as u64 > device.block_count() || fat_size == 0 {
+            return Err(Error::InvalidFilesystem);
+        }
+
+        let fat_type = match clusters {
+            0..MIN_FAT16_CLUSTERS => return Err(Error::InvalidFilesystem),
+            MIN_FAT16_CLUSTERS..MIN_FAT32_CLUSTERS => FatType::Fat16,
+            _ => FatType::Fat32,
+        };
+        if fat_size * SECTOR as u32 / fat_type.entry_size() < clusters + 2 {
+            return Err(Error::InvalidFilesystem);
+        }
+
+        let (root_cluster, fsinfo) = match fat_type {
+            FatType::Fat16 => (0, None),
+            FatType::Fat32 => {
+                let fsinfo = match le16(&boot, 48) {
+                    0 | 0xffff => None,
+                    sector => Some(sector),
+                };
+                (le32(&boot, 44), fsinfo)
+            }
+        };
+
+        let mut fs = Self {
+            device,
+            start,
+            fat_type,
+            sectors_per_cluster,
+            fat_start: reserved,
+            fat_size,
+            fats,
+            root_start,
+            root_sectors,
+            root_cluster,
+            data_start,
+            clusters,
+            fsinfo,
+            next_free: 2,
+            fsinfo_stale: false,
+            cached: None,
+            cache: [0; SECTOR],
+        };
+        if fat_type == FatType::Fat32 {
+            fs.check_cluster(root_cluster)?;
+        }
+        if let Some(sector) = fs.fsinfo {
+            let info = fs.read_sector(sector)?;
+            if le32(info, 0) == FSINFO_LEAD_SIG && le32(info, 484) == FSINFO_STRUC_SIG {
+                let hint = le32(info, FSINFO_NEXT_FREE);
+                if (2..clusters + 2).contains(&hint) {
+                    fs.next_free = hint;
+                }
+            } else {
+                fs.fsinfo = None;
+            }
+        }
+        Ok(fs)
+    }
+
+    /// Record the next free cluster hint and flush the device
+    pub fn flush(&mut self) -> Result<()> {
+        if let (Some(sector), true) = (self.fsinfo, self.fsinfo_stale) {
+            let next_free = self.next_free;
+            self.modify_sector(sector, false, |info| {
+                put32(info, FSINFO_NEXT_FREE, next_free)
+            })?;
+        }
+        self.device.flush()?;
+        Ok(())
+    }
+
+    fn read_sector(&mut self, sector: u32) -> Result<&[u8; SECTOR]> {
+        if self.cached != Some(sector) {
+            self.cached = None;
+            self.device
+                .read_blocks(self.start + sector as u64, &mut self.cache)?;
+            self.cached = Some(sector);
+        }
+        Ok(&self.cache)
+    }
+
+    /// Change part of a sector and write it back
+    ///
+    /// # Arguments
+    /// * `sector` - Volume sector number
+    /// * `whole` - Whether `f` overwrites the whole sector, making the read
+    ///   unnecessary
+    /// * `f` - Function changing the sector contents
+    fn modify_sector(
+        &mut self,
+        sector: u32,
+        whole: bool,
+        f: impl FnOnce(&mut [u8; SECTOR]),
+    ) -> Result<()> {
+        if !whole {
+            self.read_sector(sector)?;
+        }
+        self.cached = None;
+        f(&mut self.cache);
+        self.device
+            .write_blocks(self.start + sector as u64, &self.cache)?;
+        self.cached = Some(sector);
+        Ok(())
+    }
+
+    fn cluster_bytes(&self) -> u64 {
+        (self.sectors_per_cluster as usize * SECTOR) as u64
+    }
+
+    fn cluster_sector(&self, cluster: u32) -> u32 {
+        self.data_start + (cluster - 2) * self.sectors_per_cluster
+    }
+
+    fn check_cluster(&self, cluster: u32) -> Result<()> {
+        if (2..self.clusters + 2).contains(&cluster) {
+            Ok(())
+        } else {
+            Err(Error::InvalidFilesystem)
+        }
+    }
+
+    /// Sector and offset of a cluster's entry in the first FAT
+    fn fat_position(&self, cluster: u32) -> (u32, usize) {
+        let offset = cluster * self.fat_type.entry_size();
+        (
+            self.fat_start + offset / SECTOR as u32,
+            (offset % SECTOR as u32) as usize,
+        )
+    }
+
+    fn fat_entry(&mut self, cluster: u32) -> Result<u32> {
+        self.check_cluster(cluster)?;
+        let (sector, offset) = self.fat_position(cluster);
+        let fat_type = self.fat_type;
+        let raw = self.read_sector(sector)?;
+        Ok(match fat_type {
+            FatType::Fat16 => le16(raw, offset),
+            FatType::Fat32 => le32(raw, offset) & 0x0fff_ffff,
+        })
+    }
+
+    /// Set a cluster's entry in every FAT
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Result<()> {
+        self.check_cluster(cluster)?;
+        let (sector, offset) = self.fat_position(cluster);
+        let fat_type = self.fat_type;
+        for fat in 0..self.fats {
+            self.modify_sector(sector + fat * self.fat_size, false, |raw| match fat_type {
+                FatType::Fat16 => put16(raw, offset, value),
+                // The top 4 bits are reserved and must be preserved
+                FatType::Fat32 => {
+                    let old = le32(raw, offset);
+                    put32(raw, offset, old & 0xf000_0000 | value & 0x0fff_ffff);
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The cluster following `cluster` in its chain
+    ///
+    /// # Returns
+    /// * `Ok(Some(next))` - The next cluster
+    /// * `Ok(None)` - `cluster` ends the chain
+    /// * `Err(Error::InvalidFilesystem)` - The chain is broken
+    fn next_cluster(&mut self, cluster: u32) -> Result<Option<u32>> {
+        let next = self.fat_entry(cluster)?;
+        if next >= self.fat_type.min_end_of_chain() {
+            return Ok(None);
+        }
+        self.check_cluster(next)?;
+        Ok(Some(next))
+    }
+
+    /// The `n`th cluster of a chain, if the chain is that long
+    fn nth_cluster(&mut self, first: u32, n: u32) -> Result<Option<u32>> {
+        self.check_cluster(first)?;
+        let mut cluster = first;
+        for _ in 0..n {
+            match self.next_cluster(cluster)? {
+                Some(next) => cluster = next,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(cluster))
+    }
+
+    /// The `n`th cluster of a chain, extending the chain as needed
+    fn nth_cluster_or_allocate(&mut self, first: u32, n: u32) -> Result<u32> {
+        self.check_cluster(first)?;
+        let mut cluster = first;
+        for _ in 0..n {
+            cluster = match self.next_cluster(cluster)? {
+                Some(next) => next,
+                None => self.allocate(Some(cluster))?,
+            };
+        }
+        Ok(cluster)
+    }
+
+    /// FAT32 volumes track free space in FSInfo, which is marked unknown on
+    /// the first allocation or release rather than updated on every one
+    fn invalidate_free_count(&mut self) -> Result<()> {
+        if let (Some(sector), false) = (self.fsinfo, self.fsinfo_stale) {
+            self.modify_sector(sector, false, |info| {
+                put32(info, FSINFO_FREE_COUNT, u32::MAX)
+            })?;
+            self.fsinfo_stale = true;
+        }
+        Ok(())
+    }
+
+    /// Allocate a cluster at the end of a chain
+    ///
+    /// # Arguments
+    /// * `prev` - Last cluster of the chain to extend, `None` for a new chain
+    ///
+    /// # Returns
+    /// * `Ok(cluster)` - The allocated cluster; its contents are undefined
+    /// * `Err(Error::NoSpace)` - The volume is full
+    fn allocate(&mut self, prev: Option<u32>) -> Result<u32> {
+        let start = self.next_free - 2;
+        for i in 0..self.clusters {
+            let cluster = 2 + (start + i) % self.clusters;
+            if self.fat_entry(cluster)? != 0 {
+                continue;
+            }
+
+            self.invalidate_free_count()?;
+            self.set_fat_entry(cluster, self.fat_type.end_of_chain())?;
+            if let Some(prev) = prev {
+                self.set_fat_entry(prev, cluster)?;
+            }
+            self.next_free = if cluster + 1 < self.clusters + 2 {
+                cluster + 1
+            } else {
+                2
+            };
+            return Ok(cluster);
+        }
+        Err(Error::NoSpace)
+    }
+
+    /// Release every cluster of a chain
+    fn free_chain(&mut self, first: u32) -> Result<()> {
+        self.invalidate_free_count()?;
+        let mut cluster = Some(first);
+        while let Some(current) = cluster {
+            cluster = self.next_cluster(current)?;
+            self.set_fat_entry(current, 0)?;
+        }
+        Ok(())
+    }
+
+    fn zero_cluster(&mut self, cluster: u32) -> Result<()> {
+        let first = self.cluster_sector(cluster);
+        for sector in first..first + self.sectors_per_cluster {
+            self.modify_sector(sector, true, |raw| raw.fill(0))?;
+        }
+        Ok(())
+    }
+
+    /// Sector and offset of a directory slot, if the directory is that long
+    fn slot_position(&mut self, dir: Dir, index: u32) -> Result<Option<(u32, usize)>> {
+        let sector_index = index / ENTRIES_PER_SECTOR;
+        let offset = (index % ENTRIES_PER_SECTOR) as usize * ENTRY_SIZE;
+        let first = match (dir, self.fat_type) {
+            (Dir::Root, FatType::Fat16) => {
+                return Ok((sector_index < self.root_sectors)
+                    .then_some((self.root_start + sector_index, offset)));
+            }
+            (Dir::Root, FatType::Fat32) => self.root_cluster,
+            (Dir::Cluster(cluster), _) => cluster,
+        };
+
+        let cluster = self.nth_cluster(first, sector_index / self.sectors_per_cluster)?;
+        Ok(cluster.map(|cluster| {
+            (
+                self.cluster_sector(cluster) + sector_index % self.sectors_per_cluster,
+                offset,
+            )
+        }))
+    }
+
+    fn read_slot(&mut self, dir: Dir, index: u32) -> Result<Option<[u8; ENTRY_SIZE]>> {
+        let Some((sector, offset)) = self.slot_position(dir, index)? else {
+            return Ok(None);
+        };
+        let raw = self.read_sector(sector)?;
+        let mut slot = [0u8; ENTRY_SIZE];
+        slot.copy_from_slice(&raw[offset..offset + ENTRY_SIZE]);
+        Ok(Some(slot))
+    }
+
+    fn modify_slot(&mut self, dir: Dir, index: u32, f: impl FnOnce(&mut [u8])) -> Result<()> {
+        let (sector, offset) = self
+            .slot_position(dir, index)?
+            .ok_or(Error::InvalidFilesystem)?;
+        self.modify_sector(
+            sector,
+            false,
+            |raw| f(&mut raw[offset..offset + ENTRY_SIZE]),
+        )
+    }
+
+    /// Add a zeroed cluster to a directory
+    fn grow_dir(&mut self, dir: Dir) -> Result<()> {
+        let first = match (dir, self.fat_type) {
+            // The FAT16 root directory has a fixed size
+            (Dir::Root, FatType::Fat16) => return Err(Error::NoSpace),
+            (Dir::Root, FatType::Fat32) => self.root_cluster,
+            (Dir::Cluster(cluster), _) => cluster,
+        };
+
+        let mut last = first;
+        while let Some(next) = self.next_cluster(last)? {
+            last = next;
+        }
+        let cluster = self.allocate(Some(last))?;
+        self.zero_cluster(cluster)
+    }
+
+    /// Find `count` consecutive free slots, growing the directory if needed
+    fn free_slots(&mut self, dir: Dir, count: u32) -> Result<u32> {
+        let mut run_start = 0;
+        let mut run = 0;
+        let mut index = 0;
+        loop {
+            match self.read_slot(dir, index)? {
+                Some(raw) if raw[0] != MARK_FREE && raw[0] != MARK_END => run = 0,
+                Some(_) => {
+                    if run == 0 {
+                        run_start = index;
+                    }
+                    run += 1;
+                    if run == count {
+                        return Ok(run_start);
+                    }
+                }
+                None => {
+                    self.grow_dir(dir)?;
+                    continue;
+                }
+            }
+            index += 1;
+        }
+    }
+
+    /// List a directory, without `.` and `..`
+    pub fn list(&mut self, dir: Dir) -> Result<Vec<Node>> {
+        let mut nodes = Vec::new();
+        let mut long = LongName::default();
+        let mut index = 0;
+
+        while let Some(raw) = self.read_slot(dir, index)? {
+            match raw[0] {
+                MARK_END => break,
+                MARK_FREE => long.reset(),
+                _ if raw[11] & 0x3f == ATTR_LONG_NAME => long.push(&raw),
+                _ => {
+                    let entry = ShortEntry::parse(&raw);
+                    let long_name = long.finish(&entry);
+                    let hidden = entry.attr & ATTR_VOLUME_ID != 0
+                        || entry.name == DOT
+                        || entry.name == DOT_DOT;
+                    if !hidden {
+                        let (name, long_entries) =
+                            long_name.unwrap_or_else(|| (entry.display_name(), 0));
+                        nodes.push(Node {
+                            name,
+                            entry,
+                            parent: dir,
+                            slot: index,
+                            long_entries,
+                        });
+                    }
+                }
+            }
+            index += 1;
+        }
+
+        Ok(nodes)
+    }
+
+    /// Find a name in a directory, ignoring ASCII case
+    fn find(&mut self, dir: Dir, name: &str) -> Result<Option<Node>> {
+        Ok(self.list(dir)?.into_iter().find(|node| {
+            node.name.eq_ignore_ascii_case(name)
+                || node.entry.display_name().eq_ignore_ascii_case(name)
+        }))
+    }
+
+    /// Resolve a path relative to the volume root
+    ///
+    /// # Returns
+    /// * `Ok(None)` - The path is the root directory
+    /// * `Ok(Some(node))` - The file or directory at the path
+    pub fn lookup(&mut self, path: &str) -> Result<Option<Node>> {
+        let mut node: Option<Node> = None;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let dir = match &node {
+                None => Dir::Root,
+                Some(parent) if parent.is_dir() => parent.as_dir(),
+                Some(_) => return Err(Error::NotADirectory),
+            };
+            node = Some(self.find(dir, name)?.ok_or(Error::NotFound)?);
+        }
+        Ok(node)
+    }
+
+    /// The directory that would hold a path, and the path's last component
+    fn parent_of<'a>(&mut self, path: &'a str) -> Result<(Dir, &'a str)> {
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let dir = match self.lookup(parent)? {
+            None => Dir::Root,
+            Some(node) if node.is_dir() => node.as_dir(),
+            Some(_) => return Err(Error::NotADirectory),
+        };
+        Ok((dir, name))
+    }
+
+    /// The directory a resolved path refers to
+    pub fn dir_of(node: Option<&Node>) -> Result<Dir> {
+        match node {
+            None => Ok(Dir::Root),
+            Some(node) if node.is_dir() => Ok(node.as_dir()),
+            Some(_) => Err(Error::NotADirectory),
+        }
+    }
+
+    /// Create an empty file or directory
+    ///
+    /// # Arguments
+    /// * `path` - Path relative to the volume root
+    /// * `is_dir` - Whether to create a directory
+    ///
+    /// # Returns
+    /// * `Ok(node)` - The new file or directory
+    /// * `Err(Error::AlreadyExists)` - Something exists at the path already
+    /// * `Err(Error::InvalidInput)` - The name cannot be stored on FAT
+    pub fn create(&mut self, path: &str, is_dir: bool) -> Result<Node> {
+        let (parent, name) = self.parent_of(path)?;
+        if !dir::is_valid_name(name) {
+            return Err(Error::InvalidInput);
+        }
+        let siblings = self.list(parent)?;
+        let taken = |short: &[u8; 11]| siblings.iter().any(|node| node.entry.name == *short);
+        let exists = siblings.iter().any(|node| {
+            node.name.eq_ignore_ascii_case(name)
+                || node.entry.display_name().eq_ignore_ascii_case(name)
+        });
+        if exists {
+            return Err(Error::AlreadyExists);
+        }
+
+        let (short, long) = match dir::exact_short_name(name) {
+            Some(short) if !taken(&short) => (short, Vec::new()),
+            _ => {
+                let short = (1..1_000_000)
+                    .map(|n| dir::numbered_short_name(name, n))
+                    .find(|short| !taken(short))
+                    .ok_or(Error::NoSpace)?;
+                (
+                    short,
+                    dir::encode_long_name(name, dir::lfn_checksum(&short)),
+                )
+            }
+        };
+
+        let long_entries = long.len() as u32;
+        let first_slot = self.free_slots(parent, long_entries + 1)?;
+        let attr = if is_dir {
+            ATTR_DIRECTORY
+        } else {
+            dir::ATTR_ARCHIVE
+        };
+        let mut entry = ShortEntry::new(short, attr);
+
+        if is_dir {
+            let cluster = self.allocate(None)?;
+            self.zero_cluster(cluster)?;
+            entry.first_cluster = cluster;
+
+            let mut dot = entry;
+            dot.name = DOT;
+            let mut dot_dot = entry;
+            dot_dot.name = DOT_DOT;
+            dot_dot.first_cluster = match parent {
+                Dir::Root => 0,
+                Dir::Cluster(cluster) => cluster,
+            };
+            self.modify_slot(Dir::Cluster(cluster), 0, |raw| dot.encode(raw))?;
+            self.modify_slot(Dir::Cluster(cluster), 1, |raw| dot_dot.encode(raw))?;
+        }
+
+        for (i, raw) in long.iter().enumerate() {
+            self.modify_slot(parent, first_slot + i as u32, |slot| {
+                slot.copy_from_slice(raw)
+            })?;
+        }
+        let slot = first_slot + long_entries;
+        self.modify_slot(parent, slot, |raw| entry.encode(raw))?;
+
+        Ok(Node {
+            name: String::from(name),
+            entry,
+            parent,
+            slot,
+            long_entries,
+        })
+    }
+
+    /// Remove a file or an empty directory
+    ///
+    /// # Returns
+    /// * `Ok(())` - The entry is removed and its clusters released
+    /// * `Err(Error::DirectoryNotEmpty)` - The directory has entries
+    pub fn remove(&mut self, node: &Node) -> Result<()> {
+        if node.is_dir() && !self.list(node.as_dir())?.is_empty() {
+            return Err(Error::DirectoryNotEmpty);
+        }
+
+        for slot in node.slot - node.long_entries..=node.slot {
+            self.modify_slot(node.parent, slot, |raw| raw[0] = MARK_FREE)?;
+        }
+        if node.entry.first_cluster != 0 {
+            self.free_chain(node.entry.first_cluster)?;
+        }
+        Ok(())
+    }
+
+    /// Write a node's directory entry back
+    fn store(&mut self, node: &Node) -> Result<()> {
+        let entry = node.entry;
+        self.modify_slot(node.parent, node.slot, |raw| entry.encode(raw))
+    }
+
+    /// Read file data
+    ///
+    /// # Arguments
+    /// * `node` - The file
+    /// * `pos` - Offset to read from
+    /// * `buf` - Buffer to fill
+    ///
+    /// # Returns
+    /// * `Ok(n)` - Number of bytes read, 0 at or past the end of the file
+    pub fn read(&mut self, node: &Node, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        let size = node.len();
+        if pos >= size || buf.is_empty() {
+            return Ok(0);
+        }
+        let len = buf.len().min((size - pos) as usize);
+        let cluster_bytes = self.cluster_bytes();
+        let mut cluster = self
+            .nth_cluster(node.entry.first_cluster, (pos / cluster_bytes) as u32)?
+            .ok_or(Error::InvalidFilesystem)?;
+
+        let mut done = 0;
+        while done < len {
+            let at = pos + done as u64;
+            if at.is_multiple_of(cluster_bytes) && done > 0 {
+                cluster = self
+                    .next_cluster(cluster)?
+                    .ok_or(Error::InvalidFilesystem)?;
+            }
+            let sector =
+                self.cluster_sector(cluster) + ((at % cluster_bytes) / SECTOR as u64) as u32;
+            let offset = (at % SECTOR as u64) as usize;
+            let n = (SECTOR - offset).min(len - done);
+            let raw = self.read_sector(sector)?;
+            buf[done..done + n].copy_from_slice(&raw[offset..offset + n]);
+            done += n;
+        }
+        Ok(len)
+    }
+
+    /// Write file data, filling any gap after the current end with zeros
+    ///
+    /// # Arguments
+    /// * `node` - The file; its size and timestamps are updated
+    /// * `pos` - Offset to write at
+    /// * `data` - Bytes to write
+    ///
+    /// # Returns
+    /// * `Ok(n)` - Number of bytes written, all of `data`
+    /// * `Err(Error::NoSpace)` - The volume is full, or the file would exceed
+    ///   the 4 GiB FAT limit
+    pub fn write(&mut self, node: &mut Node, pos: u64, data: &[u8]) -> Result<usize> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+        let end = pos
+            .checked_add(data.len() as u64)
+            .filter(|&end| end <= u32::MAX as u64)
+            .ok_or(Error::NoSpace)?;
+        let start = pos.min(node.len());
+        let cluster_bytes = self.cluster_bytes();
+
+        if node.entry.first_cluster == 0 {
+            node.entry.first_cluster = self.allocate(None)?;
+        }
+        let mut cluster =
+            self.nth_cluster_or_allocate(node.entry.first_cluster, (start / cluster_bytes) as u32)?;
+
+        let mut at = start;
+        while at < end {
+            if at.is_multiple_of(cluster_bytes) && at != start {
+                cluster = match self.next_cluster(cluster)? {
+                    Some(next) => next,
+                    None => self.allocate(Some(cluster))?,
+                };
+            }
+            let sector =
+                self.cluster_sector(cluster) + ((at % cluster_bytes) / SECTOR as u64) as u32;
+            let offset = (at % SECTOR as u64) as usize;
+            let n = (SECTOR - offset).min((end - at) as usize);
+            // Bytes before `pos` fill the gap after the old end of the file
+            let zeros = (pos.saturating_sub(at) as usize).min(n);
+
+            self.modify_sector(sector, offset == 0 && n == SECTOR, |raw| {
+                raw[offset..offset + zeros].fill(0);
+                if zeros < n {
+                    let from = (at + zeros as u64 - pos) as usize;
+                    raw[offset + zeros..offset + n].copy_from_slice(&data[from..from + n - zeros]);
+                }
+            })?;
+            at += n as u64;
+        }
+
+        node.entry.size = node.entry.size.max(end as u32);
+        node.entry.touch();
+        self.store(node)?;
+        Ok(data.len())
+    }
+
+    /// Change the size of a file
+    ///
+    /// Growing fills the new space with zeros; shrinking releases clusters
+    /// that are no longer needed.
+    pub fn set_len(&mut self, node: &mut Node, len: u64) -> Result<()> {
+        if len > node.len() {
+            self.write(node, len - 1, &[0])?;
+            return Ok(());
+        }
+
+        let clusters = len.div_ceil(self.cluster_bytes()) as u32;
+        let first = node.entry.first_cluster;
+        if clusters == 0 {
+            if first != 0 {
+                self.free_chain(first)?;
+                node.entry.first_cluster = 0;
+            }
+        } else {
+            let last = self
+                .nth_cluster(first, clusters - 1)?
+                .ok_or(Error::InvalidFilesystem)?;
+            if let Some(rest) = self.next_cluster(last)? {
+                self.set_fat_entry(last, self.fat_type.end_of_chain())?;
+                self.free_chain(rest)?;
+            }
+        }
+
+        node.entry.size = len as u32;
+        node.entry.touch();
+        self.store(node)
+    }
+
+    /// Read the node's entry again, picking up changes made through other
+    /// handles
+    pub fn refresh(&mut self, node: &mut Node) -> Result<()> {
+        let raw = self
+            .read_slot(node.parent, node.slot)?
+            .ok_or(Error::InvalidFilesystem)?;
+        if raw[0] == MARK_FREE || raw[0] == MARK_END {
+            return Err(Error::NotFound);
+        }
+        node.entry = ShortEntry::parse(&raw);
+        Ok(())
+    }
+}
+
+/// Format a whole device as a FAT16 or FAT32 volume, without a partition
+/// table
+///
+/// Cluster sizes follow the Microsoft defaults for the device size. FAT16
+/// needs roughly 4 MiB to 2 GiB, FAT32 at least 33 MiB.
+///
+/// # Arguments
+/// * `device` - The device to format; everything on it is lost
+/// * `fat_type` - The FAT variant to create
+///
+/// # Returns
+/// * `Ok(())` - The volume is empty and ready to mount
+/// * `Err(Error::InvalidInput)` - The device size does not suit `fat_type`
+pub fn format(device: &mut dyn BlockDevice, fat_type: FatType) -> Result<()> {
+    let total = device.block_count().min(u32::MAX as u64) as u32;
+    let fats = 2;
+    let (reserved, root_entries, sectors_per_cluster) = match fat_type {
+        FatType::Fat16 => {
+            let sectors_per_cluster = match total {
+                0..=8400 => return Err(Error::InvalidInput),
+                8401..=32680 => 2,
+                32681..=262144 => 4,
+                262145..=524288 => 8,
+                524289..=1048576 => 16,
+                1048577..=2097152 => 32,
+                2097153..=4194304 => 64,
+                _ => return Err(Error::InvalidInput),
+            };
+            (1, 512, sectors_per_cluster)
+        }
+        FatType::Fat32 => {
+            let sectors_per_cluster = match total {
+                0..=66600 => return Err(Error::InvalidInput),
+                66601..=532480 => 1,
+                532481..=16777216 => 8,
+                16777217..=33554432 => 16,
+                33554433..=67108864 => 32,
+                _ => 64,
+            };
+            (32, 0, sectors_per_cluster)
+        }
+    };
+
+    // FAT size from the FAT specification, which may overestimate slightly
+    let root_sectors = (root_entries * ENTRY_SIZE as u32).div_ceil(SECTOR as u32);
+    let mut per_fat_sector = 256 * sectors_per_cluster + fats;
+    if fat_type == FatType::Fat32 {
+        per_fat_sector /= 2;
+    }
+    let fat_size = (total - reserved - root_sectors).div_ceil(per_fat_sector);
+    let data_start = reserved + fats * fat_size + root_sectors;
+    let clusters = (total - data_start) / sectors_per_cluster;
+    let expected = match clusters {
+        MIN_FAT16_CLUSTERS..MIN_FAT32_CLUSTERS => FatType::Fat16,
+        MIN_FAT32_CLUSTERS.. => FatType::Fat32,
+        _ => return Err(Error::InvalidInput),
+    };
+    if expected != fat_type {
+        return Err(Error::InvalidInput);
+    }
+
+    let mut sector = [0u8; SECTOR];
+    let root_clusters = match fat_type {
+        FatType::Fat16 => 0,
+        FatType::Fat32 => sectors_per_cluster,
+    };
+    for lba in 0..data_start + root_clusters {
+        device.write_blocks(lba as u64, &sector)?;
+    }
+
+    let volume_id = SystemTime::now().unix_secs() as u32;
+    sector[0..3].copy_from_slice(match fat_type {
+        FatType::Fat16 => &[0xeb, 0x3c, 0x90],
+        FatType::Fat32 => &[0xeb, 0x58, 0x90],
+    });
+    sector[3..11].copy_from_slice(b"MSWIN4.1");
+    put16(&mut sector, 11, SECTOR as u32);
+    sector[13] = sectors_per_cluster as u8;
+    put16(&mut sector, 14, reserved);
+    sector[16] = fats as u8;
+    put16(&mut sector, 17, root_entries);
+    if fat_type == FatType::Fat16 && total < 0x10000 {
+        put16(&mut sector, 19, total);
+    } else {
+        put32(&mut sector, 32, total);
+    }
+    sector[21] = 0xf8;
+    put16(&mut sector, 24, 63);
+    put16(&mut sector, 26, 255);
+    let ext = match fat_type {
+        FatType::Fat16 => {
+            put16(&mut sector, 22, fat_size);
+            36
+        }
+        FatType::Fat32 => {
+            put32(&mut sector, 36, fat_size);
+            put32(&mut sector, 44, 2);
+            put16(&mut sector, 48, 1);
+            put16(&mut sector, 50, 6);
+            64
+        }
+    };
+    sector[ext] = 0x80;
+    sector[ext + 2] = 0x29;
+    put32(&mut sector, ext + 3, volume_id);
+    sector[ext + 7..ext + 18].copy_from_slice(b"NO NAME    ");
+    sector[ext + 18..ext + 26].copy_from_slice(match fat_type {
+        FatType::Fat16 => b"FAT16   ",
+        FatType::Fat32 => b"FAT32   ",
+    });
+    sector[510..].copy_from_slice(&BOOT_SIGNATURE);
+    device.write_blocks(0, &sector)?;
+
+    if fat_type == FatType::Fat32 {
+        device.write_blocks(6, &sector)?;
+
+        let mut info = [0u8; SECTOR];
+        put32(&mut info, 0, FSINFO_LEAD_SIG);
+        put32(&mut info, 484, FSINFO_STRUC_SIG);
+        put32(&mut info, FSINFO_FREE_COUNT, clusters - 1);
+        put32(&mut info, FSINFO_NEXT_FREE, 3);
+        put32(&mut info, 508, FSINFO_TRAIL_SIG);
+        device.write_blocks(1, &info)?;
+        device.write_blocks(7, &info)?;
+    }
+
+    // Entries 0 and 1 are reserved; FAT32 also ends the root directory chain
+    let mut fat = [0u8; SECTOR];
+    match fat_type {
+        FatType::Fat16 => {
+            put16(&mut fat, 0, 0xfff8);
+            put16(&mut fat, 2, 0xffff);
+        }
+        FatType::Fat32 => {
+            put32(&mut fat, 0, 0x0fff_fff8);
+            put32(&mut fat, 4, 0x0fff_ffff);
+            put32(&mut fat, 8, 0x0fff_ffff);
+        }
+    }
+    for copy in 0..fats {
+        device.write_blocks((reserved + copy * fat_size) as u64, &fat)?;
+    }
+
+    device.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    extern crate std;
+
+    use super::*;
+    use common::BlockError;
+    use std::sync::{Arc, Mutex};
+
+    /// An in-memory disk that stays inspectable after being mounted
+    #[derive(Clone)]
+    pub struct RamDisk(Arc<Mutex<Vec<u8>>>);
+
+    impl RamDisk {
+        pub fn new(blocks: usize) -> Self {
+            Self(Arc::new(Mutex::new(alloc::vec![0; blocks * SECTOR])))
+        }
+    }
+
+    impl BlockDevice for RamDisk {
+        fn block_count(&self) -> u64 {
+            (self.0.lock().unwrap().len() / SECTOR) as u64
+        }
+
+        fn read_blocks(
+            &mut self,
+            lba: u64,
+            buf: &mut [u8],
+        ) -> core::result::Result<(), BlockError> {
+            common::check_request(self.block_count(), lba, buf.len())?;
+            let start = lba as usize * SECTOR;
+            buf.copy_from_slice(&self.0.lock().unwrap()[start..start + buf.len()]);
+            Ok(())
+        }
+
+        fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> core::result::Result<(), BlockError> {
+            common::check_request(self.block_count(), lba, buf.len())?;
+            let start = lba as usize * SECTOR;
+            self.0.lock().unwrap()[start..start + buf.len()].copy_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    fn formatted(blocks: usize, fat_type: FatType) -> (RamDisk, FatFs) {
+        let mut disk = RamDisk::new(blocks);
+        format(&mut disk, fat_type).unwrap();
+        let fs = FatFs::mount(Box::new(disk.clone())).unwrap();
+        assert_eq!(fs.fat_type, fat_type);
+        (disk, fs)
+    }
+
+    fn read_all(fs: &mut FatFs, path: &str) -> Vec<u8> {
+        let node = fs.lookup(path).unwrap().unwrap();
+        let mut buf = alloc::vec![0; node.len() as usize];
+        assert_eq!(fs.read(&node, 0, &mut buf).unwrap(), buf.len());
+        buf
+    }
+
+    fn exercise(fat_type: FatType, blocks: usize) {
+        let (disk, mut fs) = formatted(blocks, fat_type);
+
+        // A long name spanning clusters, written in uneven pieces
+        let data: Vec<u8> = (0..5000u32).map(|i| (i * 7) as u8).collect();
+        let mut file = fs.create("Benchmark results.csv", false).unwrap();
+        let mut pos = 0;
+        for chunk in data.chunks(777) {
+            pos += fs.write(&mut file, pos as u64, chunk).unwrap();
+        }
+        assert_eq!(file.len(), 5000);
+
+        fs.create("LOGS", true).unwrap();
+        let mut log = fs.create("LOGS/run.log", false).unwrap();
+        fs.write(&mut log, 10, b"tail").unwrap();
+        assert_eq!(fs.create("logs", true).unwrap_err(), Error::AlreadyExists);
+
+        // Remount from the raw bytes to check what reached the disk
+        drop(fs);
+        let mut fs = FatFs::mount(Box::new(disk.clone())).unwrap();
+        assert_eq!(read_all(&mut fs, "benchmark RESULTS.csv"), data);
+        assert_eq!(
+            read_all(&mut fs, "logs/RUN.LOG"),
+            b"\0\0\0\0\0\0\0\0\0\0tail"
+        );
+        let names: Vec<String> = fs
+            .list(Dir::Root)
+            .unwrap()
+            .into_iter()
+            .map(|n| n.name)
+            .collect();
+        assert_eq!(names, ["Benchmark results.csv", "LOGS"]);
+
+        let mut file = fs.lookup("Benchmark results.csv").unwrap().unwrap();
+        fs.set_len(&mut file, 100).unwrap();
+        assert_eq!(read_all(&mut fs, "Benchmark results.csv"), &data[..100]);
+
+        let logs = fs.lookup("LOGS").unwrap().unwrap();
+        assert_eq!(fs.remove(&logs).unwrap_err(), Error::DirectoryNotEmpty);
+        let log = fs.lookup("LOGS/run.log").unwrap().unwrap();
+        fs.remove(&log).unwrap();
+        fs.remove(&logs).unwrap();
+        assert_eq!(fs.lookup("LOGS").unwrap_err(), Error::NotFound);
+        fs.flush().unwrap();
+    }
+
+    #[test]
+    fn test_fat16() {
+        exercise(FatType::Fat16, 16 * 1024);
+    }
+
+    #[test]
+    fn test_fat32() {
+        exercise(FatType::Fat32, 70 * 1024);
+    }
+
+    #[test]
+    fn test_fill_directory() {
+        let (_, mut fs) = formatted(16 * 1024, FatType::Fat16);
+        let dir = fs.create("many", true).unwrap();
+        // 200 long names need 600 slots, spread over many clusters
+        for i in 0..200 {
+            fs.create(&alloc::format!("many/file number {}", i), false)
+                .unwrap();
+        }
+        let nodes = fs.list(FatFs::dir_of(Some(&dir)).unwrap()).unwrap();
+        assert_eq!(nodes.len(), 200);
+        assert_eq!(nodes[199].name, "file number 199");
+    }
+
+    #[test]
+    fn test_reject_small_or_missing() {
+        let mut disk = RamDisk::new(1024);
+        assert_eq!(
+            format(&mut disk, FatType::Fat16).unwrap_err(),
+            Error::InvalidInput
+        );
+        assert_eq!(
+            FatFs::mount(Box::new(disk)).err(),
+            Some(Error::InvalidFilesystem)
+        );
+    }
+}
@@ -0,0 +1,1081 @@
+//! Filesystem: a read-only ramdisk, with writable FAT volumes mounted on it
+//!
+//! A bin links its assets into the image with [`ramdisk!`](crate::ramdisk)
+//! after packing them in its build script with
+//! `build_helper::pack_ramdisk("assets")`. The archive is indexed on first
+//! use, and paths are relative to the packed directory, with or without a
+//! leading `/`.
+//!
+//! An archive obtained some other way (downloaded, for example) can be
+//! mounted instead with [`mount`].
+//!
+//! FAT16 and FAT32 volumes are mounted at a top-level directory with
+//! [`mount_disk`] (the platform's disk) or [`mount_device`] (any
+//! [`BlockDevice`]). Paths below the mount point refer to the volume, which
+//! can be written. Volumes are flushed when the program exits.
+
+use crate::platform::{Current, Platform};
+use crate::time::SystemTime;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+#[cfg(not(target_os = "none"))]
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use critical_section::Mutex;
+
+pub use common::{BLOCK_SIZE, BlockDevice, BlockError};
+
+mod fat;
+mod ramdisk;
+use fat::FatFs;
+pub use fat::{FatType, format};
+use ramdisk::Entry;
+
+/// Filesystem errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// No file or directory at the path
+    NotFound,
+    /// A directory operation was attempted on a file
+    NotADirectory,
+    /// A file operation was attempted on a directory
+    IsADirectory,
+    /// The ramdisk is not a valid cpio newc or ustar archive
+    InvalidArchive,
+    /// A malformed path or name, a seek before the start of a file, or an
+    /// access the file was not opened for
+    InvalidInput,
+    /// A write to the ramdisk or to a read-only file
+    ReadOnly,
+    /// The path to create exists already
+    AlreadyExists,
+    /// The directory to remove has entries
+    DirectoryNotEmpty,
+    /// The volume is full, or a file would exceed the FAT size limit
+    NoSpace,
+    /// The device holds no FAT16 or FAT32 volume, or the volume is corrupt
+    InvalidFilesystem,
+    /// The block device reported an error
+    Io,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            Error::NotFound => "no such file or directory",
+            Error::NotADirectory => "not a directory",
+            Error::IsADirectory => "is a directory",
+            Error::InvalidArchive => "invalid ramdisk archive",
+            Error::InvalidInput => "invalid input",
+            Error::ReadOnly => "read-only filesystem or file",
+            Error::AlreadyExists => "file exists",
+            Error::DirectoryNotEmpty => "directory not empty",
+            Error::NoSpace => "no space left on device",
+            Error::InvalidFilesystem => "invalid FAT filesystem",
+            Error::Io => "I/O error",
+        };
+        f.write_str(message)
+    }
+}
+
+impl From<BlockError> for Error {
+    fn from(err: BlockError) -> Self {
+        match err {
+            BlockError::ReadOnly => Error::ReadOnly,
+            _ => Error::Io,
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Index of the mounted archive, `None` until first use
+static RAMDISK: Mutex<RefCell<Option<Vec<Entry>>>> = Mutex::new(RefCell::new(None));
+
+/// The archive placed in the `.ramdisk` section by `ramdisk!()`
+fn linked_image() -> &'static [u8] {
+    #[cfg(target_os = "none")]
+    unsafe {
+        unsafe extern "C" {
+            static _sramdisk: u8;
+            static _eramdisk: u8;
+        }
+
+        let start = core::ptr::addr_of!(_sramdisk);
+        let end = core::ptr::addr_of!(_eramdisk);
+        core::slice::from_raw_parts(start, end as usize - start as usize)
+    }
+
+    #[cfg(not(target_os = "none"))]
+    {
+        let start = HOSTED_IMAGE.0.load(Ordering::Acquire);
+        if start.is_null() {
+            return &[];
+        }
+        unsafe { core::slice::from_raw_parts(start, HOSTED_IMAGE.1.load(Ordering::Acquire)) }
+    }
+}
+
+/// The archive of `ramdisk!()` in a hosted build, as start and length
+#[cfg(not(target_os = "none"))]
+static HOSTED_IMAGE: (AtomicPtr<u8>, AtomicUsize) =
+    (AtomicPtr::new(core::ptr::null_mut()), AtomicUsize::new(0));
+
+/// Record the archive of `ramdisk!()`, for hosted builds
+#[cfg(not(target_os = "none"))]
+#[doc(hidden)]
+pub fn register_image(image: &'static [u8]) {
+    HOSTED_IMAGE.1.store(image.len(), Ordering::Release);
+    HOSTED_IMAGE
+        .0
+        .store(image.as_ptr().cast_mut(), Ordering::Release);
+}
+
+/// Mount an archive, replacing the linked ramdisk
+///
+/// # Arguments
+/// * `image` - A cpio newc or ustar archive
+///
+/// # Returns
+/// * `Ok(())` - The archive is mounted
+/// * `Err(Error::InvalidArchive)` - The archive could not be parsed; the
+///   previous mount is kept
+pub fn mount(image: &'static [u8]) -> Result<()> {
+    let entries = ramdisk::parse(image)?;
+    critical_section::with(|cs| RAMDISK.borrow_ref_mut(cs).replace(entries));
+    Ok(())
+}
+
+/// Run `f` on the index, mounting the linked ramdisk on first use
+fn with_entries<T>(f: impl FnOnce(&[Entry]) -> Result<T>) -> Result<T> {
+    critical_section::with(|cs| {
+        let mut ramdisk = RAMDISK.borrow_ref_mut(cs);
+        if ramdisk.is_none() {
+            *ramdisk = Some(ramdisk::parse(linked_image())?);
+        }
+        f(ramdisk.as_deref().unwrap_or_default())
+    })
+}
+
+/// Find the entry for a path, including implicit parent directories
+fn lookup(path: &str) -> Result<Node> {
+    let path = ramdisk::normalize(path).ok_or(Error::InvalidInput)?;
+
+    with_entries(|entries| {
+        if path.is_empty() {
+            return Ok(Node::ImplicitDir);
+        }
+        if let Some(entry) = entries.iter().find(|e| e.path == path) {
+            return Ok(Node::Entry(*entry));
+        }
+        let is_parent = entries.iter().any(|e| {
+            e.path
+                .strip_prefix(path)
+                .is_some_and(|rest| rest.starts_with('/'))
+        });
+        if is_parent {
+            Ok(Node::ImplicitDir)
+        } else {
+            Err(Error::NotFound)
+        }
+    })
+}
+
+/// A resolved path
+enum Node {
+    Entry(Entry),
+    /// A directory with no archive member of its own
+    ImplicitDir,
+}
+
+impl Node {
+    fn metadata(&self) -> Metadata {
+        match self {
+            Node::Entry(entry) => Metadata {
+                file_type: entry.kind,
+                len: entry.data.len() as u64,
+                mode: entry.mode,
+                mtime: entry.mtime,
+            },
+            Node::ImplicitDir => Metadata {
+                file_type: FileType::Dir,
+                len: 0,
+                mode: 0o755,
+                mtime: 0,
+            },
+        }
+    }
+}
+
+/// A FAT volume mounted at a top-level directory
+struct Mount {
+    id: u32,
+    /// Normalized mount point, a single path component
+    point: String,
+    fs: FatFs,
+}
+
+struct Mounts {
+    next_id: u32,
+    list: Vec<Mount>,
+    /// Whether the at-exit flush is registered
+    hooked: bool,
+}
+
+static MOUNTS: Mutex<RefCell<Mounts>> = Mutex::new(RefCell::new(Mounts {
+    next_id: 0,
+    list: Vec::new(),
+    hooked: false,
+}));
+
+/// Where a path leads
+enum Route<'a> {
+    /// A normalized ramdisk path
+    Ramdisk(&'a str),
+    /// A path relative to the root of a mounted volume
+    Volume { id: u32, path: &'a str },
+}
+
+fn route(path: &str) -> Result<Route<'_>> {
+    let path = ramdisk::normalize(path).ok_or(Error::InvalidInput)?;
+    let (first, rest) = path.split_once('/').unwrap_or((path, ""));
+
+    critical_section::with(|cs| {
+        let mounts = MOUNTS.borrow_ref(cs);
+        Ok(
+            match mounts
+                .list
+                .iter()
+                .find(|m| !first.is_empty() && m.point == first)
+            {
+                Some(mount) => Route::Volume {
+                    id: mount.id,
+                    path: rest,
+                },
+                None => Route::Ramdisk(path),
+            },
+        )
+    })
+}
+
+/// Run `f` on a mounted volume
+fn with_volume<T>(id: u32, f: impl FnOnce(&mut FatFs) -> Result<T>) -> Result<T> {
+    critical_section::with(|cs| {
+        let mut mounts = MOUNTS.borrow_ref_mut(cs);
+        let mount = mounts
+            .list
+            .iter_mut()
+            .find(|m| m.id == id)
+            .ok_or(Error::NotFound)?;
+        f(&mut mount.fs)
+    })
+}
+
+/// Mount points, which appear as directories in the ramdisk root
+fn mount_points() -> Vec<String> {
+    critical_section::with(|cs| {
+        MOUNTS
+            .borrow_ref(cs)
+            .list
+            .iter()
+            .map(|m| m.point.clone())
+            .collect()
+    })
+}
+
+fn platform_disk() -> Option<Box<dyn BlockDevice + Send>> {
+    Current::block_device()
+}
+
+/// Mount the platform's disk
+///
+/// The disk is virtio-blk on QEMU and the disk controller on NEMU. It can
+/// only be mounted once.
+///
+/// # Arguments
+/// * `point` - Top-level directory to mount at, such as `"/disk"`
+///
+/// # Returns
+/// * `Ok(())` - The volume is mounted
+/// * `Err(Error::NotFound)` - The platform has no disk, or it is mounted
+///   already
+/// * `Err(error)` - As for [`mount_device`]
+pub fn mount_disk(point: &str) -> Result<()> {
+    mount_device(platform_disk().ok_or(Error::NotFound)?, point)
+}
+
+/// Mount a FAT16 or FAT32 volume from a block device
+///
+/// # Arguments
+/// * `device` - Device holding the volume, unpartitioned or with an MBR
+/// * `point` - Top-level directory to mount at, such as `"/disk"`; it hides
+///   any ramdisk entry of the same name
+///
+/// # Returns
+/// * `Ok(())` - The volume is mounted
+/// * `Err(Error::InvalidInput)` - The mount point is not a top-level name
+/// * `Err(Error::AlreadyExists)` - A volume is mounted there already
+/// * `Err(Error::InvalidFilesystem)` - The device holds no usable volume
+pub fn mount_device(device: Box<dyn BlockDevice + Send>, point: &str) -> Result<()> {
+    let point = ramdisk::normalize(point)
+        .filter(|point| !point.is_empty() && !point.contains('/'))
+        .ok_or(Error::InvalidInput)?;
+    let fs = FatFs::mount(device)?;
+
+    critical_section::with(|cs| {
+        let mut mounts = MOUNTS.borrow_ref_mut(cs);
+        if mounts.list.iter().any(|m| m.point == point) {
+            return Err(Error::AlreadyExists);
+        }
+
+        let id = mounts.next_id;
+        mounts.next_id += 1;
+        mounts.list.push(Mount {
+            id,
+            point: String::from(point),
+            fs,
+        });
+        if !mounts.hooked {
+            mounts.hooked = crate::process::at_exit(sync_at_exit).is_ok();
+        }
+        Ok(())
+    })
+}
+
+/// Flush and unmount a volume
+///
+/// Files still open on the volume fail with [`Error::NotFound`] afterwards.
+///
+/// # Returns
+/// * `Ok(())` - The volume is unmounted
+/// * `Err(Error::NotFound)` - Nothing is mounted at `point`
+pub fn unmount(point: &str) -> Result<()> {
+    let point = ramdisk::normalize(point).ok_or(Error::InvalidInput)?;
+    let mut mount = critical_section::with(|cs| -> Result<Mount> {
+        let mut mounts = MOUNTS.borrow_ref_mut(cs);
+        let index = mounts
+            .list
+            .iter()
+            .position(|m| m.point == point)
+            .ok_or(Error::NotFound)?;
+        Ok(mounts.list.remove(index))
+    })?;
+    mount.fs.flush()
+}
+
+/// Flush every mounted volume
+pub fn sync() -> Result<()> {
+    critical_section::with(|cs| {
+        MOUNTS
+            .borrow_ref_mut(cs)
+            .list
+            .iter_mut()
+            .try_for_each(|m| m.fs.flush())
+    })
+}
+
+fn sync_at_exit() {
+    let _ = sync();
+}
+
+/// Metadata of a file or directory on a volume, `None` being its root
+fn volume_metadata(node: Option<&fat::Node>) -> Metadata {
+    match node {
+        Some(node) => Metadata {
+            file_type: if node.is_dir() {
+                FileType::Dir
+            } else {
+                FileType::File
+            },
+            len: node.len(),
+            mode: match (node.is_dir(), node.is_read_only()) {
+                (true, _) => 0o755,
+                (false, true) => 0o444,
+                (false, false) => 0o644,
+            },
+            mtime: node.mtime(),
+        },
+        None => Node::ImplicitDir.metadata(),
+    }
+}
+
+/// Kind of a filesystem object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    File,
+    Dir,
+}
+
+/// Information about a file or directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    file_type: FileType,
+    len: u64,
+    mode: u32,
+    mtime: u64,
+}
+
+impl Metadata {
+    /// Kind of the object
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    /// Whether the object is a directory
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileType::Dir
+    }
+
+    /// Whether the object is a regular file
+    pub fn is_file(&self) -> bool {
+        self.file_type == FileType::File
+    }
+
+    /// Size in bytes, 0 for directories
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the size is 0
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Unix permission bits, derived from the read-only attribute on FAT
+    /// volumes
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    /// Modification time, to the second (two seconds on FAT volumes)
+    pub fn modified(&self) -> SystemTime {
+        SystemTime::from_unix_nanos(self.mtime * 1_000_000_000)
+    }
+}
+
+/// Position to seek to, relative to the start, end or current position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    End(i64),
+    Current(i64),
+}
+
+/// What an open file refers to
+#[derive(Debug)]
+enum Handle {
+    Ramdisk(Entry),
+    Volume { id: u32, node: fat::Node },
+}
+
+/// An open file
+#[derive(Debug)]
+pub struct File {
+    handle: Handle,
+    pos: u64,
+    read: bool,
+    write: bool,
+    append: bool,
+}
+
+/// Options for opening a file, like `std::fs::OpenOptions`
+///
+/// Files on the ramdisk can only be opened for reading.
+#[derive(Debug, Clone, Default)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+    create_new: bool,
+}
+
+impl OpenOptions {
+    /// Options with every flag unset
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow reading
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    /// Allow writing
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    /// Write at the end of the file, whatever the current position
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append;
+        self
+    }
+
+    /// Empty the file when opening it; requires writing
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Create the file if it does not exist; requires writing
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Create the file, failing if it exists; requires writing
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Open a file with these options
+    ///
+    /// # Arguments
+    /// * `path` - Path of the file
+    ///
+    /// # Returns
+    /// * `Ok(file)` - The file, positioned at its start
+    /// * `Err(error)` - The file could not be opened or created
+    pub fn open(&self, path: &str) -> Result<File> {
+        let writable = self.write || self.append;
+        if !writable && (self.truncate || self.create || self.create_new) {
+            return Err(Error::InvalidInput);
+        }
+
+        let handle = match route(path)? {
+            Route::Ramdisk(path) => {
+                if writable {
+                    return Err(Error::ReadOnly);
+                }
+                match lookup(path)? {
+                    Node::Entry(entry) if entry.kind == FileType::File => Handle::Ramdisk(entry),
+                    _ => return Err(Error::IsADirectory),
+                }
+            }
+            Route::Volume { id, path } => {
+                let node = with_volume(id, |fs| {
+                    let mut node = match fs.lookup(path) {
+                        Ok(Some(_)) if self.create_new => return Err(Error::AlreadyExists),
+                        Ok(Some(node)) if node.is_dir() => return Err(Error::IsADirectory),
+                        Ok(Some(node)) if writable && node.is_read_only() => {
+                            return Err(Error::ReadOnly);
+                        }
+                        Ok(Some(node)) => node,
+                        Ok(None) => return Err(Error::IsADirectory),
+                        Err(Error::NotFound) if self.create || self.create_new => {
+                            fs.create(path, false)?
+                        }
+                        Err(err) => return Err(err),
+                    };
+                    if self.truncate && node.len() > 0 {
+                        fs.set_len(&mut node, 0)?;
+                    }
+                    Ok(node)
+                })?;
+                Handle::Volume { id, node }
+            }
+        };
+
+        Ok(File {
+            handle,
+            pos: 0,
+            read: self.read,
+            write: writable,
+            append: self.append,
+        })
+    }
+}
+
+impl File {
+    /// Open a file for reading
+    ///
+    /// # Arguments
+    /// * `path` - Path of the file
+    ///
+    /// # Returns
+    /// * `Ok(file)` - The file, positioned at its start
+    /// * `Err(error)` - The path does not exist or is a directory
+    pub fn open(path: &str) -> Result<File> {
+        OpenOptions::new().read(true).open(path)
+    }
+
+    /// Create a file on a volume for writing, emptying it if it exists
+    ///
+    /// # Arguments
+    /// * `path` - Path of the file, below a mount point
+    ///
+    /// # Returns
+    /// * `Ok(file)` - The empty file
+    /// * `Err(Error::ReadOnly)` - The path is on the ramdisk
+    pub fn create(path: &str) -> Result<File> {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+    }
+
+    /// Current size of the file
+    fn len(&mut self) -> Result<u64> {
+        match &mut self.handle {
+            Handle::Ramdisk(entry) => Ok(entry.data.len() as u64),
+            Handle::Volume { id, node } => with_volume(*id, |fs| {
+                fs.refresh(node)?;
+                Ok(node.len())
+            }),
+        }
+    }
+
+    /// Read from the current position
+    ///
+    /// # Arguments
+    /// * `buf` - Buffer to read into
+    ///
+    /// # Returns
+    /// * `Ok(n)` - Number of bytes read, 0 at the end of the file
+    /// * `Err(Error::InvalidInput)` - The file was not opened for reading
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if !self.read {
+            return Err(Error::InvalidInput);
+        }
+
+        let len = match &mut self.handle {
+            Handle::Ramdisk(entry) => {
+                let data = entry.data;
+                let start = (self.pos as usize).min(data.len());
+                let len = buf.len().min(data.len() - start);
+                buf[..len].copy_from_slice(&data[start..start + len]);
+                len
+            }
+            Handle::Volume { id, node } => {
+                let pos = self.pos;
+                with_volume(*id, |fs| {
+                    fs.refresh(node)?;
+                    fs.read(node, pos, buf)
+                })?
+            }
+        };
+        self.pos += len as u64;
+        Ok(len)
+    }
+
+    /// Read everything from the current position to the end of the file
+    ///
+    /// # Arguments
+    /// * `buf` - Vector to append the bytes to
+    ///
+    /// # Returns
+    /// * `Ok(n)` - Number of bytes appended
+    pub fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        let remaining = self.len()?.saturating_sub(self.pos) as usize;
+        let start = buf.len();
+        buf.resize(start + remaining, 0);
+        let len = self.read(&mut buf[start..])?;
+        buf.truncate(start + len);
+        Ok(len)
+    }
+
+    /// Write at the current position, or at the end in append mode
+    ///
+    /// Writing past the end fills the gap with zeros.
+    ///
+    /// # Arguments
+    /// * `buf` - Bytes to write
+    ///
+    /// # Returns
+    /// * `Ok(n)` - Number of bytes written, all of `buf`
+    /// * `Err(Error::InvalidInput)` - The file was not opened for writing
+    /// * `Err(Error::NoSpace)` - The volume is full
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if !self.write {
+            return Err(Error::InvalidInput);
+        }
+        if self.append {
+            self.pos = self.len()?;
+        }
+
+        let Handle::Volume { id, node } = &mut self.handle else {
+            return Err(Error::ReadOnly);
+        };
+        let pos = self.pos;
+        let len = with_volume(*id, |fs| {
+            fs.refresh(node)?;
+            fs.write(node, pos, buf)
+        })?;
+        self.pos += len as u64;
+        Ok(len)
+    }
+
+    /// Make written data durable on the device
+    pub fn flush(&mut self) -> Result<()> {
+        match &self.handle {
+            Handle::Ramdisk(_) => Ok(()),
+            Handle::Volume { id, .. } => with_volume(*id, |fs| fs.flush()),
+        }
+    }
+
+    /// Truncate or extend the file; new bytes are zeros
+    ///
+    /// # Returns
+    /// * `Ok(())` - The file has the new size; the position is unchanged
+    /// * `Err(Error::InvalidInput)` - The file was not opened for writing
+    pub fn set_len(&mut self, len: u64) -> Result<()> {
+        if !self.write {
+            return Err(Error::InvalidInput);
+        }
+        let Handle::Volume { id, node } = &mut self.handle else {
+            return Err(Error::ReadOnly);
+        };
+        with_volume(*id, |fs| {
+            fs.refresh(node)?;
+            fs.set_len(node, len)
+        })
+    }
+
+    /// Move the current position
+    ///
+    /// Seeking past the end is allowed; reads there return 0 bytes.
+    ///
+    /// # Arguments
+    /// * `pos` - The new position
+    ///
+    /// # Returns
+    /// * `Ok(pos)` - The new position from the start of the file
+    /// * `Err(Error::InvalidInput)` - The position would be before the start
+    pub fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(pos) => (pos, 0),
+            SeekFrom::End(offset) => (self.len()?, offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+        self.pos = base.checked_add_signed(offset).ok_or(Error::InvalidInput)?;
+        Ok(self.pos)
+    }
+
+    /// Metadata of the open file
+    pub fn metadata(&mut self) -> Result<Metadata> {
+        match &mut self.handle {
+            Handle::Ramdisk(entry) => Ok(Node::Entry(*entry).metadata()),
+            Handle::Volume { id, node } => with_volume(*id, |fs| {
+                fs.refresh(node)?;
+                Ok(volume_metadata(Some(node)))
+            }),
+        }
+    }
+}
+
+/// Metadata of a path
+///
+/// # Returns
+/// * `Ok(metadata)` - Metadata of the file or directory
+/// * `Err(Error::NotFound)` - Nothing exists at the path
+pub fn metadata(path: &str) -> Result<Metadata> {
+    match route(path)? {
+        Route::Ramdisk(path) => Ok(lookup(path)?.metadata()),
+        Route::Volume { id, path } => {
+            with_volume(id, |fs| Ok(volume_metadata(fs.lookup(path)?.as_ref())))
+        }
+    }
+}
+
+/// Read a whole file
+///
+/// # Returns
+/// * `Ok(bytes)` - The file contents
+/// * `Err(error)` - The file could not be opened
+pub fn read(path: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Write a whole file, creating it or replacing its contents
+///
+/// # Returns
+/// * `Ok(())` - The file holds `data`
+/// * `Err(error)` - The file could not be created or written
+pub fn write(path: &str, data: &[u8]) -> Result<()> {
+    File::create(path)?.write(data)?;
+    Ok(())
+}
+
+/// Create a directory on a volume
+///
+/// # Returns
+/// * `Ok(())` - The directory is created
+/// * `Err(Error::AlreadyExists)` - Something exists at the path
+/// * `Err(Error::NotFound)` - The parent directory does not exist
+pub fn create_dir(path: &str) -> Result<()> {
+    match route(path)? {
+        Route::Ramdisk(_) => Err(Error::ReadOnly),
+        Route::Volume { path: "", .. } => Err(Error::AlreadyExists),
+        Route::Volume { id, path } => with_volume(id, |fs| fs.create(path, true).map(|_| ())),
+    }
+}
+
+/// Remove a file from a volume
+///
+/// # Returns
+/// * `Ok(())` - The file is removed
+/// * `Err(Error::IsADirectory)` - The path is a directory
+pub fn remove_file(path: &str) -> Result<()> {
+    match route(path)? {
+        Route::Ramdisk(_) => Err(Error::ReadOnly),
+        Route::Volume { id, path } => with_volume(id, |fs| match fs.lookup(path)? {
+            Some(node) if !node.is_dir() => fs.remove(&node),
+            _ => Err(Error::IsADirectory),
+        }),
+    }
+}
+
+/// Remove an empty directory from a volume
+///
+/// # Returns
+/// * `Ok(())` - The directory is removed
+/// * `Err(Error::DirectoryNotEmpty)` - The directory has entries
+/// * `Err(Error::InvalidInput)` - The path is a mount point
+pub fn remove_dir(path: &str) -> Result<()> {
+    match route(path)? {
+        Route::Ramdisk(_) => Err(Error::ReadOnly),
+        Route::Volume { id, path } => with_volume(id, |fs| match fs.lookup(path)? {
+            Some(node) if node.is_dir() => fs.remove(&node),
+            Some(_) => Err(Error::NotADirectory),
+            None => Err(Error::InvalidInput),
+        }),
+    }
+}
+
+/// An entry returned by [`read_dir`]
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    path: String,
+    metadata: Metadata,
+}
+
+impl DirEntry {
+    /// Full path of the entry, without a leading `/`
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Last component of the path
+    pub fn file_name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or_default()
+    }
+
+    /// Metadata of the entry
+    pub fn metadata(&self) -> Metadata {
+        self.metadata
+    }
+
+    /// Kind of the entry
+    pub fn file_type(&self) -> FileType {
+        self.metadata.file_type
+    }
+}
+
+/// Iterator over the entries of a directory, sorted by name
+#[derive(Debug)]
+pub struct ReadDir {
+    entries: alloc::vec::IntoIter<DirEntry>,
+}
+
+impl Iterator for ReadDir {
+    type Item = DirEntry;
+
+    fn next(&mut self) -> Option<DirEntry> {
+        self.entries.next()
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        String::from(name)
+    } else {
+        alloc::format!("{}/{}", dir, name)
+    }
+}
+
+/// List a directory
+///
+/// # Arguments
+/// * `path` - Path of the directory, `/` or an empty path for the root
+///
+/// # Returns
+/// * `Ok(entries)` - Iterator over the directory's entries
+/// * `Err(error)` - The path does not exist or is not a directory
+pub fn read_dir(path: &str) -> Result<ReadDir> {
+    let dir = ramdisk::normalize(path).ok_or(Error::InvalidInput)?;
+    let mut entries = match route(path)? {
+        Route::Ramdisk(_) => read_ramdisk_dir(dir)?,
+        Route::Volume { id, path } => with_volume(id, |fs| {
+            let node = fs.lookup(path)?;
+            let nodes = fs.list(FatFs::dir_of(node.as_ref())?)?;
+            Ok(nodes
+                .iter()
+                .map(|node| DirEntry {
+                    path: join(dir, &node.name),
+                    metadata: volume_metadata(Some(node)),
+                })
+                .collect())
+        })?,
+    };
+    entries.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(ReadDir {
+        entries: entries.into_iter(),
+    })
+}
+
+fn read_ramdisk_dir(dir: &str) -> Result<Vec<DirEntry>> {
+    if !lookup(dir)?.metadata().is_dir() {
+        return Err(Error::NotADirectory);
+    }
+
+    let mut names: Vec<String> = with_entries(|entries| {
+        Ok(entries
+            .iter()
+            .filter_map(|e| {
+                let rest = if dir.is_empty() {
+                    e.path
+                } else {
+                    e.path.strip_prefix(dir)?.strip_prefix('/')?
+                };
+                rest.split('/').next().map(String::from)
+            })
+            .collect())
+    })?;
+    if dir.is_empty() {
+        names.extend(mount_points());
+    }
+    names.sort_unstable();
+    names.dedup();
+
+    names
+        .iter()
+        .map(|name| {
+            let path = join(dir, name);
+            let metadata = metadata(&path)?;
+            Ok(DirEntry { path, metadata })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use fat::tests::RamDisk;
+    use ramdisk::tests::{CPIO_DIR, CPIO_FILE, cpio_image};
+
+    /// The tests share the global ramdisk and mount table
+    static FS_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    #[test]
+    fn test_ramdisk_api() {
+        let _guard = FS_LOCK.lock().unwrap();
+        mount(cpio_image(&[
+            ("images", CPIO_DIR, b""),
+            ("images/b.bin", CPIO_FILE, b"bb"),
+            ("images/a.bin", CPIO_FILE, b"0123456789"),
+            ("fonts/mono/8x8.fnt", CPIO_FILE, b"font"),
+        ]))
+        .unwrap();
+
+        let mut file = File::open("/images/a.bin").unwrap();
+        let mut buf = [0u8; 4];
+        assert_eq!(file.read(&mut buf), Ok(4));
+        assert_eq!(&buf, b"0123");
+        assert_eq!(file.seek(SeekFrom::End(-2)), Ok(8));
+        assert_eq!(file.read(&mut buf), Ok(2));
+        assert_eq!(&buf[..2], b"89");
+        assert_eq!(file.seek(SeekFrom::Current(-11)), Err(Error::InvalidInput));
+        assert_eq!(file.metadata().unwrap().len(), 10);
+
+        let names: Vec<String> = read_dir("/")
+            .unwrap()
+            .map(|e| String::from(e.file_name()))
+            .collect();
+        assert_eq!(names, ["fonts", "images"]);
+        let images: Vec<String> = read_dir("images")
+            .unwrap()
+            .map(|e| String::from(e.path()))
+            .collect();
+        assert_eq!(images, ["images/a.bin", "images/b.bin"]);
+
+        assert!(metadata("fonts/mono").unwrap().is_dir());
+        assert_eq!(read("fonts/mono/8x8.fnt").unwrap(), b"font");
+        assert_eq!(File::open("images").unwrap_err(), Error::IsADirectory);
+        assert_eq!(File::open("missing").unwrap_err(), Error::NotFound);
+        assert_eq!(read_dir("images/a.bin").unwrap_err(), Error::NotADirectory);
+    }
+
+    #[test]
+    fn test_volume_api() {
+        let _guard = FS_LOCK.lock().unwrap();
+        mount(cpio_image(&[("readme.txt", CPIO_FILE, b"hi")])).unwrap();
+
+        let mut disk = RamDisk::new(16 * 1024);
+        format(&mut disk, FatType::Fat16).unwrap();
+        mount_device(Box::new(disk.clone()), "/disk").unwrap();
+        assert_eq!(
+            mount_device(Box::new(disk), "disk").unwrap_err(),
+            Error::AlreadyExists
+        );
+
+        create_dir("/disk/results").unwrap();
+        write("/disk/results/Run 1.txt", b"accuracy").unwrap();
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open("/disk/results/Run 1.txt")
+            .unwrap();
+        assert_eq!(file.write(b" 0.98"), Ok(5));
+        assert_eq!(file.read(&mut [0; 4]), Err(Error::InvalidInput));
+        assert_eq!(read("disk/RESULTS/run 1.TXT").unwrap(), b"accuracy 0.98");
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/disk/results/Run 1.txt")
+            .unwrap();
+        assert_eq!(file.seek(SeekFrom::End(-4)), Ok(9));
+        file.write(b"1.00").unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"accuracy 1.00");
+        assert_eq!(file.metadata().unwrap().len(), 13);
+
+        let names: Vec<String> = read_dir("/")
+            .unwrap()
+            .map(|e| String::from(e.file_name()))
+            .collect();
+        assert_eq!(names, ["disk", "readme.txt"]);
+        let results: Vec<String> = read_dir("/disk/results")
+            .unwrap()
+            .map(|e| String::from(e.path()))
+            .collect();
+        assert_eq!(results, ["disk/results/Run 1.txt"]);
+        assert!(metadata("/disk").unwrap().is_dir());
+
+        assert_eq!(File::create("/readme.txt").unwrap_err(), Error::ReadOnly);
+        assert_eq!(
+            remove_dir("/disk/results").unwrap_err(),
+            Error::DirectoryNotEmpty
+        );
+        remove_file("/disk/results/Run 1.txt").unwrap();
+        remove_dir("/disk/results").unwrap();
+        assert_eq!(metadata("/disk/results").unwrap_err(), Error::NotFound);
+
+        unmount("/disk").unwrap();
+        assert_eq!(metadata("/disk").unwrap_err(), Error::NotFound);
+    }
+}
@@ -0,0 +1,282 @@
+//! Ramdisk archive parsing
+//!
+//! Two archive formats are understood, told apart by their magic:
+//! - cpio "newc" (`070701`), as written by `build_helper::pack_ramdisk`
+//! - POSIX ustar, as written by `tar --format=ustar`
+//!
+//! Parsing only indexes the archive; file contents stay in place.
+
+use super::{Error, FileType, Result};
+use alloc::vec::Vec;
+
+const CPIO_MAGIC: &[u8] = b"070701";
+const CPIO_HEADER_LEN: usize = 110;
+const CPIO_TRAILER: &str = "TRAILER!!!";
+
+const TAR_BLOCK: usize = 512;
+const TAR_MAGIC: &[u8] = b"ustar";
+const TAR_MAGIC_OFFSET: usize = 257;
+
+/// File type bits of a Unix mode
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+/// One archive member
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Entry {
+    /// Normalized path, without leading or trailing `/`
+    pub path: &'static str,
+    pub data: &'static [u8],
+    pub kind: FileType,
+    /// Permission bits
+    pub mode: u32,
+    /// Modification time in seconds since the UNIX epoch
+    pub mtime: u64,
+}
+
+/// Strip `./`, leading and trailing `/` and empty components
+///
+/// `.` and `..` components are rejected rather than resolved.
+pub(super) fn normalize(path: &str) -> Option<&str> {
+    let path = path.trim_start_matches("./").trim_matches('/');
+    if path.is_empty() || path == "." {
+        return Some("");
+    }
+    if path
+        .split('/')
+        .any(|c| c.is_empty() || c == "." || c == "..")
+    {
+        return None;
+    }
+    Some(path)
+}
+
+/// Index an archive
+///
+/// # Arguments
+/// * `image` - The whole archive; an empty image is an empty filesystem
+///
+/// # Returns
+/// * `Ok(entries)` - Regular files and directories, in archive order
+/// * `Err(Error::InvalidArchive)` - The image is neither cpio newc nor ustar,
+///   or is truncated
+pub(super) fn parse(image: &'static [u8]) -> Result<Vec<Entry>> {
+    if image.is_empty() {
+        Ok(Vec::new())
+    } else if image.starts_with(CPIO_MAGIC) {
+        parse_cpio(image)
+    } else if image.get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + TAR_MAGIC.len()) == Some(TAR_MAGIC) {
+        parse_tar(image)
+    } else {
+        Err(Error::InvalidArchive)
+    }
+}
+
+fn align4(value: usize) -> usize {
+    (value + 3) & !3
+}
+
+fn parse_number(field: &[u8], radix: u32) -> Result<u64> {
+    let text = core::str::from_utf8(field).map_err(|_| Error::InvalidArchive)?;
+    let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, radix).map_err(|_| Error::InvalidArchive)
+}
+
+fn make_entry(path: &'static str, data: &'static [u8], mode: u32, mtime: u64) -> Option<Entry> {
+    let kind = match mode & S_IFMT {
+        S_IFDIR => FileType::Dir,
+        S_IFREG => FileType::File,
+        // Links, devices and the like are not exposed
+        _ => return None,
+    };
+    let path = normalize(path)?;
+    if path.is_empty() {
+        return None;
+    }
+
+    Some(Entry {
+        path,
+        data,
+        kind,
+        mode: mode & !S_IFMT,
+        mtime,
+    })
+}
+
+fn parse_cpio(image: &'static [u8]) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let header = image
+            .get(offset..offset + CPIO_HEADER_LEN)
+            .ok_or(Error::InvalidArchive)?;
+        if !header.starts_with(CPIO_MAGIC) {
+            return Err(Error::InvalidArchive);
+        }
+        // Fields are 8 hex digits each, following the magic
+        let field = |index: usize| parse_number(&header[6 + 8 * index..14 + 8 * index], 16);
+        let mode = field(1)? as u32;
+        let mtime = field(5)?;
+        let size = field(6)? as usize;
+        let name_size = field(11)? as usize;
+
+        let name_start = offset + CPIO_HEADER_LEN;
+        let name = image
+            .get(name_start..name_start + name_size.saturating_sub(1))
+            .ok_or(Error::InvalidArchive)?;
+        let name = core::str::from_utf8(name).map_err(|_| Error::InvalidArchive)?;
+        if name == CPIO_TRAILER {
+            break;
+        }
+
+        let data_start = align4(name_start + name_size);
+        let data = image
+            .get(data_start..data_start + size)
+            .ok_or(Error::InvalidArchive)?;
+        entries.extend(make_entry(name, data, mode, mtime));
+
+        offset = align4(data_start + size);
+    }
+
+    Ok(entries)
+}
+
+fn parse_tar(image: &'static [u8]) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+
+    while let Some(header) = image.get(offset..offset + TAR_BLOCK) {
+        // The archive ends with zero blocks
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+
+        let text = |range: core::ops::Range<usize>| {
+            let field = &header[range];
+            let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+            core::str::from_utf8(&field[..end]).map_err(|_| Error::InvalidArchive)
+        };
+        let name = text(0..100)?;
+        let prefix = text(345..500)?;
+        let mode = parse_number(&header[100..108], 8)? as u32 & 0o7777;
+        let size = parse_number(&header[124..136], 8)? as usize;
+        let mtime = parse_number(&header[136..148], 8)?;
+
+        let data_start = offset + TAR_BLOCK;
+        let data = image
+            .get(data_start..data_start + size)
+            .ok_or(Error::InvalidArchive)?;
+
+        let kind = match header[156] {
+            b'0' | 0 => Some(S_IFREG),
+            b'5' => Some(S_IFDIR),
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            // Paths longer than 100 bytes are split into prefix and name,
+            // which only borrow from the archive when the prefix is empty
+            let path: &'static str = if prefix.is_empty() {
+                name
+            } else {
+                alloc::boxed::Box::leak(alloc::format!("{}/{}", prefix, name).into_boxed_str())
+            };
+            entries.extend(make_entry(path, data, kind | mode, mtime));
+        }
+
+        offset = data_start + size.div_ceil(TAR_BLOCK) * TAR_BLOCK;
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    pub const CPIO_DIR: u32 = S_IFDIR | 0o755;
+    pub const CPIO_FILE: u32 = S_IFREG | 0o644;
+
+    /// Build a cpio newc archive from `(path, mode, data)` members
+    pub fn cpio_image(members: &[(&str, u32, &[u8])]) -> &'static [u8] {
+        let mut image = Vec::new();
+        for (name, mode, data) in members {
+            cpio_member(&mut image, name, *mode, data);
+        }
+        cpio_member(&mut image, CPIO_TRAILER, 0, b"");
+        image.leak()
+    }
+
+    fn cpio_member(out: &mut Vec<u8>, name: &str, mode: u32, data: &[u8]) {
+        let fields = [
+            0,
+            mode,
+            0,
+            0,
+            1,
+            1_700_000_000,
+            data.len() as u32,
+            0,
+            0,
+            0,
+            0,
+        ];
+        let mut header = alloc::string::String::from("070701");
+        for field in fields {
+            header.push_str(&alloc::format!("{:08x}", field));
+        }
+        header.push_str(&alloc::format!("{:08x}{:08x}", name.len() + 1, 0));
+        out.extend_from_slice(header.as_bytes());
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+        out.resize(align4(out.len()), 0);
+        out.extend_from_slice(data);
+        out.resize(align4(out.len()), 0);
+    }
+
+    #[test]
+    fn test_cpio() {
+        let image = cpio_image(&[("data", CPIO_DIR, b""), ("data/a.bin", CPIO_FILE, b"hello")]);
+
+        let entries = parse(image).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, "data");
+        assert_eq!(entries[0].kind, FileType::Dir);
+        assert_eq!(entries[1].path, "data/a.bin");
+        assert_eq!(entries[1].data, b"hello");
+        assert_eq!(entries[1].mode, 0o644);
+        assert_eq!(entries[1].mtime, 1_700_000_000);
+    }
+
+    #[test]
+    fn test_tar() {
+        let mut image = alloc::vec![0u8; 3 * TAR_BLOCK];
+        let header = &mut image[..TAR_BLOCK];
+        header[..7].copy_from_slice(b"./x.txt");
+        header[100..108].copy_from_slice(b"0000644\0");
+        header[124..136].copy_from_slice(b"00000000003\0");
+        header[136..148].copy_from_slice(b"00000000012\0");
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        image[TAR_BLOCK..TAR_BLOCK + 3].copy_from_slice(b"abc");
+        let image = image.leak();
+
+        let entries = parse(image).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, "x.txt");
+        assert_eq!(entries[0].data, b"abc");
+        assert_eq!(entries[0].mtime, 0o12);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(parse(b"not an archive").unwrap_err(), Error::InvalidArchive);
+        assert_eq!(parse(b"070701").unwrap_err(), Error::InvalidArchive);
+        assert_eq!(normalize("/a//b"), None);
+        assert_eq!(normalize("./a/b/"), Some("a/b"));
+    }
+}
@@ -0,0 +1,19 @@
+//! The display
+//!
+//! [`framebuffer`] hands out the platform's display, to draw into as
+//! `0x00RRGGBB` pixels. A frame can be saved, to a file or over the
+//! network, with [`encode_ppm`].
+
+use crate::platform::{Current, Platform};
+use alloc::boxed::Box;
+
+pub use common::{Framebuffer, encode_ppm};
+
+/// Take the platform's display
+///
+/// # Returns
+/// * `Some(display)` - On the first call, if the platform has a display
+/// * `None` - There is no display, or it was taken already
+pub fn framebuffer() -> Option<Box<dyn Framebuffer + Send>> {
+    Current::framebuffer()
+}
@@ -0,0 +1,19 @@
+//! The keyboard
+//!
+//! [`keyboard`] hands out the platform's keyboard, which reports each key
+//! going down and up. Text typed on the console is read from
+//! [`stdin`](crate::io::stdin) instead.
+
+use crate::platform::{Current, Platform};
+use alloc::boxed::Box;
+
+pub use common::{Key, KeyEvent, Keyboard};
+
+/// Take the platform's keyboard
+///
+/// # Returns
+/// * `Some(keyboard)` - On the first call, if the platform has a keyboard
+/// * `None` - There is no keyboard, or it was taken already
+pub fn keyboard() -> Option<Box<dyn Keyboard + Send>> {
+    Current::keyboard()
+}
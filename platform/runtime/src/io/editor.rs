@@ -0,0 +1,437 @@
+//! Line editing for terminal input
+//!
+//! Understands the keys a VT100-style terminal sends: arrows (history and
+//! cursor movement), Home/End, Delete, Backspace, and the usual control keys
+//! (`^A`, `^E`, `^K`, `^U`, `^C`, `^D`), in both normal and
+//! application-cursor mode. Other escape sequences (function keys,
+//! modifiers) are skipped whole. Typed text is echoed as it is edited. Only
+//! ASCII is accepted.
+
+use super::{Error, Stdout, stdin};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// Number of lines kept in the history
+const HISTORY_LEN: usize = 64;
+
+const CTRL_A: u8 = 0x01;
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const CTRL_E: u8 = 0x05;
+const BACKSPACE: u8 = 0x08;
+const TAB: u8 = 0x09;
+const CTRL_K: u8 = 0x0b;
+const CTRL_U: u8 = 0x15;
+const ESC: u8 = 0x1b;
+const DEL: u8 = 0x7f;
+
+/// Candidates for the word being completed
+///
+/// Called with the line before that word and the word itself; returns the
+/// full words it could become. Words ending in `/` are not followed by a
+/// space, so that completion can continue into a directory.
+pub type Completer = fn(&str, &str) -> Vec<String>;
+
+/// Progress of an escape sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    None,
+    /// After `ESC`
+    Start,
+    /// After `ESC O`, waiting for the key letter
+    Ss3,
+    /// After `ESC [`, until a final byte: the first parameter so far, and
+    /// whether a `;` ended it (later parameters, like the modifier of
+    /// `ESC [ 1 ; 5 C`, are ignored)
+    Csi {
+        param: u16,
+        done: bool,
+    },
+}
+
+/// What a key did to the line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Event {
+    Pending,
+    /// Enter was pressed
+    Done,
+    /// `^C`
+    Interrupted,
+    /// `^D` on an empty line
+    Eof,
+}
+
+/// A line editor with history
+///
+/// [`Stdin::read_line`](super::Stdin::read_line) uses a shared one;
+/// programs that want their own history or tab completion create another.
+pub struct Editor {
+    history: Vec<String>,
+    completer: Option<Completer>,
+    prompt: String,
+    text: Vec<u8>,
+    cursor: usize,
+    /// Position in the history, and the unfinished line while browsing it
+    index: usize,
+    draft: String,
+    escape: Escape,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Editor {
+    pub const fn new() -> Self {
+        Self {
+            history: Vec::new(),
+            completer: None,
+            prompt: String::new(),
+            text: Vec::new(),
+            cursor: 0,
+            index: 0,
+            draft: String::new(),
+            escape: Escape::None,
+        }
+    }
+
+    /// Complete the word before the cursor on Tab
+    pub fn with_completer(mut self, completer: Completer) -> Self {
+        self.completer = Some(completer);
+        self
+    }
+
+    /// Lines entered so far, oldest first
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Read one line from stdin, blocking
+    ///
+    /// # Arguments
+    /// * `prompt` - Printed before the line, and again on every redraw
+    ///
+    /// # Returns
+    /// * `Ok(Some(line))` - The line, without its terminator
+    /// * `Ok(None)` - `^D` on an empty line
+    /// * `Err(Error::Interrupted)` - `^C`
+    pub fn read_line(&mut self, prompt: &str) -> Result<Option<String>, Error> {
+        self.start(prompt, &mut Stdout);
+        loop {
+            match self.feed(super::next_key(&stdin()), &mut Stdout) {
+                Event::Pending => {}
+                Event::Done => return Ok(Some(self.finish())),
+                Event::Interrupted => return Err(Error::Interrupted),
+                Event::Eof => return Ok(None),
+            }
+        }
+    }
+
+    fn start(&mut self, prompt: &str, out: &mut impl Write) {
+        self.prompt = String::from(prompt);
+        self.text.clear();
+        self.cursor = 0;
+        self.index = self.history.len();
+        self.draft.clear();
+        self.escape = Escape::None;
+        let _ = write!(out, "{}", prompt);
+    }
+
+    /// Take the entered line, recording it in the history
+    fn finish(&mut self) -> String {
+        let line = String::from(self.as_str());
+        if !line.trim().is_empty() && self.history.last() != Some(&line) {
+            if self.history.len() == HISTORY_LEN {
+                self.history.remove(0);
+            }
+            self.history.push(line.clone());
+        }
+        line
+    }
+
+    fn as_str(&self) -> &str {
+        // Only ASCII is ever inserted
+        core::str::from_utf8(&self.text).unwrap()
+    }
+
+    fn redraw(&self, out: &mut impl Write) {
+        let _ = write!(out, "\r{}{}\x1b[K", self.prompt, self.as_str());
+        if self.cursor < self.text.len() {
+            let _ = write!(out, "\x1b[{}D", self.text.len() - self.cursor);
+        }
+    }
+
+    fn replace(&mut self, text: String, out: &mut impl Write) {
+        self.text = text.into_bytes();
+        self.cursor = self.text.len();
+        self.redraw(out);
+    }
+
+    fn insert(&mut self, text: &[u8], out: &mut impl Write) {
+        self.text
+            .splice(self.cursor..self.cursor, text.iter().copied());
+        self.cursor += text.len();
+        self.redraw(out);
+    }
+
+    fn move_to(&mut self, cursor: usize, out: &mut impl Write) {
+        self.cursor = cursor;
+        self.redraw(out);
+    }
+
+    fn complete(&mut self, out: &mut impl Write) {
+        let Some(completer) = self.completer else {
+            return;
+        };
+        let before = &self.as_str()[..self.cursor];
+        let start = before.rfind(' ').map_or(0, |space| space + 1);
+        let (head, word) = before.split_at(start);
+        let word_len = word.len();
+        let candidates = completer(head, word);
+
+        let Some(first) = candidates.first() else {
+            return;
+        };
+        let common = candidates.iter().fold(first.len(), |len, candidate| {
+            first
+                .bytes()
+                .zip(candidate.bytes())
+                .take(len)
+                .take_while(|(a, b)| a == b)
+                .count()
+        });
+
+        if candidates.len() == 1 {
+            let mut rest = String::from(&first[word_len..]);
+            if !rest.ends_with('/') {
+                rest.push(' ');
+            }
+            self.insert(rest.as_bytes(), out);
+        } else if common > word_len {
+            self.insert(&first.as_bytes()[word_len..common], out);
+        } else {
+            let _ = write!(out, "\n{}\n", candidates.join("  "));
+            self.redraw(out);
+        }
+    }
+
+    fn history_up(&mut self, out: &mut impl Write) {
+        if self.index == 0 {
+            return;
+        }
+        if self.index == self.history.len() {
+            self.draft = String::from(self.as_str());
+        }
+        self.index -= 1;
+        self.replace(self.history[self.index].clone(), out);
+    }
+
+    fn history_down(&mut self, out: &mut impl Write) {
+        if self.index == self.history.len() {
+            return;
+        }
+        self.index += 1;
+        let text = self.history.get(self.index).unwrap_or(&self.draft).clone();
+        self.replace(text, out);
+    }
+
+    fn delete(&mut self, out: &mut impl Write) {
+        if self.cursor < self.text.len() {
+            self.text.remove(self.cursor);
+            self.redraw(out);
+        }
+    }
+
+    /// Act on a complete escape sequence; unknown ones are ignored
+    ///
+    /// # Arguments
+    /// * `key` - The final byte, the letter after `ESC O` or `ESC [`
+    /// * `param` - The first parameter of a CSI sequence, 0 if none
+    /// * `out` - Where the line is redrawn
+    fn escape_sequence(&mut self, key: u8, param: u16, out: &mut impl Write) {
+        match (key, param) {
+            (b'A', _) => self.history_up(out),
+            (b'B', _) => self.history_down(out),
+            (b'C', _) if self.cursor < self.text.len() => self.move_to(self.cursor + 1, out),
+            (b'D', _) if self.cursor > 0 => self.move_to(self.cursor - 1, out),
+            (b'H', _) | (b'~', 1 | 7) => self.move_to(0, out),
+            (b'F', _) | (b'~', 4 | 8) => self.move_to(self.text.len(), out),
+            (b'~', 3) => self.delete(out),
+            _ => {}
+        }
+    }
+
+    /// Handle one key, with line endings already normalized to `\n`
+    fn feed(&mut self, key: u8, out: &mut impl Write) -> Event {
+        match self.escape {
+            Escape::None => {}
+            Escape::Start => {
+                self.escape = match key {
+                    b'[' => Escape::Csi {
+                        param: 0,
+                        done: false,
+                    },
+                    b'O' => Escape::Ss3,
+                    _ => Escape::None,
+                };
+                return Event::Pending;
+            }
+            Escape::Ss3 => {
+                self.escape = Escape::None;
+                self.escape_sequence(key, 0, out);
+                return Event::Pending;
+            }
+            Escape::Csi { param, done } => {
+                match key {
+                    b'0'..=b'9' if !done => {
+                        let param = param.saturating_mul(10).saturating_add((key - b'0') as u16);
+                        self.escape = Escape::Csi { param, done };
+                    }
+                    // Further parameters and intermediate bytes
+                    0x20..=0x3f => self.escape = Escape::Csi { param, done: true },
+                    0x40..=0x7e => {
+                        self.escape = Escape::None;
+                        self.escape_sequence(key, param, out);
+                    }
+                    // Not part of a sequence: drop the sequence, keep the key
+                    _ => {
+                        self.escape = Escape::None;
+                        return self.feed(key, out);
+                    }
+                }
+                return Event::Pending;
+            }
+        }
+
+        match key {
+            b'\n' => {
+                let _ = writeln!(out);
+                return Event::Done;
+            }
+            CTRL_C => {
+                let _ = writeln!(out, "^C");
+                return Event::Interrupted;
+            }
+            CTRL_D if self.text.is_empty() => {
+                let _ = writeln!(out);
+                return Event::Eof;
+            }
+            CTRL_D => self.delete(out),
+            BACKSPACE | DEL if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.text.remove(self.cursor);
+                self.redraw(out);
+            }
+            TAB => self.complete(out),
+            CTRL_A => self.move_to(0, out),
+            CTRL_E => self.move_to(self.text.len(), out),
+            CTRL_K => {
+                self.text.truncate(self.cursor);
+                self.redraw(out);
+            }
+            CTRL_U => {
+                self.text.drain(..self.cursor);
+                self.move_to(0, out);
+            }
+            ESC => self.escape = Escape::Start,
+            0x20..0x7f => self.insert(&[key], out),
+            _ => {}
+        }
+        Event::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Type `keys` into `editor`, returning the last event
+    fn type_keys(editor: &mut Editor, keys: &[u8]) -> Event {
+        let mut out = String::new();
+        editor.start("> ", &mut out);
+        let mut event = Event::Pending;
+        for &key in keys {
+            event = editor.feed(key, &mut out);
+            if event != Event::Pending {
+                break;
+            }
+        }
+        event
+    }
+
+    #[test]
+    fn test_editing() {
+        let mut editor = Editor::new();
+        // Backspace, then insert in the middle after moving left
+        assert_eq!(
+            type_keys(&mut editor, b"helo\x7f\x7flo\x1b[D\x1b[Dl\n"),
+            Event::Done
+        );
+        assert_eq!(editor.finish(), "hello");
+
+        // Home, Delete and ^K
+        assert_eq!(
+            type_keys(&mut editor, b"xyz\x01\x1b[3~\x1b[C\x0b\n"),
+            Event::Done
+        );
+        assert_eq!(editor.finish(), "y");
+
+        assert_eq!(type_keys(&mut editor, b"abc\x03"), Event::Interrupted);
+        assert_eq!(type_keys(&mut editor, b"\x04"), Event::Eof);
+    }
+
+    #[test]
+    fn test_escape_sequences() {
+        let mut editor = Editor::new();
+        // Ctrl-Left, with a modifier parameter
+        type_keys(&mut editor, b"ab\x1b[1;5Dc\n");
+        assert_eq!(editor.finish(), "acb");
+
+        // F5 and other unknown sequences insert nothing
+        type_keys(&mut editor, b"ab\x1b[15~\x1b[2;3Z\n");
+        assert_eq!(editor.finish(), "ab");
+
+        // Application-cursor mode: Home, End and Left as SS3
+        type_keys(&mut editor, b"ab\x1bOHc\x1bOFd\x1bODe\n");
+        assert_eq!(editor.finish(), "cabed");
+
+        // A control byte ends an unfinished sequence and still counts
+        assert_eq!(type_keys(&mut editor, b"ab\x1b[1\x03"), Event::Interrupted);
+    }
+
+    #[test]
+    fn test_history() {
+        let mut editor = Editor::new();
+        for line in ["first", "second"] {
+            type_keys(&mut editor, line.as_bytes());
+            editor.finish();
+        }
+        assert_eq!(editor.history(), ["first", "second"]);
+
+        type_keys(&mut editor, b"draft\x1b[A\x1b[A");
+        assert_eq!(editor.as_str(), "first");
+        type_keys(&mut editor, b"draft\x1b[A\x1b[A\x1b[B\x1b[B");
+        assert_eq!(editor.as_str(), "draft");
+    }
+
+    #[test]
+    fn test_complete() {
+        fn completer(head: &str, word: &str) -> Vec<String> {
+            assert_eq!(head, "run ");
+            ["hello", "help"]
+                .iter()
+                .filter(|name| name.starts_with(word))
+                .map(|&name| String::from(name))
+                .collect()
+        }
+
+        let mut editor = Editor::new().with_completer(completer);
+        type_keys(&mut editor, b"run h\t");
+        assert_eq!(editor.as_str(), "run hel");
+        type_keys(&mut editor, b"run hell\t");
+        assert_eq!(editor.as_str(), "run hello ");
+    }
+}
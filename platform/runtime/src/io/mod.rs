@@ -1,54 +1,152 @@
+//! Console I/O
+//!
+//! [`Stdout`], [`Stderr`] and [`Stdin`] talk to the platform console through
+//! the `putc`, `getc` and `try_getc` of its [`Platform`] implementation. Besides
+//! `core::fmt::Write` and the line-oriented [`Stdin::read_line`], they
+//! implement the [`embedded_io`] traits, so `embedded-io` based crates can
+//! use the console directly.
+
 extern crate alloc;
 
-/// Common Stdout implementation
-///
-/// This struct provides a Write implementation that calls the platform-specific putc function.
-/// Each platform must provide a `putc(ch: u8)` function.
-pub struct Stdout;
-
-impl core::fmt::Write for Stdout {
-    fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        // Call platform-specific putc function
-        unsafe extern "Rust" {
-            fn putc(ch: u8);
-        }
+mod editor;
+mod stdout;
+pub use editor::{Completer, Editor};
+pub(crate) use stdout::flush as flush_stdout;
+pub use stdout::{BufferMode, Stdout, StdoutLock, stdout};
+
+use crate::platform::{Current, Platform};
+use crate::time::{Duration, SystemTime};
+use core::cell::{Cell, RefCell};
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+use critical_section::Mutex;
+
+pub use embedded_io::{ErrorKind, ErrorType, Read, ReadReady, Write, WriteReady};
+
+/// Whether [`Stdin::read_line`] edits lines, see [`set_line_editing`]
+static LINE_EDITING: AtomicBool = AtomicBool::new(true);
+
+/// The last key read was `\r`
+static AFTER_CR: AtomicBool = AtomicBool::new(false);
+
+/// History shared by all [`Stdin::read_line`] calls
+static EDITOR: Mutex<RefCell<Option<Editor>>> = Mutex::new(RefCell::new(None));
+
+/// A byte taken by [`ReadReady::read_ready`] to see whether input is pending
+static PEEKED: Mutex<Cell<Option<u8>>> = Mutex::new(Cell::new(None));
+
+/// Console I/O errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// No data is available and the call would have to wait
+    WouldBlock,
+    /// The operation was cancelled with `^C`
+    Interrupted,
+    /// A line is not valid UTF-8
+    InvalidUtf8,
+    /// No data arrived in time
+    TimedOut,
+    /// The platform has no such device, or no clock for a timeout
+    Unsupported,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            Error::WouldBlock => "operation would block",
+            Error::Interrupted => "interrupted",
+            Error::InvalidUtf8 => "stream did not contain valid UTF-8",
+            Error::TimedOut => "timed out",
+            Error::Unsupported => "unsupported",
+        };
+        f.write_str(message)
+    }
+}
 
-        for byte in s.bytes() {
-            unsafe { putc(byte) };
+impl embedded_io::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            // embedded-io has no kind for it, non-blocking calls are not
+            // part of its traits
+            Error::WouldBlock => ErrorKind::Other,
+            Error::Interrupted => ErrorKind::Interrupted,
+            Error::InvalidUtf8 => ErrorKind::InvalidData,
+            Error::TimedOut => ErrorKind::TimedOut,
+            Error::Unsupported => ErrorKind::Unsupported,
         }
+    }
+}
+
+pub(crate) fn putc(ch: u8) {
+    Current::putc(ch)
+}
+
+/// Standard error, for [`eprint!`](crate::eprint) and
+/// [`eprintln!`](crate::eprintln)
+///
+/// The platforms have a single console, so this writes to the same device
+/// as [`Stdout`]. It is unbuffered, and flushes stdout before each write so
+/// that the two stay in order.
+pub struct Stderr;
+
+fn write_stderr(bytes: &[u8]) {
+    stdout::flush();
+    bytes.iter().copied().for_each(putc);
+}
+
+impl fmt::Write for Stderr {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_stderr(s.as_bytes());
         Ok(())
     }
 }
 
-/// Get a Stdout handle
-pub fn stdout() -> Stdout {
-    Stdout
+impl ErrorType for Stderr {
+    type Error = Error;
+}
+
+impl Write for Stderr {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        write_stderr(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl WriteReady for Stderr {
+    fn write_ready(&mut self) -> Result<bool, Error> {
+        Ok(true)
+    }
+}
+
+/// Get a Stderr handle
+pub fn stderr() -> Stderr {
+    Stderr
 }
 
 /// Common Stdin implementation
 ///
-/// This struct provides Read-like functionality using platform-specific getc/try_getc functions.
-/// Each platform must provide:
-/// - `getc() -> u8` - Blocking character read
-/// - `try_getc() -> Option<u8>` - Non-blocking character read
+/// This struct provides Read-like functionality on the console of the
+/// platform, through its [`Platform`] implementation.
 pub struct Stdin;
 
-pub enum Error {
-    WTF,
-}
-
 impl Stdin {
     /// Read a single character (blocking)
     ///
     /// This function blocks until a character is available from stdin.
+    /// Buffered stdout is flushed first, so that prompts are visible.
     ///
     /// # Returns
     /// * The received character byte
-    pub fn read(&self) -> u8 {
-        unsafe extern "Rust" {
-            fn getc() -> u8;
+    pub fn read_byte(&self) -> u8 {
+        stdout::flush();
+        if let Some(ch) = critical_section::with(|cs| PEEKED.borrow(cs).take()) {
+            return ch;
         }
-        unsafe { getc() }
+        Current::getc()
     }
 
     /// Try to read a single character (non-blocking)
@@ -59,47 +157,159 @@ impl Stdin {
     /// * `Some(ch)` - Character byte if available
     /// * `None` - No character available
     pub fn try_getc(&self) -> Option<u8> {
-        unsafe extern "Rust" {
-            fn try_getc() -> Option<u8>;
+        if let Some(ch) = critical_section::with(|cs| PEEKED.borrow(cs).take()) {
+            return Some(ch);
+        }
+        Current::try_getc()
+    }
+
+    /// Read whatever input is pending, without waiting
+    ///
+    /// # Returns
+    /// * `Ok(n)` - `n` bytes were read, at least one unless `buf` is empty
+    /// * `Err(Error::WouldBlock)` - No input is pending
+    pub fn try_read(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut count = 0;
+        while count < buf.len() {
+            match self.try_getc() {
+                Some(ch) => buf[count] = ch,
+                None => break,
+            }
+            count += 1;
+        }
+        if count == 0 && !buf.is_empty() {
+            return Err(Error::WouldBlock);
+        }
+        Ok(count)
+    }
+
+    /// Read a single character, waiting at most `timeout`
+    ///
+    /// # Returns
+    /// * `Ok(ch)` - The received character byte
+    /// * `Err(Error::TimedOut)` - Nothing arrived in time
+    /// * `Err(Error::Unsupported)` - The platform has no clock to time out by
+    pub fn read_byte_timeout(&self, timeout: Duration) -> Result<u8, Error> {
+        if Current::rtc_read().is_none() {
+            return Err(Error::Unsupported);
+        }
+        stdout::flush();
+        let start = SystemTime::now();
+        loop {
+            if let Some(ch) = self.try_getc() {
+                return Ok(ch);
+            }
+            if start.elapsed().unwrap_or_default() >= timeout {
+                return Err(Error::TimedOut);
+            }
         }
-        unsafe { try_getc() }
     }
 
     /// Read a line into a string (blocking)
     ///
-    /// This function reads characters until a newline ('\n') is encountered.
-    /// The newline is not included in the returned string.
+    /// With line editing on (the default), typed text is echoed and can be
+    /// edited, and earlier lines are recalled with the up and down arrows,
+    /// see [`Editor`]. Otherwise bytes are taken as they come, without echo.
+    /// Either way, `\r`, `\n` and `\r\n` all end a line.
     ///
     /// # Arguments
-    /// * `buffer` - String to append the line to
+    /// * `buffer` - String to append the line to, with a `\n` terminator
     ///
     /// # Returns
-    /// * Number of bytes read (excluding newline)
+    /// * `Ok(n)` - Number of bytes appended, 0 at end of input (`^D` on an
+    ///   empty line)
+    /// * `Err(Error::Interrupted)` - The line was cancelled with `^C`
+    /// * `Err(Error::InvalidUtf8)` - Without line editing, the line is not
+    ///   valid UTF-8 (the editor only accepts ASCII)
     pub fn read_line(&self, buffer: &mut alloc::string::String) -> Result<usize, Error> {
-        let mut count = 0;
-        loop {
-            let ch = self.read();
-            count += 1;
-
-            // Handle different line endings
-            if ch == b'\n' {
-                break;
-            } else if ch == b'\r' {
-                // Check for \r\n
-                if let Some(next) = self.try_getc() {
-                    if next != b'\n' {
-                        // Just \r, treat as newline but we consumed an extra char
-                        // Push it back conceptually (just break)
-                        break;
-                    }
+        if !LINE_EDITING.load(Ordering::Relaxed) {
+            let mut line = alloc::vec::Vec::new();
+            loop {
+                match next_key(self) {
+                    b'\n' => break,
+                    ch => line.push(ch),
                 }
-                break;
             }
+            line.push(b'\n');
+            let line = alloc::string::String::from_utf8(line).map_err(|_| Error::InvalidUtf8)?;
+            buffer.push_str(&line);
+            return Ok(line.len());
+        }
+
+        // The editor is taken out while the line is typed, so that
+        // interrupts stay enabled
+        let mut editor =
+            critical_section::with(|cs| EDITOR.borrow_ref_mut(cs).take()).unwrap_or_default();
+        let line = editor.read_line("");
+        critical_section::with(|cs| EDITOR.borrow_ref_mut(cs).replace(editor));
+
+        match line? {
+            Some(line) => {
+                buffer.push_str(&line);
+                buffer.push('\n');
+                Ok(line.len() + 1)
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+/// Turn line editing in [`Stdin::read_line`] on or off
+///
+/// It is on by default. Turn it off for input that does not come from a
+/// person at a terminal, where echo and escape sequences get in the way.
+pub fn set_line_editing(enabled: bool) {
+    LINE_EDITING.store(enabled, Ordering::Relaxed);
+}
 
-            buffer.push(ch as char);
+/// Read a key, with `\r`, `\n` and `\r\n` all turned into one `\n`
+///
+/// A `\n` right after a `\r` is dropped when it arrives, rather than
+/// waited for after the `\r`: that would hold up a line ended by a bare
+/// `\r` until the next key.
+fn next_key(stdin: &Stdin) -> u8 {
+    loop {
+        let key = stdin.read_byte();
+        if let Some(key) = normalize_newline(&AFTER_CR, key) {
+            return key;
         }
+    }
+}
 
-        Ok(count)
+fn normalize_newline(after_cr: &AtomicBool, key: u8) -> Option<u8> {
+    let skip = after_cr.load(Ordering::Relaxed) && key == b'\n';
+    after_cr.store(key == b'\r', Ordering::Relaxed);
+    match key {
+        _ if skip => None,
+        b'\r' => Some(b'\n'),
+        key => Some(key),
+    }
+}
+
+impl ErrorType for Stdin {
+    type Error = Error;
+}
+
+impl Read for Stdin {
+    /// Wait for input, then read as much as is pending
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let Some((first, rest)) = buf.split_first_mut() else {
+            return Ok(0);
+        };
+        *first = self.read_byte();
+        Ok(1 + self.try_read(rest).unwrap_or(0))
+    }
+}
+
+impl ReadReady for Stdin {
+    fn read_ready(&mut self) -> Result<bool, Error> {
+        critical_section::with(|cs| {
+            let peeked = PEEKED.borrow(cs);
+            if peeked.get().is_none() {
+                peeked.set(Current::try_getc());
+            }
+            Ok(peeked.get().is_some())
+        })
     }
 }
 
@@ -107,3 +317,18 @@ impl Stdin {
 pub fn stdin() -> Stdin {
     Stdin
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_newline() {
+        let after_cr = AtomicBool::new(false);
+        let keys: alloc::vec::Vec<u8> = b"a\r\nb\rc\n\n\r\r"
+            .iter()
+            .filter_map(|&key| normalize_newline(&after_cr, key))
+            .collect();
+        assert_eq!(keys, b"a\nb\nc\n\n\n\n");
+    }
+}
@@ -0,0 +1,226 @@
+//! Buffered standard output
+//!
+//! Output is collected in a buffer shared by all [`Stdout`] handles and
+//! written to the console a line at a time (see [`BufferMode`]). Every write
+//! happens inside a critical section, so output from interrupt handlers does
+//! not end up in the middle of a line, and [`Stdout::lock`] keeps several
+//! writes together.
+//!
+//! The buffer is flushed by [`Stdout::flush`], before blocking reads from
+//! [`Stdin`](super::Stdin), before anything is written to
+//! [`Stderr`](super::Stderr), and when the program exits or panics.
+
+use super::{Error, ErrorType, Write, WriteReady, putc};
+use core::cell::RefCell;
+use core::fmt;
+use core::marker::PhantomData;
+use critical_section::Mutex;
+
+/// Size of the stdout buffer, a longer line is written out in pieces
+const BUFFER_LEN: usize = 256;
+
+/// When buffered output is written to the console
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferMode {
+    /// Every write goes straight to the console
+    Unbuffered,
+    /// Output is written at each newline, when the buffer is full, and on
+    /// [`Stdout::flush`] (the default)
+    Line,
+}
+
+struct Buffer {
+    data: [u8; BUFFER_LEN],
+    len: usize,
+    mode: BufferMode,
+}
+
+impl Buffer {
+    const fn new() -> Self {
+        Self {
+            data: [0; BUFFER_LEN],
+            len: 0,
+            mode: BufferMode::Line,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8], mut out: impl FnMut(u8)) {
+        if self.mode == BufferMode::Unbuffered {
+            self.flush(&mut out);
+            bytes.iter().copied().for_each(out);
+            return;
+        }
+
+        for &byte in bytes {
+            self.data[self.len] = byte;
+            self.len += 1;
+            if byte == b'\n' || self.len == BUFFER_LEN {
+                self.flush(&mut out);
+            }
+        }
+    }
+
+    fn flush(&mut self, out: impl FnMut(u8)) {
+        self.data[..self.len].iter().copied().for_each(out);
+        self.len = 0;
+    }
+}
+
+static BUFFER: Mutex<RefCell<Buffer>> = Mutex::new(RefCell::new(Buffer::new()));
+
+/// Run `f` on the buffer
+///
+/// If the buffer is already in use further up the stack (a `Display` impl
+/// that prints, say), the bytes bypass it rather than panicking.
+fn with_buffer(bytes: &[u8], f: impl FnOnce(&mut Buffer)) {
+    critical_section::with(|cs| match BUFFER.borrow(cs).try_borrow_mut() {
+        Ok(mut buffer) => f(&mut buffer),
+        Err(_) => bytes.iter().copied().for_each(putc),
+    })
+}
+
+/// Write out anything buffered for stdout
+pub(crate) fn flush() {
+    with_buffer(&[], |buffer| buffer.flush(putc));
+}
+
+/// Common Stdout implementation
+///
+/// This struct provides a Write implementation on top of the platform's
+/// `putc`, buffered as described in the module documentation.
+pub struct Stdout;
+
+impl Stdout {
+    /// Lock stdout, so that output written through the guard is not mixed
+    /// with output from interrupt handlers
+    ///
+    /// Interrupts stay disabled until the guard is dropped, so keep it short.
+    pub fn lock(&self) -> StdoutLock {
+        StdoutLock {
+            restore: unsafe { critical_section::acquire() },
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Choose when output is written to the console
+    ///
+    /// Anything already buffered is written first.
+    pub fn set_buffer_mode(&self, mode: BufferMode) {
+        with_buffer(&[], |buffer| {
+            buffer.flush(putc);
+            buffer.mode = mode;
+        });
+    }
+}
+
+fn write_bytes(bytes: &[u8]) {
+    with_buffer(bytes, |buffer| buffer.write(bytes, putc));
+}
+
+impl fmt::Write for Stdout {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl ErrorType for Stdout {
+    type Error = Error;
+}
+
+impl Write for Stdout {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        write_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        flush();
+        Ok(())
+    }
+}
+
+impl WriteReady for Stdout {
+    fn write_ready(&mut self) -> Result<bool, Error> {
+        Ok(true)
+    }
+}
+
+/// Get a Stdout handle
+pub fn stdout() -> Stdout {
+    Stdout
+}
+
+/// A locked [`Stdout`], see [`Stdout::lock`]
+///
+/// Holds a critical section; on the single-hart platforms this is all it
+/// takes to keep other output out.
+pub struct StdoutLock {
+    restore: critical_section::RestoreState,
+    /// The critical section belongs to this hart
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for StdoutLock {
+    fn drop(&mut self) {
+        unsafe { critical_section::release(self.restore) }
+    }
+}
+
+impl fmt::Write for StdoutLock {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl ErrorType for StdoutLock {
+    type Error = Error;
+}
+
+impl Write for StdoutLock {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        write_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        flush();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_line_buffering() {
+        let mut buffer = Buffer::new();
+        let mut out = Vec::new();
+
+        buffer.write(b"partial", |byte| out.push(byte));
+        assert!(out.is_empty());
+        buffer.write(b" line\nrest", |byte| out.push(byte));
+        assert_eq!(out, b"partial line\n");
+
+        // A full buffer is written out without waiting for the newline
+        buffer.write(&[b'x'; BUFFER_LEN], |byte| out.push(byte));
+        assert_eq!(out.len(), b"partial line\n".len() + BUFFER_LEN);
+        assert!(out[b"partial line\n".len()..].starts_with(b"restxxx"));
+
+        buffer.flush(|byte| out.push(byte));
+        assert_eq!(out.len(), b"partial line\nrest".len() + BUFFER_LEN);
+    }
+
+    #[test]
+    fn test_unbuffered() {
+        let mut buffer = Buffer::new();
+        let mut out = Vec::new();
+        buffer.write(b"held", |byte| out.push(byte));
+        buffer.mode = BufferMode::Unbuffered;
+        buffer.write(b"!", |byte| out.push(byte));
+        assert_eq!(out, b"held!");
+    }
+}
@@ -0,0 +1,550 @@
+//! Persistent key-value store
+//!
+//! Small settings and counters that survive across runs, kept in flash: on
+//! QEMU, the `kvstore` partition of the second pflash bank (see the
+//! build-helper memory map). Other platforms have no flash, and every call
+//! returns [`Error::Unsupported`].
+//!
+//! The store is a log. Each sector starts with a header of [`MAGIC`] and a
+//! sequence number, and the valid sector with the highest number is active.
+//! Changes are appended to the active sector as records:
+//!
+//! | Bytes | Field                                                     |
+//! |-------|-----------------------------------------------------------|
+//! | 4     | Key length (low half), value length or `0xffff` (removal) |
+//! | n     | Key, then value, zero padded to 4 bytes                   |
+//! | 4     | CRC-32 of the above, left erased if the write was cut off |
+//!
+//! When the active sector is full, the live entries are copied to the next
+//! sector, whose header is written last. An interrupted write or copy thus
+//! leaves the previous state readable.
+
+use crate::platform::{Current, Platform};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+use critical_section::Mutex;
+
+pub use common::{FlashDevice, FlashError};
+
+/// Marks a sector that belongs to the store ("AMKV")
+pub const MAGIC: u32 = 0x564b_4d41;
+/// Longest key in bytes
+pub const MAX_KEY_LEN: usize = 255;
+/// Longest value in bytes, further limited by the sector size
+pub const MAX_VALUE_LEN: usize = 0xfffe;
+
+const HEADER_LEN: u32 = 8;
+const ERASED: u32 = u32::MAX;
+/// Value length of a removal record
+const REMOVED: u32 = 0xffff;
+
+/// Key-value store errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The platform has no flash for the store
+    Unsupported,
+    /// The key is empty or longer than [`MAX_KEY_LEN`]
+    InvalidKey,
+    /// The value is too long for a record
+    TooLarge,
+    /// The live entries no longer fit in a sector
+    NoSpace,
+    /// The value is not a counter written by [`increment`]
+    NotACounter,
+    /// The flash reported an error
+    Flash(FlashError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Unsupported => f.write_str("no flash for the key-value store"),
+            Error::InvalidKey => f.write_str("invalid key"),
+            Error::TooLarge => f.write_str("value too large"),
+            Error::NoSpace => f.write_str("key-value store full"),
+            Error::NotACounter => f.write_str("value is not a counter"),
+            Error::Flash(err) => write!(f, "flash error: {}", err),
+        }
+    }
+}
+
+impl From<FlashError> for Error {
+    fn from(err: FlashError) -> Self {
+        Error::Flash(err)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// CRC-32 (IEEE 802.3)
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                crc >> 1 ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Length of a record body (length word, key and value), padded
+fn body_len(key_len: usize, value_len: usize) -> u32 {
+    (4 + key_len + value_len).next_multiple_of(4) as u32
+}
+
+/// A store on a flash region of at least two sectors
+pub struct KvStore {
+    flash: Box<dyn FlashDevice + Send>,
+    sector_size: u32,
+    sectors: u32,
+    /// Index of the active sector
+    active: u32,
+    /// Sequence number of the active sector
+    seq: u32,
+    /// Offset of the first free byte in the active sector
+    end: u32,
+}
+
+impl KvStore {
+    /// Open the store on a flash region, initializing it if needed
+    ///
+    /// # Returns
+    /// * `Ok(store)` - The store, with whatever it held before
+    /// * `Err(Error::Unsupported)` - The region has fewer than two sectors,
+    ///   or a write size over 4 bytes
+    pub fn open(flash: Box<dyn FlashDevice + Send>) -> Result<Self> {
+        let sector_size = flash.sector_size();
+        let sectors = flash.size() / sector_size;
+        let write_size = flash.write_size();
+        if sectors < 2 || write_size > 4 || !4u32.is_multiple_of(write_size) {
+            return Err(Error::Unsupported);
+        }
+
+        let mut store = Self {
+            flash,
+            sector_size,
+            sectors,
+            active: 0,
+            seq: 0,
+            end: HEADER_LEN,
+        };
+
+        let mut newest = None;
+        for sector in 0..sectors {
+            let mut header = [0u8; HEADER_LEN as usize];
+            store.flash.read(sector * sector_size, &mut header)?;
+            let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+            let seq = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+            if magic == MAGIC && seq != ERASED && newest.is_none_or(|(_, newest)| seq > newest) {
+                newest = Some((sector, seq));
+            }
+        }
+
+        match newest {
+            Some((sector, seq)) => {
+                store.active = sector;
+                store.seq = seq;
+                store.end = store.scan(|_, _| {})?;
+            }
+            None => {
+                store.flash.erase(0)?;
+                store.write_header(0, 1)?;
+                store.seq = 1;
+            }
+        }
+        Ok(store)
+    }
+
+    fn write_header(&mut self, sector: u32, seq: u32) -> Result<()> {
+        let mut header = [0u8; HEADER_LEN as usize];
+        header[..4].copy_from_slice(&MAGIC.to_le_bytes());
+        header[4..].copy_from_slice(&seq.to_le_bytes());
+        self.flash.program(sector * self.sector_size, &header)?;
+        Ok(())
+    }
+
+    fn read_word(&mut self, offset: u32) -> Result<u32> {
+        let mut word = [0u8; 4];
+        self.flash.read(offset, &mut word)?;
+        Ok(u32::from_le_bytes(word))
+    }
+
+    /// Call `f` with the key and value (`None` for a removal) of every
+    /// committed record of the active sector, oldest first
+    ///
+    /// # Returns
+    /// * `Ok(end)` - Offset of the first free byte in the sector
+    fn scan(&mut self, mut f: impl FnMut(&[u8], Option<&[u8]>)) -> Result<u32> {
+        let base = self.active * self.sector_size;
+        let mut pos = HEADER_LEN;
+
+        while pos + 4 <= self.sector_size {
+            let word = self.read_word(base + pos)?;
+            if word == ERASED {
+                return Ok(pos);
+            }
+
+            let key_len = (word & 0xffff) as usize;
+            let value_len = match word >> 16 {
+                REMOVED => 0,
+                len => len as usize,
+            };
+            let body = body_len(key_len, value_len);
+            if pos + body + 4 > self.sector_size {
+                // A damaged length word: nothing after it can be trusted
+                return Ok(self.sector_size);
+            }
+
+            let mut data = alloc::vec![0u8; body as usize];
+            self.flash.read(base + pos, &mut data)?;
+            let crc = self.read_word(base + pos + body)?;
+            // Records cut off before their CRC was written are skipped
+            if crc == crc32(&data) {
+                let key = &data[4..4 + key_len];
+                let value =
+                    (word >> 16 != REMOVED).then(|| &data[4 + key_len..4 + key_len + value_len]);
+                f(key, value);
+            }
+            pos += body + 4;
+        }
+        Ok(self.sector_size)
+    }
+
+    /// Latest value of every key, without removed keys
+    fn live(&mut self) -> Result<BTreeMap<Vec<u8>, Vec<u8>>> {
+        let mut entries = BTreeMap::new();
+        self.scan(|key, value| match value {
+            Some(value) => {
+                entries.insert(key.to_vec(), value.to_vec());
+            }
+            None => {
+                entries.remove(key);
+            }
+        })?;
+        Ok(entries)
+    }
+
+    /// Program a record at `pos` in `sector`
+    fn write_record(
+        &mut self,
+        sector: u32,
+        pos: u32,
+        key: &[u8],
+        value: Option<&[u8]>,
+    ) -> Result<u32> {
+        let value_len = value.map_or(0, <[u8]>::len);
+        let len_word = key.len() as u32 | value.map_or(REMOVED, |v| v.len() as u32) << 16;
+        let mut body = Vec::with_capacity(body_len(key.len(), value_len) as usize);
+        body.extend_from_slice(&len_word.to_le_bytes());
+        body.extend_from_slice(key);
+        body.extend_from_slice(value.unwrap_or_default());
+        body.resize(body_len(key.len(), value_len) as usize, 0);
+
+        let offset = sector * self.sector_size + pos;
+        self.flash.program(offset, &body)?;
+        self.flash
+            .program(offset + body.len() as u32, &crc32(&body).to_le_bytes())?;
+        Ok(pos + body.len() as u32 + 4)
+    }
+
+    /// Copy the live entries, with one change applied, to the next sector
+    fn compact(&mut self, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+        let mut entries = self.live()?;
+        match value {
+            Some(value) => entries.insert(key.to_vec(), value.to_vec()),
+            None => entries.remove(key),
+        };
+
+        let needed: u32 = entries
+            .iter()
+            .map(|(key, value)| body_len(key.len(), value.len()) + 4)
+            .sum();
+        if HEADER_LEN + needed > self.sector_size {
+            return Err(Error::NoSpace);
+        }
+
+        let next = (self.active + 1) % self.sectors;
+        self.flash.erase(next * self.sector_size)?;
+        let mut pos = HEADER_LEN;
+        for (key, value) in &entries {
+            pos = self.write_record(next, pos, key, Some(value))?;
+        }
+        self.write_header(next, self.seq + 1)?;
+
+        self.active = next;
+        self.seq += 1;
+        self.end = pos;
+        Ok(())
+    }
+
+    fn append(&mut self, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+        let len = body_len(key.len(), value.map_or(0, <[u8]>::len)) + 4;
+        if HEADER_LEN + len > self.sector_size {
+            return Err(Error::TooLarge);
+        }
+        if self.end + len > self.sector_size {
+            return self.compact(key, value);
+        }
+        self.end = self.write_record(self.active, self.end, key, value)?;
+        Ok(())
+    }
+
+    /// Read a value
+    ///
+    /// # Returns
+    /// * `Ok(Some(value))` - The latest value of the key
+    /// * `Ok(None)` - The key is not set
+    pub fn get(&mut self, key: &str) -> Result<Option<Vec<u8>>> {
+        let key = check_key(key)?;
+        let mut found = None;
+        self.scan(|k, value| {
+            if k == key {
+                found = value.map(<[u8]>::to_vec);
+            }
+        })?;
+        Ok(found)
+    }
+
+    /// Set a value, replacing any previous one
+    ///
+    /// Setting the value a key already has writes nothing.
+    ///
+    /// # Returns
+    /// * `Ok(())` - The value is stored in flash
+    /// * `Err(Error::TooLarge)` - The value is too long
+    /// * `Err(Error::NoSpace)` - The entries no longer fit in a sector
+    pub fn set(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        if value.len() > MAX_VALUE_LEN {
+            return Err(Error::TooLarge);
+        }
+        if self.get(key)?.as_deref() == Some(value) {
+            return Ok(());
+        }
+        self.append(key.as_bytes(), Some(value))
+    }
+
+    /// Remove a key
+    ///
+    /// # Returns
+    /// * `Ok(true)` - The key was set and is removed
+    /// * `Ok(false)` - The key was not set
+    pub fn remove(&mut self, key: &str) -> Result<bool> {
+        if self.get(key)?.is_none() {
+            return Ok(false);
+        }
+        self.append(key.as_bytes(), None)?;
+        Ok(true)
+    }
+
+    /// Every key that is set, sorted
+    pub fn keys(&mut self) -> Result<Vec<String>> {
+        Ok(self
+            .live()?
+            .into_keys()
+            .map(|key| String::from_utf8_lossy(&key).into_owned())
+            .collect())
+    }
+
+    /// Add one to a counter, starting from 0
+    ///
+    /// # Returns
+    /// * `Ok(count)` - The new count
+    /// * `Err(Error::NotACounter)` - The key holds some other value
+    pub fn increment(&mut self, key: &str) -> Result<u64> {
+        let count = match self.get(key)? {
+            None => 0,
+            Some(value) => u64::from_le_bytes(value.try_into().map_err(|_| Error::NotACounter)?),
+        };
+        let count = count.wrapping_add(1);
+        self.set(key, &count.to_le_bytes())?;
+        Ok(count)
+    }
+}
+
+fn check_key(key: &str) -> Result<&[u8]> {
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return Err(Error::InvalidKey);
+    }
+    Ok(key.as_bytes())
+}
+
+/// The platform store, opened on first use
+static STORE: Mutex<RefCell<Option<KvStore>>> = Mutex::new(RefCell::new(None));
+
+fn platform_flash() -> Option<Box<dyn FlashDevice + Send>> {
+    Current::kv_flash()
+}
+
+/// Run `f` on the platform store, opening it on first use
+fn with_store<T>(f: impl FnOnce(&mut KvStore) -> Result<T>) -> Result<T> {
+    critical_section::with(|cs| {
+        let mut store = STORE.borrow_ref_mut(cs);
+        if store.is_none() {
+            let flash = platform_flash().ok_or(Error::Unsupported)?;
+            *store = Some(KvStore::open(flash)?);
+        }
+        f(store.as_mut().ok_or(Error::Unsupported)?)
+    })
+}
+
+/// Read a value from the platform store; see [`KvStore::get`]
+pub fn get(key: &str) -> Result<Option<Vec<u8>>> {
+    with_store(|store| store.get(key))
+}
+
+/// Set a value in the platform store; see [`KvStore::set`]
+pub fn set(key: &str, value: &[u8]) -> Result<()> {
+    with_store(|store| store.set(key, value))
+}
+
+/// Remove a key from the platform store; see [`KvStore::remove`]
+pub fn remove(key: &str) -> Result<bool> {
+    with_store(|store| store.remove(key))
+}
+
+/// Keys of the platform store; see [`KvStore::keys`]
+pub fn keys() -> Result<Vec<String>> {
+    with_store(|store| store.keys())
+}
+
+/// Add one to a counter in the platform store; see [`KvStore::increment`]
+pub fn increment(key: &str) -> Result<u64> {
+    with_store(|store| store.increment(key))
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// NOR flash in memory: programming can only clear bits
+    #[derive(Clone)]
+    struct MemFlash {
+        data: Arc<Mutex<Vec<u8>>>,
+        sector_size: u32,
+    }
+
+    impl MemFlash {
+        fn new(sectors: u32, sector_size: u32) -> Self {
+            Self {
+                data: Arc::new(Mutex::new(
+                    alloc::vec![0xff; (sectors * sector_size) as usize],
+                )),
+                sector_size,
+            }
+        }
+    }
+
+    impl FlashDevice for MemFlash {
+        fn size(&self) -> u32 {
+            self.data.lock().unwrap().len() as u32
+        }
+
+        fn sector_size(&self) -> u32 {
+            self.sector_size
+        }
+
+        fn write_size(&self) -> u32 {
+            4
+        }
+
+        fn read(&mut self, offset: u32, buf: &mut [u8]) -> core::result::Result<(), FlashError> {
+            common::check_flash_access(self.size(), 1, offset, buf.len())?;
+            let start = offset as usize;
+            buf.copy_from_slice(&self.data.lock().unwrap()[start..start + buf.len()]);
+            Ok(())
+        }
+
+        fn erase(&mut self, offset: u32) -> core::result::Result<(), FlashError> {
+            common::check_flash_access(self.size(), self.sector_size, offset, 0)?;
+            let start = offset as usize;
+            self.data.lock().unwrap()[start..start + self.sector_size as usize].fill(0xff);
+            Ok(())
+        }
+
+        fn program(&mut self, offset: u32, data: &[u8]) -> core::result::Result<(), FlashError> {
+            common::check_flash_access(self.size(), 4, offset, data.len())?;
+            let mut flash = self.data.lock().unwrap();
+            for (cell, byte) in flash[offset as usize..].iter_mut().zip(data) {
+                *cell &= byte;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_set_get_remove_reopen() {
+        let flash = MemFlash::new(2, 1024);
+        let mut store = KvStore::open(Box::new(flash.clone())).unwrap();
+        assert_eq!(store.get("volume").unwrap(), None);
+        store.set("volume", b"7").unwrap();
+        store.set("name", b"am-rs").unwrap();
+        store.set("volume", b"11").unwrap();
+        assert_eq!(store.increment("boots"), Ok(1));
+        assert_eq!(store.remove("name"), Ok(true));
+        assert_eq!(store.remove("name"), Ok(false));
+        assert_eq!(store.increment("volume"), Err(Error::NotACounter));
+        assert_eq!(store.set("", b"x"), Err(Error::InvalidKey));
+
+        let mut store = KvStore::open(Box::new(flash)).unwrap();
+        assert_eq!(store.get("volume").unwrap().as_deref(), Some(&b"11"[..]));
+        assert_eq!(store.get("name").unwrap(), None);
+        assert_eq!(store.increment("boots"), Ok(2));
+        assert_eq!(store.keys().unwrap(), ["boots", "volume"]);
+    }
+
+    #[test]
+    fn test_compaction() {
+        let flash = MemFlash::new(3, 256);
+        let mut store = KvStore::open(Box::new(flash.clone())).unwrap();
+        store.set("config", b"keep me").unwrap();
+        for _ in 0..100 {
+            store.increment("counter").unwrap();
+        }
+        assert!(store.seq > 10);
+
+        let mut store = KvStore::open(Box::new(flash)).unwrap();
+        assert_eq!(store.increment("counter"), Ok(101));
+        assert_eq!(
+            store.get("config").unwrap().as_deref(),
+            Some(&b"keep me"[..])
+        );
+        assert_eq!(store.set("big", &[0; 300]), Err(Error::TooLarge));
+    }
+
+    #[test]
+    fn test_interrupted_write() {
+        let flash = MemFlash::new(2, 1024);
+        let mut store = KvStore::open(Box::new(flash.clone())).unwrap();
+        store.set("a", b"1").unwrap();
+
+        // A record whose CRC was never written
+        let end = store.end;
+        let torn = (1u32 | 1 << 16).to_le_bytes();
+        flash.clone().program(end, &torn).unwrap();
+        flash.clone().program(end + 4, b"b2\0\0").unwrap();
+
+        let mut store = KvStore::open(Box::new(flash)).unwrap();
+        assert_eq!(store.get("b").unwrap(), None);
+        store.set("b", b"3").unwrap();
+        assert_eq!(store.end, end + 12 + 12);
+        assert_eq!(store.get("b").unwrap().as_deref(), Some(&b"3"[..]));
+        assert_eq!(store.get("a").unwrap().as_deref(), Some(&b"1"[..]));
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+}
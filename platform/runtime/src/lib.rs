@@ -1,4 +1,10 @@
 #![no_std]
+// The unwinder needs the personality lang item and the catch intrinsic
+#![cfg_attr(
+    feature = "unwind",
+    feature(lang_items, core_intrinsics),
+    allow(internal_features)
+)]
 
 #[cfg(feature = "nemu")]
 pub use nemu_runtime::*;
@@ -9,7 +15,16 @@ pub use qemu_runtime::*;
 #[cfg(feature = "spike")]
 pub use spike_runtime::*;
 
-macros::mod_pub!(io);
+#[cfg(feature = "native")]
+pub use native_runtime::*;
+
+macros::mod_pub!(
+    arch, backtrace, dlog, elf, env, fs, graphic, input, io, kv, logger, mem, panic, platform,
+    power, process, random, time
+);
+
+#[cfg(feature = "unwind")]
+mod unwind;
 #[macro_export]
 macro_rules! print {
     ($($arg:tt)*) => {
@@ -33,6 +48,29 @@ macro_rules! println {
     };
 }
 
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => {
+        {
+            use core::fmt::Write;
+            let _ = write!($crate::io::stderr(), $($arg)*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! eprintln {
+    () => {
+        $crate::eprint!("\n")
+    };
+    ($($arg:tt)*) => {
+        {
+            use core::fmt::Write;
+            let _ = writeln!($crate::io::stderr(), $($arg)*);
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! preclude {
     () => {
@@ -48,7 +86,11 @@ macro_rules! addtest {
         mod on_test {
             #[test]
             fn main() {
-                super::main();
+                extern crate std;
+
+                let mut out = std::string::String::new();
+                let code = $crate::process::Termination::report(super::main(), &mut out);
+                assert_eq!(code, 0, "main failed: {}", out);
             }
         }
     };
@@ -66,9 +108,8 @@ macro_rules! binInit {
     () => {
         $crate::preclude!();
 
-        use embedded_alloc::LlffHeap;
         #[global_allocator]
-        static ALLOCATOR: LlffHeap = LlffHeap::empty();
+        static ALLOCATOR: $crate::mem::Heap = $crate::mem::Heap::empty();
 
         $crate::entry!(main);
     };
@@ -79,32 +120,96 @@ macro_rules! entry {
     ($path:path) => {
         #[unsafe(export_name = "user_entry")]
         pub unsafe fn __user_entry() -> ! {
-            let f: fn() = $path;
-
+            $crate::mem::paint_stack();
             $crate::heap_init!();
+            $crate::logger::init();
+
+            // As for hosted programs, a panic unwinding out of main (with
+            // the `unwind` feature) exits with PANIC_EXIT_CODE
+            let code = match $crate::panic::catch_unwind($path) {
+                Ok(value) => $crate::process::Termination::report(value, &mut $crate::io::stderr()),
+                Err(_) => $crate::process::PANIC_EXIT_CODE,
+            };
+            $crate::process::exit(code)
+        }
 
-            f();
+        $crate::native_main!();
+    };
+}
 
-            unsafe extern "Rust" {
-                fn platform_exit(code: i32) -> !;
+/// The C `main` of a native build, which starts the program as the
+/// platform's startup code does elsewhere
+#[cfg(feature = "native")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! native_main {
+    () => {
+        mod __native_main {
+            #[unsafe(no_mangle)]
+            extern "C" fn main(argc: core::ffi::c_int, argv: *const *const u8) -> core::ffi::c_int {
+                $crate::native_start(argc as usize, argv, || {
+                    $crate::process::exit($crate::process::PANIC_EXIT_CODE)
+                })
             }
-            unsafe { platform_exit(0) }
         }
     };
 }
 
+#[cfg(not(feature = "native"))]
+#[doc(hidden)]
 #[macro_export]
-macro_rules! heap_init {
+macro_rules! native_main {
+    () => {};
+}
+
+/// Link a ramdisk archive into the image, for [`fs`]
+///
+/// Without arguments, links the archive packed by
+/// `build_helper::pack_ramdisk` in the bin's build script.
+#[macro_export]
+macro_rules! ramdisk {
     () => {
-        unsafe {
-            unsafe extern "C" {
-                static mut _sheap: u8;
-                static mut _eheap: u8;
+        $crate::ramdisk!(env!("AM_RAMDISK"));
+    };
+    ($path:expr) => {
+        #[unsafe(link_section = ".ramdisk")]
+        #[used]
+        static __RAMDISK: [u8; include_bytes!($path).len()] = *include_bytes!($path);
+
+        $crate::native_ramdisk!(__RAMDISK);
+    };
+}
+
+/// Register the archive of `ramdisk!` in a native build, which has no
+/// linker script to bound it, from a constructor run before `main`
+#[cfg(feature = "native")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! native_ramdisk {
+    ($image:ident) => {
+        #[used]
+        #[unsafe(link_section = ".init_array")]
+        static __RAMDISK_INIT: extern "C" fn() = {
+            extern "C" fn register() {
+                $crate::fs::register_image(&$image);
             }
+            register
+        };
+    };
+}
+
+#[cfg(not(feature = "native"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! native_ramdisk {
+    ($image:ident) => {};
+}
 
-            let heap_start = core::ptr::addr_of_mut!(_sheap) as usize;
-            let heap_end = core::ptr::addr_of_mut!(_eheap) as usize;
-            let heap_size = heap_end - heap_start;
+#[macro_export]
+macro_rules! heap_init {
+    () => {
+        unsafe {
+            let (heap_start, heap_size) = $crate::mem::heap_region();
             ALLOCATOR.init(heap_start, heap_size);
         }
     };
@@ -112,13 +217,25 @@ macro_rules! heap_init {
 
 #[cfg(all(not(test), any(feature = "nemu", feature = "qemu", feature = "spike")))]
 mod panic_handler {
-    use crate::{exit::platform_exit, println};
+    use crate::process;
     use core::panic::PanicInfo;
 
     #[panic_handler]
     fn panic(info: &PanicInfo) -> ! {
-        println!("Panic: {}", info);
-        platform_exit(1)
+        eprintln!("Panic: {}", info);
+        // An infallible allocation failed and `handle_alloc_error` panicked
+        if let Some(layout) = crate::mem::failed_allocation() {
+            eprintln!(
+                "memory allocation of {} bytes (align {}) failed",
+                layout.size(),
+                layout.align()
+            );
+            eprintln!("heap: {}", crate::mem::heap_stats());
+        }
+        eprint!("{}", crate::backtrace::Backtrace::capture());
+        #[cfg(feature = "unwind")]
+        crate::unwind::begin_panic(info);
+        process::exit(process::PANIC_EXIT_CODE)
     }
 }
 
@@ -128,7 +245,7 @@ pub use alloc::rc;
 pub use alloc::vec;
 
 pub mod prelude {
-    pub use crate::{print, println};
+    pub use crate::{eprint, eprintln, print, println};
     pub use alloc::boxed::Box;
     pub use alloc::string::String;
     pub use alloc::vec::Vec;
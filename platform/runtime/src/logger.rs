@@ -0,0 +1,200 @@
+//! Backend for the [`log`] crate
+//!
+//! [`entry!`](crate::entry) installs it before `main` runs, so bins only
+//! need the `log` macros, re-exported here (`runtime::logger::info!` and
+//! friends).
+//!
+//! Each record becomes one line on stderr: a timestamp, the hart id, the
+//! level and the target (the module path by default), as in
+//! `[      123456] hart0 INFO  rv64emu: loaded 4096 bytes`.
+//!
+//! Records can be filtered twice. At compile time, the runtime's
+//! `max_level_*` and `release_max_level_*` features (those of the `log`
+//! crate) remove everything above a level from the binary. At run time,
+//! [`set_max_level`] hides records up to that ceiling, which is also the
+//! initial level. Lines are coloured by level with the `log-color` feature
+//! or [`set_color`].
+
+use crate::io::stderr;
+use crate::time::SystemTime;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use log::{Log, Metadata, Record};
+
+pub use log::{
+    Level, LevelFilter, STATIC_MAX_LEVEL, debug, error, info, log, max_level, trace, warn,
+};
+
+/// What the timestamp at the start of each line counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timestamp {
+    /// The cycle counter, see [`arch::cycles`](crate::arch::cycles) (the
+    /// default)
+    Cycles = 0,
+    /// Seconds read from the real-time clock, with microseconds
+    Time = 1,
+    /// No timestamp
+    None = 2,
+}
+
+static TIMESTAMP: AtomicU8 = AtomicU8::new(Timestamp::Cycles as u8);
+
+static COLOR: AtomicBool = AtomicBool::new(cfg!(feature = "log-color"));
+
+static LOGGER: Logger = Logger;
+
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let stamp = match timestamp() {
+            Timestamp::Cycles => Stamp::Cycles(crate::arch::cycles()),
+            Timestamp::Time => Stamp::Time(SystemTime::now().unix_nanos()),
+            Timestamp::None => Stamp::None,
+        };
+        let color = COLOR.load(Ordering::Relaxed);
+
+        // Keep the line in one piece if an interrupt handler logs too
+        critical_section::with(|_| {
+            let _ = write_record(&mut stderr(), stamp, crate::arch::hart_id(), record, color);
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// Install the logger, if no other logger is installed yet
+///
+/// Called by [`entry!`](crate::entry); the run-time level starts at the
+/// compile-time ceiling, [`STATIC_MAX_LEVEL`].
+pub fn init() {
+    critical_section::with(|_| {
+        // Safety: nothing else can run while interrupts are off, and the
+        // platforms have a single hart
+        if unsafe { log::set_logger_racy(&LOGGER) }.is_ok() {
+            set_max_level(STATIC_MAX_LEVEL);
+        }
+    });
+}
+
+/// Hide records above `level`
+///
+/// Levels above [`STATIC_MAX_LEVEL`] stay hidden, they are not in the
+/// binary.
+pub fn set_max_level(level: LevelFilter) {
+    // `log::set_max_level` needs atomic read-modify-write instructions,
+    // which riscv32i and riscv32im lack
+    critical_section::with(|_| unsafe { log::set_max_level_racy(level) });
+}
+
+/// Choose what the timestamp at the start of each line counts
+pub fn set_timestamp(timestamp: Timestamp) {
+    TIMESTAMP.store(timestamp as u8, Ordering::Relaxed);
+}
+
+fn timestamp() -> Timestamp {
+    match TIMESTAMP.load(Ordering::Relaxed) {
+        0 => Timestamp::Cycles,
+        1 => Timestamp::Time,
+        _ => Timestamp::None,
+    }
+}
+
+/// Turn ANSI colours on or off, on by default with the `log-color` feature
+pub fn set_color(enabled: bool) {
+    COLOR.store(enabled, Ordering::Relaxed);
+}
+
+/// A timestamp that has been read
+#[derive(Clone, Copy)]
+enum Stamp {
+    Cycles(u64),
+    /// Nanoseconds since the UNIX epoch
+    Time(u64),
+    None,
+}
+
+fn level_color(level: Level) -> &'static str {
+    match level {
+        Level::Error => "\x1b[31m",
+        Level::Warn => "\x1b[33m",
+        Level::Info => "\x1b[32m",
+        Level::Debug => "\x1b[34m",
+        Level::Trace => "\x1b[90m",
+    }
+}
+
+/// Format one record as a line
+fn write_record(
+    out: &mut impl Write,
+    stamp: Stamp,
+    hart: usize,
+    record: &Record,
+    color: bool,
+) -> fmt::Result {
+    match stamp {
+        Stamp::Cycles(cycles) => write!(out, "[{:>12}] ", cycles)?,
+        Stamp::Time(nanos) => write!(
+            out,
+            "[{:>6}.{:06}] ",
+            nanos / 1_000_000_000,
+            nanos % 1_000_000_000 / 1000
+        )?,
+        Stamp::None => {}
+    }
+    write!(out, "hart{} ", hart)?;
+
+    let level = record.level();
+    if color {
+        write!(out, "{}{:<5}\x1b[0m", level_color(level), level)?;
+    } else {
+        write!(out, "{:<5}", level)?;
+    }
+    writeln!(out, " {}: {}", record.target(), record.args())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+
+    fn format(stamp: Stamp, level: Level, color: bool) -> String {
+        let mut out = String::new();
+        write_record(
+            &mut out,
+            stamp,
+            0,
+            &Record::builder()
+                .args(format_args!("{} bytes", 42))
+                .level(level)
+                .target("shell")
+                .build(),
+            color,
+        )
+        .unwrap();
+        out
+    }
+
+    #[test]
+    fn test_write_record() {
+        assert_eq!(
+            format(Stamp::Cycles(1234), Level::Info, false),
+            "[        1234] hart0 INFO  shell: 42 bytes\n"
+        );
+        assert_eq!(
+            format(Stamp::Time(3_000_250_000), Level::Warn, false),
+            "[     3.000250] hart0 WARN  shell: 42 bytes\n"
+        );
+        assert_eq!(
+            format(Stamp::None, Level::Error, true),
+            "hart0 \x1b[31mERROR\x1b[0m shell: 42 bytes\n"
+        );
+    }
+}
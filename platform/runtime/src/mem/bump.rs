@@ -0,0 +1,120 @@
+//! A bump allocator, for the `alloc-bump` feature
+//!
+//! Allocation moves a pointer up through the heap and never searches, so it
+//! takes constant time and never fragments. Memory is only given back when
+//! the most recent allocation is freed, or grown in place by `realloc`,
+//! which suits programs that allocate their data once at startup.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::Cell;
+use core::ptr;
+use critical_section::Mutex;
+
+/// The next free address and the end of the heap
+pub struct BumpHeap {
+    state: Mutex<Cell<(usize, usize)>>,
+}
+
+impl BumpHeap {
+    /// An allocator without memory, until [`init`](Self::init)
+    pub const fn empty() -> Self {
+        Self {
+            state: Mutex::new(Cell::new((0, 0))),
+        }
+    }
+
+    /// Give the allocator its memory
+    ///
+    /// # Arguments
+    /// * `start` - First address of the heap
+    /// * `size` - Size of the heap in bytes
+    ///
+    /// # Safety
+    /// The memory must be unused by anything else, and this must be called
+    /// once, before any allocation.
+    pub unsafe fn init(&self, start: usize, size: usize) {
+        critical_section::with(|cs| self.state.borrow(cs).set((start, start + size)));
+    }
+}
+
+unsafe impl GlobalAlloc for BumpHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        critical_section::with(|cs| {
+            let state = self.state.borrow(cs);
+            let (next, end) = state.get();
+            let start = next
+                .checked_next_multiple_of(layout.align())
+                .unwrap_or(usize::MAX);
+            let new_next = start.saturating_add(layout.size());
+            if new_next > end {
+                return ptr::null_mut();
+            }
+            state.set((new_next, end));
+            start as *mut u8
+        })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        critical_section::with(|cs| {
+            let state = self.state.borrow(cs);
+            let (next, end) = state.get();
+            if ptr as usize + layout.size() == next {
+                state.set((ptr as usize, end));
+            }
+        })
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // The most recent allocation grows or shrinks in place
+        let in_place = critical_section::with(|cs| {
+            let state = self.state.borrow(cs);
+            let (next, end) = state.get();
+            let fits = end - ptr as usize >= new_size;
+            if ptr as usize + layout.size() == next && fits {
+                state.set((ptr as usize + new_size, end));
+                return true;
+            }
+            false
+        });
+        if in_place {
+            return ptr;
+        }
+
+        let new_layout = unsafe { Layout::from_size_align_unchecked(new_size, layout.align()) };
+        let new_ptr = unsafe { self.alloc(new_layout) };
+        if !new_ptr.is_null() {
+            unsafe { ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size)) };
+            unsafe { self.dealloc(ptr, layout) };
+        }
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bump_heap() {
+        let mut memory = [0usize; 16];
+        let start = memory.as_mut_ptr() as usize;
+        let heap = BumpHeap::empty();
+        unsafe { heap.init(start, 64) };
+
+        let byte = Layout::from_size_align(1, 1).unwrap();
+        let word = Layout::from_size_align(8, 8).unwrap();
+        unsafe {
+            let a = heap.alloc(byte);
+            assert_eq!(a as usize, start);
+            let b = heap.alloc(word);
+            assert_eq!(b as usize, start + 8);
+
+            // Only the last allocation is given back, or grown in place
+            heap.dealloc(a, byte);
+            assert_eq!(heap.realloc(b, word, 56), b);
+            assert!(heap.alloc(byte).is_null());
+            heap.dealloc(b, Layout::from_size_align(56, 8).unwrap());
+            assert_eq!(heap.alloc(word) as usize, start + 8);
+        }
+    }
+}
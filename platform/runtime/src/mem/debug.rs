@@ -0,0 +1,364 @@
+//! The debug heap, for the `debug-heap` feature
+//!
+//! Every allocation is laid out as a header, a red zone, the block and
+//! another red zone. Red zones hold [`RED_ZONE_BYTE`], a fresh block
+//! [`ALLOC_POISON`], and a freed block [`FREE_POISON`]: freed blocks stay
+//! in a quarantine of [`QUARANTINE_LEN`] blocks before their memory is
+//! reused, so that a write through a dangling pointer is still visible.
+//!
+//! Red zones and headers are verified when a block is freed, freed blocks
+//! when they leave quarantine, and everything by [`check_heap`]. The first
+//! corruption found is reported, with the size of the block and where it
+//! was allocated, and the program aborts.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::RefCell;
+use core::fmt;
+use core::ptr;
+use critical_section::Mutex;
+
+/// Fills the red zones around blocks
+pub const RED_ZONE_BYTE: u8 = 0xfd;
+
+/// Fills newly allocated blocks
+pub const ALLOC_POISON: u8 = 0xcd;
+
+/// Fills freed blocks
+pub const FREE_POISON: u8 = 0xdd;
+
+/// Freed blocks held back before their memory is reused
+pub const QUARANTINE_LEN: usize = 64;
+
+/// Bytes of red zone on each side of a block
+const RED_ZONE: usize = 16;
+
+/// Return addresses kept for the allocation site
+const SITE_FRAMES: usize = 6;
+
+const LIVE: usize = 0x4c49_5645;
+const FREED: usize = 0x4652_4545;
+
+/// Bookkeeping in front of each block
+#[repr(C)]
+struct Header {
+    magic: usize,
+    size: usize,
+    align: usize,
+    prev: *mut Header,
+    next: *mut Header,
+    site: [usize; SITE_FRAMES],
+}
+
+/// What is wrong with a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Corruption {
+    /// Its header was overwritten, or it was never allocated
+    BadHeader,
+    /// Bytes in front of it were written
+    Underflow,
+    /// Bytes past its end were written
+    Overflow,
+    /// It was written after being freed
+    UseAfterFree,
+    /// It was freed twice
+    DoubleFree,
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::BadHeader => "bad header, freeing a block not allocated here",
+            Self::Underflow => "buffer underflow, write before the start",
+            Self::Overflow => "buffer overflow, write past the end",
+            Self::UseAfterFree => "use after free, write to a freed block",
+            Self::DoubleFree => "double free",
+        })
+    }
+}
+
+/// Live blocks, as a list through their headers, and the quarantine
+struct State {
+    live: *mut Header,
+    quarantine: [*mut Header; QUARANTINE_LEN],
+    next_slot: usize,
+}
+
+/// The debug layer of [`Heap`](super::Heap), over its allocator
+pub(crate) struct DebugState {
+    state: Mutex<RefCell<State>>,
+}
+
+// SAFETY: the headers are only reached inside critical sections
+unsafe impl Sync for DebugState {}
+
+/// Offset of a block from the start of its allocation
+fn front(align: usize) -> usize {
+    (size_of::<Header>() + RED_ZONE).next_multiple_of(align.max(align_of::<Header>()))
+}
+
+/// The layout of the whole allocation for a block
+fn outer_layout(size: usize, align: usize) -> Option<Layout> {
+    let total = front(align).checked_add(size)?.checked_add(RED_ZONE)?;
+    Layout::from_size_align(total, align.max(align_of::<Header>())).ok()
+}
+
+fn block(header: *mut Header) -> *mut u8 {
+    unsafe { (header as *mut u8).add(front((*header).align)) }
+}
+
+fn all(start: *const u8, len: usize, byte: u8) -> bool {
+    unsafe { core::slice::from_raw_parts(start, len) }
+        .iter()
+        .all(|&b| b == byte)
+}
+
+/// Verify the red zones around a block
+fn check_red_zones(header: *mut Header) -> Result<(), Corruption> {
+    let (size, block) = (unsafe { (*header).size }, block(header));
+    let header_end = unsafe { (header as *const u8).add(size_of::<Header>()) };
+    let front_len = block as usize - header_end as usize;
+    if !all(header_end, front_len, RED_ZONE_BYTE) {
+        return Err(Corruption::Underflow);
+    }
+    if !all(unsafe { block.add(size) }, RED_ZONE, RED_ZONE_BYTE) {
+        return Err(Corruption::Overflow);
+    }
+    Ok(())
+}
+
+/// Verify a block in quarantine
+fn check_freed(header: *mut Header) -> Result<(), Corruption> {
+    if unsafe { (*header).magic } != FREED {
+        return Err(Corruption::BadHeader);
+    }
+    check_red_zones(header)?;
+    if !all(block(header), unsafe { (*header).size }, FREE_POISON) {
+        return Err(Corruption::UseAfterFree);
+    }
+    Ok(())
+}
+
+impl DebugState {
+    pub(crate) const fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(State {
+                live: ptr::null_mut(),
+                quarantine: [ptr::null_mut(); QUARANTINE_LEN],
+                next_slot: 0,
+            })),
+        }
+    }
+
+    /// Allocate a block with red zones from `inner`
+    pub(crate) unsafe fn alloc(&self, inner: &impl GlobalAlloc, layout: Layout) -> *mut u8 {
+        let Some(outer) = outer_layout(layout.size(), layout.align()) else {
+            return ptr::null_mut();
+        };
+        let header = unsafe { inner.alloc(outer) } as *mut Header;
+        if header.is_null() {
+            return ptr::null_mut();
+        }
+
+        let mut site = [0; SITE_FRAMES];
+        common::caller_frames(&mut site);
+        let start = header as *mut u8;
+        unsafe {
+            ptr::write_bytes(start, RED_ZONE_BYTE, outer.size());
+            header.write(Header {
+                magic: LIVE,
+                size: layout.size(),
+                align: layout.align(),
+                prev: ptr::null_mut(),
+                next: ptr::null_mut(),
+                site,
+            });
+            ptr::write_bytes(block(header), ALLOC_POISON, layout.size());
+        }
+
+        critical_section::with(|cs| {
+            let mut state = self.state.borrow_ref_mut(cs);
+            unsafe {
+                (*header).next = state.live;
+                if let Some(next) = state.live.as_mut() {
+                    next.prev = header;
+                }
+            }
+            state.live = header;
+        });
+        block(header)
+    }
+
+    /// Verify and poison a block, and put it in quarantine; the block
+    /// leaving quarantine is verified and given back to `inner`
+    pub(crate) unsafe fn dealloc(&self, inner: &impl GlobalAlloc, ptr: *mut u8, layout: Layout) {
+        let header = unsafe { ptr.sub(front(layout.align())) } as *mut Header;
+        let magic = unsafe { (*header).magic };
+        if magic != LIVE || unsafe { (*header).size } != layout.size() {
+            let fault = if magic == FREED {
+                Corruption::DoubleFree
+            } else {
+                Corruption::BadHeader
+            };
+            report(fault, header);
+        }
+        if let Err(fault) = check_red_zones(header) {
+            report(fault, header);
+        }
+
+        let evicted = critical_section::with(|cs| {
+            let mut state = self.state.borrow_ref_mut(cs);
+            unsafe {
+                let (prev, next) = ((*header).prev, (*header).next);
+                match prev.as_mut() {
+                    Some(prev) => prev.next = next,
+                    None => state.live = next,
+                }
+                if let Some(next) = next.as_mut() {
+                    next.prev = prev;
+                }
+                (*header).magic = FREED;
+                ptr::write_bytes(ptr, FREE_POISON, layout.size());
+            }
+
+            let slot = state.next_slot;
+            state.next_slot = (slot + 1) % QUARANTINE_LEN;
+            core::mem::replace(&mut state.quarantine[slot], header)
+        });
+
+        if !evicted.is_null() {
+            if let Err(fault) = check_freed(evicted) {
+                report(fault, evicted);
+            }
+            let (size, align) = unsafe { ((*evicted).size, (*evicted).align) };
+            let outer = outer_layout(size, align).expect("layout checked at allocation");
+            unsafe { inner.dealloc(evicted as *mut u8, outer) };
+        }
+    }
+
+    /// Move a block, as `alloc`, copy and `dealloc`, so that the old block
+    /// goes through quarantine
+    pub(crate) unsafe fn realloc(
+        &self,
+        inner: &impl GlobalAlloc,
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+    ) -> *mut u8 {
+        let new_layout = unsafe { Layout::from_size_align_unchecked(new_size, layout.align()) };
+        let new_ptr = unsafe { self.alloc(inner, new_layout) };
+        if !new_ptr.is_null() {
+            unsafe {
+                ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+                self.dealloc(inner, ptr, layout);
+            }
+        }
+        new_ptr
+    }
+
+    /// Verify every live and quarantined block
+    ///
+    /// # Returns
+    /// * `Err((fault, header))` - The first corrupted block
+    fn check(&self) -> Result<(), (Corruption, *mut Header)> {
+        critical_section::with(|cs| {
+            let state = self.state.borrow_ref(cs);
+            let mut header = state.live;
+            while !header.is_null() {
+                if unsafe { (*header).magic } != LIVE {
+                    return Err((Corruption::BadHeader, header));
+                }
+                check_red_zones(header).map_err(|fault| (fault, header))?;
+                header = unsafe { (*header).next };
+            }
+            for &header in state.quarantine.iter().filter(|header| !header.is_null()) {
+                check_freed(header).map_err(|fault| (fault, header))?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Verify every block, reporting the first corrupted one and aborting
+    pub(crate) fn check_heap(&self) {
+        if let Err((fault, header)) = self.check() {
+            report(fault, header);
+        }
+    }
+}
+
+/// Report a corrupted block and abort
+#[cold]
+fn report(fault: Corruption, header: *mut Header) -> ! {
+    crate::eprintln!("Heap corruption: {}", fault);
+    // A bad header cannot be trusted for the size and site
+    if fault != Corruption::BadHeader {
+        let (size, site) = unsafe { ((*header).size, (*header).site) };
+        crate::eprintln!(
+            "  {}-byte block at {:p}, allocated at:",
+            size,
+            block(header)
+        );
+        let frames = site.iter().filter(|&&addr| addr != 0);
+        for &addr in frames.skip_while(|&&addr| in_allocator(addr)) {
+            match common::symbolize(addr.saturating_sub(1)) {
+                Some((name, offset)) => {
+                    crate::eprintln!("    0x{:08x}  {}+0x{:x}", addr, name, offset + 1)
+                }
+                None => crate::eprintln!("    0x{:08x}", addr),
+            }
+        }
+    } else {
+        crate::eprintln!("  at {:p}", header);
+    }
+    crate::eprint!("{}", common::Backtrace::capture());
+    crate::process::abort()
+}
+
+/// Whether a return address is inside the allocator rather than at the
+/// allocation site
+fn in_allocator(addr: usize) -> bool {
+    let Some((name, _)) = common::symbolize(addr.saturating_sub(1)) else {
+        return false;
+    };
+    let name = name.trim_start_matches('<');
+    [
+        "runtime::mem::",
+        "alloc::alloc::",
+        "alloc::raw_vec::",
+        "__rust",
+        "__rdl",
+    ]
+    .iter()
+    .any(|prefix| name.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::BumpHeap;
+
+    #[test]
+    fn test_debug_heap() {
+        static mut MEMORY: [usize; 1024] = [0; 1024];
+        let inner = BumpHeap::empty();
+        unsafe { inner.init(&raw mut MEMORY as usize, 4096) };
+        let debug = DebugState::new();
+
+        let layout = Layout::from_size_align(10, 8).unwrap();
+        let a = unsafe { debug.alloc(&inner, layout) };
+        assert_eq!(a as usize % 8, 0);
+        assert!(all(a, 10, ALLOC_POISON));
+        let header = unsafe { a.sub(front(8)) } as *mut Header;
+        assert_eq!(debug.check(), Ok(()));
+
+        // One byte past the end
+        unsafe { *a.add(10) = 0 };
+        assert_eq!(debug.check(), Err((Corruption::Overflow, header)));
+        unsafe { *a.add(10) = RED_ZONE_BYTE };
+
+        unsafe { debug.dealloc(&inner, a, layout) };
+        assert!(all(a, 10, FREE_POISON));
+        assert_eq!(debug.check(), Ok(()));
+        unsafe { *a.add(3) = 0 };
+        assert_eq!(debug.check(), Err((Corruption::UseAfterFree, header)));
+    }
+}
@@ -0,0 +1,350 @@
+//! The global allocator of `binInit!`
+//!
+//! [`Heap`] wraps the allocator chosen by the runtime's features: the
+//! linked-list first fit of `embedded-alloc` by default, its two-level
+//! segregated fit with `alloc-tlsf` (constant-time, for allocation-heavy
+//! programs), or [`BumpHeap`] with `alloc-bump` (`alloc-bump` wins when
+//! both are enabled). On top of it, it counts allocations for
+//! [`heap_stats`] and checks the stack canary. A failed allocation returns
+//! null, so `try_reserve` and the like get their `Err`; its layout is kept
+//! until the next successful call, so that when an infallible allocation
+//! fails, the panic handler reports the layout and the heap usage. With `debug-heap`,
+//! blocks also go through the checks of the debug heap, see [`check_heap`].
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::Cell;
+use core::fmt;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use critical_section::Mutex;
+
+#[cfg(feature = "alloc-bump")]
+type Inner = super::BumpHeap;
+#[cfg(all(feature = "alloc-tlsf", not(feature = "alloc-bump")))]
+type Inner = embedded_alloc::TlsfHeap;
+#[cfg(not(any(feature = "alloc-tlsf", feature = "alloc-bump")))]
+type Inner = embedded_alloc::LlffHeap;
+
+/// The heap given to [`Heap::init`], for [`heap_stats`]
+static HEAP: AtomicPtr<Heap> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Usage of the heap, see [`heap_stats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Size of the heap in bytes
+    pub size: usize,
+    /// Bytes currently allocated
+    pub used: usize,
+    /// Most bytes allocated at once
+    pub peak: usize,
+    /// Allocations made so far
+    pub allocations: usize,
+    /// Allocations freed so far
+    pub deallocations: usize,
+    /// The largest allocation that would succeed now, in bytes
+    pub largest_free: usize,
+}
+
+impl HeapStats {
+    /// Allocations not freed yet
+    pub fn live(&self) -> usize {
+        self.allocations - self.deallocations
+    }
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} bytes used (peak {}), {} allocations live of {}, largest free block {} bytes",
+            self.used,
+            self.size,
+            self.peak,
+            self.live(),
+            self.allocations,
+            self.largest_free
+        )
+    }
+}
+
+/// The global allocator, counting what it allocates
+pub struct Heap {
+    inner: Inner,
+    stats: Mutex<Cell<HeapStats>>,
+    /// The allocation that failed, if the last call failed
+    failed: Mutex<Cell<Option<Layout>>>,
+    #[cfg(feature = "debug-heap")]
+    debug: super::debug::DebugState,
+}
+
+impl Heap {
+    /// An allocator without memory, until [`init`](Self::init)
+    pub const fn empty() -> Self {
+        Self {
+            inner: Inner::empty(),
+            stats: Mutex::new(Cell::new(HeapStats {
+                size: 0,
+                used: 0,
+                peak: 0,
+                allocations: 0,
+                deallocations: 0,
+                largest_free: 0,
+            })),
+            failed: Mutex::new(Cell::new(None)),
+            #[cfg(feature = "debug-heap")]
+            debug: super::debug::DebugState::new(),
+        }
+    }
+
+    /// Give the allocator its memory, and make it the heap of
+    /// [`heap_stats`]
+    ///
+    /// # Arguments
+    /// * `start` - First address of the heap
+    /// * `size` - Size of the heap in bytes
+    ///
+    /// # Safety
+    /// The memory must be unused by anything else, and this must be called
+    /// once, before any allocation.
+    pub unsafe fn init(&'static self, start: usize, size: usize) {
+        unsafe { self.inner.init(start, size) };
+        self.update(|stats| stats.size = size);
+        HEAP.store(self as *const Heap as *mut Heap, Ordering::Release);
+    }
+
+    /// Usage of this heap
+    pub fn stats(&self) -> HeapStats {
+        let mut stats = critical_section::with(|cs| self.stats.borrow(cs).get());
+        stats.largest_free = self.largest_free(stats.size - stats.used);
+        stats
+    }
+
+    /// Count a successful call, which also forgets an earlier failure
+    fn update(&self, f: impl FnOnce(&mut HeapStats)) {
+        critical_section::with(|cs| {
+            let cell = self.stats.borrow(cs);
+            let mut stats = cell.get();
+            f(&mut stats);
+            stats.peak = stats.peak.max(stats.used);
+            cell.set(stats);
+            self.failed.borrow(cs).set(None);
+        })
+    }
+
+    /// Record a failed allocation, leaving the statistics alone
+    #[cold]
+    fn fail(&self, layout: Layout) -> *mut u8 {
+        critical_section::with(|cs| self.failed.borrow(cs).set(Some(layout)));
+        core::ptr::null_mut()
+    }
+
+    /// The largest block that can be allocated, found by trying: the
+    /// allocators do not keep track of it. Debug heap overheads are not
+    /// taken off.
+    fn largest_free(&self, free: usize) -> usize {
+        let (mut low, mut high) = (0, free);
+        while low < high {
+            let size = low + (high - low).div_ceil(2);
+            let Ok(layout) = Layout::from_size_align(size, size_of::<usize>()) else {
+                high = size - 1;
+                continue;
+            };
+            let ptr = unsafe { self.inner.alloc(layout) };
+            if ptr.is_null() {
+                high = size - 1;
+            } else {
+                unsafe { self.inner.dealloc(ptr, layout) };
+                low = size;
+            }
+        }
+        low
+    }
+}
+
+unsafe impl GlobalAlloc for Heap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        super::check_stack();
+        #[cfg(feature = "debug-heap")]
+        let ptr = unsafe { self.debug.alloc(&self.inner, layout) };
+        #[cfg(not(feature = "debug-heap"))]
+        let ptr = unsafe { self.inner.alloc(layout) };
+        if ptr.is_null() {
+            return self.fail(layout);
+        }
+        self.update(|stats| {
+            stats.used += layout.size();
+            stats.allocations += 1;
+        });
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        super::check_stack();
+        #[cfg(feature = "debug-heap")]
+        unsafe {
+            self.debug.dealloc(&self.inner, ptr, layout)
+        };
+        #[cfg(not(feature = "debug-heap"))]
+        unsafe {
+            self.inner.dealloc(ptr, layout)
+        };
+        self.update(|stats| {
+            stats.used -= layout.size();
+            stats.deallocations += 1;
+        });
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        super::check_stack();
+        #[cfg(feature = "debug-heap")]
+        let new_ptr = unsafe { self.debug.realloc(&self.inner, ptr, layout, new_size) };
+        #[cfg(not(feature = "debug-heap"))]
+        let new_ptr = unsafe { self.inner.realloc(ptr, layout, new_size) };
+        if new_ptr.is_null() {
+            return self
+                .fail(unsafe { Layout::from_size_align_unchecked(new_size, layout.align()) });
+        }
+        self.update(|stats| stats.used = stats.used - layout.size() + new_size);
+        new_ptr
+    }
+}
+
+/// Usage of the program's heap, the allocator of `binInit!`
+///
+/// # Returns
+/// * The statistics, all zero before the heap is initialized or with
+///   another global allocator
+pub fn heap_stats() -> HeapStats {
+    let heap = HEAP.load(Ordering::Acquire);
+    if heap.is_null() {
+        return HeapStats::default();
+    }
+    unsafe { &*heap }.stats()
+}
+
+/// The allocation the program's heap just failed, for the panic handler
+///
+/// # Returns
+/// * `Some(layout)` - The last call to the heap was an allocation of
+///   `layout` that failed
+/// * `None` - The last call succeeded, or no heap is initialized
+#[cfg(any(test, feature = "nemu", feature = "qemu", feature = "spike"))]
+pub(crate) fn failed_allocation() -> Option<Layout> {
+    let heap = unsafe { HEAP.load(Ordering::Acquire).as_ref() }?;
+    critical_section::with(|cs| heap.failed.borrow(cs).get())
+}
+
+/// Verify every block of the debug heap: red zones around live blocks,
+/// and poison in freed blocks still in quarantine
+///
+/// The first corrupted block is reported, with its size and allocation
+/// site, and the program aborts. Without the `debug-heap` feature, or
+/// with another global allocator, this does nothing.
+pub fn check_heap() {
+    #[cfg(feature = "debug-heap")]
+    if let Some(heap) = unsafe { HEAP.load(Ordering::Acquire).as_ref() } {
+        heap.debug.check_heap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use alloc::vec::Vec;
+    use std::alloc::System;
+
+    static TEST_HEAP: Heap = Heap::empty();
+    static mut MEMORY: [usize; 1024] = [0; 1024];
+
+    std::thread_local! {
+        /// Whether this thread allocates from `TEST_HEAP`
+        static ROUTED: Cell<bool> = const { Cell::new(false) };
+    }
+
+    /// The allocator of the test binary: the system's, or `TEST_HEAP` on
+    /// threads that asked for it
+    struct Router;
+
+    #[global_allocator]
+    static ROUTER: Router = Router;
+
+    fn in_test_heap(ptr: *mut u8) -> bool {
+        let start = &raw const MEMORY as usize;
+        (start..start + 4096).contains(&(ptr as usize))
+    }
+
+    unsafe impl GlobalAlloc for Router {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            if ROUTED.try_with(Cell::get).unwrap_or(false) {
+                unsafe { TEST_HEAP.alloc(layout) }
+            } else {
+                unsafe { System.alloc(layout) }
+            }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            if in_test_heap(ptr) {
+                unsafe { TEST_HEAP.dealloc(ptr, layout) }
+            } else {
+                unsafe { System.dealloc(ptr, layout) }
+            }
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            if in_test_heap(ptr) {
+                unsafe { TEST_HEAP.realloc(ptr, layout, new_size) }
+            } else {
+                unsafe { System.realloc(ptr, layout, new_size) }
+            }
+        }
+    }
+
+    #[test]
+    fn test_heap_stats() {
+        unsafe { TEST_HEAP.init(&raw mut MEMORY as usize, 4096) };
+
+        let layout = Layout::from_size_align(100, 4).unwrap();
+        let a = unsafe { TEST_HEAP.alloc(layout) };
+        let b = unsafe { TEST_HEAP.alloc(layout) };
+        unsafe { TEST_HEAP.dealloc(a, layout) };
+        let b = unsafe { TEST_HEAP.realloc(b, layout, 300) };
+
+        let stats = heap_stats();
+        assert_eq!((stats.size, stats.used, stats.peak), (4096, 300, 300));
+        assert_eq!((stats.allocations, stats.live()), (2, 1));
+        assert!(stats.largest_free > 0 && stats.largest_free <= 4096 - 300);
+
+        // Finding the largest free block leaves the heap as it was
+        assert_eq!(heap_stats(), stats);
+        unsafe { TEST_HEAP.dealloc(b, Layout::from_size_align(300, 4).unwrap()) };
+
+        // A failed allocation returns null and leaves the counts alone
+        let stats = heap_stats();
+        let huge = Layout::from_size_align(8192, 4).unwrap();
+        assert!(unsafe { TEST_HEAP.alloc(huge) }.is_null());
+        let small = Layout::from_size_align(100, 4).unwrap();
+        let c = unsafe { TEST_HEAP.alloc(small) };
+        assert!(unsafe { TEST_HEAP.realloc(c, small, 8192) }.is_null());
+        assert_eq!(
+            failed_allocation(),
+            Some(Layout::from_size_align(8192, 4).unwrap())
+        );
+        unsafe { TEST_HEAP.dealloc(c, small) };
+        assert_eq!(failed_allocation(), None);
+        assert_eq!(heap_stats().used, stats.used);
+        assert_eq!(heap_stats().live(), stats.live());
+
+        // So fallible collections get their error
+        ROUTED.set(true);
+        let mut fits = Vec::<u8>::new();
+        let fits_result = fits.try_reserve(64);
+        let mut huge = Vec::<u8>::new();
+        let huge_result = huge.try_reserve(8192);
+        ROUTED.set(false);
+        assert!(fits_result.is_ok() && in_test_heap(fits.as_mut_ptr()));
+        assert!(huge_result.is_err());
+        drop(fits);
+        assert_eq!(heap_stats().used, stats.used);
+    }
+}
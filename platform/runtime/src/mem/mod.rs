@@ -0,0 +1,52 @@
+//! Memory functions, the heap and stack usage
+//!
+//! Along with everything in `core::mem`, this module provides the global
+//! allocator of `binInit!` ([`Heap`], with [`heap_stats`]) and reports how
+//! much of the stack a program has used. By default the stack sits right
+//! above the heap with nothing in between, so a deep recursion would silently
+//! corrupt heap blocks: a canary at the stack limit is checked on every
+//! allocator call and when the program exits, and a breached canary ends
+//! the program with
+//! [`STACK_OVERFLOW_EXIT_CODE`](crate::process::STACK_OVERFLOW_EXIT_CODE).
+
+mod bump;
+#[cfg(feature = "debug-heap")]
+mod debug;
+mod heap;
+pub use bump::BumpHeap;
+#[cfg(feature = "debug-heap")]
+pub use debug::{ALLOC_POISON, FREE_POISON, QUARANTINE_LEN, RED_ZONE_BYTE};
+pub use heap::{Heap, HeapStats, check_heap, heap_stats};
+#[cfg(all(not(test), any(feature = "nemu", feature = "qemu", feature = "spike")))]
+pub(crate) use heap::failed_allocation;
+
+pub use common::{stack_high_water, stack_overflowed, stack_size};
+pub use core::mem::*;
+
+#[doc(hidden)]
+pub use common::paint_stack;
+
+/// The memory managed by the global allocator of `binInit!`
+///
+/// # Returns
+/// * `(start, size)` - The region between `_sheap` and `_eheap` of the
+///   linker script, or host memory on the native platform
+pub fn heap_region() -> (usize, usize) {
+    use crate::platform::{Current, Platform};
+    Current::heap_region()
+}
+
+/// Report a stack overflow and exit with
+/// [`STACK_OVERFLOW_EXIT_CODE`](crate::process::STACK_OVERFLOW_EXIT_CODE),
+/// if the canary was overwritten
+///
+/// At-exit hooks are not run: the heap may be corrupted.
+pub fn check_stack() {
+    if stack_overflowed() {
+        crate::eprintln!(
+            "Stack overflow: the stack outgrew its {} bytes",
+            stack_size()
+        );
+        crate::process::platform_exit(crate::process::STACK_OVERFLOW_EXIT_CODE);
+    }
+}
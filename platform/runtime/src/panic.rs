@@ -0,0 +1,114 @@
+//! Catching panics
+//!
+//! By default panics abort: the panic handler prints the message and a
+//! backtrace, then exits with [`PANIC_EXIT_CODE`](crate::process::PANIC_EXIT_CODE).
+//! With the `unwind` feature and a `panic = "unwind"` build (the
+//! `release-unwind` profile, see the README), the handler instead unwinds
+//! the stack to the innermost [`catch_unwind`], running destructors on the
+//! way; it still exits when nothing catches the panic.
+//!
+//! Without unwinding, [`catch_unwind`] calls the closure and never returns
+//! `Err`, so code using it works either way.
+
+use alloc::boxed::Box;
+use core::any::Any;
+
+pub use core::panic::{AssertUnwindSafe, Location, PanicInfo, RefUnwindSafe, UnwindSafe};
+
+/// Run a closure, catching a panic in it
+///
+/// # Arguments
+/// * `f` - The closure
+///
+/// # Returns
+/// * `Ok(value)` - What the closure returned
+/// * `Err(payload)` - The closure panicked; the payload is the message, as a
+///   `&'static str` or a `String`
+#[cfg(feature = "unwind")]
+pub fn catch_unwind<F: FnOnce() -> R + UnwindSafe, R>(f: F) -> Result<R, Box<dyn Any + Send>> {
+    use core::mem::ManuallyDrop;
+
+    union Data<F, R> {
+        f: ManuallyDrop<F>,
+        r: ManuallyDrop<R>,
+        p: ManuallyDrop<Box<dyn Any + Send>>,
+    }
+
+    fn do_call<F: FnOnce() -> R, R>(data: *mut u8) {
+        let data = unsafe { &mut *(data as *mut Data<F, R>) };
+        let f = unsafe { ManuallyDrop::take(&mut data.f) };
+        data.r = ManuallyDrop::new(f());
+    }
+
+    fn do_catch<F: FnOnce() -> R, R>(data: *mut u8, exception: *mut u8) {
+        let data = unsafe { &mut *(data as *mut Data<F, R>) };
+        data.p = ManuallyDrop::new(crate::unwind::take_payload(exception));
+    }
+
+    let mut data = Data {
+        f: ManuallyDrop::new(f),
+    };
+    let data_ptr = &raw mut data as *mut u8;
+    unsafe {
+        if core::intrinsics::catch_unwind(do_call::<F, R>, data_ptr, do_catch::<F, R>) == 0 {
+            Ok(ManuallyDrop::into_inner(data.r))
+        } else {
+            Err(ManuallyDrop::into_inner(data.p))
+        }
+    }
+}
+
+/// Run a closure, catching a panic in it
+///
+/// Without the `unwind` feature a panic exits the program, so this always
+/// returns `Ok`.
+#[cfg(not(feature = "unwind"))]
+pub fn catch_unwind<F: FnOnce() -> R + UnwindSafe, R>(f: F) -> Result<R, Box<dyn Any + Send>> {
+    Ok(f())
+}
+
+/// Continue a panic caught by [`catch_unwind`], without calling the panic
+/// handler again
+///
+/// # Arguments
+/// * `payload` - The payload, usually the `Err` of `catch_unwind`
+pub fn resume_unwind(payload: Box<dyn Any + Send>) -> ! {
+    #[cfg(feature = "unwind")]
+    crate::unwind::raise(payload);
+    #[cfg(not(feature = "unwind"))]
+    drop(payload);
+
+    crate::process::exit(crate::process::PANIC_EXIT_CODE)
+}
+
+/// The message of a payload from [`catch_unwind`]
+///
+/// # Returns
+/// * `Some(message)` - The payload of a `panic!`
+/// * `None` - Another payload, from [`resume_unwind`]
+pub fn payload_str(payload: &(dyn Any + Send)) -> Option<&str> {
+    if let Some(message) = payload.downcast_ref::<&'static str>() {
+        return Some(message);
+    }
+    payload
+        .downcast_ref::<alloc::string::String>()
+        .map(|message| message.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+
+    #[test]
+    fn test_catch_unwind() {
+        assert_eq!(catch_unwind(|| 7).ok(), Some(7));
+
+        let payload: Box<dyn Any + Send> = Box::new("static");
+        assert_eq!(payload_str(&*payload), Some("static"));
+        let payload: Box<dyn Any + Send> = Box::new(String::from("formatted"));
+        assert_eq!(payload_str(&*payload), Some("formatted"));
+        let payload: Box<dyn Any + Send> = Box::new(7);
+        assert_eq!(payload_str(&*payload), None);
+    }
+}
@@ -0,0 +1,136 @@
+//! The platform the program runs on
+//!
+//! The runtime's platform feature picks the platform crate, and the rest of
+//! the runtime reaches it through its [`Platform`] implementation, with
+//! static dispatch. [`info`] describes it.
+
+use core::fmt;
+
+pub use common::{MemoryRegion, Platform};
+
+#[cfg(feature = "nemu")]
+pub(crate) type Current = nemu_runtime::Nemu;
+
+#[cfg(feature = "qemu")]
+pub(crate) type Current = qemu_runtime::Qemu;
+
+#[cfg(feature = "spike")]
+pub(crate) type Current = spike_runtime::Spike;
+
+#[cfg(feature = "native")]
+pub(crate) type Current = native_runtime::Native;
+
+#[cfg(not(any(
+    feature = "nemu",
+    feature = "qemu",
+    feature = "spike",
+    feature = "native"
+)))]
+pub(crate) type Current = none::NoPlatform;
+
+#[cfg(not(any(
+    feature = "nemu",
+    feature = "qemu",
+    feature = "spike",
+    feature = "native"
+)))]
+mod none {
+    use super::{MemoryRegion, Platform};
+
+    /// Stand-in for builds without a platform feature, such as host tests:
+    /// output is dropped, there is never input, and exiting is a bug
+    pub(crate) struct NoPlatform;
+
+    impl Platform for NoPlatform {
+        const NAME: &'static str = "none";
+        const MEMORY_MAP: &'static [MemoryRegion] = &[];
+
+        fn putc(_ch: u8) {}
+
+        fn try_getc() -> Option<u8> {
+            None
+        }
+
+        fn exit(code: i32) -> ! {
+            unreachable!("exit({}) called without a platform", code);
+        }
+    }
+}
+
+/// The ISA the runtime was compiled for, from the enabled target features
+const ISA: &str = if cfg!(target_arch = "x86_64") {
+    "x86_64"
+} else if cfg!(not(target_arch = "riscv32")) {
+    "unknown"
+} else if cfg!(target_feature = "zve32x") {
+    "riscv32im_zve32x"
+} else if cfg!(target_feature = "a") {
+    "riscv32imac"
+} else if cfg!(target_feature = "m") {
+    "riscv32im"
+} else {
+    "riscv32i"
+};
+
+/// A description of the platform, see [`info`]
+#[derive(Debug, Clone, Copy)]
+pub struct Info {
+    /// Name of the platform, as in `ARCH`
+    pub name: &'static str,
+    /// The ISA, as in `ARCH`
+    pub isa: &'static str,
+    /// Physical memory regions, from the build-helper memory map
+    pub memory_map: &'static [MemoryRegion],
+}
+
+impl fmt::Display for Info {
+    /// `<isa>-<platform>`, then one line per memory region
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.isa, self.name)?;
+        for region in self.memory_map {
+            write!(
+                f,
+                "\n{:<10} 0x{:08x} 0x{:08x}  {}",
+                region.name, region.base, region.size, region.kind
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Describe the platform the program runs on
+///
+/// # Returns
+/// * Its name, ISA and memory map
+pub fn info() -> Info {
+    Info {
+        name: Current::NAME,
+        isa: ISA,
+        memory_map: Current::MEMORY_MAP,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_info() {
+        let info = Info {
+            name: "qemu",
+            isa: "riscv32im",
+            memory_map: &[MemoryRegion {
+                name: "ram",
+                base: 0x8000_0000,
+                size: 0x800_0000,
+                kind: "ram",
+            }],
+        };
+        assert_eq!(
+            alloc::format!("{}", info),
+            "riscv32im-qemu\nram        0x80000000 0x08000000  ram"
+        );
+        let current = alloc::format!("{}", self::info());
+        assert!(current.starts_with(&alloc::format!("{}-{}", ISA, Current::NAME)));
+    }
+}
@@ -0,0 +1,38 @@
+//! Machine power control
+//!
+//! On QEMU both operations go through the SiFive test device, or through
+//! SBI system reset when built with the `sbi` feature. Spike and NEMU can
+//! only shut down.
+
+use crate::platform::{Current, Platform};
+use core::fmt;
+
+/// Error returned by [`reboot`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerError {
+    /// The platform cannot perform the operation
+    Unsupported,
+}
+
+impl fmt::Display for PowerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PowerError::Unsupported => write!(f, "operation not supported by the platform"),
+        }
+    }
+}
+
+/// Power the machine off, reporting success
+pub fn shutdown() -> ! {
+    crate::process::exit(0)
+}
+
+/// Reset the machine
+///
+/// # Returns
+/// * `PowerError::Unsupported` - Only returns if the platform cannot reset
+pub fn reboot() -> PowerError {
+    crate::io::flush_stdout();
+    Current::reboot();
+    PowerError::Unsupported
+}
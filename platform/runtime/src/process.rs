@@ -0,0 +1,44 @@
+//! Process termination
+//!
+//! A bare-metal program is the whole machine, so terminating it stops the
+//! platform: QEMU and Spike exit with the given code and NEMU reports it as
+//! the trap's return value.
+//!
+//! [`exit`] runs the hooks registered with [`at_exit`] and flushes stdout
+//! first; [`abort`] does neither. Either way, a program whose stack
+//! overflowed exits with [`STACK_OVERFLOW_EXIT_CODE`] instead, see
+//! [`mem`](crate::mem).
+
+pub use common::{HookTableFull, MAX_EXIT_HOOKS, STACK_OVERFLOW_EXIT_CODE, Termination, at_exit};
+
+use crate::platform::{Current, Platform};
+
+/// Exit code used when the program panics, as for hosted Rust programs
+pub const PANIC_EXIT_CODE: i32 = 101;
+
+/// Exit code used by [`abort`], as for a process killed by SIGABRT
+pub const ABORT_EXIT_CODE: i32 = 134;
+
+pub(crate) fn platform_exit(code: i32) -> ! {
+    Current::exit(code)
+}
+
+/// Run the at-exit hooks and flush stdout, then terminate the program with
+/// the given code
+///
+/// # Arguments
+/// * `code` - Exit code, 0 for success
+pub fn exit(code: i32) -> ! {
+    crate::mem::check_stack();
+    common::run_exit_hooks();
+    crate::io::flush_stdout();
+    platform_exit(code)
+}
+
+/// Terminate the program abnormally, with [`ABORT_EXIT_CODE`]
+///
+/// At-exit hooks are not run, and buffered output is lost.
+pub fn abort() -> ! {
+    crate::mem::check_stack();
+    platform_exit(ABORT_EXIT_CODE)
+}
@@ -0,0 +1,154 @@
+//! Random number generation
+//!
+//! [`fill_bytes`] draws from the platform's hardware entropy source when it
+//! has one (a virtio-rng device on QEMU). Otherwise it falls back to a global
+//! [`SmallRng`] seeded from the cycle counter on first use.
+//!
+//! [`SmallRng`] is a fast, seedable xoshiro128++ generator, suitable for
+//! reproducible randomized test inputs. It is not cryptographically secure.
+
+use crate::platform::{Current, Platform};
+use core::cell::RefCell;
+use critical_section::Mutex;
+
+pub use rand_core::{RngCore, SeedableRng};
+
+/// A small, fast pseudo-random generator (xoshiro128++)
+#[derive(Debug, Clone)]
+pub struct SmallRng {
+    s: [u32; 4],
+}
+
+impl SmallRng {
+    /// Create a generator seeded from [`fill_bytes`]
+    pub fn from_entropy() -> Self {
+        let mut seed = [0u8; 16];
+        fill_bytes(&mut seed);
+        Self::from_seed(seed)
+    }
+}
+
+impl RngCore for SmallRng {
+    fn next_u32(&mut self) -> u32 {
+        let s = &mut self.s;
+        let result = s[0].wrapping_add(s[3]).rotate_left(7).wrapping_add(s[0]);
+
+        let t = s[1] << 9;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(11);
+
+        result
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_u32(self)
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        rand_core::impls::fill_bytes_via_next(self, dst)
+    }
+}
+
+impl SeedableRng for SmallRng {
+    type Seed = [u8; 16];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        let mut s = [0u32; 4];
+        for (word, chunk) in s.iter_mut().zip(seed.chunks_exact(4)) {
+            *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+
+        // The all-zero state is a fixed point of xoshiro, never use it
+        if s == [0; 4] {
+            s = [0x9e37_79b9, 0x243f_6a88, 0xb7e1_5162, 0x7f4a_7c15];
+        }
+
+        Self { s }
+    }
+}
+
+/// Fallback generator, seeded from the cycle counter on first use
+static GLOBAL_RNG: Mutex<RefCell<Option<SmallRng>>> = Mutex::new(RefCell::new(None));
+
+fn hardware_fill(buf: &mut [u8]) -> usize {
+    Current::entropy_fill(buf)
+}
+
+/// Fill a buffer with random bytes
+///
+/// Uses hardware entropy when the platform provides it, and the global
+/// [`SmallRng`] otherwise.
+///
+/// # Arguments
+/// * `buf` - Buffer to fill
+pub fn fill_bytes(buf: &mut [u8]) {
+    let filled = hardware_fill(buf);
+    if filled >= buf.len() {
+        return;
+    }
+
+    critical_section::with(|cs| {
+        let mut rng = GLOBAL_RNG.borrow_ref_mut(cs);
+        rng.get_or_insert_with(|| SmallRng::seed_from_u64(crate::arch::cycles()))
+            .fill_bytes(&mut buf[filled..]);
+    });
+}
+
+/// Get a random u32, see [`fill_bytes`]
+pub fn next_u32() -> u32 {
+    let mut bytes = [0u8; 4];
+    fill_bytes(&mut bytes);
+    u32::from_le_bytes(bytes)
+}
+
+/// Get a random u64, see [`fill_bytes`]
+pub fn next_u64() -> u64 {
+    let mut bytes = [0u8; 8];
+    fill_bytes(&mut bytes);
+    u64::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reference_output() {
+        let mut seed = [0u8; 16];
+        for (i, chunk) in seed.chunks_exact_mut(4).enumerate() {
+            chunk.copy_from_slice(&(i as u32 + 1).to_le_bytes());
+        }
+
+        let mut rng = SmallRng::from_seed(seed);
+        let output: [u32; 4] = core::array::from_fn(|_| rng.next_u32());
+        assert_eq!(output, [641, 1573767, 3222811527, 3517856514]);
+    }
+
+    #[test]
+    fn test_seed_is_reproducible() {
+        let mut a = SmallRng::seed_from_u64(42);
+        let mut b = SmallRng::seed_from_u64(42);
+        let (mut x, mut y) = ([0u8; 37], [0u8; 37]);
+        a.fill_bytes(&mut x);
+        b.fill_bytes(&mut y);
+        assert_eq!(x, y);
+        assert_ne!(x, [0u8; 37]);
+    }
+
+    #[test]
+    fn test_fill_bytes_fallback() {
+        let mut buf = [0u8; 64];
+        fill_bytes(&mut buf);
+        assert_ne!(buf, [0u8; 64]);
+    }
+
+    #[test]
+    fn test_zero_seed() {
+        let mut rng = SmallRng::from_seed([0; 16]);
+        assert_ne!(rng.next_u64(), 0);
+    }
+}
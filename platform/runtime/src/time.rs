@@ -0,0 +1,394 @@
+//! Wall-clock time
+//!
+//! [`SystemTime`] reads the platform's real-time clock: the Goldfish RTC on
+//! QEMU, which follows the host clock, and the RTC counter on NEMU, which
+//! counts from simulator start. Platforms without a clock (Spike) always
+//! report [`UNIX_EPOCH`].
+//!
+//! [`DateTime`] converts between UNIX time and the proleptic Gregorian
+//! calendar in UTC. One-shot alarms are available through [`set_alarm`] on
+//! platforms with an alarm interrupt.
+
+use crate::platform::{Current, Platform};
+use core::fmt;
+use core::ops::{Add, Sub};
+
+pub use core::time::Duration;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+const SECS_PER_DAY: u64 = 86_400;
+
+/// A point in wall-clock time, with nanosecond precision
+///
+/// Times before the UNIX epoch cannot be represented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemTime {
+    nanos: u64,
+}
+
+/// 1970-01-01 00:00:00 UTC
+pub const UNIX_EPOCH: SystemTime = SystemTime { nanos: 0 };
+
+/// Error returned by [`SystemTime::duration_since`] when the other time is
+/// later, holding how much later it is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SystemTimeError(Duration);
+
+impl SystemTimeError {
+    /// How far the second time lies after the first
+    pub fn duration(&self) -> Duration {
+        self.0
+    }
+}
+
+impl fmt::Display for SystemTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "second time provided was later than self")
+    }
+}
+
+fn rtc_read() -> Option<u64> {
+    Current::rtc_read()
+}
+
+impl SystemTime {
+    /// Read the current time from the platform's real-time clock
+    ///
+    /// # Returns
+    /// * The current time, [`UNIX_EPOCH`] if the platform has no clock
+    pub fn now() -> Self {
+        Self {
+            nanos: rtc_read().unwrap_or(0),
+        }
+    }
+
+    /// Create a time from nanoseconds since the UNIX epoch
+    pub const fn from_unix_nanos(nanos: u64) -> Self {
+        Self { nanos }
+    }
+
+    /// Nanoseconds since the UNIX epoch
+    pub const fn unix_nanos(&self) -> u64 {
+        self.nanos
+    }
+
+    /// Whole seconds since the UNIX epoch
+    pub const fn unix_secs(&self) -> u64 {
+        self.nanos / NANOS_PER_SEC
+    }
+
+    /// Time elapsed from an earlier point to this one
+    ///
+    /// # Arguments
+    /// * `earlier` - The starting point
+    ///
+    /// # Returns
+    /// * `Ok(duration)` - `self - earlier`
+    /// * `Err(error)` - `earlier` is later than `self`
+    pub fn duration_since(&self, earlier: SystemTime) -> Result<Duration, SystemTimeError> {
+        match self.nanos.checked_sub(earlier.nanos) {
+            Some(nanos) => Ok(Duration::from_nanos(nanos)),
+            None => Err(SystemTimeError(Duration::from_nanos(
+                earlier.nanos - self.nanos,
+            ))),
+        }
+    }
+
+    /// Time elapsed since this point, see [`SystemTime::duration_since`]
+    pub fn elapsed(&self) -> Result<Duration, SystemTimeError> {
+        SystemTime::now().duration_since(*self)
+    }
+
+    /// Add a duration, returning `None` on overflow
+    pub fn checked_add(&self, duration: Duration) -> Option<SystemTime> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        self.nanos.checked_add(nanos).map(Self::from_unix_nanos)
+    }
+
+    /// Subtract a duration, returning `None` before the UNIX epoch
+    pub fn checked_sub(&self, duration: Duration) -> Option<SystemTime> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        self.nanos.checked_sub(nanos).map(Self::from_unix_nanos)
+    }
+
+    /// Break the time down into a calendar date and time of day (UTC)
+    pub fn to_datetime(&self) -> DateTime {
+        DateTime::from_unix_nanos(self.nanos)
+    }
+}
+
+impl Add<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn add(self, duration: Duration) -> SystemTime {
+        self.checked_add(duration)
+            .expect("overflow when adding duration to time")
+    }
+}
+
+impl Sub<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn sub(self, duration: Duration) -> SystemTime {
+        self.checked_sub(duration)
+            .expect("overflow when subtracting duration from time")
+    }
+}
+
+/// Day of the week
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+/// A calendar date and time of day in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: u32,
+    /// 1-12
+    pub month: u8,
+    /// 1-31
+    pub day: u8,
+    /// 0-23
+    pub hour: u8,
+    /// 0-59
+    pub minute: u8,
+    /// 0-59
+    pub second: u8,
+    /// 0-999_999_999
+    pub nanosecond: u32,
+}
+
+fn is_leap_year(year: u32) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+fn days_in_month(year: u32, month: u8) -> u8 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+impl DateTime {
+    /// Convert nanoseconds since the UNIX epoch to a calendar date and time
+    pub fn from_unix_nanos(nanos: u64) -> Self {
+        let secs = nanos / NANOS_PER_SEC;
+        let days = secs / SECS_PER_DAY;
+        let time = secs % SECS_PER_DAY;
+
+        // Civil-from-days on 400-year eras starting at 0000-03-01
+        // (Howard Hinnant's algorithm), offset so the epoch is day 0
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let doe = z % 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + u64::from(month <= 2);
+
+        Self {
+            year: year as u32,
+            month: month as u8,
+            day: day as u8,
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+            nanosecond: (nanos % NANOS_PER_SEC) as u32,
+        }
+    }
+
+    /// Convert the date and time back to nanoseconds since the UNIX epoch
+    ///
+    /// # Returns
+    /// * `Some(nanos)` - Nanoseconds since the UNIX epoch
+    /// * `None` - A field is out of range, or the time is before 1970 or
+    ///   does not fit in a [`SystemTime`]
+    pub fn to_unix_nanos(&self) -> Option<u64> {
+        if self.year < 1970
+            || !(1..=12).contains(&self.month)
+            || self.day == 0
+            || self.day > days_in_month(self.year, self.month)
+            || self.hour > 23
+            || self.minute > 59
+            || self.second > 59
+            || self.nanosecond as u64 >= NANOS_PER_SEC
+        {
+            return None;
+        }
+
+        // Days-from-civil, the inverse of `from_unix_nanos`
+        let year = self.year as u64 - u64::from(self.month <= 2);
+        let era = year / 400;
+        let yoe = year % 400;
+        let month = self.month as u64;
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + self.day as u64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146_097 + doe - 719_468;
+
+        let secs = days * SECS_PER_DAY
+            + self.hour as u64 * 3600
+            + self.minute as u64 * 60
+            + self.second as u64;
+        secs.checked_mul(NANOS_PER_SEC)?
+            .checked_add(self.nanosecond as u64)
+    }
+
+    /// Convert to a [`SystemTime`], see [`DateTime::to_unix_nanos`]
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        self.to_unix_nanos().map(SystemTime::from_unix_nanos)
+    }
+
+    /// Day of the week of the date
+    pub fn weekday(&self) -> Weekday {
+        const DAYS: [Weekday; 7] = [
+            Weekday::Thursday,
+            Weekday::Friday,
+            Weekday::Saturday,
+            Weekday::Sunday,
+            Weekday::Monday,
+            Weekday::Tuesday,
+            Weekday::Wednesday,
+        ];
+
+        let date = DateTime {
+            hour: 0,
+            minute: 0,
+            second: 0,
+            nanosecond: 0,
+            ..*self
+        };
+        let days = date.to_unix_nanos().unwrap_or(0) / NANOS_PER_SEC / SECS_PER_DAY;
+        DAYS[(days % 7) as usize]
+    }
+}
+
+impl fmt::Display for DateTime {
+    /// Format as ISO 8601, e.g. `2024-02-29T13:05:09Z`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Error returned by [`set_alarm`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmError {
+    /// The platform has no alarm interrupt
+    Unsupported,
+}
+
+/// Call `handler` once the real-time clock reaches `at`
+///
+/// The handler runs in interrupt context, so it must be short and must not
+/// block. Only one alarm can be pending; setting a new one replaces it. A
+/// time in the past fires as soon as interrupts are enabled.
+///
+/// # Arguments
+/// * `at` - When to fire
+/// * `handler` - Function to call from the alarm interrupt
+///
+/// # Returns
+/// * `Ok(())` - The alarm is armed
+/// * `Err(AlarmError::Unsupported)` - The platform has no alarm interrupt
+pub fn set_alarm(at: SystemTime, handler: fn()) -> Result<(), AlarmError> {
+    if Current::rtc_set_alarm(at.nanos, handler) {
+        Ok(())
+    } else {
+        Err(AlarmError::Unsupported)
+    }
+}
+
+/// Call `handler` after `delay` has passed, see [`set_alarm`]
+pub fn set_alarm_after(delay: Duration, handler: fn()) -> Result<(), AlarmError> {
+    set_alarm(SystemTime::now() + delay, handler)
+}
+
+/// Cancel the pending alarm, if any
+pub fn cancel_alarm() {
+    Current::rtc_cancel_alarm()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datetime(year: u32, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            nanosecond: 0,
+        }
+    }
+
+    #[test]
+    fn test_known_dates() {
+        let cases = [
+            (0, datetime(1970, 1, 1, 0, 0, 0)),
+            (951_782_400, datetime(2000, 2, 29, 0, 0, 0)),
+            (1_700_000_000, datetime(2023, 11, 14, 22, 13, 20)),
+            (4_107_542_399, datetime(2100, 2, 28, 23, 59, 59)),
+        ];
+
+        for (secs, expected) in cases {
+            let time = SystemTime::from_unix_nanos(secs * NANOS_PER_SEC);
+            assert_eq!(time.to_datetime(), expected);
+            assert_eq!(expected.to_system_time(), Some(time));
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for day in (0..200_000u64).step_by(7) {
+            let nanos = (day * SECS_PER_DAY + day % SECS_PER_DAY) * NANOS_PER_SEC + day;
+            assert_eq!(
+                DateTime::from_unix_nanos(nanos).to_unix_nanos(),
+                Some(nanos)
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_fields() {
+        assert_eq!(datetime(2023, 2, 29, 0, 0, 0).to_unix_nanos(), None);
+        assert_eq!(datetime(2023, 13, 1, 0, 0, 0).to_unix_nanos(), None);
+        assert_eq!(datetime(1969, 12, 31, 23, 59, 59).to_unix_nanos(), None);
+    }
+
+    #[test]
+    fn test_weekday_and_display() {
+        assert_eq!(datetime(1970, 1, 1, 12, 0, 0).weekday(), Weekday::Thursday);
+        let date = datetime(2024, 2, 29, 13, 5, 9);
+        assert_eq!(date.weekday(), Weekday::Thursday);
+        assert_eq!(alloc::format!("{}", date), "2024-02-29T13:05:09Z");
+    }
+
+    #[test]
+    fn test_duration_since() {
+        let a = SystemTime::from_unix_nanos(5 * NANOS_PER_SEC);
+        let b = a + Duration::from_millis(1500);
+        assert_eq!(b.duration_since(a), Ok(Duration::from_millis(1500)));
+        assert_eq!(
+            a.duration_since(b).unwrap_err().duration(),
+            Duration::from_millis(1500)
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! The unwinder behind the `unwind` feature
+//!
+//! A panic raises an [`Exception`] and unwinds in the two phases of the
+//! Itanium ABI: first walk the stack, without changing anything, for a
+//! frame that catches; then, only if there is one, walk it again, jumping
+//! into each landing pad on the way. A cleanup pad runs the frame's drops
+//! and calls `_Unwind_Resume`, which carries on from there; the catch pad
+//! of `catch_unwind` ends the unwinding.
+//!
+//! Frames are unwound with the `.eh_frame` tables (`common::eh_frame`) and
+//! their landing pads found in `.gcc_except_table` (`common::lsda`), both
+//! kept by the linker scripts. Only Rust code is ever unwound through, so
+//! the unwinder reads the LSDA itself rather than calling a personality
+//! routine; `rust_eh_personality` exists only because rustc references it.
+
+use alloc::boxed::Box;
+use common::{CfiError, EhAction, Fde, REG_A0, REG_A1, UnwindContext, find_eh_action, find_fde};
+use core::any::Any;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Frames walked before giving up, against corrupted stacks
+const MAX_DEPTH: usize = 1024;
+
+/// Set while unwinding, to exit on a panic in a destructor
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// What a panic throws, passed to landing pads in `a0`
+struct Exception {
+    payload: Box<dyn Any + Send>,
+}
+
+unsafe extern "C" {
+    static __eh_frame_start: u8;
+    static __eh_frame_end: u8;
+    static __gcc_except_table_end: u8;
+}
+
+fn eh_frame() -> &'static [u8] {
+    let start = &raw const __eh_frame_start;
+    let end = &raw const __eh_frame_end;
+    unsafe { core::slice::from_raw_parts(start, end as usize - start as usize) }
+}
+
+/// A function's LSDA, up to the end of `.gcc_except_table`
+fn lsda(addr: usize) -> &'static [u8] {
+    let end = &raw const __gcc_except_table_end as usize;
+    unsafe { core::slice::from_raw_parts(addr as *const u8, end.saturating_sub(addr)) }
+}
+
+/// What the frame of `context` does with an exception
+fn eh_action(context: &UnwindContext) -> Result<(EhAction, Fde<'static>), CfiError> {
+    let ip = context.pc - 1;
+    let fde = find_fde(eh_frame(), ip)?;
+    let action = match fde.lsda {
+        Some(addr) => find_eh_action(lsda(addr), fde.start, ip)?,
+        None => EhAction::None,
+    };
+    Ok((action, fde))
+}
+
+/// Phase 1: whether a frame above `context` catches
+fn search(mut context: UnwindContext) -> Result<bool, CfiError> {
+    for _ in 0..MAX_DEPTH {
+        if context.pc == 0 {
+            return Ok(false);
+        }
+        match eh_action(&context)? {
+            (EhAction::Catch(_), _) => return Ok(true),
+            (EhAction::Terminate, _) => return Ok(false),
+            (_, fde) => context.step(&fde)?,
+        }
+    }
+    Ok(false)
+}
+
+/// Phase 2: jump into the next landing pad above `context`; returns only
+/// if the walk fails
+fn cleanup(mut context: UnwindContext, exception: *mut Exception) -> CfiError {
+    for _ in 0..MAX_DEPTH {
+        if context.pc == 0 {
+            return CfiError::NoFde;
+        }
+        match eh_action(&context) {
+            Ok((EhAction::Cleanup(landing_pad) | EhAction::Catch(landing_pad), _)) => {
+                context.regs[REG_A0] = exception as usize;
+                context.regs[REG_A1] = 0;
+                context.pc = landing_pad;
+                unsafe { __am_unwind_restore(&context) }
+            }
+            Ok((EhAction::Terminate, _)) => return CfiError::NoFde,
+            Ok((EhAction::None, fde)) => {
+                if let Err(err) = context.step(&fde) {
+                    return err;
+                }
+            }
+            Err(err) => return err,
+        }
+    }
+    CfiError::NoFde
+}
+
+/// Unwind a panic, from the panic handler
+///
+/// Returns when nothing catches it, the stack cannot be unwound, or this
+/// is a panic during unwinding; the panic handler then exits.
+pub(crate) fn begin_panic(info: &PanicInfo) {
+    if PANICKING.load(Ordering::Relaxed) {
+        crate::eprintln!("Panic while unwinding, exiting");
+        return;
+    }
+    let payload: Box<dyn Any + Send> = match info.message().as_str() {
+        Some(message) => Box::new(message),
+        None => Box::new(alloc::format!("{}", info.message())),
+    };
+    raise(payload);
+}
+
+/// Unwind to the innermost `catch_unwind`
+///
+/// # Returns
+/// * The payload back, when nothing catches it or the stack cannot be
+///   unwound
+#[inline(never)]
+pub(crate) fn raise(payload: Box<dyn Any + Send>) -> Box<dyn Any + Send> {
+    extern "C" fn unwind(context: &mut UnwindContext, exception: *mut u8) {
+        if let Ok(true) = search(*context) {
+            cleanup(*context, exception as *mut Exception);
+        }
+    }
+
+    PANICKING.store(true, Ordering::Relaxed);
+    let exception = Box::into_raw(Box::new(Exception { payload }));
+    unsafe { __am_unwind_save(unwind, exception as *mut u8) };
+    unsafe { Box::from_raw(exception) }.payload
+}
+
+/// The payload of the exception a catch pad received, freeing it
+pub(crate) fn take_payload(exception: *mut u8) -> Box<dyn Any + Send> {
+    PANICKING.store(false, Ordering::Relaxed);
+    unsafe { Box::from_raw(exception as *mut Exception) }.payload
+}
+
+/// Called by cleanup pads when they are done, to unwind further
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+#[inline(never)]
+pub extern "C-unwind" fn _Unwind_Resume(exception: *mut u8) -> ! {
+    extern "C" fn unwind(context: &mut UnwindContext, exception: *mut u8) {
+        let err = cleanup(*context, exception as *mut Exception);
+        crate::eprintln!("Panic: cannot unwind further: {:?}", err);
+    }
+
+    unsafe { __am_unwind_save(unwind, exception) };
+    crate::process::abort()
+}
+
+#[lang = "eh_personality"]
+extern "C" fn rust_eh_personality(
+    _version: i32,
+    _actions: i32,
+    _class: u64,
+    _exception: *mut u8,
+    _context: *mut u8,
+) -> i32 {
+    // _URC_CONTINUE_UNWIND; never called, see the module documentation
+    8
+}
+
+// "C-unwind": the unwinder starts from the call to `__am_unwind_save`, which
+// must then appear in the caller's landing pad table
+unsafe extern "C-unwind" {
+    /// Save the registers into an [`UnwindContext`] describing the caller,
+    /// then call `f` with it and `data`
+    fn __am_unwind_save(f: extern "C" fn(&mut UnwindContext, *mut u8), data: *mut u8);
+}
+
+unsafe extern "C" {
+    /// Load the registers of a context and jump to its pc
+    fn __am_unwind_restore(context: &UnwindContext) -> !;
+}
+
+// The context is 33 words: x0 to x31, then the pc. `__am_unwind_save`
+// stores it on its own stack with `sp` as it was before the call and the
+// return address as the pc, so that it describes the calling frame.
+#[cfg(target_arch = "riscv32")]
+core::arch::global_asm!(
+    ".section .text.__am_unwind_save, \"ax\"",
+    ".global __am_unwind_save",
+    "__am_unwind_save:",
+    ".cfi_startproc",
+    "addi sp, sp, -144",
+    ".cfi_def_cfa_offset 144",
+    "sw ra, 140(sp)",
+    ".cfi_offset ra, -4",
+    "sw x1, 4(sp)",
+    "sw x3, 12(sp)",
+    "sw x4, 16(sp)",
+    "sw x5, 20(sp)",
+    "sw x6, 24(sp)",
+    "sw x7, 28(sp)",
+    "sw x8, 32(sp)",
+    "sw x9, 36(sp)",
+    "sw x10, 40(sp)",
+    "sw x11, 44(sp)",
+    "sw x12, 48(sp)",
+    "sw x13, 52(sp)",
+    "sw x14, 56(sp)",
+    "sw x15, 60(sp)",
+    "sw x16, 64(sp)",
+    "sw x17, 68(sp)",
+    "sw x18, 72(sp)",
+    "sw x19, 76(sp)",
+    "sw x20, 80(sp)",
+    "sw x21, 84(sp)",
+    "sw x22, 88(sp)",
+    "sw x23, 92(sp)",
+    "sw x24, 96(sp)",
+    "sw x25, 100(sp)",
+    "sw x26, 104(sp)",
+    "sw x27, 108(sp)",
+    "sw x28, 112(sp)",
+    "sw x29, 116(sp)",
+    "sw x30, 120(sp)",
+    "sw x31, 124(sp)",
+    "sw zero, 0(sp)",
+    "addi t0, sp, 144",
+    "sw t0, 8(sp)",
+    "sw ra, 128(sp)",
+    "mv t0, a0",
+    "mv a0, sp",
+    "jalr t0",
+    "lw ra, 140(sp)",
+    "addi sp, sp, 144",
+    ".cfi_def_cfa_offset 0",
+    "ret",
+    ".cfi_endproc",
+    "",
+    ".section .text.__am_unwind_restore, \"ax\"",
+    ".global __am_unwind_restore",
+    "__am_unwind_restore:",
+    "lw x1, 4(a0)",
+    "lw x2, 8(a0)",
+    "lw x3, 12(a0)",
+    "lw x4, 16(a0)",
+    "lw x6, 24(a0)",
+    "lw x7, 28(a0)",
+    "lw x8, 32(a0)",
+    "lw x9, 36(a0)",
+    "lw x11, 44(a0)",
+    "lw x12, 48(a0)",
+    "lw x13, 52(a0)",
+    "lw x14, 56(a0)",
+    "lw x15, 60(a0)",
+    "lw x16, 64(a0)",
+    "lw x17, 68(a0)",
+    "lw x18, 72(a0)",
+    "lw x19, 76(a0)",
+    "lw x20, 80(a0)",
+    "lw x21, 84(a0)",
+    "lw x22, 88(a0)",
+    "lw x23, 92(a0)",
+    "lw x24, 96(a0)",
+    "lw x25, 100(a0)",
+    "lw x26, 104(a0)",
+    "lw x27, 108(a0)",
+    "lw x28, 112(a0)",
+    "lw x29, 116(a0)",
+    "lw x30, 120(a0)",
+    "lw x31, 124(a0)",
+    // t0 is dead in a landing pad, it holds the target
+    "lw t0, 128(a0)",
+    "lw a0, 40(a0)",
+    "jr t0",
+);
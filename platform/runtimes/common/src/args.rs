@@ -0,0 +1,34 @@
+//! Program arguments passed by a loader
+//!
+//! `runtime::elf` starts a program with `argc` in `a0`, `argv` in `a1` and
+//! [`ARGS_MAGIC`] in `a2`, with the argument strings at the top of the new
+//! stack. The platform `_start` then keeps the stack it was given and stores
+//! `argc` and `argv` in `__am_args` before any Rust code runs. Programs
+//! started any other way have no arguments.
+
+/// Value in `a2` marking a start by a loader that passes arguments ("ARGV")
+pub const ARGS_MAGIC: usize = 0x4152_4756;
+
+/// `argc` and `argv`, written by `_start`; kept in `.data` so that nothing
+/// clears it after the write
+#[unsafe(export_name = "__am_args")]
+#[unsafe(link_section = ".data.am_args")]
+static mut ARGS: [usize; 2] = [0, 0];
+
+/// Record `argc` and `argv`
+///
+/// `_start` stores into `__am_args` directly; this is for loaders that call
+/// into a program some other way.
+pub fn save_args(argc: usize, argv: *const *const u8) {
+    unsafe { core::ptr::write_volatile(&raw mut ARGS, [argc, argv as usize]) }
+}
+
+/// The arguments recorded at startup
+///
+/// # Returns
+/// * `(argc, argv)` - `argv` points to `argc` NUL-terminated strings, or is
+///   null when `argc` is 0
+pub fn startup_args() -> (usize, *const *const u8) {
+    let [argc, argv] = unsafe { core::ptr::read_volatile(&raw const ARGS) };
+    (argc, argv as *const *const u8)
+}
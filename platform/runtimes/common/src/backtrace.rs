@@ -0,0 +1,214 @@
+//! Stack walking through frame pointers
+//!
+//! Images are built with `-Cforce-frame-pointers=yes`, so every function
+//! compiled here keeps `s0` pointing just above its frame, with the return
+//! address at `s0 - 4` and the caller's `s0` at `s0 - 8` (`- 8` and `- 16`
+//! on RV64). The walk stops at the first frame pointer outside the stack
+//! or not above the previous one. Precompiled `core` code has no frame
+//! pointers: calls through it lose a frame, but do not derail the walk.
+
+use crate::symtab::symbolize;
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Most frames kept in a [`Backtrace`]
+pub const MAX_FRAMES: usize = 32;
+
+/// Program counter and frame pointer of a fatal trap, see [`record_trap`]
+static TRAP_PC: AtomicUsize = AtomicUsize::new(0);
+static TRAP_FP: AtomicUsize = AtomicUsize::new(0);
+
+/// Record where a fatal trap happened, for the panic that reports it
+///
+/// The next [`Backtrace::capture`] then starts at the trapped instruction,
+/// rather than in the trap handler.
+///
+/// # Arguments
+/// * `pc` - `mepc`, the trapped instruction
+/// * `fp` - `s0` at the time of the trap
+pub fn record_trap(pc: usize, fp: usize) {
+    TRAP_FP.store(fp, Ordering::Relaxed);
+    TRAP_PC.store(pc, Ordering::Relaxed);
+}
+
+/// Return addresses of the calls leading to a point in the program
+pub struct Backtrace {
+    frames: [usize; MAX_FRAMES],
+    len: usize,
+}
+
+impl Backtrace {
+    /// Walk the stack of the caller, or of the trap recorded by
+    /// [`record_trap`]
+    #[inline(never)]
+    pub fn capture() -> Self {
+        // No atomic swap without the A extension; a panic only runs once
+        let pc = TRAP_PC.load(Ordering::Relaxed);
+        if pc != 0 {
+            TRAP_PC.store(0, Ordering::Relaxed);
+            return Self::from_frame(Some(pc), TRAP_FP.load(Ordering::Relaxed));
+        }
+        Self::from_frame(None, frame_pointer())
+    }
+
+    /// Walk the stack from a frame pointer
+    ///
+    /// # Arguments
+    /// * `pc` - An address to list first, the current instruction of the
+    ///   frame
+    /// * `fp` - The frame pointer to start from
+    pub fn from_frame(pc: Option<usize>, fp: usize) -> Self {
+        let mut backtrace = Self {
+            frames: [0; MAX_FRAMES],
+            len: 0,
+        };
+        if let Some(pc) = pc {
+            backtrace.push(pc);
+        }
+
+        backtrace.walk(fp, stack_bounds());
+        backtrace
+    }
+
+    /// Follow the chain of frame pointers within `bottom..=top`
+    fn walk(&mut self, fp: usize, bounds: (usize, usize)) {
+        walk(fp, bounds, |ra| {
+            self.push(ra);
+            self.len < MAX_FRAMES
+        });
+    }
+
+    fn push(&mut self, addr: usize) {
+        if self.len < MAX_FRAMES {
+            self.frames[self.len] = addr;
+            self.len += 1;
+        }
+    }
+
+    /// The addresses, innermost first
+    pub fn frames(&self) -> &[usize] {
+        &self.frames[..self.len]
+    }
+}
+
+/// Return addresses of the calls leading to the caller, innermost first,
+/// without the cost of a whole [`Backtrace`]
+///
+/// # Arguments
+/// * `frames` - Where to store them; the walk stops when it is full
+///
+/// # Returns
+/// * The number of addresses stored
+#[inline(never)]
+pub fn caller_frames(frames: &mut [usize]) -> usize {
+    let mut len = 0;
+    walk(frame_pointer(), stack_bounds(), |ra| {
+        if len < frames.len() {
+            frames[len] = ra;
+            len += 1;
+        }
+        len < frames.len()
+    });
+    len
+}
+
+/// Follow the chain of frame pointers from `fp` within `bottom..=top`,
+/// passing each return address to `f` until it returns false
+fn walk(mut fp: usize, (bottom, top): (usize, usize), mut f: impl FnMut(usize) -> bool) {
+    let word = size_of::<usize>();
+    while fp >= bottom + 2 * word && fp <= top && fp.is_multiple_of(word) {
+        let (ra, caller_fp) = unsafe {
+            (
+                *((fp - word) as *const usize),
+                *((fp - 2 * word) as *const usize),
+            )
+        };
+        if ra == 0 || !f(ra) || caller_fp <= fp {
+            break;
+        }
+        fp = caller_fp;
+    }
+}
+
+/// One line per frame, with the function name when the symbol table has
+/// been filled in
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Backtrace:")?;
+        for (i, &addr) in self.frames().iter().enumerate() {
+            write!(f, "  {:>2}: 0x{:08x}", i, addr)?;
+            // A return address can be just past the end of the calling
+            // function, look up the call instead
+            match symbolize(addr.saturating_sub(1)) {
+                Some((name, offset)) => writeln!(f, "  {}+0x{:x}", name, offset + 1)?,
+                None => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+#[inline(always)]
+fn frame_pointer() -> usize {
+    let fp: usize;
+    unsafe { core::arch::asm!("mv {}, s0", out(reg) fp, options(nomem, nostack)) };
+    fp
+}
+
+#[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+fn frame_pointer() -> usize {
+    0
+}
+
+/// The stack lies between `_sstack` and the end of RAM
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+fn stack_bounds() -> (usize, usize) {
+    unsafe extern "C" {
+        static _sstack: u8;
+        static _stack_top: u8;
+    }
+    (&raw const _sstack as usize, &raw const _stack_top as usize)
+}
+
+#[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+fn stack_bounds() -> (usize, usize) {
+    (0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_walk() {
+        // Three frames, each `[caller fp, return address]` below its fp;
+        // the outermost one ends the chain with a zero return address
+        let mut stack = [0usize; 8];
+        let base = stack.as_ptr() as usize;
+        let fp = |index: usize| base + index * size_of::<usize>();
+        stack[0..2].copy_from_slice(&[fp(4), 0x8000_0010]);
+        stack[2..4].copy_from_slice(&[fp(6), 0x8000_0020]);
+        stack[4..6].copy_from_slice(&[fp(8), 0x8000_0030]);
+        stack[6..8].copy_from_slice(&[0, 0]);
+
+        let mut backtrace = Backtrace {
+            frames: [0; MAX_FRAMES],
+            len: 0,
+        };
+        backtrace.push(0x8000_0000);
+        backtrace.walk(fp(2), (base, fp(8)));
+        assert_eq!(
+            backtrace.frames(),
+            [0x8000_0000, 0x8000_0010, 0x8000_0020, 0x8000_0030]
+        );
+
+        // A frame pointer outside the stack stops the walk
+        let mut backtrace = Backtrace {
+            frames: [0; MAX_FRAMES],
+            len: 0,
+        };
+        backtrace.walk(fp(2), (base, fp(4)));
+        assert_eq!(backtrace.frames(), [0x8000_0010, 0x8000_0020]);
+    }
+}
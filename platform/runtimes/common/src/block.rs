@@ -0,0 +1,75 @@
+//! Block device abstraction
+//!
+//! Filesystems access storage through [`BlockDevice`], in units of
+//! [`BLOCK_SIZE`]-byte blocks. Platforms implement it for their disk devices.
+
+use core::fmt;
+
+/// Size of one block in bytes
+pub const BLOCK_SIZE: usize = 512;
+
+/// Errors reported by block devices
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockError {
+    /// The request reaches past the end of the device
+    OutOfRange,
+    /// The buffer length is not a multiple of [`BLOCK_SIZE`]
+    Misaligned,
+    /// The device refuses writes
+    ReadOnly,
+    /// The device reported an error
+    Io,
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            BlockError::OutOfRange => "block out of range",
+            BlockError::Misaligned => "buffer is not a whole number of blocks",
+            BlockError::ReadOnly => "device is read-only",
+            BlockError::Io => "device I/O error",
+        };
+        f.write_str(message)
+    }
+}
+
+/// A device storing fixed-size blocks
+pub trait BlockDevice {
+    /// Number of blocks on the device
+    fn block_count(&self) -> u64;
+
+    /// Read consecutive blocks
+    ///
+    /// # Arguments
+    /// * `lba` - Index of the first block
+    /// * `buf` - Buffer to fill, a multiple of [`BLOCK_SIZE`] bytes long
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError>;
+
+    /// Write consecutive blocks
+    ///
+    /// # Arguments
+    /// * `lba` - Index of the first block
+    /// * `buf` - Data to write, a multiple of [`BLOCK_SIZE`] bytes long
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError>;
+
+    /// Make previous writes durable
+    fn flush(&mut self) -> Result<(), BlockError> {
+        Ok(())
+    }
+}
+
+/// Check a request against the device size and the block size
+///
+/// # Returns
+/// * `Ok(blocks)` - Number of blocks covered by `len` bytes
+/// * `Err(error)` - The request is misaligned or out of range
+pub fn check_request(block_count: u64, lba: u64, len: usize) -> Result<u64, BlockError> {
+    if !len.is_multiple_of(BLOCK_SIZE) {
+        return Err(BlockError::Misaligned);
+    }
+    let blocks = (len / BLOCK_SIZE) as u64;
+    match lba.checked_add(blocks) {
+        Some(end) if end <= block_count => Ok(blocks),
+        _ => Err(BlockError::OutOfRange),
+    }
+}
@@ -0,0 +1,690 @@
+//! DWARF call frame information, for unwinding
+//!
+//! Reads the `.eh_frame` section: a list of CIEs (common information
+//! entries) and FDEs (frame description entries), each FDE covering one
+//! function. Running the FDE's call frame instructions up to an address
+//! gives the rules that recover the caller's registers there, which
+//! [`UnwindContext::step`] applies. Only what LLVM emits for RISC-V is
+//! supported: CFA expressions are rejected, and floating-point registers
+//! are not tracked.
+
+/// Registers of an [`UnwindContext`], `x0` to `x31` by DWARF number
+pub const UNWIND_REGS: usize = 32;
+
+/// DWARF number of `sp`
+pub const REG_SP: usize = 2;
+
+/// DWARF number of `a0`, which carries the exception into a landing pad
+pub const REG_A0: usize = 10;
+
+/// DWARF number of `a1`, which carries the selector into a landing pad
+pub const REG_A1: usize = 11;
+
+// Pointer encodings
+pub(crate) const DW_EH_PE_OMIT: u8 = 0xff;
+const DW_EH_PE_PCREL: u8 = 0x10;
+const DW_EH_PE_INDIRECT: u8 = 0x80;
+
+/// Depth of `DW_CFA_remember_state`
+const STATE_STACK: usize = 4;
+
+/// Why a frame cannot be unwound
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CfiError {
+    /// No FDE covers the address
+    NoFde,
+    /// The tables are truncated or inconsistent
+    Malformed,
+    /// An encoding or instruction this unwinder does not implement
+    Unsupported(u8),
+}
+
+/// Reads DWARF data, never past the end of its slice
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    pub(crate) fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], CfiError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or(CfiError::Malformed)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], CfiError> {
+        self.bytes(N)?.try_into().map_err(|_| CfiError::Malformed)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, CfiError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, CfiError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn uleb(&mut self) -> Result<u64, CfiError> {
+        let (mut value, mut shift) = (0u64, 0);
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as u64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    pub(crate) fn sleb(&mut self) -> Result<i64, CfiError> {
+        let (mut value, mut shift) = (0i64, 0);
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Ok(value);
+            }
+        }
+    }
+
+    /// A value in the format given by the low bits of `encoding`
+    pub(crate) fn value(&mut self, encoding: u8) -> Result<usize, CfiError> {
+        Ok(match encoding & 0x0f {
+            0x00 => usize::from_le_bytes(self.array()?),
+            0x01 => self.uleb()? as usize,
+            0x02 => u16::from_le_bytes(self.array()?) as usize,
+            0x03 => self.u32()? as usize,
+            0x04 => u64::from_le_bytes(self.array()?) as usize,
+            0x09 => self.sleb()? as usize,
+            0x0a => i16::from_le_bytes(self.array()?) as usize,
+            0x0b => i32::from_le_bytes(self.array()?) as usize,
+            0x0c => i64::from_le_bytes(self.array()?) as usize,
+            _ => return Err(CfiError::Unsupported(encoding)),
+        })
+    }
+
+    /// A pointer, relative to where it is stored for `DW_EH_PE_pcrel`
+    pub(crate) fn pointer(&mut self, encoding: u8) -> Result<usize, CfiError> {
+        let field = self.data.as_ptr() as usize + self.pos;
+        let value = self.value(encoding)?;
+        let address = match encoding & 0x70 {
+            0x00 => value,
+            DW_EH_PE_PCREL => field.wrapping_add(value),
+            _ => return Err(CfiError::Unsupported(encoding)),
+        };
+        if encoding & DW_EH_PE_INDIRECT != 0 {
+            return Ok(unsafe { *(address as *const usize) });
+        }
+        Ok(address)
+    }
+}
+
+/// The parts of a CIE the unwinder needs
+#[derive(Clone, Copy)]
+struct Cie<'a> {
+    code_align: u64,
+    data_align: i64,
+    ra_reg: usize,
+    fde_encoding: u8,
+    lsda_encoding: u8,
+    has_augmentation_data: bool,
+    instructions: &'a [u8],
+}
+
+/// The unwind information of one function
+#[derive(Clone, Copy)]
+pub struct Fde<'a> {
+    /// First address of the function
+    pub start: usize,
+    /// Address just past the function
+    pub end: usize,
+    /// Its language-specific data area: the landing pads, see
+    /// [`find_eh_action`](crate::find_eh_action)
+    pub lsda: Option<usize>,
+    cie: Cie<'a>,
+    instructions: &'a [u8],
+}
+
+/// Where a register of the caller is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rule {
+    /// The register is not recoverable; for the return address, the end
+    /// of the stack
+    Undefined,
+    /// The register is unchanged
+    SameValue,
+    /// Saved at CFA + offset
+    Offset(i64),
+    /// Its value is CFA + offset
+    ValOffset(i64),
+    /// Held in another register
+    Register(usize),
+}
+
+/// The rules at one address of a function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Row {
+    cfa_reg: usize,
+    cfa_offset: i64,
+    rules: [Rule; UNWIND_REGS],
+}
+
+fn parse_cie(eh_frame: &[u8], offset: usize) -> Result<Cie<'_>, CfiError> {
+    let mut reader = Reader::new(eh_frame, offset);
+    let length = reader.u32()? as usize;
+    if length == 0xffff_ffff {
+        return Err(CfiError::Unsupported(0xff));
+    }
+    let end = reader.pos() + length;
+    if reader.u32()? != 0 {
+        return Err(CfiError::Malformed);
+    }
+
+    let version = reader.u8()?;
+    let augmentation_start = reader.pos();
+    while reader.u8()? != 0 {}
+    let augmentation = &eh_frame[augmentation_start..reader.pos() - 1];
+
+    let code_align = reader.uleb()?;
+    let data_align = reader.sleb()?;
+    let ra_reg = if version == 1 {
+        reader.u8()? as usize
+    } else {
+        reader.uleb()? as usize
+    };
+
+    let mut cie = Cie {
+        code_align,
+        data_align,
+        ra_reg,
+        fde_encoding: 0,
+        lsda_encoding: DW_EH_PE_OMIT,
+        has_augmentation_data: augmentation.first() == Some(&b'z'),
+        instructions: &[],
+    };
+    if cie.has_augmentation_data {
+        let length = reader.uleb()? as usize;
+        let data_end = reader.pos() + length;
+        for &kind in &augmentation[1..] {
+            match kind {
+                b'L' => cie.lsda_encoding = reader.u8()?,
+                b'R' => cie.fde_encoding = reader.u8()?,
+                b'P' => {
+                    // The personality routine; the unwinder reads the LSDA
+                    // itself and does not call it
+                    let encoding = reader.u8()?;
+                    reader.pointer(encoding)?;
+                }
+                b'S' => {}
+                _ => break,
+            }
+        }
+        reader = Reader::new(eh_frame, data_end);
+    }
+
+    cie.instructions = eh_frame.get(reader.pos()..end).ok_or(CfiError::Malformed)?;
+    Ok(cie)
+}
+
+/// Parse the FDE whose initial location is at `offset`, up to `end`
+fn parse_fde<'a>(
+    eh_frame: &'a [u8],
+    offset: usize,
+    end: usize,
+    cie: Cie<'a>,
+) -> Result<Fde<'a>, CfiError> {
+    let mut reader = Reader::new(eh_frame, offset);
+    let start = reader.pointer(cie.fde_encoding)?;
+    let len = reader.value(cie.fde_encoding)?;
+
+    let mut lsda = None;
+    if cie.has_augmentation_data {
+        let length = reader.uleb()? as usize;
+        let data_end = reader.pos() + length;
+        if cie.lsda_encoding != DW_EH_PE_OMIT {
+            lsda = Some(reader.pointer(cie.lsda_encoding)?).filter(|&lsda| lsda != 0);
+        }
+        reader = Reader::new(eh_frame, data_end);
+    }
+
+    Ok(Fde {
+        start,
+        end: start.wrapping_add(len),
+        lsda,
+        cie,
+        instructions: eh_frame.get(reader.pos()..end).ok_or(CfiError::Malformed)?,
+    })
+}
+
+/// Find the FDE covering an address
+///
+/// # Arguments
+/// * `eh_frame` - The `.eh_frame` section, at its load address
+/// * `pc` - An address within the function
+///
+/// # Returns
+/// * `Ok(fde)` - The function's unwind information
+/// * `Err(CfiError::NoFde)` - No function covers `pc`, it was compiled
+///   without unwind tables
+pub fn find_fde(eh_frame: &[u8], pc: usize) -> Result<Fde<'_>, CfiError> {
+    let mut offset = 0;
+    while offset + 4 <= eh_frame.len() {
+        let mut reader = Reader::new(eh_frame, offset);
+        let length = reader.u32()? as usize;
+        if length == 0 {
+            break;
+        }
+        if length == 0xffff_ffff {
+            return Err(CfiError::Unsupported(0xff));
+        }
+        let end = offset + 4 + length;
+
+        // Zero for a CIE, the distance back to the CIE for an FDE
+        let id_pos = reader.pos();
+        let cie_pointer = reader.u32()? as usize;
+        if cie_pointer != 0 {
+            let cie = parse_cie(
+                eh_frame,
+                id_pos.checked_sub(cie_pointer).ok_or(CfiError::Malformed)?,
+            )?;
+            let fde = parse_fde(eh_frame, reader.pos(), end, cie)?;
+            if (fde.start..fde.end).contains(&pc) {
+                return Ok(fde);
+            }
+        }
+        offset = end;
+    }
+    Err(CfiError::NoFde)
+}
+
+impl Fde<'_> {
+    /// Run the call frame instructions up to `pc`
+    fn row_at(&self, pc: usize) -> Result<Row, CfiError> {
+        let mut row = Row {
+            cfa_reg: REG_SP,
+            cfa_offset: 0,
+            rules: [Rule::SameValue; UNWIND_REGS],
+        };
+        let initial = execute(
+            self.cie.instructions,
+            &self.cie,
+            row,
+            None,
+            usize::MAX,
+            usize::MAX,
+        )?;
+        row = execute(
+            self.instructions,
+            &self.cie,
+            initial,
+            Some(&initial),
+            self.start,
+            pc,
+        )?;
+        Ok(row)
+    }
+}
+
+/// Apply call frame instructions starting at `loc`, stopping before those
+/// for addresses past `pc`
+fn execute(
+    instructions: &[u8],
+    cie: &Cie<'_>,
+    mut row: Row,
+    initial: Option<&Row>,
+    mut loc: usize,
+    pc: usize,
+) -> Result<Row, CfiError> {
+    let mut reader = Reader::new(instructions, 0);
+    let mut stack = [row; STATE_STACK];
+    let mut depth = 0;
+
+    let factored = |offset: u64| offset as i64 * cie.data_align;
+    let set = |row: &mut Row, reg: u64, rule: Rule| {
+        // Floating-point registers are not tracked
+        if let Some(slot) = row.rules.get_mut(reg as usize) {
+            *slot = rule;
+        }
+    };
+
+    while reader.pos() < instructions.len() {
+        let op = reader.u8()?;
+        let advance = match op >> 6 {
+            1 => Some((op & 0x3f) as u64),
+            2 => {
+                let offset = reader.uleb()?;
+                set(&mut row, (op & 0x3f) as u64, Rule::Offset(factored(offset)));
+                None
+            }
+            3 => {
+                let reg = (op & 0x3f) as usize;
+                let rule = initial.ok_or(CfiError::Malformed)?.rules.get(reg).copied();
+                set(&mut row, reg as u64, rule.unwrap_or(Rule::SameValue));
+                None
+            }
+            _ => match op {
+                // DW_CFA_nop
+                0x00 => None,
+                // DW_CFA_set_loc
+                0x01 => {
+                    loc = reader.pointer(cie.fde_encoding)?;
+                    if loc > pc {
+                        break;
+                    }
+                    None
+                }
+                // DW_CFA_advance_loc1, 2, 4
+                0x02 => Some(reader.u8()? as u64),
+                0x03 => Some(reader.value(0x02)? as u64),
+                0x04 => Some(reader.value(0x03)? as u64),
+                // DW_CFA_offset_extended
+                0x05 => {
+                    let reg = reader.uleb()?;
+                    let offset = reader.uleb()?;
+                    set(&mut row, reg, Rule::Offset(factored(offset)));
+                    None
+                }
+                // DW_CFA_restore_extended
+                0x06 => {
+                    let reg = reader.uleb()?;
+                    let rule = initial
+                        .ok_or(CfiError::Malformed)?
+                        .rules
+                        .get(reg as usize)
+                        .copied();
+                    set(&mut row, reg, rule.unwrap_or(Rule::SameValue));
+                    None
+                }
+                // DW_CFA_undefined, DW_CFA_same_value
+                0x07 => {
+                    set(&mut row, reader.uleb()?, Rule::Undefined);
+                    None
+                }
+                0x08 => {
+                    set(&mut row, reader.uleb()?, Rule::SameValue);
+                    None
+                }
+                // DW_CFA_register
+                0x09 => {
+                    let reg = reader.uleb()?;
+                    let other = reader.uleb()? as usize;
+                    set(&mut row, reg, Rule::Register(other));
+                    None
+                }
+                // DW_CFA_remember_state
+                0x0a => {
+                    *stack.get_mut(depth).ok_or(CfiError::Unsupported(op))? = row;
+                    depth += 1;
+                    None
+                }
+                // DW_CFA_restore_state
+                0x0b => {
+                    depth = depth.checked_sub(1).ok_or(CfiError::Malformed)?;
+                    row = stack[depth];
+                    None
+                }
+                // DW_CFA_def_cfa
+                0x0c => {
+                    row.cfa_reg = reader.uleb()? as usize;
+                    row.cfa_offset = reader.uleb()? as i64;
+                    None
+                }
+                // DW_CFA_def_cfa_register
+                0x0d => {
+                    row.cfa_reg = reader.uleb()? as usize;
+                    None
+                }
+                // DW_CFA_def_cfa_offset
+                0x0e => {
+                    row.cfa_offset = reader.uleb()? as i64;
+                    None
+                }
+                // DW_CFA_offset_extended_sf
+                0x11 => {
+                    let reg = reader.uleb()?;
+                    let offset = reader.sleb()? * cie.data_align;
+                    set(&mut row, reg, Rule::Offset(offset));
+                    None
+                }
+                // DW_CFA_def_cfa_sf
+                0x12 => {
+                    row.cfa_reg = reader.uleb()? as usize;
+                    row.cfa_offset = reader.sleb()? * cie.data_align;
+                    None
+                }
+                // DW_CFA_def_cfa_offset_sf
+                0x13 => {
+                    row.cfa_offset = reader.sleb()? * cie.data_align;
+                    None
+                }
+                // DW_CFA_val_offset, DW_CFA_val_offset_sf
+                0x14 => {
+                    let reg = reader.uleb()?;
+                    let offset = reader.uleb()?;
+                    set(&mut row, reg, Rule::ValOffset(factored(offset)));
+                    None
+                }
+                0x15 => {
+                    let reg = reader.uleb()?;
+                    let offset = reader.sleb()? * cie.data_align;
+                    set(&mut row, reg, Rule::ValOffset(offset));
+                    None
+                }
+                // DW_CFA_GNU_args_size
+                0x2e => {
+                    reader.uleb()?;
+                    None
+                }
+                _ => return Err(CfiError::Unsupported(op)),
+            },
+        };
+
+        if let Some(delta) = advance {
+            loc = loc.wrapping_add((delta * cie.code_align) as usize);
+            if loc > pc {
+                break;
+            }
+        }
+    }
+    Ok(row)
+}
+
+/// The registers of a frame, as the unwinder sees them
+///
+/// `repr(C)` because the runtime saves and restores it in assembly: the
+/// registers by DWARF number, then the pc.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct UnwindContext {
+    pub regs: [usize; UNWIND_REGS],
+    /// A return address into the frame's function, or 0 past the last frame
+    pub pc: usize,
+}
+
+impl UnwindContext {
+    /// Move to the caller's frame
+    ///
+    /// # Arguments
+    /// * `fde` - The unwind information of this frame, from [`find_fde`]
+    ///   with `self.pc - 1`
+    ///
+    /// # Returns
+    /// * `Ok(())` - The context now describes the caller; its `pc` is 0 if
+    ///   there is none
+    /// * `Err(err)` - The frame cannot be unwound
+    pub fn step(&mut self, fde: &Fde<'_>) -> Result<(), CfiError> {
+        // The return address may be just past the end of a call at the end
+        // of the function
+        let row = fde.row_at(self.pc - 1)?;
+        let base = *self.regs.get(row.cfa_reg).ok_or(CfiError::Malformed)?;
+        let cfa = base.wrapping_add(row.cfa_offset as usize);
+
+        let mut regs = self.regs;
+        for (reg, rule) in row.rules.iter().enumerate() {
+            regs[reg] = match *rule {
+                Rule::Undefined if reg == fde.cie.ra_reg => 0,
+                Rule::Undefined | Rule::SameValue => self.regs[reg],
+                Rule::Offset(offset) => unsafe {
+                    *(cfa.wrapping_add(offset as usize) as *const usize)
+                },
+                Rule::ValOffset(offset) => cfa.wrapping_add(offset as usize),
+                Rule::Register(other) => *self.regs.get(other).ok_or(CfiError::Malformed)?,
+            };
+        }
+        regs[REG_SP] = cfa;
+        self.pc = *regs.get(fde.cie.ra_reg).ok_or(CfiError::Malformed)?;
+        self.regs = regs;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    const WORD: usize = size_of::<usize>();
+
+    /// A CIE for `sp`-based frames with absolute pointers and data aligned
+    /// to words, then one FDE
+    fn eh_frame(start: usize, len: usize, lsda: usize, instructions: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        // CIE: version 1, "zLR", code align 1, data align -WORD, ra = x1,
+        // LSDA and FDE pointers absolute, CFA = sp
+        let data_align = (WORD as u8).wrapping_neg() & 0x7f;
+        let cie = [
+            &[0, 0, 0, 0, 1][..],
+            b"zLR\0",
+            &[1, data_align, 1, 2, 0, 0],
+            &[0x0c, 2, 0],
+        ]
+        .concat();
+        data.extend_from_slice(&(cie.len() as u32).to_le_bytes());
+        data.extend_from_slice(&cie);
+
+        let mut fde = Vec::new();
+        fde.extend_from_slice(&((data.len() + 4) as u32).to_le_bytes());
+        fde.extend_from_slice(&start.to_le_bytes());
+        fde.extend_from_slice(&len.to_le_bytes());
+        fde.push(WORD as u8);
+        fde.extend_from_slice(&lsda.to_le_bytes());
+        fde.extend_from_slice(instructions);
+        data.extend_from_slice(&(fde.len() as u32).to_le_bytes());
+        data.extend_from_slice(&fde);
+        data
+    }
+
+    // A prologue saving ra and s0 in the top two words of a 4-word frame,
+    // then making s0 the frame pointer
+    const PROLOGUE: [u8; 12] = [
+        0x44,
+        0x0e,
+        4 * WORD as u8,
+        0x44,
+        0x81,
+        1,
+        0x88,
+        2,
+        0x44,
+        0x0c,
+        8,
+        0,
+    ];
+
+    #[test]
+    fn test_find_fde() {
+        let data = eh_frame(0x1000, 0x40, 0x2000, &PROLOGUE);
+        let fde = find_fde(&data, 0x1010).unwrap();
+        assert_eq!(
+            (fde.start, fde.end, fde.lsda),
+            (0x1000, 0x1040, Some(0x2000))
+        );
+        assert!(find_fde(&data, 0x1040).is_err_and(|err| err == CfiError::NoFde));
+    }
+
+    #[test]
+    fn test_row_at() {
+        let data = eh_frame(0x1000, 0x40, 0, &PROLOGUE);
+        let fde = find_fde(&data, 0x1000).unwrap();
+        assert_eq!(fde.lsda, None);
+        let word = WORD as i64;
+
+        let row = fde.row_at(0x1000).unwrap();
+        assert_eq!(
+            (row.cfa_reg, row.cfa_offset, row.rules[1]),
+            (REG_SP, 0, Rule::SameValue)
+        );
+        let row = fde.row_at(0x1004).unwrap();
+        assert_eq!(
+            (row.cfa_offset, row.rules[1], row.rules[8]),
+            (4 * word, Rule::SameValue, Rule::SameValue)
+        );
+        let row = fde.row_at(0x1008).unwrap();
+        assert_eq!(
+            (row.cfa_reg, row.rules[1], row.rules[8]),
+            (REG_SP, Rule::Offset(-word), Rule::Offset(-2 * word))
+        );
+        let row = fde.row_at(0x1020).unwrap();
+        assert_eq!((row.cfa_reg, row.cfa_offset), (8, 0));
+    }
+
+    #[test]
+    fn test_step() {
+        let data = eh_frame(0x1000, 0x40, 0, &PROLOGUE);
+        let fde = find_fde(&data, 0x1020).unwrap();
+
+        let frame = [0, 0, 0x4000, 0x3000usize];
+        let sp = frame.as_ptr() as usize;
+        let mut context = UnwindContext {
+            regs: [0; UNWIND_REGS],
+            pc: 0x1021,
+        };
+        context.regs[REG_SP] = sp;
+        context.regs[8] = sp + 4 * WORD;
+        context.regs[9] = 0x99;
+
+        context.step(&fde).unwrap();
+        assert_eq!(context.regs[REG_SP], sp + 4 * WORD);
+        assert_eq!(
+            (
+                context.pc,
+                context.regs[1],
+                context.regs[8],
+                context.regs[9]
+            ),
+            (0x3000, 0x3000, 0x4000, 0x99)
+        );
+    }
+
+    #[test]
+    fn test_leb() {
+        let data = [0xe5, 0x8e, 0x26, 0x7f, 0x80, 0x7f];
+        let mut reader = Reader::new(&data, 0);
+        assert_eq!(reader.uleb(), Ok(624485));
+        assert_eq!(reader.sleb(), Ok(-1));
+        assert_eq!(reader.sleb(), Ok(-128));
+        assert_eq!(reader.u8(), Err(CfiError::Malformed));
+    }
+}
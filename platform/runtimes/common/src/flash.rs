@@ -0,0 +1,77 @@
+//! NOR flash abstraction
+//!
+//! Persistent stores access flash through [`FlashDevice`]. Erasing sets a
+//! whole sector to `0xff`; programming can only clear bits, so a location
+//! must be erased before it is programmed with new data.
+
+use core::fmt;
+
+/// Errors reported by flash devices
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlashError {
+    /// The access reaches past the end of the device
+    OutOfRange,
+    /// The offset or length is not a multiple of the erase or write size
+    Misaligned,
+    /// The sector is locked against erasing and programming
+    Locked,
+    /// The device reported an erase or program failure
+    Device,
+}
+
+impl fmt::Display for FlashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            FlashError::OutOfRange => "flash access out of range",
+            FlashError::Misaligned => "misaligned flash access",
+            FlashError::Locked => "flash sector is locked",
+            FlashError::Device => "flash erase or program failed",
+        };
+        f.write_str(message)
+    }
+}
+
+/// A region of NOR flash, addressed by byte offset from its start
+pub trait FlashDevice {
+    /// Size of the region in bytes, a multiple of the sector size
+    fn size(&self) -> u32;
+
+    /// Size of the erase unit in bytes
+    fn sector_size(&self) -> u32;
+
+    /// Size of the program unit in bytes; writes are aligned to it
+    fn write_size(&self) -> u32;
+
+    /// Read bytes at any offset
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), FlashError>;
+
+    /// Erase the sector starting at `offset`
+    fn erase(&mut self, offset: u32) -> Result<(), FlashError>;
+
+    /// Program erased bytes
+    ///
+    /// # Arguments
+    /// * `offset` - Where to program, a multiple of the write size
+    /// * `data` - Bytes to program, a multiple of the write size long
+    fn program(&mut self, offset: u32, data: &[u8]) -> Result<(), FlashError>;
+}
+
+/// Check an access against the region size and an alignment
+///
+/// # Arguments
+/// * `size` - Size of the region
+/// * `align` - Required alignment of `offset` and `len`
+pub fn check_flash_access(
+    size: u32,
+    align: u32,
+    offset: u32,
+    len: usize,
+) -> Result<(), FlashError> {
+    if !offset.is_multiple_of(align) || !(len as u32).is_multiple_of(align) {
+        return Err(FlashError::Misaligned);
+    }
+    match offset.checked_add(len as u32) {
+        Some(end) if end <= size && len <= u32::MAX as usize => Ok(()),
+        _ => Err(FlashError::OutOfRange),
+    }
+}
@@ -0,0 +1,51 @@
+//! Framebuffer abstraction
+//!
+//! Programs draw into a [`Framebuffer`] of `0x00RRGGBB` pixels, row by row,
+//! and [`flush`](Framebuffer::flush) it to make the frame visible. A frame
+//! can be saved as a binary PPM with [`encode_ppm`].
+
+use alloc::vec::Vec;
+
+/// A display with a linear framebuffer
+pub trait Framebuffer {
+    /// Width of the display, in pixels
+    fn width(&self) -> usize;
+
+    /// Height of the display, in pixels
+    fn height(&self) -> usize;
+
+    /// The pixels, `width * height` of them, row by row from the top left
+    fn pixels(&mut self) -> &mut [u32];
+
+    /// Show what was drawn since the last flush
+    fn flush(&mut self) {}
+}
+
+/// Encode pixels as a binary PPM (P6) image
+///
+/// # Arguments
+/// * `width` - Width of the image, in pixels
+/// * `height` - Height of the image, in pixels
+/// * `pixels` - At least `width * height` pixels, `0x00RRGGBB`, row by row
+///
+/// # Returns
+/// * The header followed by the red, green and blue bytes of each pixel
+pub fn encode_ppm(width: usize, height: usize, pixels: &[u32]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(32 + width * height * 3);
+    out.extend_from_slice(alloc::format!("P6\n{} {}\n255\n", width, height).as_bytes());
+    for &pixel in &pixels[..width * height] {
+        out.extend_from_slice(&pixel.to_be_bytes()[1..]);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_ppm() {
+        let ppm = encode_ppm(2, 1, &[0x00ff_8000, 0x0000_00ff, 0xdead_beef]);
+        assert_eq!(ppm, b"P6\n2 1\n255\n\xff\x80\x00\x00\x00\xff");
+    }
+}
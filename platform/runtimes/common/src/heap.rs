@@ -1,19 +1,13 @@
 /// Heap initialization macro
 ///
-/// Initializes the heap allocator using linker symbols.
+/// Initializes the heap allocator `ALLOCATOR` with the platform's
+/// [`heap_region`](crate::Platform::heap_region), by default the region
+/// between the linker symbols `_sheap` and `_eheap`.
 #[macro_export]
 macro_rules! heap_init {
-    () => {
-        // Import linker symbols for heap region
-        unsafe extern "C" {
-            static mut _sheap: u8;
-            static mut _eheap: u8;
-        }
-
+    ($platform:ty) => {
         unsafe {
-            let heap_start = core::ptr::addr_of_mut!(_sheap) as usize;
-            let heap_end = core::ptr::addr_of_mut!(_eheap) as usize;
-            let heap_size = heap_end - heap_start;
+            let (heap_start, heap_size) = <$platform as $crate::Platform>::heap_region();
             ALLOCATOR.init(heap_start, heap_size)
         }
     };
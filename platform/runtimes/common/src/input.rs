@@ -0,0 +1,76 @@
+//! Keyboard abstraction
+//!
+//! Platforms report key presses and releases through [`Keyboard`], as
+//! [`KeyEvent`]s in the order they happened.
+
+/// A key, by what it produces rather than where it sits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// A key that types a character, in lower case for letters
+    Char(char),
+    Enter,
+    Escape,
+    Backspace,
+    Tab,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Key {
+    /// Parse a key name: a single character, or `Enter`, `Escape`,
+    /// `Backspace`, `Tab`, `Up`, `Down`, `Left`, `Right` or `Space`
+    /// (case-insensitive)
+    pub fn from_name(name: &str) -> Option<Key> {
+        let mut chars = name.chars();
+        if let (Some(ch), None) = (chars.next(), chars.next()) {
+            return Some(Key::Char(ch.to_ascii_lowercase()));
+        }
+
+        let key = match name.to_ascii_lowercase().as_str() {
+            "enter" => Key::Enter,
+            "escape" => Key::Escape,
+            "backspace" => Key::Backspace,
+            "tab" => Key::Tab,
+            "up" => Key::Up,
+            "down" => Key::Down,
+            "left" => Key::Left,
+            "right" => Key::Right,
+            "space" => Key::Char(' '),
+            _ => return None,
+        };
+        Some(key)
+    }
+}
+
+/// A key changing state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: Key,
+    /// `true` when the key went down, `false` when it came up
+    pub pressed: bool,
+}
+
+/// A source of key events
+pub trait Keyboard {
+    /// Take the oldest pending event
+    ///
+    /// # Returns
+    /// * `None` - No key changed state since the last call
+    fn poll(&mut self) -> Option<KeyEvent>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_names() {
+        assert_eq!(Key::from_name("A"), Some(Key::Char('a')));
+        assert_eq!(Key::from_name("ENTER"), Some(Key::Enter));
+        assert_eq!(Key::from_name("space"), Some(Key::Char(' ')));
+        assert_eq!(Key::from_name("F13"), None);
+        assert_eq!(Key::from_name(""), None);
+    }
+}
@@ -1,22 +1,33 @@
 #![no_std]
 
-macros::mod_flat!(heap);
+extern crate alloc;
 
+macros::mod_flat!(
+    args, backtrace, block, eh_frame, flash, graphic, heap, input, lsda, platform, process, stack,
+    symtab
+);
+
+/// Define the entry of a bin built on a platform crate directly, without
+/// `runtime`
+///
+/// The entry sets up the heap of the bin's `ALLOCATOR`, runs `main`,
+/// reports what it returned on the console, runs the at-exit hooks and
+/// exits with the code of `main`.
+///
+/// # Arguments
+/// * `$platform` - The platform crate's [`Platform`] implementation
+/// * `$path` - The bin's `main`
 #[macro_export]
 macro_rules! entry {
-    ($path:path) => {
-        #[export_name = "main"]
-        pub unsafe fn __main() -> ! {
-            let f: fn() = $path;
-
-            $crate::heap_init!();
-
-            f();
+    ($platform:ty, $path:path) => {
+        #[unsafe(export_name = "user_entry")]
+        pub unsafe fn __user_entry() -> ! {
+            $crate::heap_init!($platform);
 
-            unsafe extern "Rust" {
-                fn platform_exit(code: i32) -> !;
-            }
-            unsafe { platform_exit(0) }
+            let code =
+                $crate::Termination::report($path(), &mut $crate::Console::<$platform>::new());
+            $crate::run_exit_hooks();
+            <$platform as $crate::Platform>::exit(code)
         }
     };
 }
@@ -28,7 +39,11 @@ macro_rules! addtest {
         mod on_test {
             #[test]
             fn main() {
-                super::main();
+                extern crate std;
+
+                let mut out = std::string::String::new();
+                let code = $crate::Termination::report(super::main(), &mut out);
+                assert_eq!(code, 0, "main failed: {}", out);
             }
         }
     };
@@ -0,0 +1,105 @@
+//! Landing pads, from a function's language-specific data area
+//!
+//! The LSDA (in `.gcc_except_table`) maps each call site of a function to
+//! the landing pad that runs when an exception passes through the call,
+//! and to an action: cleanup only (drops), or catch. Rust only catches in
+//! `catch_unwind`, so the type of a catch clause is never looked at.
+
+use crate::eh_frame::{CfiError, DW_EH_PE_OMIT, Reader};
+
+/// What a frame does with an exception passing through it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EhAction {
+    /// Nothing, unwind past it
+    None,
+    /// Run the landing pad, which resumes unwinding when done
+    Cleanup(usize),
+    /// Run the landing pad, which ends unwinding
+    Catch(usize),
+    /// The call cannot unwind: it is missing from the table
+    Terminate,
+}
+
+/// Find what a function does at a call site
+///
+/// # Arguments
+/// * `lsda` - The function's LSDA, at its load address
+/// * `func_start` - First address of the function
+/// * `ip` - An address within the call instruction, the return address
+///   minus one
+pub fn find_eh_action(lsda: &[u8], func_start: usize, ip: usize) -> Result<EhAction, CfiError> {
+    let mut reader = Reader::new(lsda, 0);
+    let lp_start_encoding = reader.u8()?;
+    let lp_start = match lp_start_encoding {
+        DW_EH_PE_OMIT => func_start,
+        encoding => reader.pointer(encoding)?,
+    };
+    if reader.u8()? != DW_EH_PE_OMIT {
+        // Offset of the type table
+        reader.uleb()?;
+    }
+
+    let call_site_encoding = reader.u8()?;
+    let call_site_table_len = reader.uleb()? as usize;
+    let action_table = reader.pos() + call_site_table_len;
+
+    // Sorted by start address
+    while reader.pos() < action_table {
+        let start = func_start.wrapping_add(reader.value(call_site_encoding)?);
+        let len = reader.value(call_site_encoding)?;
+        let landing_pad = reader.value(call_site_encoding)?;
+        let action = reader.uleb()? as usize;
+
+        if ip < start {
+            break;
+        }
+        if ip >= start.wrapping_add(len) {
+            continue;
+        }
+        if landing_pad == 0 {
+            return Ok(EhAction::None);
+        }
+
+        let landing_pad = lp_start.wrapping_add(landing_pad);
+        if action == 0 {
+            return Ok(EhAction::Cleanup(landing_pad));
+        }
+        // A 1-based offset into the action table; a type filter of zero is
+        // a cleanup, anything else catches
+        let mut record = Reader::new(lsda, action_table + action - 1);
+        return Ok(match record.sleb()? {
+            0 => EhAction::Cleanup(landing_pad),
+            _ => EhAction::Catch(landing_pad),
+        });
+    }
+    Ok(EhAction::Terminate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_eh_action() {
+        #[rustfmt::skip]
+        let lsda = [
+            0xff, // landing pads relative to the function
+            0xff, // no type table
+            0x01, 12, // call sites, uleb128, 12 bytes
+            0x00, 0x08, 0x00, 0, // 0..8: no landing pad
+            0x08, 0x08, 0x30, 0, // 8..16: cleanup at 0x30
+            0x10, 0x08, 0x40, 1, // 16..24: action 1, catch at 0x40
+            0x01, 0x00, // action 1: type filter 1, no next action
+        ];
+
+        let action = |offset: usize| find_eh_action(&lsda, 0x1000, 0x1000 + offset).unwrap();
+        assert_eq!(action(0x04), EhAction::None);
+        assert_eq!(action(0x08), EhAction::Cleanup(0x1030));
+        assert_eq!(action(0x17), EhAction::Catch(0x1040));
+        assert_eq!(action(0x18), EhAction::Terminate);
+        assert_eq!(
+            find_eh_action(&lsda[..6], 0x1000, 0x1000),
+            Err(CfiError::Malformed)
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! The contract between `runtime` and the platform crates
+//!
+//! Each platform crate implements [`Platform`] once, on a unit struct, and
+//! `runtime` calls the implementation of the platform its feature selects,
+//! statically. The console and exit are required. The heap defaults to the
+//! linker script's, and time, reset and the optional devices to what a
+//! platform without them does, so a platform only implements what it has.
+
+use alloc::boxed::Box;
+
+use crate::{BlockDevice, FlashDevice, Framebuffer, Keyboard};
+
+/// A named range of physical addresses, from the build-helper memory map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub name: &'static str,
+    pub base: usize,
+    pub size: usize,
+    /// `ram`, `flash`, `partition` or `device`
+    pub kind: &'static str,
+}
+
+/// What `runtime` needs from a platform
+pub trait Platform {
+    /// Name of the platform, as in `ARCH`
+    const NAME: &'static str;
+
+    /// Physical memory regions, from the build-helper memory map
+    const MEMORY_MAP: &'static [MemoryRegion];
+
+    /// Write a byte to the console
+    fn putc(ch: u8);
+
+    /// Read a byte from the console, if one is pending
+    fn try_getc() -> Option<u8>;
+
+    /// Read a byte from the console, waiting for one
+    fn getc() -> u8 {
+        loop {
+            if let Some(ch) = Self::try_getc() {
+                return ch;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Stop the machine
+    ///
+    /// # Arguments
+    /// * `code` - Exit code, 0 for success
+    fn exit(code: i32) -> !;
+
+    /// The memory the global allocator manages
+    ///
+    /// # Returns
+    /// * `(start, size)` - By default, from `_sheap` to `_eheap` of the
+    ///   linker script
+    fn heap_region() -> (usize, usize) {
+        unsafe extern "C" {
+            static _sheap: u8;
+            static _eheap: u8;
+        }
+        let start = &raw const _sheap as usize;
+        (start, &raw const _eheap as usize - start)
+    }
+
+    /// Reset the machine
+    ///
+    /// Returns only if the platform cannot reset, which is the default.
+    fn reboot() {}
+
+    /// Read the wall-clock time
+    ///
+    /// # Returns
+    /// * `Some(ns)` - Nanoseconds since the UNIX epoch
+    /// * `None` - The platform has no clock, the default
+    fn rtc_read() -> Option<u64> {
+        None
+    }
+
+    /// Arm the alarm, replacing a pending one
+    ///
+    /// # Arguments
+    /// * `at_ns` - When to fire, as from [`rtc_read`](Self::rtc_read)
+    /// * `handler` - Called from the alarm interrupt
+    ///
+    /// # Returns
+    /// * `false` - The platform has no alarm, the default
+    fn rtc_set_alarm(at_ns: u64, handler: fn()) -> bool {
+        let _ = (at_ns, handler);
+        false
+    }
+
+    /// Cancel the pending alarm, if any
+    fn rtc_cancel_alarm() {}
+
+    /// Fill a buffer with hardware entropy
+    ///
+    /// # Returns
+    /// * The number of bytes filled, 0 without an entropy source
+    fn entropy_fill(buf: &mut [u8]) -> usize {
+        let _ = buf;
+        0
+    }
+
+    /// Take the platform's disk, once
+    ///
+    /// # Returns
+    /// * `None` - There is no disk, the default
+    fn block_device() -> Option<Box<dyn BlockDevice + Send>> {
+        None
+    }
+
+    /// Take the flash partition for the key-value store, once
+    ///
+    /// # Returns
+    /// * `None` - There is no flash, the default
+    fn kv_flash() -> Option<Box<dyn FlashDevice + Send>> {
+        None
+    }
+
+    /// Take the platform's display, once
+    ///
+    /// # Returns
+    /// * `None` - There is no display, the default
+    fn framebuffer() -> Option<Box<dyn Framebuffer + Send>> {
+        None
+    }
+
+    /// Take the platform's keyboard, once
+    ///
+    /// # Returns
+    /// * `None` - There is no keyboard, the default
+    fn keyboard() -> Option<Box<dyn Keyboard + Send>> {
+        None
+    }
+}
+
+/// Enter the program, from a platform's startup code
+///
+/// The bin's `runtime::entry!` defines `user_entry`; it paints the stack,
+/// sets up the heap and runs `main`. Bins without `runtime` define it with
+/// [`entry!`](crate::entry).
+///
+/// # Safety
+/// Called once, on the boot stack, with the machine initialized.
+pub unsafe fn enter_program() -> ! {
+    unsafe extern "Rust" {
+        fn user_entry() -> !;
+    }
+    unsafe { user_entry() }
+}
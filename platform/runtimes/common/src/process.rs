@@ -0,0 +1,212 @@
+//! Program termination support for the entry macros
+//!
+//! [`Termination`] turns the value returned by `main` into an exit code, and
+//! [`at_exit`] registers hooks that run before the platform exits.
+
+use crate::Platform;
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::marker::PhantomData;
+
+/// Maximum number of registered at-exit hooks
+pub const MAX_EXIT_HOOKS: usize = 16;
+
+/// Values that `main` may return
+pub trait Termination {
+    /// Turn the value into an exit code, printing any error to `out`
+    ///
+    /// # Arguments
+    /// * `out` - Where to report errors
+    ///
+    /// # Returns
+    /// * The exit code, 0 for success
+    fn report(self, out: &mut dyn fmt::Write) -> i32;
+}
+
+impl Termination for () {
+    fn report(self, _out: &mut dyn fmt::Write) -> i32 {
+        0
+    }
+}
+
+impl Termination for i32 {
+    fn report(self, _out: &mut dyn fmt::Write) -> i32 {
+        self
+    }
+}
+
+impl Termination for u8 {
+    fn report(self, _out: &mut dyn fmt::Write) -> i32 {
+        self as i32
+    }
+}
+
+impl<T: Termination, E: fmt::Debug> Termination for Result<T, E> {
+    /// `Ok` reports its value, `Err` prints `Error: {:?}` and reports 1
+    fn report(self, out: &mut dyn fmt::Write) -> i32 {
+        match self {
+            Ok(value) => value.report(out),
+            Err(err) => {
+                let _ = writeln!(out, "Error: {:?}", err);
+                1
+            }
+        }
+    }
+}
+
+/// Console output through a platform's [`Platform::putc`], for reporting
+/// errors
+pub struct Console<P: Platform>(PhantomData<P>);
+
+impl<P: Platform> Console<P> {
+    pub const fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<P: Platform> Default for Console<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Platform> fmt::Write for Console<P> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            P::putc(byte);
+        }
+        Ok(())
+    }
+}
+
+/// Error returned by [`at_exit`] when [`MAX_EXIT_HOOKS`] are registered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HookTableFull;
+
+/// Registered hooks and their count, used as a stack
+type HookStack = ([Option<fn()>; MAX_EXIT_HOOKS], usize);
+
+struct ExitHooks(UnsafeCell<HookStack>);
+
+// SAFETY: In a single-core bare-metal environment hooks are only registered
+// and run from the main flow of control, never concurrently
+unsafe impl Sync for ExitHooks {}
+
+static EXIT_HOOKS: ExitHooks = ExitHooks(UnsafeCell::new(([None; MAX_EXIT_HOOKS], 0)));
+
+/// Register a function to run when the program exits
+///
+/// Hooks run in reverse order of registration, when `main` returns or the
+/// program exits explicitly. They must not be registered from interrupt
+/// handlers.
+///
+/// # Arguments
+/// * `hook` - Function to run at exit
+///
+/// # Returns
+/// * `Ok(())` - The hook is registered
+/// * `Err(HookTableFull)` - [`MAX_EXIT_HOOKS`] hooks are already registered
+pub fn at_exit(hook: fn()) -> Result<(), HookTableFull> {
+    let (hooks, count) = unsafe { &mut *EXIT_HOOKS.0.get() };
+    if *count == MAX_EXIT_HOOKS {
+        return Err(HookTableFull);
+    }
+
+    hooks[*count] = Some(hook);
+    *count += 1;
+    Ok(())
+}
+
+/// Run and unregister all at-exit hooks, most recently registered first
+///
+/// Each hook is unregistered before it runs, so a hook that exits or panics
+/// is not run again. Hooks registered by a running hook run as well.
+pub fn run_exit_hooks() {
+    loop {
+        let hook = unsafe {
+            let (hooks, count) = &mut *EXIT_HOOKS.0.get();
+            if *count == 0 {
+                break;
+            }
+            *count -= 1;
+            hooks[*count].take()
+        };
+
+        if let Some(hook) = hook {
+            hook();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::string::String;
+
+    #[test]
+    fn test_report() {
+        let mut out = String::new();
+        assert_eq!(().report(&mut out), 0);
+        assert_eq!(3i32.report(&mut out), 3);
+        assert_eq!(Ok::<u8, ()>(7).report(&mut out), 7);
+        assert!(out.is_empty());
+
+        assert_eq!(Err::<(), _>("bad input").report(&mut out), 1);
+        assert_eq!(out, "Error: \"bad input\"\n");
+    }
+
+    /// Collects the console output of [`TestPlatform`]
+    static OUTPUT: std::sync::Mutex<String> = std::sync::Mutex::new(String::new());
+
+    struct TestPlatform;
+
+    impl Platform for TestPlatform {
+        const NAME: &'static str = "test";
+        const MEMORY_MAP: &'static [crate::MemoryRegion] = &[];
+
+        fn putc(ch: u8) {
+            OUTPUT.lock().unwrap().push(ch as char);
+        }
+
+        fn try_getc() -> Option<u8> {
+            None
+        }
+
+        fn exit(code: i32) -> ! {
+            panic!("exit({})", code)
+        }
+    }
+
+    fn failing_main() -> Result<(), &'static str> {
+        Err("bad input")
+    }
+
+    #[test]
+    fn test_console() {
+        let mut console = Console::<TestPlatform>::new();
+        assert_eq!(failing_main().report(&mut console), 1);
+        assert_eq!(*OUTPUT.lock().unwrap(), "Error: \"bad input\"\n");
+    }
+
+    static ORDER: AtomicUsize = AtomicUsize::new(0);
+
+    fn first() {
+        ORDER.store(ORDER.load(Ordering::SeqCst) * 10 + 1, Ordering::SeqCst);
+    }
+
+    fn second() {
+        ORDER.store(ORDER.load(Ordering::SeqCst) * 10 + 2, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_exit_hooks_run_once_in_reverse() {
+        at_exit(first).unwrap();
+        at_exit(second).unwrap();
+        run_exit_hooks();
+        run_exit_hooks();
+        assert_eq!(ORDER.load(Ordering::SeqCst), 21);
+    }
+}
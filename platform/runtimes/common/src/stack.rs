@@ -0,0 +1,129 @@
+//! Stack painting and overflow detection
+//!
+//! The stack runs from `_sstack` up to `_stack_top`, by default right
+//! above the heap.
+//! At boot [`paint_stack`] fills it with [`STACK_PAINT`], so the lowest
+//! word that no longer holds the pattern marks the deepest the stack has
+//! been ([`stack_high_water`]). The first word of the region holds
+//! [`STACK_CANARY`]: a stack that grew past its limit has overwritten it,
+//! along with whatever lies below it.
+
+/// Pattern the unused stack is filled with
+pub const STACK_PAINT: usize = 0x5a5a_5a5a;
+
+/// Value at the stack limit while the stack has not overflowed
+pub const STACK_CANARY: usize = 0xc0ff_ee11;
+
+/// Bytes below the caller's stack pointer left unpainted, for the frame of
+/// [`paint_stack`] itself, which the compiler may set up after reading `sp`
+const PAINT_MARGIN: usize = 256;
+
+/// Exit code used when the stack overflowed, as for a process killed by
+/// SIGSEGV
+pub const STACK_OVERFLOW_EXIT_CODE: i32 = 139;
+
+/// Fill the stack below the caller with [`STACK_PAINT`] and set the canary
+///
+/// Called once at boot, before `main`; the part of the stack in use by then
+/// counts as used.
+#[inline(never)]
+pub fn paint_stack() {
+    let (bottom, _) = stack_bounds();
+    let end = stack_pointer().saturating_sub(PAINT_MARGIN);
+    if bottom == 0 || end <= bottom {
+        return;
+    }
+
+    let word = size_of::<usize>();
+    let mut addr = bottom + word;
+    while addr < end {
+        unsafe { (addr as *mut usize).write_volatile(STACK_PAINT) };
+        addr += word;
+    }
+    unsafe { (bottom as *mut usize).write_volatile(STACK_CANARY) };
+}
+
+/// The most stack used since boot, in bytes
+///
+/// Only meaningful after [`paint_stack`]; a stack that overflowed reports
+/// its whole size.
+pub fn stack_high_water() -> usize {
+    let (bottom, top) = stack_bounds();
+    if bottom == 0 {
+        return 0;
+    }
+
+    let len = (top - bottom) / size_of::<usize>();
+    let stack = unsafe { core::slice::from_raw_parts(bottom as *const usize, len) };
+    high_water(stack)
+}
+
+/// Bytes of `stack` below its top that are no longer painted, not
+/// counting the canary
+fn high_water(stack: &[usize]) -> usize {
+    let painted = stack
+        .iter()
+        .skip(1)
+        .take_while(|&&word| word == STACK_PAINT)
+        .count();
+    (stack.len() - 1 - painted) * size_of::<usize>()
+}
+
+/// Size of the stack region, in bytes
+pub fn stack_size() -> usize {
+    let (bottom, top) = stack_bounds();
+    top - bottom
+}
+
+/// Whether the stack grew past its limit, overwriting the canary
+pub fn stack_overflowed() -> bool {
+    let (bottom, _) = stack_bounds();
+    bottom != 0 && unsafe { (bottom as *const usize).read_volatile() } != STACK_CANARY
+}
+
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+#[inline(always)]
+fn stack_pointer() -> usize {
+    let sp: usize;
+    unsafe { core::arch::asm!("mv {}, sp", out(reg) sp, options(nomem, nostack)) };
+    sp
+}
+
+#[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+fn stack_pointer() -> usize {
+    0
+}
+
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+fn stack_bounds() -> (usize, usize) {
+    unsafe extern "C" {
+        static _sstack: u8;
+        static _stack_top: u8;
+    }
+    (&raw const _sstack as usize, &raw const _stack_top as usize)
+}
+
+#[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+fn stack_bounds() -> (usize, usize) {
+    (0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_high_water() {
+        let word = size_of::<usize>();
+        let mut stack = [STACK_PAINT; 8];
+        stack[0] = STACK_CANARY;
+        assert_eq!(high_water(&stack), 0);
+
+        stack[6] = 0;
+        stack[7] = 0;
+        assert_eq!(high_water(&stack), 2 * word);
+        // Used words that happen to hold the pattern still count
+        stack[3] = 1;
+        assert_eq!(high_water(&stack), 5 * word);
+    }
+}
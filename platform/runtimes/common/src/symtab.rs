@@ -0,0 +1,123 @@
+//! Function names for backtraces
+//!
+//! Every image reserves [`SYMTAB_SIZE`] bytes in the `.am_symtab` section.
+//! The table cannot exist before the image is linked, so build-helper's
+//! `embed_symbols` fills the reserved space in afterwards, from the ELF's
+//! own symbol table; the build scripts do this after every build. The
+//! layout, all fields little-endian `u32`:
+//! - [`SYMTAB_MAGIC`], then the number of entries
+//! - the entries, sorted by address: the address of a function and the
+//!   offset of its name from the start of the table; the last entry marks
+//!   the end of the code, with the name offset [`NO_NAME`]
+//! - the names, each a length byte followed by that many bytes of UTF-8
+
+use core::ptr::addr_of;
+
+/// Bytes reserved for the table
+pub const SYMTAB_SIZE: usize = 32 * 1024;
+
+/// First word of a filled-in table ("SYMT")
+pub const SYMTAB_MAGIC: u32 = 0x544d_5953;
+
+/// Name offset of the entry that ends the table
+pub const NO_NAME: u32 = u32::MAX;
+
+/// First word of the reserved space before it is filled in ("NONE"); not
+/// zero, so that the section is not turned into `.bss`
+const EMPTY_MAGIC: u32 = 0x454e_4f4e;
+
+#[repr(C, align(4))]
+struct Reserved([u8; SYMTAB_SIZE]);
+
+#[unsafe(link_section = ".am_symtab")]
+#[used]
+static SYMTAB: Reserved = {
+    let mut bytes = [0; SYMTAB_SIZE];
+    let magic = EMPTY_MAGIC.to_le_bytes();
+    let mut i = 0;
+    while i < magic.len() {
+        bytes[i] = magic[i];
+        i += 1;
+    }
+    Reserved(bytes)
+};
+
+/// The table, when it has been filled in
+fn table() -> Option<&'static [u8]> {
+    // The contents change after compilation, so they must not be folded
+    // into the code as the zeros seen here
+    let start = core::hint::black_box(addr_of!(SYMTAB) as *const u8);
+    let table = unsafe { core::slice::from_raw_parts(start, SYMTAB_SIZE) };
+    (word(table, 0)? == SYMTAB_MAGIC).then_some(table)
+}
+
+fn word(table: &[u8], offset: usize) -> Option<u32> {
+    let bytes = table.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Find the function containing `addr` in a table
+fn lookup(table: &'static [u8], addr: usize) -> Option<(&'static str, usize)> {
+    let count = word(table, 4)? as usize;
+    let entry = |index: usize| {
+        Some((
+            word(table, 8 + 8 * index)? as usize,
+            word(table, 12 + 8 * index)?,
+        ))
+    };
+
+    // Index of the last entry starting at or before `addr`
+    let (mut low, mut high) = (0, count);
+    while low < high {
+        let mid = (low + high) / 2;
+        if entry(mid)?.0 <= addr {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    let (start, name) = entry(low.checked_sub(1)?)?;
+    if name == NO_NAME {
+        return None;
+    }
+
+    let name = name as usize;
+    let len = *table.get(name)? as usize;
+    let name = core::str::from_utf8(table.get(name + 1..name + 1 + len)?).ok()?;
+    Some((name, addr - start))
+}
+
+/// Find the function containing an address
+///
+/// # Returns
+/// * `Some((name, offset))` - The function's name and the offset of `addr`
+///   into it
+/// * `None` - The address is outside the code, or the table has not been
+///   filled in
+pub fn symbolize(addr: usize) -> Option<(&'static str, usize)> {
+    lookup(table()?, addr)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_lookup() {
+        let mut table = Vec::new();
+        for word in [SYMTAB_MAGIC, 3, 0x100, 32, 0x180, 37, 0x200, NO_NAME] {
+            table.extend_from_slice(&word.to_le_bytes());
+        }
+        table.extend_from_slice(b"\x04main\x03foo");
+        let table = table.leak();
+
+        assert_eq!(lookup(table, 0xff), None);
+        assert_eq!(lookup(table, 0x100), Some(("main", 0)));
+        assert_eq!(lookup(table, 0x17f), Some(("main", 0x7f)));
+        assert_eq!(lookup(table, 0x1a0), Some(("foo", 0x20)));
+        assert_eq!(lookup(table, 0x200), None);
+    }
+}
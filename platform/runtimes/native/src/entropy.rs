@@ -0,0 +1,18 @@
+//! Native entropy source: the host's `/dev/urandom`
+
+use std::fs::File;
+use std::io::Read;
+
+/// Fill a buffer with host entropy
+///
+/// # Arguments
+/// * `buf` - Buffer to fill
+///
+/// # Returns
+/// * Number of bytes filled, 0 if `/dev/urandom` cannot be read
+pub fn entropy_fill(buf: &mut [u8]) -> usize {
+    match File::open("/dev/urandom").and_then(|mut file| file.read_exact(buf)) {
+        Ok(()) => buf.len(),
+        Err(_) => 0,
+    }
+}
@@ -0,0 +1,12 @@
+//! Native exit: ends the process
+
+use std::io::Write;
+
+/// Exit the process
+///
+/// # Arguments
+/// * `code` - Exit status of the process
+pub fn platform_exit(code: i32) -> ! {
+    let _ = std::io::stdout().flush();
+    std::process::exit(code)
+}
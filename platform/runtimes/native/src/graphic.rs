@@ -0,0 +1,102 @@
+//! Native display: a surface in memory
+//!
+//! The surface is [`WIDTH`] x [`HEIGHT`], as the native display of
+//! abstract-machine. Each flush writes it to the file named by
+//! `AM_FRAMEBUFFER` as a PPM image, replacing the previous frame; without
+//! the variable frames are only kept in memory.
+
+use common::{Framebuffer, encode_ppm};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Width of the display, in pixels
+pub const WIDTH: usize = 400;
+
+/// Height of the display, in pixels
+pub const HEIGHT: usize = 300;
+
+/// Set once the display has been taken
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// A display held in memory
+pub struct Surface {
+    width: usize,
+    height: usize,
+    pixels: Vec<u32>,
+    /// Where each flush writes the frame
+    output: Option<PathBuf>,
+}
+
+impl Surface {
+    /// Create a black surface
+    ///
+    /// # Arguments
+    /// * `width` - Width, in pixels
+    /// * `height` - Height, in pixels
+    /// * `output` - Where to write the PPM image at each flush
+    pub fn new(width: usize, height: usize, output: Option<PathBuf>) -> Self {
+        Surface {
+            width,
+            height,
+            pixels: vec![0; width * height],
+            output,
+        }
+    }
+
+    /// The current frame, as a PPM image
+    pub fn ppm(&self) -> Vec<u8> {
+        encode_ppm(self.width, self.height, &self.pixels)
+    }
+}
+
+impl Framebuffer for Surface {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn pixels(&mut self) -> &mut [u32] {
+        &mut self.pixels
+    }
+
+    fn flush(&mut self) {
+        if let Some(output) = &self.output
+            && let Err(err) = std::fs::write(output, self.ppm())
+        {
+            eprintln!("Cannot write the frame to {}: {}", output.display(), err);
+        }
+    }
+}
+
+/// Take the display, once
+///
+/// # Returns
+/// * `None` - The display was already taken
+pub fn framebuffer() -> Option<Box<dyn Framebuffer + Send>> {
+    if TAKEN.swap(true, Ordering::SeqCst) {
+        return None;
+    }
+    let output = std::env::var_os("AM_FRAMEBUFFER").map(PathBuf::from);
+    Some(Box::new(Surface::new(WIDTH, HEIGHT, output)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flush() {
+        let path = std::env::temp_dir().join(format!("am-native-{}.ppm", std::process::id()));
+        let mut surface = Surface::new(2, 2, Some(path.clone()));
+        surface.pixels()[3] = 0x0012_3456;
+        surface.flush();
+
+        let ppm = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(ppm.starts_with(b"P6\n2 2\n255\n"));
+        assert_eq!(&ppm[ppm.len() - 3..], &[0x12, 0x34, 0x56]);
+    }
+}
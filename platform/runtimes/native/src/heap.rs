@@ -0,0 +1,88 @@
+//! Native heap: one block of host memory
+//!
+//! `runtime`'s allocator is the global allocator of the process, so the
+//! block comes from the system allocator directly. Linux maps its pages on
+//! first touch, which keeps a large default cheap. Nothing here may use the
+//! global allocator, which is that heap.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::ffi::{CStr, c_char};
+use std::sync::OnceLock;
+
+/// Heap size without `AM_HEAP_SIZE`, the RAM of the other platforms
+pub const DEFAULT_HEAP_SIZE: usize = 128 * 1024 * 1024;
+
+/// `(start, size)`, allocated on the first call
+static HEAP: OnceLock<(usize, usize)> = OnceLock::new();
+
+/// Read `AM_HEAP_SIZE` without allocating: `std::env` returns owned
+/// strings, which would come from the heap being sized
+///
+/// # Returns
+/// * `Some(size)` - The variable is set to a number of bytes
+/// * `None` - It is unset or not a number
+fn heap_size_var() -> Option<usize> {
+    unsafe extern "C" {
+        fn getenv(name: *const c_char) -> *const c_char;
+    }
+
+    let value = unsafe { getenv(c"AM_HEAP_SIZE".as_ptr()) };
+    if value.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(value) }.to_str().ok()?.parse().ok()
+}
+
+/// The heap, allocated on the first call
+///
+/// # Returns
+/// * `(start, size)` - `AM_HEAP_SIZE` bytes, or [`DEFAULT_HEAP_SIZE`]
+pub fn heap_region() -> (usize, usize) {
+    *HEAP.get_or_init(|| {
+        let size = heap_size_var().unwrap_or(DEFAULT_HEAP_SIZE);
+        let layout = Layout::from_size_align(size, 4096).expect("invalid AM_HEAP_SIZE");
+        let start = unsafe { System.alloc(layout) };
+        if start.is_null() {
+            eprintln!("Cannot allocate a heap of {} bytes", size);
+            std::process::exit(1);
+        }
+        (start as usize, size)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    std::thread_local! {
+        /// Allocations made by this thread
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// The system allocator, counting allocations per thread
+    struct Counting;
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static COUNTING: Counting = Counting;
+
+    #[test]
+    fn test_heap_size_var() {
+        unsafe { std::env::set_var("AM_HEAP_SIZE", "1048576") };
+        let before = ALLOCATIONS.get();
+        let (_, size) = heap_region();
+        assert_eq!(ALLOCATIONS.get(), before);
+        assert_eq!(size, 1048576);
+    }
+}
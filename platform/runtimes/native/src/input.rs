@@ -0,0 +1,97 @@
+//! Native keyboard: scripted key presses
+//!
+//! The keys come from `AM_KEYS`, a whitespace-separated list of key names
+//! as read by [`Key::from_name`], e.g. `AM_KEYS="h i Enter"`. Each one is
+//! pressed and released in turn; once they are all out, the keyboard stays
+//! idle.
+
+use common::{Key, KeyEvent, Keyboard};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set once the keyboard has been taken
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// A keyboard replaying a list of events
+pub struct Script {
+    events: VecDeque<KeyEvent>,
+}
+
+impl Script {
+    /// Parse a list of key names
+    ///
+    /// # Arguments
+    /// * `keys` - Whitespace-separated key names
+    ///
+    /// # Returns
+    /// * `Err(name)` - The first name that is not a key
+    pub fn parse(keys: &str) -> Result<Self, String> {
+        let mut events = VecDeque::new();
+        for name in keys.split_whitespace() {
+            let key = Key::from_name(name).ok_or_else(|| name.to_string())?;
+            events.push_back(KeyEvent { key, pressed: true });
+            events.push_back(KeyEvent {
+                key,
+                pressed: false,
+            });
+        }
+        Ok(Script { events })
+    }
+}
+
+impl Keyboard for Script {
+    fn poll(&mut self) -> Option<KeyEvent> {
+        self.events.pop_front()
+    }
+}
+
+/// Take the keyboard, once
+///
+/// # Returns
+/// * `None` - The keyboard was already taken
+pub fn keyboard() -> Option<Box<dyn Keyboard + Send>> {
+    if TAKEN.swap(true, Ordering::SeqCst) {
+        return None;
+    }
+    let keys = std::env::var("AM_KEYS").unwrap_or_default();
+    let script = Script::parse(&keys).unwrap_or_else(|name| {
+        eprintln!("AM_KEYS: unknown key `{}`, ignoring the script", name);
+        Script {
+            events: VecDeque::new(),
+        }
+    });
+    Some(Box::new(script))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script() {
+        let mut script = Script::parse("a Enter").unwrap();
+        let events: Vec<_> = std::iter::from_fn(|| script.poll()).collect();
+        assert_eq!(
+            events,
+            [
+                KeyEvent {
+                    key: Key::Char('a'),
+                    pressed: true
+                },
+                KeyEvent {
+                    key: Key::Char('a'),
+                    pressed: false
+                },
+                KeyEvent {
+                    key: Key::Enter,
+                    pressed: true
+                },
+                KeyEvent {
+                    key: Key::Enter,
+                    pressed: false
+                },
+            ]
+        );
+        assert_eq!(Script::parse("a F13").err().as_deref(), Some("F13"));
+    }
+}
@@ -0,0 +1,115 @@
+//! The native platform: bins run as ordinary host processes
+//!
+//! The console is the terminal, the heap a block of host memory, and the
+//! clock the host's. The display and keyboard are in memory: the display
+//! is written out as a PPM image at each flush, and key presses are
+//! scripted. A native bin is a normal executable, to be run and debugged
+//! with gdb, valgrind or perf.
+//!
+//! The stack is the host's: it is not painted, so `runtime::mem` reports no
+//! stack usage, and an overflow is caught by the host as a segfault.
+//!
+//! Environment variables:
+//! * `AM_HEAP_SIZE` - Size of the heap in bytes, [`heap::DEFAULT_HEAP_SIZE`]
+//!   if unset
+//! * `AM_FRAMEBUFFER` - Where to write the display at each flush
+//! * `AM_KEYS` - Keys to press, see [`input`]
+
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::sync::OnceLock;
+
+pub mod entropy;
+pub mod exit;
+pub mod graphic;
+pub mod heap;
+pub mod input;
+pub mod rtc;
+pub mod stdio;
+
+/// The native platform, for `runtime`
+pub struct Native;
+
+impl common::Platform for Native {
+    const NAME: &'static str = "native";
+    // Everything is allocated from the host at run time
+    const MEMORY_MAP: &'static [common::MemoryRegion] = &[];
+
+    fn putc(ch: u8) {
+        stdio::putc(ch)
+    }
+
+    fn try_getc() -> Option<u8> {
+        stdio::try_getc()
+    }
+
+    fn getc() -> u8 {
+        stdio::getc()
+    }
+
+    fn exit(code: i32) -> ! {
+        exit::platform_exit(code)
+    }
+
+    fn heap_region() -> (usize, usize) {
+        heap::heap_region()
+    }
+
+    fn rtc_read() -> Option<u64> {
+        Some(rtc::rtc_read())
+    }
+
+    fn rtc_set_alarm(at_ns: u64, handler: fn()) -> bool {
+        rtc::rtc_set_alarm(at_ns, handler);
+        true
+    }
+
+    fn rtc_cancel_alarm() {
+        rtc::rtc_cancel_alarm()
+    }
+
+    fn entropy_fill(buf: &mut [u8]) -> usize {
+        entropy::entropy_fill(buf)
+    }
+
+    fn framebuffer() -> Option<Box<dyn common::Framebuffer + Send>> {
+        graphic::framebuffer()
+    }
+
+    fn keyboard() -> Option<Box<dyn common::Keyboard + Send>> {
+        input::keyboard()
+    }
+}
+
+/// What to do after reporting a panic, from [`native_start`]
+static ON_PANIC: OnceLock<fn() -> !> = OnceLock::new();
+
+/// Start the program, from the C `main` that `runtime::entry!` defines
+///
+/// This stands in for the startup code of the other platforms. Nothing may
+/// allocate before the program has set up its heap, so the panic hook is a
+/// zero-sized closure and the rest of the state is created on first use.
+///
+/// # Arguments
+/// * `argc` - Number of arguments
+/// * `argv` - The arguments, as passed to `main`
+/// * `on_panic` - Called after a panic has been reported; `runtime` exits
+///   with `PANIC_EXIT_CODE`
+pub fn native_start(argc: usize, argv: *const *const u8, on_panic: fn() -> !) -> ! {
+    common::save_args(argc, argv);
+    rtc::init();
+    let _ = ON_PANIC.set(on_panic);
+    std::panic::set_hook(Box::new(|info| {
+        eprintln!("Panic: {}", info);
+        // With RUST_BACKTRACE set, as for other host programs
+        let backtrace = Backtrace::capture();
+        if backtrace.status() == BacktraceStatus::Captured {
+            eprint!("{}", backtrace);
+        }
+        match ON_PANIC.get() {
+            Some(on_panic) => on_panic(),
+            None => std::process::abort(),
+        }
+    }));
+
+    unsafe { common::enter_program() }
+}
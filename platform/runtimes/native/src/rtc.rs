@@ -0,0 +1,76 @@
+//! Native clock: the host's, kept monotonic
+//!
+//! The wall-clock time is read once at startup and advanced with
+//! [`Instant`], so it never goes backwards when the host clock is set. An
+//! alarm is a thread sleeping until its deadline; its handler runs on that
+//! thread, as it would in an interrupt on the other platforms.
+
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The time at startup, as an `Instant` and in nanoseconds since the epoch
+static BOOT: OnceLock<(Instant, u64)> = OnceLock::new();
+
+/// Incremented by every set and cancel, so that a replaced alarm's thread
+/// knows not to fire
+static ALARM_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Record the startup time
+pub fn init() {
+    BOOT.get_or_init(|| {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        (Instant::now(), now.as_nanos() as u64)
+    });
+}
+
+/// Read the current time
+///
+/// # Returns
+/// * Nanoseconds since the UNIX epoch
+pub fn rtc_read() -> u64 {
+    init();
+    let (instant, nanos) = BOOT.get().unwrap();
+    nanos + instant.elapsed().as_nanos() as u64
+}
+
+/// Arm the alarm, replacing a pending one
+///
+/// # Arguments
+/// * `at_ns` - When to fire, in nanoseconds since the UNIX epoch
+/// * `handler` - Called from the alarm thread when it fires
+pub fn rtc_set_alarm(at_ns: u64, handler: fn()) {
+    let generation = ALARM_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    let delay = Duration::from_nanos(at_ns.saturating_sub(rtc_read()));
+    thread::spawn(move || {
+        thread::sleep(delay);
+        if ALARM_GENERATION.load(Ordering::SeqCst) == generation {
+            handler();
+        }
+    });
+}
+
+/// Cancel the pending alarm, if any
+pub fn rtc_cancel_alarm() {
+    ALARM_GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    static FIRED: AtomicBool = AtomicBool::new(false);
+
+    #[test]
+    fn test_alarm() {
+        let start = rtc_read();
+        rtc_set_alarm(start + 10_000_000, || FIRED.store(true, Ordering::SeqCst));
+        thread::sleep(Duration::from_millis(100));
+        assert!(FIRED.load(Ordering::SeqCst));
+        assert!(rtc_read() >= start + 100_000_000);
+    }
+}
@@ -0,0 +1,63 @@
+//! Native stdio: the process's stdin and stdout
+//!
+//! `runtime` buffers its output already, so bytes are written out as they
+//! come. Stdin is read by a thread started on the first read, which lets
+//! [`try_getc`] poll it; the end of input reads as Ctrl-D, as from a
+//! terminal.
+
+use std::io::{Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+
+/// What a read returns once stdin is closed
+const CTRL_D: u8 = 0x04;
+
+/// Bytes read from stdin, in order
+static INPUT: OnceLock<Mutex<Receiver<u8>>> = OnceLock::new();
+
+fn input() -> &'static Mutex<Receiver<u8>> {
+    INPUT.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in std::io::stdin().lock().bytes() {
+                match byte {
+                    Ok(byte) if sender.send(byte).is_ok() => {}
+                    _ => break,
+                }
+            }
+        });
+        Mutex::new(receiver)
+    })
+}
+
+/// Write a character to stdout
+///
+/// # Arguments
+/// * `ch` - Character byte to write
+pub fn putc(ch: u8) {
+    let mut stdout = std::io::stdout().lock();
+    let _ = stdout.write_all(&[ch]);
+    let _ = stdout.flush();
+}
+
+/// Read a character from stdin (blocking)
+///
+/// # Returns
+/// * The character byte, Ctrl-D at the end of input
+pub fn getc() -> u8 {
+    input().lock().unwrap().recv().unwrap_or(CTRL_D)
+}
+
+/// Read a character from stdin (non-blocking)
+///
+/// # Returns
+/// * `Some(ch)` - A character was pending, Ctrl-D at the end of input
+/// * `None` - No input yet
+pub fn try_getc() -> Option<u8> {
+    match input().lock().unwrap().try_recv() {
+        Ok(ch) => Some(ch),
+        Err(TryRecvError::Empty) => None,
+        Err(TryRecvError::Disconnected) => Some(CTRL_D),
+    }
+}
@@ -0,0 +1,104 @@
+//! NEMU platform disk
+//!
+//! NEMU's disk controller (the AM `DISK` device) transfers whole blocks
+//! between the disk image and guest memory. Its registers are:
+//!
+//! | Offset | Register  | Meaning                                  |
+//! |--------|-----------|------------------------------------------|
+//! | 0x00   | `PRESENT` | 1 if a disk image is attached            |
+//! | 0x04   | `BLKSZ`   | Block size in bytes                      |
+//! | 0x08   | `BLKCNT`  | Number of blocks                         |
+//! | 0x0c   | `BUF`     | Guest address of the transfer buffer     |
+//! | 0x10   | `BLKNO`   | First block of the transfer              |
+//! | 0x14   | `COUNT`   | Number of blocks to transfer             |
+//! | 0x18   | `CMD`     | Write 0 to read, 1 to write; synchronous |
+
+use alloc::boxed::Box;
+use common::{BLOCK_SIZE, BlockDevice, BlockError, check_request};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Disk controller base address
+const DISK_ADDR: usize = 0x10000300;
+
+mod reg {
+    pub const PRESENT: usize = 0x00;
+    pub const BLKSZ: usize = 0x04;
+    pub const BLKCNT: usize = 0x08;
+    pub const BUF: usize = 0x0c;
+    pub const BLKNO: usize = 0x10;
+    pub const COUNT: usize = 0x14;
+    pub const CMD: usize = 0x18;
+}
+
+const CMD_READ: u32 = 0;
+const CMD_WRITE: u32 = 1;
+
+fn read(offset: usize) -> u32 {
+    unsafe { core::ptr::read_volatile((DISK_ADDR + offset) as *const u32) }
+}
+
+fn write(offset: usize, value: u32) {
+    unsafe { core::ptr::write_volatile((DISK_ADDR + offset) as *mut u32, value) }
+}
+
+/// The NEMU disk
+pub struct NemuDisk {
+    block_count: u64,
+}
+
+impl NemuDisk {
+    /// Probe for an attached disk image
+    ///
+    /// # Returns
+    /// * `Some(disk)` - A disk with [`BLOCK_SIZE`]-byte blocks is attached
+    /// * `None` - No disk, or an unsupported block size
+    pub fn new() -> Option<Self> {
+        if read(reg::PRESENT) == 0 || read(reg::BLKSZ) as usize != BLOCK_SIZE {
+            return None;
+        }
+        Some(Self {
+            block_count: read(reg::BLKCNT) as u64,
+        })
+    }
+
+    fn transfer(&mut self, cmd: u32, lba: u64, buf: usize, len: usize) -> Result<(), BlockError> {
+        let blocks = check_request(self.block_count, lba, len)?;
+        write(reg::BUF, buf as u32);
+        write(reg::BLKNO, lba as u32);
+        write(reg::COUNT, blocks as u32);
+        write(reg::CMD, cmd);
+        Ok(())
+    }
+}
+
+impl BlockDevice for NemuDisk {
+    fn block_count(&self) -> u64 {
+        self.block_count
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.transfer(CMD_READ, lba, buf.as_mut_ptr() as usize, buf.len())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        self.transfer(CMD_WRITE, lba, buf.as_ptr() as usize, buf.len())
+    }
+}
+
+/// Whether the disk has been handed out already
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Take the platform's disk
+///
+/// # Returns
+/// * `Some(device)` - The disk, on the first call only
+/// * `None` - No disk image is attached, or it was already taken
+pub fn block_device() -> Option<Box<dyn BlockDevice + Send>> {
+    if TAKEN.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    let device = NemuDisk::new()?;
+    TAKEN.store(true, Ordering::Relaxed);
+    Some(Box::new(device))
+}
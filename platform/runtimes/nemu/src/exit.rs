@@ -7,7 +7,6 @@
 ///
 /// This function is called when the user's main function returns.
 /// For NEMU, we execute an ebreak instruction to halt the simulator.
-#[unsafe(no_mangle)]
 pub fn platform_exit(code: i32) -> ! {
     // On RISC-V targets: place exit code in a0 then trap with ebreak (never returns)
     #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
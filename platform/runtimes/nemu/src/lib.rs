@@ -1,24 +1,63 @@
 #![no_std]
 
+extern crate alloc;
+
+use alloc::boxed::Box;
+
 // Platform-specific modules
 pub mod critical_section;
+pub mod disk;
 pub mod exit;
+pub mod rtc;
 pub mod startup;
 pub mod stdio;
 
+/// Physical addresses of the NEMU machine, from the build-helper memory map
+pub mod memory_map {
+    include!(concat!(env!("OUT_DIR"), "/memory_map.rs"));
+}
+
+/// The NEMU platform, for `runtime`
+pub struct Nemu;
+
+impl common::Platform for Nemu {
+    const NAME: &'static str = "nemu";
+    const MEMORY_MAP: &'static [common::MemoryRegion] = memory_map::REGIONS;
+
+    fn putc(ch: u8) {
+        stdio::putc(ch)
+    }
+
+    fn try_getc() -> Option<u8> {
+        stdio::try_getc()
+    }
+
+    fn getc() -> u8 {
+        stdio::getc()
+    }
+
+    fn exit(code: i32) -> ! {
+        exit::platform_exit(code)
+    }
+
+    fn rtc_read() -> Option<u64> {
+        rtc::rtc_read()
+    }
+
+    fn block_device() -> Option<Box<dyn common::BlockDevice + Send>> {
+        disk::block_device()
+    }
+}
+
 #[unsafe(export_name = "isa_init")]
 #[unsafe(link_section = ".text.isa_init")]
 pub unsafe extern "C" fn isa_init() -> ! {
-    unsafe extern "Rust" {
-        fn user_entry() -> !;
-    }
-
     unsafe {
         core::arch::asm!(
             "li x10, 0x200",
             "csrs mstatus, x10",
             options(nomem, nostack, preserves_flags)
         );
-        user_entry();
+        common::enter_program()
     }
 }
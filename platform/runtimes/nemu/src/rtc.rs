@@ -0,0 +1,23 @@
+//! NEMU platform real-time clock
+//!
+//! NEMU's RTC device exposes a 64-bit microsecond counter. It counts from
+//! simulator start rather than from a calendar epoch, so wall-clock time on
+//! NEMU starts at the UNIX epoch. NEMU has no alarm interrupt.
+
+/// RTC registers: the low word at +0, the high word at +4
+const RTC_ADDR: usize = 0x10000048;
+
+/// Read the wall-clock time
+///
+/// # Returns
+/// * `Some(ns)` - Nanoseconds since simulator start
+pub fn rtc_read() -> Option<u64> {
+    // Reading the high word makes NEMU refresh both words
+    let (hi, lo) = unsafe {
+        let hi = core::ptr::read_volatile((RTC_ADDR + 4) as *const u32);
+        let lo = core::ptr::read_volatile(RTC_ADDR as *const u32);
+        (hi, lo)
+    };
+    let us = ((hi as u64) << 32) | lo as u64;
+    Some(us * 1000)
+}
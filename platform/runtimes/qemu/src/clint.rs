@@ -0,0 +1,44 @@
+//! QEMU platform CLINT (Core Local Interruptor) access
+//!
+//! The virt machine exposes a SiFive-compatible CLINT at 0x2000000. Only the
+//! free-running `mtime` counter is used here, as a monotonic time source for
+//! drivers that need timeouts.
+
+/// CLINT base address on the QEMU virt machine
+const CLINT_BASE: usize = 0x0200_0000;
+
+/// Offset of the 64-bit `mtime` register
+const CLINT_MTIME: usize = CLINT_BASE + 0xbff8;
+
+/// Frequency of the `mtime` counter on the QEMU virt machine (10 MHz)
+pub const MTIME_FREQ_HZ: u64 = 10_000_000;
+
+/// Read the 64-bit `mtime` counter
+///
+/// On RV32 the counter is read as two 32-bit halves; the high half is read
+/// twice to detect a carry from the low half in between.
+///
+/// # Returns
+/// * Number of `mtime` ticks since reset
+pub fn mtime() -> u64 {
+    let lo_ptr = CLINT_MTIME as *const u32;
+    let hi_ptr = (CLINT_MTIME + 4) as *const u32;
+
+    loop {
+        unsafe {
+            let hi = core::ptr::read_volatile(hi_ptr);
+            let lo = core::ptr::read_volatile(lo_ptr);
+            if core::ptr::read_volatile(hi_ptr) == hi {
+                return ((hi as u64) << 32) | lo as u64;
+            }
+        }
+    }
+}
+
+/// Read `mtime` converted to milliseconds
+///
+/// # Returns
+/// * Milliseconds since reset
+pub fn uptime_ms() -> u64 {
+    mtime() / (MTIME_FREQ_HZ / 1000)
+}
@@ -0,0 +1,26 @@
+//! QEMU platform disk
+//!
+//! The disk is the first virtio-blk device, when one is attached.
+
+use crate::virtio::blk::VirtioBlk;
+use alloc::boxed::Box;
+use common::BlockDevice;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether the disk has been handed out already
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Take the platform's disk
+///
+/// # Returns
+/// * `Some(device)` - The disk, on the first call only
+/// * `None` - No virtio-blk device is attached, or it was already taken
+pub fn block_device() -> Option<Box<dyn BlockDevice + Send>> {
+    if TAKEN.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    let device = VirtioBlk::new().ok()?;
+    TAKEN.store(true, Ordering::Relaxed);
+    Some(Box::new(device))
+}
@@ -0,0 +1,28 @@
+//! QEMU platform entropy source
+//!
+//! Hardware entropy comes from a virtio-rng device when one is attached
+//! (`-device virtio-rng-device`). The device is probed once, on first use.
+
+use crate::virtio::rng::VirtioRng;
+use core::cell::RefCell;
+use critical_section::Mutex;
+
+/// `None` until probed, then `Some(None)` if no device is attached
+static RNG: Mutex<RefCell<Option<Option<VirtioRng>>>> = Mutex::new(RefCell::new(None));
+
+/// Fill a buffer with hardware entropy
+///
+/// # Arguments
+/// * `buf` - Buffer to fill
+///
+/// # Returns
+/// * Number of bytes filled, 0 if no virtio-rng device is attached
+pub fn entropy_fill(buf: &mut [u8]) -> usize {
+    critical_section::with(|cs| {
+        let mut rng = RNG.borrow_ref_mut(cs);
+        match rng.get_or_insert_with(|| VirtioRng::new().ok()) {
+            Some(device) => device.fill(buf),
+            None => 0,
+        }
+    })
+}
@@ -9,35 +9,71 @@
 /// to exit QEMU with a specific exit code.
 ///
 /// Write 0x5555 to exit with success (exit code 0)
-/// Write 0x3333 to exit with failure (exit code 1)
+/// Write (code << 16) | 0x3333 to exit with failure (exit code `code`)
+/// Write 0x7777 to reset the machine
 const QEMU_TEST_DEVICE: usize = 0x100000;
 const QEMU_EXIT_SUCCESS: u32 = 0x5555;
 const QEMU_EXIT_FAILURE: u32 = 0x3333;
+const QEMU_RESET: u32 = 0x7777;
+
+/// Encode an exit code for the test device
+///
+/// The device carries 16 bits of exit code. Non-zero codes whose low 16
+/// bits are zero would read as success, so they are reported as 1.
+fn test_device_value(code: i32) -> u32 {
+    match code as u32 & 0xffff {
+        _ if code == 0 => QEMU_EXIT_SUCCESS,
+        0 => (1 << 16) | QEMU_EXIT_FAILURE,
+        status => (status << 16) | QEMU_EXIT_FAILURE,
+    }
+}
+
+fn hang() -> ! {
+    loop {
+        core::hint::spin_loop();
+    }
+}
 
 /// Platform-specific exit function
 ///
 /// This function is called when the user's main function returns.
-/// For QEMU, we write to the test device to trigger a clean shutdown.
+/// For QEMU, we write to the test device to trigger a clean shutdown,
+/// or use SBI system reset when built with the `sbi` feature.
 ///
 /// # Arguments
-/// * `code` - Exit code (0 for success, non-zero for failure)
-#[unsafe(no_mangle)]
+/// * `code` - Exit code, QEMU exits with it (as the host truncates it)
 pub fn platform_exit(code: i32) -> ! {
-    unsafe {
-        // Map exit code to QEMU test device value
-        // 0 -> 0x5555 (success), non-zero -> 0x3333 (failure)
-        let exit_value = if code == 0 {
-            QEMU_EXIT_SUCCESS
+    #[cfg(feature = "sbi")]
+    {
+        let reason = if code == 0 {
+            crate::sbi::RESET_REASON_NONE
         } else {
-            QEMU_EXIT_FAILURE
+            crate::sbi::RESET_REASON_FAILURE
         };
+        crate::sbi::system_reset(crate::sbi::RESET_TYPE_SHUTDOWN, reason);
+    }
 
+    unsafe {
         // Write to QEMU test device to trigger exit
-        core::ptr::write_volatile(QEMU_TEST_DEVICE as *mut u32, exit_value);
+        core::ptr::write_volatile(QEMU_TEST_DEVICE as *mut u32, test_device_value(code));
     }
 
     // If the test device write didn't work, fall back to infinite loop
-    loop {
-        core::hint::spin_loop();
+    hang()
+}
+
+/// Reset the machine
+///
+/// # Returns
+/// * Only if the reset request was not honored
+pub fn platform_reboot() {
+    #[cfg(feature = "sbi")]
+    crate::sbi::system_reset(
+        crate::sbi::RESET_TYPE_COLD_REBOOT,
+        crate::sbi::RESET_REASON_NONE,
+    );
+
+    unsafe {
+        core::ptr::write_volatile(QEMU_TEST_DEVICE as *mut u32, QEMU_RESET);
     }
 }
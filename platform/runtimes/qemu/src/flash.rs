@@ -0,0 +1,269 @@
+//! CFI parallel flash driver for the QEMU virt machine
+//!
+//! The virt machine has two flash banks, persisted to host files with
+//! `-drive if=pflash,unit=N,format=raw,file=...`. Each bank is a pair of
+//! 16-bit Intel command set (CFI 0x0001) devices side by side on a 32-bit
+//! bus, so commands are written to both halves of a word and status is read
+//! from both.
+//!
+//! The bank reads as plain memory in read-array mode, which the driver
+//! returns to after every command.
+
+use crate::memory_map;
+use alloc::boxed::Box;
+use common::{FlashDevice, FlashError, check_flash_access};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Bus width in bytes
+const BANK_WIDTH: usize = 4;
+
+/// Commands, repeated for each device on the bus
+mod cmd {
+    pub const READ_ARRAY: u32 = 0x00ff_00ff;
+    pub const READ_STATUS: u32 = 0x0070_0070;
+    pub const CLEAR_STATUS: u32 = 0x0050_0050;
+    pub const QUERY: u32 = 0x0098_0098;
+    pub const BLOCK_ERASE: u32 = 0x0020_0020;
+    pub const ERASE_CONFIRM: u32 = 0x00d0_00d0;
+    pub const WORD_PROGRAM: u32 = 0x0040_0040;
+}
+
+/// Status register bits
+mod status {
+    pub const READY: u8 = 0x80;
+    pub const ERASE_ERROR: u8 = 0x20;
+    pub const PROGRAM_ERROR: u8 = 0x10;
+    pub const VPP_ERROR: u8 = 0x08;
+    pub const LOCKED: u8 = 0x02;
+}
+
+/// Word address the query command is written to
+const QUERY_ADDR: usize = 0x55;
+
+/// CFI query structure offsets, in bus words
+mod query {
+    pub const SIGNATURE: usize = 0x10;
+    pub const COMMAND_SET: usize = 0x13;
+    pub const DEVICE_SIZE: usize = 0x27;
+    pub const ERASE_REGIONS: usize = 0x2c;
+    pub const REGION_BLOCK_SIZE: usize = 0x2f;
+}
+
+/// CFI primary command sets with the Intel block erase and word program
+/// commands
+const INTEL_COMMAND_SETS: [u16; 2] = [0x0001, 0x0003];
+
+fn write(addr: usize, value: u32) {
+    unsafe { core::ptr::write_volatile(addr as *mut u32, value) }
+}
+
+fn read(addr: usize) -> u32 {
+    unsafe { core::ptr::read_volatile(addr as *const u32) }
+}
+
+/// A CFI flash bank
+pub struct CfiBank {
+    base: usize,
+    size: usize,
+    sector_size: usize,
+}
+
+impl CfiBank {
+    /// Identify a bank with a CFI query
+    ///
+    /// # Arguments
+    /// * `base` - Address of the bank
+    /// * `size` - Size of the bank, to work out how many devices share the bus
+    ///
+    /// # Returns
+    /// * `Some(bank)` - A bank with uniform sectors and the Intel command set
+    /// * `None` - No CFI flash answered, or it is not supported
+    pub fn probe(base: usize, size: usize) -> Option<Self> {
+        write(base + QUERY_ADDR * BANK_WIDTH, cmd::QUERY);
+        let byte = |offset: usize| read(base + offset * BANK_WIDTH) as u8;
+        let signature = [
+            byte(query::SIGNATURE),
+            byte(query::SIGNATURE + 1),
+            byte(query::SIGNATURE + 2),
+        ];
+        let command_set =
+            u16::from_le_bytes([byte(query::COMMAND_SET), byte(query::COMMAND_SET + 1)]);
+        let device_size_log2 = byte(query::DEVICE_SIZE) as u32;
+        let regions = byte(query::ERASE_REGIONS);
+        let block_size = u16::from_le_bytes([
+            byte(query::REGION_BLOCK_SIZE),
+            byte(query::REGION_BLOCK_SIZE + 1),
+        ]) as usize
+            * 256;
+        write(base, cmd::READ_ARRAY);
+
+        if &signature != b"QRY"
+            || !INTEL_COMMAND_SETS.contains(&command_set)
+            || regions != 1
+            || device_size_log2 >= usize::BITS
+        {
+            return None;
+        }
+        // Each device reports its own size and sectors
+        let devices = size / (1 << device_size_log2);
+        if devices == 0 || block_size == 0 {
+            return None;
+        }
+
+        Some(Self {
+            base,
+            size,
+            sector_size: block_size * devices,
+        })
+    }
+
+    /// Size of the erase unit in bytes
+    pub fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    /// Wait for the command at `addr` to finish and return to read-array mode
+    fn wait(&self, addr: usize) -> Result<(), FlashError> {
+        write(addr, cmd::READ_STATUS);
+        let errors = loop {
+            let word = read(addr);
+            let lanes = [word as u8, (word >> 16) as u8];
+            if lanes.iter().all(|s| s & status::READY != 0) {
+                break (lanes[0] | lanes[1])
+                    & (status::ERASE_ERROR
+                        | status::PROGRAM_ERROR
+                        | status::VPP_ERROR
+                        | status::LOCKED);
+            }
+        };
+
+        if errors != 0 {
+            write(addr, cmd::CLEAR_STATUS);
+        }
+        write(addr, cmd::READ_ARRAY);
+        match errors {
+            0 => Ok(()),
+            _ if errors & status::LOCKED != 0 => Err(FlashError::Locked),
+            _ => Err(FlashError::Device),
+        }
+    }
+
+    /// Erase the sector at a bank offset
+    pub fn erase_sector(&mut self, offset: usize) -> Result<(), FlashError> {
+        check_flash_access(self.size as u32, self.sector_size as u32, offset as u32, 0)?;
+        let addr = self.base + offset;
+        write(addr, cmd::BLOCK_ERASE);
+        write(addr, cmd::ERASE_CONFIRM);
+        self.wait(addr)
+    }
+
+    /// Program one bus word at a bank offset
+    pub fn program_word(&mut self, offset: usize, word: u32) -> Result<(), FlashError> {
+        check_flash_access(
+            self.size as u32,
+            BANK_WIDTH as u32,
+            offset as u32,
+            BANK_WIDTH,
+        )?;
+        let addr = self.base + offset;
+        write(addr, cmd::WORD_PROGRAM);
+        write(addr, word);
+        self.wait(addr)
+    }
+
+    /// Read bytes at a bank offset
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), FlashError> {
+        check_flash_access(self.size as u32, 1, offset as u32, buf.len())?;
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte = unsafe { core::ptr::read_volatile((self.base + offset + i) as *const u8) };
+        }
+        Ok(())
+    }
+}
+
+/// A sector-aligned part of a bank
+pub struct CfiPartition {
+    bank: CfiBank,
+    /// Offset of the partition in the bank
+    start: usize,
+    size: usize,
+}
+
+impl CfiPartition {
+    /// Probe the bank holding a partition
+    ///
+    /// # Arguments
+    /// * `bank_base` - Address of the bank
+    /// * `bank_size` - Size of the bank
+    /// * `base` - Address of the partition
+    /// * `size` - Size of the partition
+    ///
+    /// # Returns
+    /// * `Some(partition)` - The bank answered and the partition is
+    ///   sector-aligned within it
+    pub fn new(bank_base: usize, bank_size: usize, base: usize, size: usize) -> Option<Self> {
+        let bank = CfiBank::probe(bank_base, bank_size)?;
+        let start = base.checked_sub(bank_base)?;
+        let aligned =
+            start.is_multiple_of(bank.sector_size) && size.is_multiple_of(bank.sector_size);
+        (aligned && start + size <= bank_size).then_some(Self { bank, start, size })
+    }
+}
+
+impl FlashDevice for CfiPartition {
+    fn size(&self) -> u32 {
+        self.size as u32
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.bank.sector_size as u32
+    }
+
+    fn write_size(&self) -> u32 {
+        BANK_WIDTH as u32
+    }
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), FlashError> {
+        check_flash_access(self.size(), 1, offset, buf.len())?;
+        self.bank.read(self.start + offset as usize, buf)
+    }
+
+    fn erase(&mut self, offset: u32) -> Result<(), FlashError> {
+        check_flash_access(self.size(), self.sector_size(), offset, 0)?;
+        self.bank.erase_sector(self.start + offset as usize)
+    }
+
+    fn program(&mut self, offset: u32, data: &[u8]) -> Result<(), FlashError> {
+        check_flash_access(self.size(), BANK_WIDTH as u32, offset, data.len())?;
+        for (i, word) in data.chunks_exact(BANK_WIDTH).enumerate() {
+            let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            self.bank
+                .program_word(self.start + offset as usize + i * BANK_WIDTH, word)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether the key-value store partition has been handed out already
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Take the flash partition for the key-value store
+///
+/// # Returns
+/// * `Some(flash)` - The `kvstore` partition of the memory map, on the first
+///   call only
+/// * `None` - The flash did not answer, or it was already taken
+pub fn kv_flash() -> Option<Box<dyn FlashDevice + Send>> {
+    if TAKEN.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    let flash = CfiPartition::new(
+        memory_map::KVSTORE_BANK_BASE,
+        memory_map::FLASH1_SIZE,
+        memory_map::KVSTORE_BASE,
+        memory_map::KVSTORE_SIZE,
+    )?;
+    TAKEN.store(true, Ordering::Relaxed);
+    Some(Box::new(flash))
+}
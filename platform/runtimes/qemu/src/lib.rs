@@ -1,23 +1,90 @@
 #![no_std]
 
+extern crate alloc;
+
+use alloc::boxed::Box;
+
+pub mod clint;
 pub mod critical_section;
+pub mod disk;
+pub mod entropy;
 pub mod exit;
+pub mod flash;
+pub mod plic;
+pub mod rtc;
+#[cfg(feature = "sbi")]
+pub mod sbi;
 pub mod startup;
 pub mod stdio;
+pub mod trap;
+pub mod virtio;
+
+/// Physical addresses of the QEMU virt machine, from the build-helper memory map
+pub mod memory_map {
+    include!(concat!(env!("OUT_DIR"), "/memory_map.rs"));
+}
+
+/// The QEMU virt platform, for `runtime`
+pub struct Qemu;
+
+impl common::Platform for Qemu {
+    const NAME: &'static str = "qemu";
+    const MEMORY_MAP: &'static [common::MemoryRegion] = memory_map::REGIONS;
+
+    fn putc(ch: u8) {
+        stdio::putc(ch)
+    }
+
+    fn try_getc() -> Option<u8> {
+        stdio::try_getc()
+    }
+
+    fn getc() -> u8 {
+        stdio::getc()
+    }
+
+    fn exit(code: i32) -> ! {
+        exit::platform_exit(code)
+    }
+
+    fn reboot() {
+        exit::platform_reboot()
+    }
+
+    fn rtc_read() -> Option<u64> {
+        rtc::rtc_read()
+    }
+
+    fn rtc_set_alarm(at_ns: u64, handler: fn()) -> bool {
+        rtc::rtc_set_alarm(at_ns, handler)
+    }
+
+    fn rtc_cancel_alarm() {
+        rtc::rtc_cancel_alarm()
+    }
+
+    fn entropy_fill(buf: &mut [u8]) -> usize {
+        entropy::entropy_fill(buf)
+    }
+
+    fn block_device() -> Option<Box<dyn common::BlockDevice + Send>> {
+        disk::block_device()
+    }
+
+    fn kv_flash() -> Option<Box<dyn common::FlashDevice + Send>> {
+        flash::kv_flash()
+    }
+}
 
 #[unsafe(export_name = "isa_init")]
 #[unsafe(link_section = ".text.isa_init")]
 pub unsafe extern "C" fn isa_init() -> ! {
-    unsafe extern "Rust" {
-        fn user_entry() -> !;
-    }
-
     unsafe {
         core::arch::asm!(
             "li x10, 0x200",
             "csrs mstatus, x10",
             options(nomem, nostack, preserves_flags)
         );
-        user_entry();
+        common::enter_program()
     }
 }
@@ -0,0 +1,64 @@
+//! QEMU platform PLIC (Platform-Level Interrupt Controller) access
+//!
+//! The virt machine routes device interrupts through a SiFive-compatible PLIC
+//! at 0xc000000. Only hart 0's machine-mode context is used.
+
+/// PLIC base address on the QEMU virt machine
+const PLIC_BASE: usize = 0x0c00_0000;
+
+/// Per-source priority registers, one word per interrupt source
+const PLIC_PRIORITY: usize = PLIC_BASE;
+
+/// Enable bits of hart 0's machine-mode context
+const PLIC_ENABLE: usize = PLIC_BASE + 0x2000;
+
+/// Priority threshold of hart 0's machine-mode context
+const PLIC_THRESHOLD: usize = PLIC_BASE + 0x20_0000;
+
+/// Claim/complete register of hart 0's machine-mode context
+const PLIC_CLAIM: usize = PLIC_BASE + 0x20_0004;
+
+fn write(addr: usize, value: u32) {
+    unsafe { core::ptr::write_volatile(addr as *mut u32, value) };
+}
+
+fn read(addr: usize) -> u32 {
+    unsafe { core::ptr::read_volatile(addr as *const u32) }
+}
+
+/// Route an interrupt source to hart 0 in machine mode
+///
+/// # Arguments
+/// * `irq` - Interrupt source number
+pub fn enable(irq: u32) {
+    write(PLIC_PRIORITY + 4 * irq as usize, 1);
+    write(PLIC_THRESHOLD, 0);
+
+    let word = PLIC_ENABLE + 4 * (irq as usize / 32);
+    write(word, read(word) | 1 << (irq % 32));
+}
+
+/// Stop routing an interrupt source to hart 0
+///
+/// # Arguments
+/// * `irq` - Interrupt source number
+pub fn disable(irq: u32) {
+    let word = PLIC_ENABLE + 4 * (irq as usize / 32);
+    write(word, read(word) & !(1 << (irq % 32)));
+}
+
+/// Claim the highest-priority pending interrupt
+///
+/// # Returns
+/// * The claimed source number, 0 if nothing is pending
+pub fn claim() -> u32 {
+    read(PLIC_CLAIM)
+}
+
+/// Signal that a claimed interrupt has been handled
+///
+/// # Arguments
+/// * `irq` - Source number returned by [`claim`]
+pub fn complete(irq: u32) {
+    write(PLIC_CLAIM, irq);
+}
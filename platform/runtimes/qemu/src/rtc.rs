@@ -0,0 +1,111 @@
+//! Goldfish RTC driver for the QEMU virt machine
+//!
+//! The virt machine has a Goldfish real-time clock at 0x101000, initialized
+//! from the host clock (or `-rtc base=...`). It counts nanoseconds since the
+//! UNIX epoch and has a single one-shot alarm, wired to PLIC source 11.
+
+use crate::trap::{self, Handler};
+use core::cell::Cell;
+use critical_section::Mutex;
+
+/// Goldfish RTC base address on the QEMU virt machine
+const RTC_BASE: usize = 0x0010_1000;
+
+/// PLIC interrupt source of the RTC
+const RTC_IRQ: u32 = 11;
+
+/// Register offsets
+mod reg {
+    /// Reading latches the upper half into `TIME_HIGH`
+    pub const TIME_LOW: usize = 0x00;
+    pub const TIME_HIGH: usize = 0x04;
+    /// Writing arms the alarm, after `ALARM_HIGH` has been written
+    pub const ALARM_LOW: usize = 0x08;
+    pub const ALARM_HIGH: usize = 0x0c;
+    pub const IRQ_ENABLED: usize = 0x10;
+    pub const CLEAR_ALARM: usize = 0x14;
+    pub const CLEAR_INTERRUPT: usize = 0x1c;
+}
+
+/// Handler to call when the pending alarm fires
+static ALARM_HANDLER: Mutex<Cell<Option<Handler>>> = Mutex::new(Cell::new(None));
+
+fn write(offset: usize, value: u32) {
+    unsafe { core::ptr::write_volatile((RTC_BASE + offset) as *mut u32, value) };
+}
+
+fn read(offset: usize) -> u32 {
+    unsafe { core::ptr::read_volatile((RTC_BASE + offset) as *const u32) }
+}
+
+/// Read the current time
+///
+/// # Returns
+/// * Nanoseconds since the UNIX epoch
+pub fn now_ns() -> u64 {
+    // TIME_LOW must be read first, it latches TIME_HIGH
+    let lo = read(reg::TIME_LOW);
+    let hi = read(reg::TIME_HIGH);
+    ((hi as u64) << 32) | lo as u64
+}
+
+/// Arm the alarm without touching the interrupt handler
+///
+/// # Arguments
+/// * `at_ns` - Alarm time in nanoseconds since the UNIX epoch
+pub fn arm_alarm(at_ns: u64) {
+    write(reg::ALARM_HIGH, (at_ns >> 32) as u32);
+    write(reg::ALARM_LOW, at_ns as u32);
+}
+
+/// Disarm the alarm and acknowledge a pending alarm interrupt
+pub fn disarm_alarm() {
+    write(reg::CLEAR_ALARM, 1);
+    write(reg::CLEAR_INTERRUPT, 1);
+}
+
+fn alarm_interrupt() {
+    write(reg::CLEAR_INTERRUPT, 1);
+    write(reg::IRQ_ENABLED, 0);
+
+    // The alarm is one-shot: the handler must re-arm it if needed
+    let handler = critical_section::with(|cs| ALARM_HANDLER.borrow(cs).take());
+    if let Some(handler) = handler {
+        handler();
+    }
+}
+
+/// Read the wall-clock time
+///
+/// # Returns
+/// * `Some(ns)` - Nanoseconds since the UNIX epoch
+pub fn rtc_read() -> Option<u64> {
+    Some(now_ns())
+}
+
+/// Call `handler` from interrupt context once the clock reaches `at_ns`
+///
+/// Replaces any pending alarm. An alarm time in the past fires immediately.
+///
+/// # Arguments
+/// * `at_ns` - Alarm time in nanoseconds since the UNIX epoch
+/// * `handler` - Function to call when the alarm fires
+///
+/// # Returns
+/// * `true` - The alarm is armed
+pub fn rtc_set_alarm(at_ns: u64, handler: fn()) -> bool {
+    disarm_alarm();
+    critical_section::with(|cs| ALARM_HANDLER.borrow(cs).set(Some(handler)));
+
+    trap::register(RTC_IRQ, alarm_interrupt);
+    write(reg::IRQ_ENABLED, 1);
+    arm_alarm(at_ns);
+    true
+}
+
+/// Cancel the pending alarm, if any
+pub fn rtc_cancel_alarm() {
+    write(reg::IRQ_ENABLED, 0);
+    disarm_alarm();
+    critical_section::with(|cs| ALARM_HANDLER.borrow(cs).set(None));
+}
@@ -0,0 +1,40 @@
+//! SBI (Supervisor Binary Interface) calls
+//!
+//! Only used when the program runs in S-mode on top of an SBI firmware such
+//! as OpenSBI (`-bios default`), where the test device may not be mapped.
+//! Enabled with the `sbi` feature.
+
+/// System Reset extension ID ("SRST")
+const EID_SRST: usize = 0x5352_5354;
+
+/// `sbi_system_reset` function ID
+const FID_SYSTEM_RESET: usize = 0;
+
+pub const RESET_TYPE_SHUTDOWN: u32 = 0;
+pub const RESET_TYPE_COLD_REBOOT: u32 = 1;
+
+pub const RESET_REASON_NONE: u32 = 0;
+pub const RESET_REASON_FAILURE: u32 = 1;
+
+/// Request a system reset or shutdown from the SBI firmware
+///
+/// # Arguments
+/// * `reset_type` - One of the `RESET_TYPE_*` constants
+/// * `reason` - One of the `RESET_REASON_*` constants
+///
+/// # Returns
+/// * The SBI error code, only if the firmware refused the request
+pub fn system_reset(reset_type: u32, reason: u32) -> isize {
+    let error: isize;
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") reset_type as usize => error,
+            inlateout("a1") reason as usize => _,
+            in("a6") FID_SYSTEM_RESET,
+            in("a7") EID_SRST,
+            options(nostack)
+        );
+    }
+    error
+}
@@ -7,19 +7,34 @@
 ///
 /// This function is the first code that runs on the CPU.
 /// It initializes the stack pointer using the linker symbol `_stack_top`
-/// and then jumps to `__start__`.
+/// and then jumps to `__start__`. When started by `runtime::elf` (marked by
+/// `common::ARGS_MAGIC` in `a2`), it keeps the stack it was given, which
+/// holds the arguments, and records `argc` and `argv` from `a0` and `a1`.
 #[unsafe(link_section = ".text._start")]
 #[unsafe(export_name = "_start")]
 #[unsafe(naked)]
 pub unsafe extern "C" fn _start() -> ! {
     core::arch::naked_asm!(
         "
+        # A loader passing arguments has set up the stack already
+        li t0, {magic}
+        beq a2, t0, 1f
+
         # Load stack pointer from linker symbol
         # Stack grows downward, so we set sp to the top of RAM
         la sp, _stack_top
+        li a0, 0
+        li a1, 0
+
+    1:
+        # Record argc and argv
+        la t0, __am_args
+        sw a0, 0(t0)
+        sw a1, 4(t0)
 
         # Jump to common startup code
         j isa_init
-        "
+        ",
+        magic = const common::ARGS_MAGIC,
     )
 }
@@ -0,0 +1,158 @@
+//! QEMU platform trap handling
+//!
+//! The trap vector is only installed once a driver registers an external
+//! interrupt handler, so programs that never use interrupts run exactly as
+//! before. Machine external interrupts are claimed from the PLIC and
+//! dispatched to the registered handler; any other trap is fatal.
+
+use crate::plic;
+use core::cell::Cell;
+use critical_section::Mutex;
+
+/// Number of PLIC interrupt sources that can have a handler
+const MAX_IRQ: usize = 32;
+
+/// `mcause` interrupt bit
+const MCAUSE_INTERRUPT: usize = 1 << (usize::BITS - 1);
+
+/// `mcause` code of a machine external interrupt
+const MACHINE_EXTERNAL: usize = 11;
+
+/// `mie.MEIE`: machine external interrupt enable
+const MIE_MEIE: usize = 1 << 11;
+
+/// An interrupt handler, called with interrupts disabled
+pub type Handler = fn();
+
+type HandlerTable = [Option<Handler>; MAX_IRQ];
+
+static HANDLERS: Mutex<Cell<HandlerTable>> = Mutex::new(Cell::new([None; MAX_IRQ]));
+
+// Saves the caller-saved registers, calls `trap_handler(mcause, mepc, s0)`
+// and returns with `mret`. Callee-saved registers are preserved by the
+// handler; `s0` is passed on as the trapped code's frame pointer.
+core::arch::global_asm!(
+    ".section .text.trap_vector, \"ax\"",
+    ".balign 4",
+    ".global qemu_trap_vector",
+    "qemu_trap_vector:",
+    "addi sp, sp, -64",
+    "sw ra, 0(sp)",
+    "sw t0, 4(sp)",
+    "sw t1, 8(sp)",
+    "sw t2, 12(sp)",
+    "sw a0, 16(sp)",
+    "sw a1, 20(sp)",
+    "sw a2, 24(sp)",
+    "sw a3, 28(sp)",
+    "sw a4, 32(sp)",
+    "sw a5, 36(sp)",
+    "sw a6, 40(sp)",
+    "sw a7, 44(sp)",
+    "sw t3, 48(sp)",
+    "sw t4, 52(sp)",
+    "sw t5, 56(sp)",
+    "sw t6, 60(sp)",
+    "csrr a0, mcause",
+    "csrr a1, mepc",
+    "mv a2, s0",
+    "call {handler}",
+    "lw ra, 0(sp)",
+    "lw t0, 4(sp)",
+    "lw t1, 8(sp)",
+    "lw t2, 12(sp)",
+    "lw a0, 16(sp)",
+    "lw a1, 20(sp)",
+    "lw a2, 24(sp)",
+    "lw a3, 28(sp)",
+    "lw a4, 32(sp)",
+    "lw a5, 36(sp)",
+    "lw a6, 40(sp)",
+    "lw a7, 44(sp)",
+    "lw t3, 48(sp)",
+    "lw t4, 52(sp)",
+    "lw t5, 56(sp)",
+    "lw t6, 60(sp)",
+    "addi sp, sp, 64",
+    "mret",
+    handler = sym trap_handler,
+);
+
+extern "C" fn trap_handler(mcause: usize, mepc: usize, fp: usize) {
+    if mcause != MCAUSE_INTERRUPT | MACHINE_EXTERNAL {
+        // The panic's backtrace starts at the trapped instruction
+        common::record_trap(mepc, fp);
+        panic!("unhandled trap: mcause=0x{:x}, mepc=0x{:x}", mcause, mepc);
+    }
+
+    loop {
+        let irq = plic::claim();
+        if irq == 0 {
+            break;
+        }
+
+        let handler = critical_section::with(|cs| HANDLERS.borrow(cs).get())
+            .get(irq as usize)
+            .copied()
+            .flatten();
+        if let Some(handler) = handler {
+            handler();
+        }
+        plic::complete(irq);
+    }
+}
+
+/// Install the trap vector and enable machine external interrupts
+fn install() {
+    unsafe extern "C" {
+        fn qemu_trap_vector();
+    }
+
+    unsafe {
+        core::arch::asm!(
+            "csrw mtvec, {vector}",
+            "csrs mie, {meie}",
+            "csrsi mstatus, 0x8",
+            vector = in(reg) qemu_trap_vector as *const () as usize,
+            meie = in(reg) MIE_MEIE,
+            options(nomem, nostack)
+        );
+    }
+}
+
+/// Register a handler for a PLIC interrupt source and enable the source
+///
+/// The handler runs in interrupt context with interrupts disabled, after
+/// the source has been claimed; the device must be quiesced by the handler.
+///
+/// # Arguments
+/// * `irq` - Interrupt source number, below 32
+/// * `handler` - Function to call when the source fires
+pub fn register(irq: u32, handler: Handler) {
+    assert!((irq as usize) < MAX_IRQ, "IRQ {} out of range", irq);
+
+    critical_section::with(|cs| {
+        let handlers = HANDLERS.borrow(cs);
+        let mut table = handlers.get();
+        table[irq as usize] = Some(handler);
+        handlers.set(table);
+    });
+    plic::enable(irq);
+    install();
+}
+
+/// Disable a PLIC interrupt source and drop its handler
+///
+/// # Arguments
+/// * `irq` - Interrupt source number, below 32
+pub fn unregister(irq: u32) {
+    plic::disable(irq);
+    critical_section::with(|cs| {
+        let handlers = HANDLERS.borrow(cs);
+        let mut table = handlers.get();
+        if let Some(slot) = table.get_mut(irq as usize) {
+            *slot = None;
+        }
+        handlers.set(table);
+    });
+}
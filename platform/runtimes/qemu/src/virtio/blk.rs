@@ -0,0 +1,162 @@
+//! Virtio block device driver
+//!
+//! A polling driver issuing one request at a time on queue 0. Each request
+//! is a three-descriptor chain: header, data, status byte.
+//!
+//! On QEMU, attach a disk image with for example:
+//! `-drive file=disk.img,if=none,format=raw,id=hd0 -device virtio-blk-device,drive=hd0`
+
+use super::queue::{Buffer, VirtQueue};
+use super::{DeviceType, Error, VirtioMmio};
+use common::{BLOCK_SIZE, BlockDevice, BlockError, check_request};
+
+/// Device is read-only
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
+/// Device supports the flush command
+const VIRTIO_BLK_F_FLUSH: u64 = 1 << 9;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_T_FLUSH: u32 = 4;
+
+const VIRTIO_BLK_S_OK: u8 = 0;
+
+const REQUEST_QUEUE: u16 = 0;
+
+/// Request header (`struct virtio_blk_req` without data and status)
+#[repr(C)]
+struct RequestHeader {
+    kind: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+/// A virtio block device
+pub struct VirtioBlk {
+    transport: VirtioMmio,
+    queue: VirtQueue,
+    capacity: u64,
+    read_only: bool,
+    can_flush: bool,
+}
+
+impl VirtioBlk {
+    /// Probe for a virtio-blk device and bring it up
+    ///
+    /// # Returns
+    /// * `Ok(device)` - The initialized device
+    /// * `Err(error)` - No device was found or initialization failed
+    pub fn new() -> Result<Self, Error> {
+        let mut transport = VirtioMmio::probe(DeviceType::Block)?;
+        let features = transport.init(VIRTIO_BLK_F_RO | VIRTIO_BLK_F_FLUSH)?;
+
+        // Capacity in 512-byte sectors, the first field of the configuration
+        let capacity = (0..8).fold(0u64, |value, i| {
+            value | (transport.config_read_u8(i) as u64) << (8 * i)
+        });
+
+        if transport.queue_max(REQUEST_QUEUE) < 4 {
+            return Err(Error::QueueUnavailable);
+        }
+        // One request in flight needs three descriptors
+        let queue = VirtQueue::new(4)?;
+        transport.attach_queue(REQUEST_QUEUE, &queue);
+        transport.driver_ok();
+
+        Ok(Self {
+            transport,
+            queue,
+            capacity,
+            read_only: features & VIRTIO_BLK_F_RO != 0,
+            can_flush: features & VIRTIO_BLK_F_FLUSH != 0,
+        })
+    }
+
+    /// Whether the device refuses writes
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Submit one request and wait for its completion
+    fn request(&mut self, kind: u32, sector: u64, data: Option<Buffer>) -> Result<(), BlockError> {
+        let header = RequestHeader {
+            kind,
+            reserved: 0,
+            sector,
+        };
+        let mut status = 0xffu8;
+
+        let header_buffer = Buffer {
+            addr: &header as *const RequestHeader as usize,
+            len: size_of::<RequestHeader>() as u32,
+            device_writable: false,
+        };
+        let status_buffer = Buffer {
+            addr: &mut status as *mut u8 as usize,
+            len: 1,
+            device_writable: true,
+        };
+        let head = match data {
+            Some(data) => self.queue.add(&[header_buffer, data, status_buffer]),
+            None => self.queue.add(&[header_buffer, status_buffer]),
+        };
+        if head.is_none() {
+            return Err(BlockError::Io);
+        }
+        self.transport.notify(REQUEST_QUEUE);
+
+        while self.queue.pop_used().is_none() {
+            core::hint::spin_loop();
+        }
+
+        // The device wrote the status behind the compiler's back
+        match unsafe { core::ptr::read_volatile(&status) } {
+            VIRTIO_BLK_S_OK => Ok(()),
+            _ => Err(BlockError::Io),
+        }
+    }
+}
+
+impl BlockDevice for VirtioBlk {
+    fn block_count(&self) -> u64 {
+        // Virtio sectors are always 512 bytes, the same as BLOCK_SIZE
+        self.capacity * 512 / BLOCK_SIZE as u64
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self.block_count(), lba, buf.len())?;
+        let data = Buffer {
+            addr: buf.as_mut_ptr() as usize,
+            len: buf.len() as u32,
+            device_writable: true,
+        };
+        self.request(VIRTIO_BLK_T_IN, lba, Some(data))
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        if self.read_only {
+            return Err(BlockError::ReadOnly);
+        }
+        check_request(self.block_count(), lba, buf.len())?;
+        let data = Buffer {
+            addr: buf.as_ptr() as usize,
+            len: buf.len() as u32,
+            device_writable: false,
+        };
+        self.request(VIRTIO_BLK_T_OUT, lba, Some(data))
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        if !self.can_flush {
+            return Ok(());
+        }
+        self.request(VIRTIO_BLK_T_FLUSH, 0, None)
+    }
+}
+
+impl Drop for VirtioBlk {
+    /// Reset the device before its queue memory is freed
+    fn drop(&mut self) {
+        self.transport.reset();
+    }
+}
@@ -0,0 +1,241 @@
+//! Virtio-MMIO transport for the QEMU virt machine
+//!
+//! QEMU's virt machine provides eight virtio-mmio transports starting at
+//! 0x10001000, each 0x1000 bytes apart. Devices added with
+//! `-device virtio-*-device` are plugged into these slots, so drivers locate
+//! their device by probing every slot for a matching device ID.
+//!
+//! Both the legacy (version 1) and the modern (version 2) register layouts
+//! are supported; QEMU uses the legacy layout unless
+//! `-global virtio-mmio.force-legacy=false` is given.
+
+pub mod blk;
+pub mod net;
+pub mod queue;
+pub mod rng;
+
+/// Base address of the first virtio-mmio transport
+const VIRTIO_MMIO_BASE: usize = 0x1000_1000;
+
+/// Distance between two consecutive virtio-mmio transports
+const VIRTIO_MMIO_STRIDE: usize = 0x1000;
+
+/// Number of virtio-mmio transports on the virt machine
+const VIRTIO_MMIO_SLOTS: usize = 8;
+
+/// "virt" in little-endian
+const VIRTIO_MAGIC: u32 = 0x7472_6976;
+
+/// Register offsets (virtio spec 1.1, section 4.2.2)
+mod reg {
+    pub const MAGIC_VALUE: usize = 0x000;
+    pub const VERSION: usize = 0x004;
+    pub const DEVICE_ID: usize = 0x008;
+    pub const DEVICE_FEATURES: usize = 0x010;
+    pub const DEVICE_FEATURES_SEL: usize = 0x014;
+    pub const DRIVER_FEATURES: usize = 0x020;
+    pub const DRIVER_FEATURES_SEL: usize = 0x024;
+    pub const GUEST_PAGE_SIZE: usize = 0x028;
+    pub const QUEUE_SEL: usize = 0x030;
+    pub const QUEUE_NUM_MAX: usize = 0x034;
+    pub const QUEUE_NUM: usize = 0x038;
+    pub const QUEUE_ALIGN: usize = 0x03c;
+    pub const QUEUE_PFN: usize = 0x040;
+    pub const QUEUE_READY: usize = 0x044;
+    pub const QUEUE_NOTIFY: usize = 0x050;
+    pub const STATUS: usize = 0x070;
+    pub const QUEUE_DESC_LOW: usize = 0x080;
+    pub const QUEUE_DESC_HIGH: usize = 0x084;
+    pub const QUEUE_DRIVER_LOW: usize = 0x090;
+    pub const QUEUE_DRIVER_HIGH: usize = 0x094;
+    pub const QUEUE_DEVICE_LOW: usize = 0x0a0;
+    pub const QUEUE_DEVICE_HIGH: usize = 0x0a4;
+    pub const CONFIG: usize = 0x100;
+}
+
+/// Device status bits
+mod status {
+    pub const ACKNOWLEDGE: u32 = 1;
+    pub const DRIVER: u32 = 2;
+    pub const DRIVER_OK: u32 = 4;
+    pub const FEATURES_OK: u32 = 8;
+    pub const FAILED: u32 = 128;
+}
+
+/// Feature bit required for modern (non-legacy) devices
+pub const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+/// Page size reported to legacy devices
+pub(crate) const PAGE_SIZE: usize = 4096;
+
+/// Virtio device types (virtio spec 1.1, section 5)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum DeviceType {
+    Net = 1,
+    Block = 2,
+    Rng = 4,
+}
+
+/// Errors reported while bringing up a virtio device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// No transport carries a device of the requested type
+    NotFound,
+    /// The device rejected the negotiated feature set
+    FeaturesRejected,
+    /// The requested queue does not exist
+    QueueUnavailable,
+}
+
+/// A virtio-mmio transport with a device attached
+pub struct VirtioMmio {
+    base: usize,
+    version: u32,
+}
+
+impl VirtioMmio {
+    /// Find the first transport carrying a device of the given type
+    ///
+    /// # Arguments
+    /// * `device` - Device type to look for
+    ///
+    /// # Returns
+    /// * `Ok(transport)` - Transport of the first matching device
+    /// * `Err(Error::NotFound)` - No such device is attached
+    pub fn probe(device: DeviceType) -> Result<Self, Error> {
+        (0..VIRTIO_MMIO_SLOTS)
+            .map(|slot| Self {
+                base: VIRTIO_MMIO_BASE + slot * VIRTIO_MMIO_STRIDE,
+                version: 0,
+            })
+            .find(|transport| {
+                transport.read(reg::MAGIC_VALUE) == VIRTIO_MAGIC
+                    && transport.read(reg::DEVICE_ID) == device as u32
+            })
+            .map(|mut transport| {
+                transport.version = transport.read(reg::VERSION);
+                transport
+            })
+            .ok_or(Error::NotFound)
+    }
+
+    /// Whether the transport uses the legacy (version 1) register layout
+    pub fn is_legacy(&self) -> bool {
+        self.version == 1
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { core::ptr::read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { core::ptr::write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    /// Reset the device and negotiate features
+    ///
+    /// Performs the initialization sequence up to (but excluding) DRIVER_OK.
+    /// `VIRTIO_F_VERSION_1` is added automatically for modern devices.
+    ///
+    /// # Arguments
+    /// * `wanted` - Feature bits the driver understands
+    ///
+    /// # Returns
+    /// * `Ok(features)` - Feature bits accepted by both sides
+    /// * `Err(Error::FeaturesRejected)` - The device did not accept the features
+    pub fn init(&mut self, wanted: u64) -> Result<u64, Error> {
+        self.reset();
+        self.write(reg::STATUS, status::ACKNOWLEDGE);
+        self.write(reg::STATUS, status::ACKNOWLEDGE | status::DRIVER);
+
+        self.write(reg::DEVICE_FEATURES_SEL, 0);
+        let mut offered = self.read(reg::DEVICE_FEATURES) as u64;
+        self.write(reg::DEVICE_FEATURES_SEL, 1);
+        offered |= (self.read(reg::DEVICE_FEATURES) as u64) << 32;
+
+        let wanted = if self.is_legacy() {
+            wanted
+        } else {
+            wanted | VIRTIO_F_VERSION_1
+        };
+        let accepted = offered & wanted;
+
+        self.write(reg::DRIVER_FEATURES_SEL, 0);
+        self.write(reg::DRIVER_FEATURES, accepted as u32);
+        self.write(reg::DRIVER_FEATURES_SEL, 1);
+        self.write(reg::DRIVER_FEATURES, (accepted >> 32) as u32);
+
+        if self.is_legacy() {
+            self.write(reg::GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+        } else {
+            let value = status::ACKNOWLEDGE | status::DRIVER | status::FEATURES_OK;
+            self.write(reg::STATUS, value);
+            if self.read(reg::STATUS) & status::FEATURES_OK == 0 {
+                self.write(reg::STATUS, status::FAILED);
+                return Err(Error::FeaturesRejected);
+            }
+        }
+
+        Ok(accepted)
+    }
+
+    /// Tell the device that the driver is ready
+    pub fn driver_ok(&self) {
+        let value = self.read(reg::STATUS);
+        self.write(reg::STATUS, value | status::DRIVER_OK);
+    }
+
+    /// Reset the device, stopping all DMA into driver memory
+    pub fn reset(&self) {
+        self.write(reg::STATUS, 0);
+        while self.read(reg::STATUS) != 0 {}
+    }
+
+    /// Maximum size of the given queue
+    ///
+    /// # Returns
+    /// * Maximum number of descriptors, or 0 if the queue does not exist
+    pub fn queue_max(&self, index: u16) -> u16 {
+        self.write(reg::QUEUE_SEL, index as u32);
+        self.read(reg::QUEUE_NUM_MAX) as u16
+    }
+
+    /// Hand a queue's memory to the device
+    ///
+    /// # Arguments
+    /// * `index` - Queue index
+    /// * `queue` - Queue whose rings the device should use
+    pub fn attach_queue(&self, index: u16, queue: &queue::VirtQueue) {
+        self.write(reg::QUEUE_SEL, index as u32);
+        self.write(reg::QUEUE_NUM, queue.size() as u32);
+
+        if self.is_legacy() {
+            self.write(reg::QUEUE_ALIGN, PAGE_SIZE as u32);
+            self.write(reg::QUEUE_PFN, (queue.desc_addr() / PAGE_SIZE) as u32);
+        } else {
+            let (desc, driver, device) = (
+                queue.desc_addr() as u64,
+                queue.avail_addr() as u64,
+                queue.used_addr() as u64,
+            );
+            self.write(reg::QUEUE_DESC_LOW, desc as u32);
+            self.write(reg::QUEUE_DESC_HIGH, (desc >> 32) as u32);
+            self.write(reg::QUEUE_DRIVER_LOW, driver as u32);
+            self.write(reg::QUEUE_DRIVER_HIGH, (driver >> 32) as u32);
+            self.write(reg::QUEUE_DEVICE_LOW, device as u32);
+            self.write(reg::QUEUE_DEVICE_HIGH, (device >> 32) as u32);
+            self.write(reg::QUEUE_READY, 1);
+        }
+    }
+
+    /// Notify the device that new buffers are available in a queue
+    pub fn notify(&self, index: u16) {
+        self.write(reg::QUEUE_NOTIFY, index as u32);
+    }
+
+    /// Read a byte from the device-specific configuration space
+    pub fn config_read_u8(&self, offset: usize) -> u8 {
+        unsafe { core::ptr::read_volatile((self.base + reg::CONFIG + offset) as *const u8) }
+    }
+}
@@ -0,0 +1,172 @@
+//! Virtio network device driver
+//!
+//! A polling driver for virtio-net: queue 0 receives and queue 1 transmits.
+//! Every receive buffer is a single descriptor large enough for a full
+//! Ethernet frame plus the virtio-net header, so no offloads or mergeable
+//! buffers are negotiated.
+//!
+//! On QEMU, attach it with for example:
+//! `-netdev user,id=net0 -device virtio-net-device,netdev=net0`
+
+use super::queue::{Buffer, MAX_QUEUE_SIZE, VirtQueue};
+use super::{DeviceType, Error, VirtioMmio};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Device has a MAC address in its configuration space
+const VIRTIO_NET_F_MAC: u64 = 1 << 5;
+
+const RX_QUEUE: u16 = 0;
+const TX_QUEUE: u16 = 1;
+
+/// Size of `struct virtio_net_hdr` for legacy devices
+const LEGACY_HEADER_LEN: usize = 10;
+/// Size of `struct virtio_net_hdr` once `VIRTIO_F_VERSION_1` is negotiated
+const MODERN_HEADER_LEN: usize = 12;
+
+/// Largest Ethernet frame handled, without the frame check sequence
+pub const MAX_FRAME_LEN: usize = 1514;
+
+/// Default MAC address used when the device does not provide one
+const DEFAULT_MAC: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+
+/// A virtio network device
+pub struct VirtioNet {
+    transport: VirtioMmio,
+    rx: VirtQueue,
+    tx: VirtQueue,
+    rx_buffers: Vec<Vec<u8>>,
+    header_len: usize,
+    mac: [u8; 6],
+}
+
+impl VirtioNet {
+    /// Probe for a virtio-net device and bring it up
+    ///
+    /// # Returns
+    /// * `Ok(device)` - The initialized device, with all receive buffers posted
+    /// * `Err(error)` - No device was found or initialization failed
+    pub fn new() -> Result<Self, Error> {
+        let mut transport = VirtioMmio::probe(DeviceType::Net)?;
+        let features = transport.init(VIRTIO_NET_F_MAC)?;
+
+        let header_len = if transport.is_legacy() {
+            LEGACY_HEADER_LEN
+        } else {
+            MODERN_HEADER_LEN
+        };
+
+        let mac = if features & VIRTIO_NET_F_MAC != 0 {
+            core::array::from_fn(|i| transport.config_read_u8(i))
+        } else {
+            DEFAULT_MAC
+        };
+
+        let rx = Self::setup_queue(&transport, RX_QUEUE)?;
+        let tx = Self::setup_queue(&transport, TX_QUEUE)?;
+
+        let mut net = Self {
+            transport,
+            rx_buffers: (0..rx.size()).map(|_| Vec::new()).collect(),
+            rx,
+            tx,
+            header_len,
+            mac,
+        };
+
+        for _ in 0..net.rx.size() {
+            net.post_rx_buffer(vec![0; header_len + MAX_FRAME_LEN]);
+        }
+        net.transport.driver_ok();
+        net.transport.notify(RX_QUEUE);
+
+        Ok(net)
+    }
+
+    fn setup_queue(transport: &VirtioMmio, index: u16) -> Result<VirtQueue, Error> {
+        let max = transport.queue_max(index);
+        if max == 0 {
+            return Err(Error::QueueUnavailable);
+        }
+
+        // Queue sizes must be powers of two; round the device maximum down
+        let size = MAX_QUEUE_SIZE.min(1 << (15 - max.leading_zeros()));
+        let queue = VirtQueue::new(size)?;
+        transport.attach_queue(index, &queue);
+        Ok(queue)
+    }
+
+    fn post_rx_buffer(&mut self, mut buffer: Vec<u8>) {
+        let head = self.rx.add(&[Buffer {
+            addr: buffer.as_mut_ptr() as usize,
+            len: buffer.len() as u32,
+            device_writable: true,
+        }]);
+
+        if let Some(head) = head {
+            self.rx_buffers[head as usize] = buffer;
+        }
+    }
+
+    /// MAC address of the device
+    pub fn mac(&self) -> [u8; 6] {
+        self.mac
+    }
+
+    /// Transmit one Ethernet frame (blocking)
+    ///
+    /// Waits until the device has consumed the frame.
+    ///
+    /// # Arguments
+    /// * `frame` - Ethernet frame starting at the destination MAC, without FCS
+    pub fn send(&mut self, frame: &[u8]) {
+        let len = frame.len().min(MAX_FRAME_LEN);
+        let mut packet = vec![0u8; self.header_len + len];
+        packet[self.header_len..].copy_from_slice(&frame[..len]);
+
+        let head = self.tx.add(&[Buffer {
+            addr: packet.as_ptr() as usize,
+            len: packet.len() as u32,
+            device_writable: false,
+        }]);
+        if head.is_none() {
+            return;
+        }
+        self.transport.notify(TX_QUEUE);
+
+        while self.tx.pop_used().is_none() {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Receive one Ethernet frame (non-blocking)
+    ///
+    /// # Arguments
+    /// * `frame` - Buffer of at least [`MAX_FRAME_LEN`] bytes for the frame
+    ///
+    /// # Returns
+    /// * `Some(len)` - A frame of `len` bytes was copied into `frame`
+    /// * `None` - No frame is pending
+    pub fn receive(&mut self, frame: &mut [u8]) -> Option<usize> {
+        let (head, written) = self.rx.pop_used()?;
+        let buffer = core::mem::take(&mut self.rx_buffers[head as usize]);
+
+        let payload = buffer
+            .get(self.header_len..written as usize)
+            .unwrap_or_default();
+        let len = payload.len().min(frame.len());
+        frame[..len].copy_from_slice(&payload[..len]);
+
+        self.post_rx_buffer(buffer);
+        self.transport.notify(RX_QUEUE);
+
+        Some(len)
+    }
+}
+
+impl Drop for VirtioNet {
+    /// Reset the device so it stops writing into the receive buffers
+    fn drop(&mut self) {
+        self.transport.reset();
+    }
+}
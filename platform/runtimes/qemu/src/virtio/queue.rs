@@ -0,0 +1,250 @@
+//! Split virtqueue implementation
+//!
+//! The queue memory uses the legacy contiguous layout (descriptor table,
+//! available ring, then the used ring on the next page boundary), which is
+//! also valid for modern devices. All accesses to the rings go through
+//! volatile reads and writes separated by fences, as the device reads and
+//! writes them concurrently.
+
+use super::{Error, PAGE_SIZE};
+use alloc::alloc::{alloc_zeroed, dealloc};
+use core::alloc::Layout;
+use core::sync::atomic::{Ordering, fence};
+
+/// Descriptor continues via the `next` field
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+/// Buffer is write-only for the device
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+/// Largest queue size used by the drivers, to keep the memory footprint small
+pub const MAX_QUEUE_SIZE: u16 = 16;
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// One buffer of a descriptor chain
+#[derive(Debug, Clone, Copy)]
+pub struct Buffer {
+    /// Physical address of the buffer
+    pub addr: usize,
+    /// Length of the buffer in bytes
+    pub len: u32,
+    /// Whether the device writes into (rather than reads from) the buffer
+    pub device_writable: bool,
+}
+
+/// A split virtqueue shared with a device
+pub struct VirtQueue {
+    mem: *mut u8,
+    layout: Layout,
+    size: u16,
+    free_head: u16,
+    num_free: u16,
+    avail_idx: u16,
+    last_used_idx: u16,
+}
+
+// SAFETY: the queue exclusively owns its ring memory; the device only
+// touches it while the owning driver keeps the queue attached
+unsafe impl Send for VirtQueue {}
+
+fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+impl VirtQueue {
+    /// Allocate a zeroed queue with the given number of descriptors
+    ///
+    /// # Arguments
+    /// * `size` - Number of descriptors, a power of two no larger than the
+    ///   device's maximum for this queue
+    ///
+    /// # Returns
+    /// * `Ok(queue)` - The allocated queue
+    /// * `Err(Error::QueueUnavailable)` - `size` is zero or not a power of two
+    pub fn new(size: u16) -> Result<Self, Error> {
+        if size == 0 || !size.is_power_of_two() {
+            return Err(Error::QueueUnavailable);
+        }
+
+        let layout = Layout::from_size_align(Self::mem_size(size), PAGE_SIZE)
+            .map_err(|_| Error::QueueUnavailable)?;
+        let mem = unsafe { alloc_zeroed(layout) };
+        if mem.is_null() {
+            alloc::alloc::handle_alloc_error(layout);
+        }
+
+        let queue = Self {
+            mem,
+            layout,
+            size,
+            free_head: 0,
+            num_free: size,
+            avail_idx: 0,
+            last_used_idx: 0,
+        };
+
+        // Link all descriptors into the free list
+        for i in 0..size {
+            unsafe { (*queue.desc(i)).next = (i + 1) % size };
+        }
+
+        Ok(queue)
+    }
+
+    fn avail_offset(size: u16) -> usize {
+        16 * size as usize
+    }
+
+    fn used_offset(size: u16) -> usize {
+        align_up(Self::avail_offset(size) + 6 + 2 * size as usize, PAGE_SIZE)
+    }
+
+    fn mem_size(size: u16) -> usize {
+        Self::used_offset(size) + align_up(6 + 8 * size as usize, PAGE_SIZE)
+    }
+
+    /// Number of descriptors in the queue
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// Address of the descriptor table
+    pub fn desc_addr(&self) -> usize {
+        self.mem as usize
+    }
+
+    /// Address of the available (driver) ring
+    pub fn avail_addr(&self) -> usize {
+        self.desc_addr() + Self::avail_offset(self.size)
+    }
+
+    /// Address of the used (device) ring
+    pub fn used_addr(&self) -> usize {
+        self.desc_addr() + Self::used_offset(self.size)
+    }
+
+    fn desc(&self, index: u16) -> *mut Descriptor {
+        unsafe { (self.mem as *mut Descriptor).add(index as usize) }
+    }
+
+    /// Expose a descriptor chain to the device
+    ///
+    /// The caller must keep the buffers alive and untouched until the chain
+    /// is returned by [`VirtQueue::pop_used`], and must notify the device
+    /// afterwards.
+    ///
+    /// # Arguments
+    /// * `buffers` - Buffers forming the chain, in order
+    ///
+    /// # Returns
+    /// * `Some(head)` - Index of the chain's first descriptor
+    /// * `None` - Not enough free descriptors
+    pub fn add(&mut self, buffers: &[Buffer]) -> Option<u16> {
+        if buffers.is_empty() || buffers.len() > self.num_free as usize {
+            return None;
+        }
+
+        let head = self.free_head;
+        let mut index = head;
+        for (i, buffer) in buffers.iter().enumerate() {
+            let desc = self.desc(index);
+            unsafe {
+                let next = (*desc).next;
+                let mut flags = if buffer.device_writable {
+                    VIRTQ_DESC_F_WRITE
+                } else {
+                    0
+                };
+                if i + 1 < buffers.len() {
+                    flags |= VIRTQ_DESC_F_NEXT;
+                }
+                core::ptr::write_volatile(
+                    desc,
+                    Descriptor {
+                        addr: buffer.addr as u64,
+                        len: buffer.len,
+                        flags,
+                        next,
+                    },
+                );
+                if i + 1 < buffers.len() {
+                    index = next;
+                } else {
+                    self.free_head = next;
+                }
+            }
+        }
+        self.num_free -= buffers.len() as u16;
+
+        // Publish the chain head, then the new index
+        let avail = self.avail_addr() as *mut u16;
+        unsafe {
+            let slot = avail.add(2 + (self.avail_idx % self.size) as usize);
+            core::ptr::write_volatile(slot, head);
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            core::ptr::write_volatile(avail.add(1), self.avail_idx);
+            fence(Ordering::SeqCst);
+        }
+
+        Some(head)
+    }
+
+    /// Take the next chain the device has finished with
+    ///
+    /// # Returns
+    /// * `Some((head, len))` - Head of the returned chain and the number of
+    ///   bytes the device wrote into it
+    /// * `None` - No chain has been returned since the last call
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        let used = self.used_addr() as *mut u16;
+
+        fence(Ordering::SeqCst);
+        let used_idx = unsafe { core::ptr::read_volatile(used.add(1)) };
+        if used_idx == self.last_used_idx {
+            return None;
+        }
+
+        let elem = unsafe {
+            (used.add(2) as *const u32).add(2 * (self.last_used_idx % self.size) as usize)
+        };
+        let (head, len) = unsafe {
+            (
+                core::ptr::read_volatile(elem) as u16,
+                core::ptr::read_volatile(elem.add(1)),
+            )
+        };
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+
+        // Return the chain to the free list
+        let mut index = head;
+        loop {
+            self.num_free += 1;
+            let desc = self.desc(index);
+            let (flags, next) = unsafe { ((*desc).flags, (*desc).next) };
+            if flags & VIRTQ_DESC_F_NEXT == 0 {
+                unsafe { (*desc).next = self.free_head };
+                break;
+            }
+            index = next;
+        }
+        self.free_head = head;
+
+        Some((head, len))
+    }
+}
+
+impl Drop for VirtQueue {
+    /// Free the queue memory
+    ///
+    /// The device must have been reset before the queue is dropped.
+    fn drop(&mut self) {
+        unsafe { dealloc(self.mem, self.layout) };
+    }
+}
@@ -0,0 +1,97 @@
+//! Virtio entropy device driver
+//!
+//! The device fills driver-supplied buffers with random bytes from the
+//! host. On QEMU, attach it with `-device virtio-rng-device`.
+
+use super::queue::{Buffer, VirtQueue};
+use super::{DeviceType, Error, VirtioMmio};
+
+const REQUEST_QUEUE: u16 = 0;
+
+/// Polls of the used ring before a request is given up on
+const POLL_LIMIT: u32 = 1 << 24;
+
+/// A virtio entropy device
+pub struct VirtioRng {
+    transport: VirtioMmio,
+    queue: VirtQueue,
+}
+
+impl VirtioRng {
+    /// Probe for a virtio-rng device and bring it up
+    ///
+    /// # Returns
+    /// * `Ok(device)` - The initialized device
+    /// * `Err(error)` - No device was found or initialization failed
+    pub fn new() -> Result<Self, Error> {
+        let mut transport = VirtioMmio::probe(DeviceType::Rng)?;
+        transport.init(0)?;
+
+        if transport.queue_max(REQUEST_QUEUE) == 0 {
+            return Err(Error::QueueUnavailable);
+        }
+        // Requests are issued one at a time, a single descriptor is enough
+        let queue = VirtQueue::new(1)?;
+        transport.attach_queue(REQUEST_QUEUE, &queue);
+        transport.driver_ok();
+
+        Ok(Self { transport, queue })
+    }
+
+    /// Fill a buffer with random bytes from the host (blocking)
+    ///
+    /// # Arguments
+    /// * `buf` - Buffer to fill
+    ///
+    /// # Returns
+    /// * Number of bytes filled, `buf.len()` unless the device stops
+    ///   returning bytes. A request the device does not complete in time
+    ///   resets it, so it no longer writes to `buf`; later calls return 0.
+    pub fn fill(&mut self, buf: &mut [u8]) -> usize {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let rest = &mut buf[filled..];
+            let head = self.queue.add(&[Buffer {
+                addr: rest.as_mut_ptr() as usize,
+                len: rest.len() as u32,
+                device_writable: true,
+            }]);
+            if head.is_none() {
+                break;
+            }
+            self.transport.notify(REQUEST_QUEUE);
+
+            let Some(written) = self.wait_used() else {
+                self.transport.reset();
+                break;
+            };
+            if written == 0 {
+                break;
+            }
+            filled += written.min(rest.len());
+        }
+        filled
+    }
+
+    /// Wait for the device to complete the request in flight
+    ///
+    /// # Returns
+    /// * `Some(len)` - Bytes the device wrote
+    /// * `None` - The request did not complete within [`POLL_LIMIT`] polls
+    fn wait_used(&mut self) -> Option<usize> {
+        for _ in 0..POLL_LIMIT {
+            if let Some((_, len)) = self.queue.pop_used() {
+                return Some(len as usize);
+            }
+            core::hint::spin_loop();
+        }
+        None
+    }
+}
+
+impl Drop for VirtioRng {
+    /// Reset the device before its queue memory is freed
+    fn drop(&mut self) {
+        self.transport.reset();
+    }
+}
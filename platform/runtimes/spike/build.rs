@@ -0,0 +1,3 @@
+fn main() {
+    build_helper::emit_memory_map(build_helper::Platform::Spike);
+}
@@ -6,7 +6,8 @@
 //! Spike exit via HTIF (Host-Target Interface)
 //!
 //! In bare-metal Spike, we use HTIF to communicate exit to the host.
-//! Setting tohost = 1 signals the host to terminate the simulation.
+//! Setting tohost = (code << 1) | 1 signals the host to terminate the
+//! simulation with exit code `code`.
 //!
 //! If not on a RISC-V target (e.g. host-side analysis), we provide a dummy
 //! implementation that never returns but marks unreachable.
@@ -20,18 +21,18 @@ unsafe extern "C" {
 /// Platform-specific exit function
 ///
 /// This function is called when the user's main function returns.
-/// For Spike, set tohost = 1 to signal exit to the host.
+/// For Spike, set tohost = (code << 1) | 1 to signal exit to the host,
+/// which then exits with `code`.
 ///
 /// # Arguments
 /// * `code` - Exit code (0 for success, non-zero for failure)
-#[unsafe(no_mangle)]
 pub fn platform_exit(code: i32) -> ! {
     // RISC-V implementation: use HTIF to exit
     #[cfg(any(target_arch = "riscv32"))]
     unsafe {
-        let _ = code;
-        // Set tohost = 1 to signal exit (Spike interprets this as exit(0))
-        core::ptr::write_volatile(&raw mut tohost, 1u64);
+        // The low bit marks an exit request, the rest is the exit code
+        let value = ((code as u32 as u64) << 1) | 1;
+        core::ptr::write_volatile(&raw mut tohost, value);
         // Loop forever; Spike will terminate the simulation
         loop {}
     }
@@ -1,24 +1,51 @@
 #![no_std]
 
+extern crate alloc;
+
 // Platform-specific modules
 pub mod critical_section;
 pub mod exit;
 pub mod startup;
 pub mod stdio;
 
+/// Physical addresses of the Spike machine, from the build-helper memory map
+pub mod memory_map {
+    include!(concat!(env!("OUT_DIR"), "/memory_map.rs"));
+}
+
+/// The Spike platform, for `runtime`
+pub struct Spike;
+
+impl common::Platform for Spike {
+    const NAME: &'static str = "spike";
+    const MEMORY_MAP: &'static [common::MemoryRegion] = memory_map::REGIONS;
+
+    fn putc(ch: u8) {
+        stdio::putc(ch)
+    }
+
+    fn try_getc() -> Option<u8> {
+        stdio::try_getc()
+    }
+
+    fn getc() -> u8 {
+        stdio::getc()
+    }
+
+    fn exit(code: i32) -> ! {
+        exit::platform_exit(code)
+    }
+}
+
 #[unsafe(export_name = "isa_init")]
 #[unsafe(link_section = ".text.isa_init")]
 pub unsafe extern "C" fn isa_init() -> ! {
-    unsafe extern "Rust" {
-        fn user_entry() -> !;
-    }
-
     unsafe {
         core::arch::asm!(
             "li x10, 0x200",
             "csrs mstatus, x10",
             options(nomem, nostack, preserves_flags)
         );
-        user_entry();
+        common::enter_program()
     }
 }
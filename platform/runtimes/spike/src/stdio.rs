@@ -20,7 +20,6 @@ const UART_LSR_DR: u8 = 0x01; // Data Ready (receive buffer has data)
 ///
 /// # Arguments
 /// * `ch` - Character byte to transmit
-#[unsafe(no_mangle)]
 pub fn putc(ch: u8) {
     unsafe {
         // Wait until transmit holding register is empty
@@ -40,7 +39,6 @@ pub fn putc(ch: u8) {
 ///
 /// # Safety
 /// This function blocks indefinitely if no data arrives.
-#[unsafe(no_mangle)]
 pub fn getc() -> u8 {
     unsafe {
         // Wait until data is ready (receive buffer has data)
@@ -58,7 +56,6 @@ pub fn getc() -> u8 {
 /// # Returns
 /// * `Some(ch)` - Character byte if data is available
 /// * `None` - No data available
-#[unsafe(no_mangle)]
 pub fn try_getc() -> Option<u8> {
     unsafe {
         // Check if data is ready
@@ -0,0 +1,238 @@
+//! Frame decoding
+//!
+//! The wire format is described in `runtime::dlog`; the constants here must
+//! match it.
+
+use crate::format::{self, Value};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+/// First byte of every frame
+const FRAME_START: u8 = 0xff;
+
+const TAG_UNSIGNED: u8 = 0;
+const TAG_SIGNED: u8 = 1;
+const TAG_F32: u8 = 2;
+const TAG_F64: u8 = 3;
+const TAG_BOOL: u8 = 4;
+const TAG_CHAR: u8 = 5;
+const TAG_STR: u8 = 6;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The ELF could not be parsed
+    Elf(elf::ParseError),
+    /// The ELF has no `.am_fmt` section, it has no `dprintln!`s or was not
+    /// built with the `dlog` feature
+    NoStrings,
+    /// No format string starts at the index
+    UnknownIndex(u64),
+    /// An argument with an unknown tag
+    BadTag(u8),
+    /// A value that cannot be what its tag says
+    BadValue,
+    /// The stream ended in the middle of a frame
+    Truncated,
+    Format(format::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Elf(err) => write!(f, "bad ELF: {}", err),
+            Error::NoStrings => write!(
+                f,
+                "no .am_fmt section, was the program built with the dlog feature?"
+            ),
+            Error::UnknownIndex(index) => write!(f, "no format string at index {}", index),
+            Error::BadTag(tag) => write!(f, "unknown argument tag {}", tag),
+            Error::BadValue => write!(f, "malformed argument"),
+            Error::Truncated => write!(f, "truncated frame"),
+            Error::Format(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<format::Error> for Error {
+    fn from(err: format::Error) -> Self {
+        Error::Format(err)
+    }
+}
+
+pub struct Decoder {
+    /// Contents of `.am_fmt`, NUL-terminated strings
+    strings: Vec<u8>,
+}
+
+impl Decoder {
+    /// Load the format strings from an ELF image
+    pub fn from_elf(image: &[u8]) -> Result<Self, Error> {
+        let file =
+            elf::ElfBytes::<elf::endian::AnyEndian>::minimal_parse(image).map_err(Error::Elf)?;
+        let header = file
+            .section_header_by_name(".am_fmt")
+            .map_err(Error::Elf)?
+            .ok_or(Error::NoStrings)?;
+        let (data, _) = file.section_data(&header).map_err(Error::Elf)?;
+        Ok(Self {
+            strings: data.to_vec(),
+        })
+    }
+
+    fn format_string(&self, index: u64) -> Result<&str, Error> {
+        let unknown = || Error::UnknownIndex(index);
+        let start = usize::try_from(index).map_err(|_| unknown())?;
+        let tail = self.strings.get(start..).ok_or_else(unknown)?;
+        let len = tail
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(unknown)?;
+        std::str::from_utf8(&tail[..len]).map_err(|_| unknown())
+    }
+
+    /// Decode the frame after a [`FRAME_START`]
+    ///
+    /// # Arguments
+    /// * `next` - Returns the next byte of the stream, `None` at its end
+    ///
+    /// # Returns
+    /// * The formatted line, without a newline
+    fn frame(&self, next: &mut impl FnMut() -> io::Result<Option<u8>>) -> Result<String, Error> {
+        let mut byte = || next()?.ok_or(Error::Truncated);
+        let fmt = self.format_string(leb128(&mut byte)?)?;
+        let count = fmt.matches('{').count() - 2 * fmt.matches("{{").count();
+
+        let mut args = Vec::with_capacity(count);
+        for _ in 0..count {
+            let value = match byte()? {
+                TAG_UNSIGNED => Value::Unsigned(leb128(&mut byte)?),
+                TAG_SIGNED => {
+                    let zigzag = leb128(&mut byte)?;
+                    Value::Signed((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64))
+                }
+                TAG_F32 => Value::F32(f32::from_le_bytes(bytes(&mut byte)?)),
+                TAG_F64 => Value::F64(f64::from_le_bytes(bytes(&mut byte)?)),
+                TAG_BOOL => Value::Bool(byte()? != 0),
+                TAG_CHAR => {
+                    let code = u32::try_from(leb128(&mut byte)?).map_err(|_| Error::BadValue)?;
+                    Value::Char(char::from_u32(code).ok_or(Error::BadValue)?)
+                }
+                TAG_STR => {
+                    let len = leb128(&mut byte)?;
+                    let text = (0..len)
+                        .map(|_| byte())
+                        .collect::<Result<Vec<u8>, Error>>()?;
+                    Value::Str(String::from_utf8(text).map_err(|_| Error::BadValue)?)
+                }
+                tag => return Err(Error::BadTag(tag)),
+            };
+            args.push(value);
+        }
+        Ok(format::format(fmt, &args)?)
+    }
+
+    /// Copy `input` to `output`, replacing frames by their text
+    ///
+    /// A frame that cannot be decoded is reported in its place, and decoding
+    /// goes on with the bytes after it.
+    pub fn run(&self, input: impl io::Read, mut output: impl Write) -> Result<(), Error> {
+        let mut input = io::BufReader::new(input);
+        let mut next = || -> io::Result<Option<u8>> {
+            let byte = input.fill_buf()?.first().copied();
+            input.consume(byte.is_some() as usize);
+            Ok(byte)
+        };
+
+        while let Some(byte) = next()? {
+            if byte == FRAME_START {
+                match self.frame(&mut next) {
+                    Ok(line) => writeln!(output, "{}", line)?,
+                    Err(Error::Io(err)) => return Err(Error::Io(err)),
+                    Err(err) => writeln!(output, "<dlog: {}>", err)?,
+                }
+            } else {
+                output.write_all(&[byte])?;
+            }
+            // Keep up with a program that is still running
+            if byte == b'\n' || byte == FRAME_START {
+                output.flush()?;
+            }
+        }
+        output.flush()?;
+        Ok(())
+    }
+}
+
+fn leb128(byte: &mut impl FnMut() -> Result<u8, Error>) -> Result<u64, Error> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let next = byte()?;
+        value |= ((next & 0x7f) as u64) << shift;
+        if next & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(Error::BadValue)
+}
+
+fn bytes<const N: usize>(byte: &mut impl FnMut() -> Result<u8, Error>) -> Result<[u8; N], Error> {
+    let mut out = [0; N];
+    for slot in &mut out {
+        *slot = byte()?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(strings: &[u8], input: &[u8]) -> String {
+        let decoder = Decoder {
+            strings: strings.to_vec(),
+        };
+        let mut output = Vec::new();
+        decoder.run(input, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_frames() {
+        let mut strings = vec![0; 300];
+        strings.extend_from_slice(b"{} {} {:.2} {} {:?} {}\0");
+
+        // The frame from runtime::dlog's test_encode_frame, between text
+        let mut input = b"before\n".to_vec();
+        input.extend_from_slice(&[
+            0xff, 0xac, 0x02, 0, 5, 1, 3, 2, 0x00, 0x00, 0xc0, 0x3f, 4, 1, 5, 0xe9, 0x01, 6, 2,
+            b'h', b'i',
+        ]);
+        input.extend_from_slice(b"after\n");
+        assert_eq!(
+            decode(&strings, &input),
+            "before\n5 -2 1.50 true 'é' hi\nafter\n"
+        );
+    }
+
+    #[test]
+    fn test_bad_frames() {
+        let strings = b"{}\0";
+        assert_eq!(
+            decode(strings, &[0xff, 9]),
+            "<dlog: no format string at index 9>\n"
+        );
+        assert_eq!(
+            decode(strings, &[0xff, 0, 7, b'x']),
+            "<dlog: unknown argument tag 7>\nx"
+        );
+        assert_eq!(decode(strings, &[0xff, 0, 0]), "<dlog: truncated frame>\n");
+    }
+}
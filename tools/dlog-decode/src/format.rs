@@ -0,0 +1,364 @@
+//! Formatting of decoded arguments, following `core::fmt`
+//!
+//! Supports the subset `dprintln!` allows: `{}` and `{:spec}` placeholders
+//! with fill and alignment, `+`, `#`, `0`, width, precision, and the `?`,
+//! `x`, `X`, `o`, `b`, `e` and `E` types. Widths and precisions given as
+//! arguments (`*`, `$`) are not supported.
+
+use std::fmt;
+
+/// A decoded argument
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Unsigned(u64),
+    Signed(i64),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A `{` or `}` without its pair
+    UnmatchedBrace,
+    /// A placeholder this decoder cannot format
+    Unsupported(String),
+    /// The type cannot be formatted as the value
+    Mismatch(String),
+    /// More placeholders than arguments
+    MissingArgument,
+    /// More arguments than placeholders
+    ExtraArgument,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnmatchedBrace => write!(f, "unmatched brace"),
+            Error::Unsupported(spec) => write!(f, "unsupported placeholder {{{}}}", spec),
+            Error::Mismatch(spec) => write!(f, "argument does not fit {{{}}}", spec),
+            Error::MissingArgument => write!(f, "missing argument"),
+            Error::ExtraArgument => write!(f, "too many arguments"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// A parsed `{:spec}`
+#[derive(Debug, Default)]
+struct Spec<'a> {
+    fill: Option<char>,
+    align: Option<Align>,
+    plus: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    kind: &'a str,
+}
+
+fn align_of(ch: char) -> Option<Align> {
+    match ch {
+        '<' => Some(Align::Left),
+        '^' => Some(Align::Center),
+        '>' => Some(Align::Right),
+        _ => None,
+    }
+}
+
+/// Split leading ASCII digits off `text`
+fn digits(text: &str) -> (Option<usize>, &str) {
+    let end = text
+        .find(|ch: char| !ch.is_ascii_digit())
+        .unwrap_or(text.len());
+    (text[..end].parse().ok(), &text[end..])
+}
+
+impl<'a> Spec<'a> {
+    /// Parse the text between the braces
+    fn parse(placeholder: &'a str) -> Result<Self, Error> {
+        let unsupported = || Error::Unsupported(String::from(placeholder));
+        let mut spec = Spec::default();
+        let mut rest = match placeholder.split_once(':') {
+            None if placeholder.is_empty() => return Ok(spec),
+            Some(("", rest)) => rest,
+            // Positional and named arguments
+            _ => return Err(unsupported()),
+        };
+
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next().and_then(align_of)) {
+            (Some(fill), Some(align)) => {
+                spec.fill = Some(fill);
+                spec.align = Some(align);
+                rest = chars.as_str();
+            }
+            (Some(first), _) if align_of(first).is_some() => {
+                spec.align = align_of(first);
+                rest = &rest[1..];
+            }
+            _ => {}
+        }
+        if let Some(after) = rest.strip_prefix('+') {
+            spec.plus = true;
+            rest = after;
+        }
+        if let Some(after) = rest.strip_prefix('#') {
+            spec.alternate = true;
+            rest = after;
+        }
+        if let Some(after) = rest.strip_prefix('0') {
+            spec.zero = true;
+            rest = after;
+        }
+        let (width, after) = digits(rest);
+        spec.width = width.unwrap_or(0);
+        rest = after;
+        if let Some(after) = rest.strip_prefix('.') {
+            let (precision, after) = digits(after);
+            spec.precision = Some(precision.ok_or_else(unsupported)?);
+            rest = after;
+        }
+
+        if !matches!(rest, "" | "?" | "x" | "X" | "o" | "b" | "e" | "E") {
+            return Err(unsupported());
+        }
+        spec.kind = rest;
+        Ok(spec)
+    }
+
+    /// Format `value`, without padding
+    ///
+    /// # Returns
+    /// * The sign (or an empty string), and the rest, including any `0x`
+    ///   style prefix
+    fn body(&self, value: &Value) -> Option<(&'static str, String)> {
+        let radix_prefix = |prefix: &'static str| if self.alternate { prefix } else { "" };
+        let (negative, text) = match (value, self.kind) {
+            (Value::Unsigned(v), "" | "?") => (false, v.to_string()),
+            (Value::Signed(v), "" | "?") => (*v < 0, v.unsigned_abs().to_string()),
+            (Value::Unsigned(v), "e") => (false, format!("{:e}", v)),
+            (Value::Unsigned(v), "E") => (false, format!("{:E}", v)),
+            (Value::Signed(v), "e") => (*v < 0, format!("{:e}", v.unsigned_abs())),
+            (Value::Signed(v), "E") => (*v < 0, format!("{:E}", v.unsigned_abs())),
+            (Value::Unsigned(_) | Value::Signed(_), kind) => {
+                // Negative numbers show their two's complement, as in Rust
+                let bits = match value {
+                    Value::Signed(v) => *v as u64,
+                    Value::Unsigned(v) => *v,
+                    _ => unreachable!(),
+                };
+                let text = match kind {
+                    "x" => format!("{}{:x}", radix_prefix("0x"), bits),
+                    "X" => format!("{}{:X}", radix_prefix("0x"), bits),
+                    "o" => format!("{}{:o}", radix_prefix("0o"), bits),
+                    "b" => format!("{}{:b}", radix_prefix("0b"), bits),
+                    _ => return None,
+                };
+                (false, text)
+            }
+            (Value::F32(v), kind) => {
+                float(v.abs(), negative_float(*v as f64), kind, self.precision)?
+            }
+            (Value::F64(v), kind) => float(v.abs(), negative_float(*v), kind, self.precision)?,
+            (Value::Bool(v), "" | "?") => (false, v.to_string()),
+            (Value::Char(v), "") => (false, v.to_string()),
+            (Value::Char(v), "?") => (false, format!("{:?}", v)),
+            (Value::Str(v), "") => match self.precision {
+                Some(p) => (false, v.chars().take(p).collect()),
+                None => (false, v.clone()),
+            },
+            (Value::Str(v), "?") => (false, format!("{:?}", v)),
+            _ => return None,
+        };
+
+        let sign = match (negative, self.plus) {
+            (true, _) => "-",
+            (false, true) if is_numeric(value) => "+",
+            _ => "",
+        };
+        Some((sign, text))
+    }
+}
+
+/// Whether a float is printed with a minus sign: NaN never is, -0.0 is
+fn negative_float(value: f64) -> bool {
+    value.is_sign_negative() && !value.is_nan()
+}
+
+/// Format a float magnitude, the sign is handled by the caller
+fn float<T>(
+    magnitude: T,
+    negative: bool,
+    kind: &str,
+    precision: Option<usize>,
+) -> Option<(bool, String)>
+where
+    T: fmt::Display + fmt::Debug + fmt::LowerExp + fmt::UpperExp,
+{
+    let text = match (kind, precision) {
+        ("" | "?", Some(p)) => format!("{:.*}", p, magnitude),
+        ("", None) => format!("{}", magnitude),
+        ("?", None) => format!("{:?}", magnitude),
+        ("e", Some(p)) => format!("{:.*e}", p, magnitude),
+        ("e", None) => format!("{:e}", magnitude),
+        ("E", Some(p)) => format!("{:.*E}", p, magnitude),
+        ("E", None) => format!("{:E}", magnitude),
+        _ => return None,
+    };
+    Some((negative, text))
+}
+
+fn is_numeric(value: &Value) -> bool {
+    matches!(
+        value,
+        Value::Unsigned(_) | Value::Signed(_) | Value::F32(_) | Value::F64(_)
+    )
+}
+
+/// Format one argument, with padding
+fn format_value(placeholder: &str, value: &Value) -> Result<String, Error> {
+    let spec = Spec::parse(placeholder)?;
+    let (sign, body) = spec
+        .body(value)
+        .ok_or_else(|| Error::Mismatch(String::from(placeholder)))?;
+
+    let len = sign.chars().count() + body.chars().count();
+    let padding = spec.width.saturating_sub(len);
+    if padding == 0 {
+        return Ok(format!("{}{}", sign, body));
+    }
+
+    // Zero padding goes between the sign and prefix, and the digits
+    if spec.zero && is_numeric(value) {
+        let prefix_len = if spec.alternate && matches!(spec.kind, "x" | "X" | "o" | "b") {
+            2
+        } else {
+            0
+        };
+        let (prefix, digits) = body.split_at(prefix_len);
+        return Ok(format!(
+            "{}{}{}{}",
+            sign,
+            prefix,
+            "0".repeat(padding),
+            digits
+        ));
+    }
+
+    let default = if is_numeric(value) {
+        Align::Right
+    } else {
+        Align::Left
+    };
+    let (before, after) = match spec.align.unwrap_or(default) {
+        Align::Left => (0, padding),
+        Align::Center => (padding / 2, padding - padding / 2),
+        Align::Right => (padding, 0),
+    };
+    let fill = spec.fill.unwrap_or(' ').to_string();
+    Ok(format!(
+        "{}{}{}{}",
+        fill.repeat(before),
+        sign,
+        body,
+        fill.repeat(after)
+    ))
+}
+
+/// Format `fmt` with `args`, as `format!` would
+pub fn format(fmt: &str, args: &[Value]) -> Result<String, Error> {
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut rest = fmt;
+
+    while let Some(pos) = rest.find(['{', '}']) {
+        out.push_str(&rest[..pos]);
+        let brace = &rest[pos..];
+        if brace.starts_with("{{") || brace.starts_with("}}") {
+            out.push_str(&brace[..1]);
+            rest = &brace[2..];
+        } else if brace.starts_with('}') {
+            return Err(Error::UnmatchedBrace);
+        } else {
+            let end = brace.find('}').ok_or(Error::UnmatchedBrace)?;
+            let value = args.next().ok_or(Error::MissingArgument)?;
+            out.push_str(&format_value(&brace[1..end], value)?);
+            rest = &brace[end + 1..];
+        }
+    }
+    out.push_str(rest);
+
+    match args.next() {
+        Some(_) => Err(Error::ExtraArgument),
+        None => Ok(out),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integers() {
+        let args = [Value::Unsigned(255), Value::Signed(-42)];
+        assert_eq!(format("{} {}", &args).unwrap(), format!("{} {}", 255, -42));
+        assert_eq!(
+            format("{:#06x} {:+05}", &args).unwrap(),
+            format!("{:#06x} {:+05}", 255, -42)
+        );
+        assert_eq!(
+            format("{:*^9b}|{:<6}|", &args).unwrap(),
+            format!("{:*^9b}|{:<6}|", 255, -42)
+        );
+    }
+
+    #[test]
+    fn test_floats() {
+        let args = [Value::F32(0.123_456_78), Value::F64(-1.5)];
+        assert_eq!(
+            format("scale: {:.6} {:08.2}", &args).unwrap(),
+            format!("scale: {:.6} {:08.2}", 0.123_456_78f32, -1.5f64)
+        );
+        assert_eq!(
+            format("{} {:?}", &[Value::F32(1.0), Value::F64(0.1)]).unwrap(),
+            format!("{} {:?}", 1.0f32, 0.1f64)
+        );
+    }
+
+    #[test]
+    fn test_text() {
+        let args = [
+            Value::Str(String::from("hi")),
+            Value::Char('é'),
+            Value::Bool(true),
+        ];
+        assert_eq!(
+            format("{{{:?}}} {:>3} {:5}|", &args).unwrap(),
+            format!("{{{:?}}} {:>3} {:5}|", "hi", 'é', true)
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(format("{}", &[]), Err(Error::MissingArgument));
+        assert_eq!(format("x", &[Value::Bool(true)]), Err(Error::ExtraArgument));
+        assert_eq!(
+            format("{:x}", &[Value::Bool(true)]),
+            Err(Error::Mismatch(String::from(":x")))
+        );
+        assert_eq!(
+            format("{0}", &[Value::Bool(true)]),
+            Err(Error::Unsupported(String::from("0")))
+        );
+        assert_eq!(format("}", &[]), Err(Error::UnmatchedBrace));
+    }
+}
@@ -0,0 +1,33 @@
+//! Decode `runtime::dprintln!` output
+//!
+//! Reads console output on stdin and writes it to stdout, with the frames
+//! sent by `dprintln!` turned back into text using the format strings in the
+//! program's ELF. Ordinary output passes through unchanged:
+//!
+//! `just run mnist riscv32im-qemu | cargo run -p dlog-decode -- target/disasm/qemu/riscv32im/mnist/image.elf`
+
+mod decoder;
+mod format;
+
+use decoder::Decoder;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let (Some(path), None) = (args.next(), args.next()) else {
+        eprintln!("usage: dlog-decode <program.elf>");
+        return ExitCode::FAILURE;
+    };
+
+    let result = std::fs::read(&path)
+        .map_err(decoder::Error::Io)
+        .and_then(|image| Decoder::from_elf(&image))
+        .and_then(|decoder| decoder.run(std::io::stdin().lock(), std::io::stdout().lock()));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("dlog-decode: {}: {}", path, err);
+            ExitCode::FAILURE
+        }
+    }
+}
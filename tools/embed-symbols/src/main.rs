@@ -0,0 +1,29 @@
+//! Fill in the symbol table of built images
+//!
+//! Cargo has no post-link step, so the build scripts run this on every image
+//! they build; see `build_helper::embed_symbols`:
+//!
+//! `cargo run -p embed-symbols -- target/riscv32im-unknown-none-elf/release/mnist`
+
+use std::path::Path;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let paths: Vec<String> = std::env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("usage: embed-symbols <program.elf>...");
+        return ExitCode::FAILURE;
+    }
+
+    let mut status = ExitCode::SUCCESS;
+    for path in paths {
+        match build_helper::embed_symbols(Path::new(&path)) {
+            Ok(count) => println!("{}: {} functions", path, count),
+            Err(err) => {
+                eprintln!("embed-symbols: {}", err);
+                status = ExitCode::FAILURE;
+            }
+        }
+    }
+    status
+}